@@ -1,10 +1,16 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs}; // Зависимость стандартной библиотеки для работы с сетевыми адресами
 use std::path::{Path, PathBuf}; // Зависимость стандартной библиотеки для работы с файловыми путями
+use std::sync::Arc; // Оценки серверов, разделяемые параметрами отправки и получения
+use std::time::{Duration, Instant}; // Зависимость стандартной библиотеки для работы с длительностью
 
 use clap::Parser; // Внешние зависимости для работы с аргументами командной строки
-use clap::{arg, command};
 use clap_derive::{Parser, ValueEnum};
+use leafcommon::reed_solomon_scheme::{
+    AddressMode, AuditOptions, Chunker, ManifestFormat, OperationStats, OutputAttrs, PeerScores,
+    RecoveryRetry, RecvOptions, SendOptions, SendRetry, ShardConflict,
+}; // Параметры отправки и восстановления файла
 
-use leafcommon;
+const DEFAULT_PING_COUNT: usize = 4; // Количество пробных запросов по умолчанию
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -12,8 +18,114 @@ pub struct Args {
     // Структура для хранения аргументов командной строки
     #[arg(value_enum, short, long)]
     action: Action, // Аргумент, отвечающий за реализуемое действие
+    #[arg(short, long, required_unless_present_any = ["seed", "key", "root_hash", "count", "peer_scores", "manifest"])]
+    file: Option<String>, // Аргумент, указывающий целевой файл
     #[arg(short, long)]
-    file: String, // Аргумент, указывающий целевой файл
+    output: Option<String>, // Аргумент, указывающий файл для восстановленных данных ("-" - стандартный вывод)
+    #[arg(long)]
+    ack_window_ms: Option<u64>, // Аргумент, указывающий время сбора подтверждений от серверов при отправке, мс
+    #[arg(long)]
+    keep_local: bool, // Флаг сохранения копии каждого чанка на сервере этого же узла при отправке
+    #[arg(long)]
+    source_port: Option<u16>, // Аргумент, указывающий фиксированный порт клиента для правил межсетевого экрана
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=63))]
+    dscp: Option<u8>, // Аргумент, указывающий класс обслуживания DSCP (0-63) пакетов клиента для QoS
+    #[arg(long)]
+    dedup: bool, // Флаг однократной отправки одинаковых чанков (серверы узнают о повторах в файле)
+    #[arg(long)]
+    content_defined: bool, // Флаг разбиения файла на блоки по содержимому (хэши неизмененных частей сохраняются между версиями файла)
+    #[arg(long)]
+    preserve_attrs: bool, // Флаг сохранения в метаданных размера, времени изменения и прав доступа файла
+    #[arg(long)]
+    consume_source: bool, // Флаг замены исходного файла метаданными после успешной отправки (по умолчанию метаданные записываются в <файл>.leaf)
+    #[arg(long, value_parser = parse_manifest_format, default_value = "binary")]
+    manifest_format: ManifestFormat, // Аргумент, указывающий формат файла метаданных: binary (компактный) или json (для просмотра и сравнения)
+    #[arg(long)]
+    peer_scores: Option<PathBuf>, // Аргумент, указывающий файл оценок надежности серверов (оценки обновляются каждой передачей и учитываются при выборе серверов)
+    #[arg(skip)]
+    scores: Option<Arc<PeerScores>>, // Оценки серверов, прочитанные из файла --peer-scores
+    #[arg(long)]
+    crypto_threads: Option<usize>, // Аргумент, указывающий количество потоков шифрования и хэширования (по умолчанию - на одно меньше количества ядер)
+    #[arg(long)]
+    max_chunks_per_file: Option<usize>, // Аргумент, указывающий наибольшее количество чанков файла (или сегмента)
+    #[arg(long)]
+    max_manifest_size: Option<usize>, // Аргумент, указывающий наибольший размер списка чанков в метаданных файла (или сегмента) в байтах
+    #[arg(long)]
+    segment_size: Option<usize>, // Аргумент, указывающий максимальный размер сегмента большого файла в байтах (метаданные сегментов хранятся в домене)
+    #[arg(long)]
+    password_file: Option<PathBuf>, // Аргумент, указывающий файл с паролем для переносимого режима (параметры шифрования хранятся в метаданных файла)
+    #[arg(long)]
+    root: bool, // Флаг сохранения метаданных файла в домене при отправке (выводится только корневой хэш)
+    #[arg(long)]
+    embed_params: bool, // Флаг сохранения параметров кода Рида-Соломона отдельным чанком при отправке с --root (файл восстанавливается, даже если они утеряны в метаданных)
+    #[arg(long)]
+    root_hash: Option<String>, // Аргумент, указывающий корневой хэш файла для получения без файла метаданных
+    #[arg(short, long)]
+    key: Option<String>, // Аргумент, указывающий пользовательский ключ файла для действий store и fetch
+    #[arg(long, value_parser = parse_mode)]
+    mode: Option<u32>, // Аргумент, указывающий права доступа восстановленного файла в восьмеричном виде (например, 640)
+    #[arg(long)]
+    uid: Option<u32>, // Аргумент, указывающий пользователя-владельца восстановленного файла
+    #[arg(long)]
+    gid: Option<u32>, // Аргумент, указывающий группу-владельца восстановленного файла
+    #[arg(long)]
+    chunks_dir: Vec<PathBuf>, // Аргумент, указывающий каталог с файлами чанков для восстановления без домена (можно указать несколько раз)
+    #[arg(long)]
+    discard_conflicting: bool, // Флаг восстановления чанков, копии которых в каталогах различаются, по остальным чанкам (по умолчанию - ошибка)
+    #[arg(long)]
+    count: Option<usize>, // Аргумент, указывающий количество пробных запросов при проверке связи с доменом
+    #[arg(long, value_delimiter = ',', value_parser = parse_peer)]
+    peers: Vec<SocketAddr>, // Аргумент, указывающий серверы через запятую (host:port) для сетей без широковещания
+    #[arg(long)]
+    broadcast: Option<Ipv4Addr>, // Аргумент, указывающий адрес широковещания подсети домена (например, 192.168.122.255) на узле с несколькими интерфейсами
+    #[arg(long, conflicts_with = "broadcast")]
+    multicast: Option<Ipv6Addr>, // Аргумент, указывающий группу IPv6 домена (например, ff02::1f:6c) вместо широковещания IPv4
+    #[arg(long)]
+    seed: Vec<SocketAddr>, // Аргумент, указывающий серверы-затравки для обнаружения серверов (можно указать несколько раз)
+    #[arg(long)]
+    dry_run: bool, // Флаг проверки отправки без передачи данных в домен
+    #[arg(long)]
+    recovery_rounds: Option<usize>, // Аргумент, указывающий количество повторных попыток получения недостающих чанков
+    #[arg(long)]
+    recovery_interval_ms: Option<u64>, // Аргумент, указывающий время ожидания перед повторной попыткой, мс
+    #[arg(long)]
+    recovery_deadline_ms: Option<u64>, // Аргумент, указывающий максимальное общее время повторных попыток, мс
+    #[arg(long)]
+    pipeline: bool, // Флаг шифрования чанков одновременно с отправкой уже зашифрованных (быстрее для больших файлов)
+    #[arg(long)]
+    send_concurrency: Option<usize>, // Аргумент, указывающий количество одновременно отправляемых чанков
+    #[arg(long)]
+    send_retries: Option<usize>, // Аргумент, указывающий количество повторных отправок чанка без ответа
+    #[arg(long)]
+    send_backoff_ms: Option<u64>, // Аргумент, указывающий время ожидания ответа на первую попытку отправки чанка, мс (удваивается с каждой следующей)
+    #[arg(long, default_value_t = 0)]
+    max_per_peer: usize, // Аргумент, указывающий количество чанков, одновременно отправляемых одному серверу (0 - без ограничения)
+    #[arg(long)]
+    recv_concurrency: Option<usize>, // Аргумент, указывающий количество одновременно запрашиваемых чанков при получении
+    #[arg(long)]
+    verify: bool, // Флаг проверки хэш-сумм полученных чанков перед восстановлением файла (выполняется параллельно)
+    #[arg(long)]
+    resume: bool, // Флаг продолжения прерванного получения: полученные чанки сохраняются в файл .part рядом с результатом
+    #[arg(long)]
+    allow_untagged: bool, // Флаг приема метаданных без тега, отправленных старыми версиями клиента (список чанков не проверяется)
+    #[arg(long)]
+    fail_on_wrong_size: bool, // Флаг отказа от чанка при первом ответе неверного размера (по умолчанию ожидаются другие серверы)
+    #[arg(long)]
+    default_home: Option<PathBuf>, // Аргумент, указывающий каталог приложения на случай, если не заданы ни LEAF_HOME, ни домашний каталог
+    #[arg(long)]
+    quorum: Option<usize>, // Аргумент, указывающий количество серверов, которые должны прислать одинаковую копию чанка
+    #[arg(long)]
+    manifest: Vec<PathBuf>, // Аргумент, указывающий дополнительные файлы метаданных для действия audit (можно указать несколько раз) или файл метаданных для действия diff
+    #[arg(long)]
+    min_replicas: Option<usize>, // Аргумент, указывающий количество серверов, которые должны хранить каждый чанк
+    #[arg(long)]
+    probe_concurrency: Option<usize>, // Аргумент, указывающий количество хэшей, проверяемых одновременно
+    #[arg(long)]
+    probe_timeout_ms: Option<u64>, // Аргумент, указывающий время ожидания ответов на одну группу проверок, мс
+    #[arg(long)]
+    repair: bool, // Флаг восстановления избыточности файлов, у которых найдены чанки с недостаточным количеством копий
+    #[arg(long)]
+    deadline_ms: Option<u64>, // Аргумент, указывающий максимальное время отправки или получения файла целиком, мс
 }
 
 impl Args {
@@ -23,15 +135,222 @@ impl Args {
     }
     pub fn get_file(&self) -> PathBuf {
         // Получение аргумента пути к файлу
-        PathBuf::from(&self.file)
+        PathBuf::from(self.file.clone().unwrap_or_default())
+    }
+    pub fn get_ack_window(&self) -> Duration {
+        // Получение времени сбора подтверждений (по умолчанию - значение библиотеки)
+        self.ack_window_ms
+            .map(Duration::from_millis)
+            .unwrap_or(leafcommon::reed_solomon_scheme::DEFAULT_ACK_WINDOW)
+    }
+    pub fn get_send_options(&self) -> SendOptions {
+        // Получение параметров отправки чанков
+        SendOptions {
+            ack_window: self.get_ack_window(),
+            keep_local: self.keep_local,
+            dedup: self.dedup,
+            source_port: self.source_port,
+            dscp: self.dscp,
+            send_concurrency: self
+                .send_concurrency
+                .unwrap_or(SendOptions::default().send_concurrency),
+            max_per_peer: self.max_per_peer,
+            retry: self.get_send_retry(),
+            chunker: match self.content_defined {
+                true => Chunker::ContentDefined,
+                false => Chunker::Fixed,
+            },
+            preserve_attrs: self.preserve_attrs,
+            peers: self.peers.clone(),
+            consume_source: self.consume_source,
+            manifest_format: self.manifest_format,
+            broadcast: self.broadcast,
+            address_mode: self.get_address_mode(),
+            scores: self.scores.clone(),
+            max_chunks_per_file: self
+                .max_chunks_per_file
+                .unwrap_or(SendOptions::default().max_chunks_per_file),
+            max_manifest_size: self
+                .max_manifest_size
+                .unwrap_or(SendOptions::default().max_manifest_size),
+            embed_erasure_params: self.embed_params,
+        }
+    }
+    pub fn get_password(&self) -> Result<Option<Vec<u8>>, std::io::Error> {
+        // Чтение пароля из файла (завершающий перевод строки не считается частью пароля)
+        let Some(path) = &self.password_file else {
+            return Ok(None);
+        };
+        let mut password = std::fs::read(path)?;
+        while password.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            password.pop();
+        }
+        Ok(Some(password))
+    }
+    pub fn get_key(&self) -> Result<&str, MissingKeyError> {
+        // Получение аргумента пользовательского ключа
+        self.key.as_deref().ok_or(MissingKeyError)
+    }
+    pub fn get_chunks_dirs(&self) -> Result<&[PathBuf], MissingChunksDirError> {
+        // Получение каталогов с файлами чанков для восстановления без домена
+        match self.chunks_dir.is_empty() {
+            true => Err(MissingChunksDirError),
+            false => Ok(&self.chunks_dir),
+        }
+    }
+    pub fn get_output_attrs(&self) -> OutputAttrs {
+        // Получение прав доступа и владельца восстановленного файла
+        OutputAttrs {
+            mode: self.mode,
+            uid: self.uid,
+            gid: self.gid,
+        }
+    }
+    pub fn get_deadline(&self) -> Option<Duration> {
+        self.deadline_ms.map(Duration::from_millis) // Получение максимального времени операции
+    }
+    pub fn get_recv_options(&self) -> RecvOptions {
+        // Получение параметров получения чанков (по умолчанию - значения библиотеки)
+        let default = RecvOptions::default();
+        RecvOptions {
+            concurrency: self.recv_concurrency.unwrap_or(default.concurrency),
+            retry: self.get_recovery_retry(),
+            source_port: self.source_port,
+            dscp: self.dscp,
+            fail_on_wrong_size: self.fail_on_wrong_size,
+            quorum: self.quorum.unwrap_or(default.quorum),
+            peers: self.peers.clone(),
+            verify: self.verify,
+            resume: self.resume,
+            scores: self.scores.clone(),
+            broadcast: self.broadcast,
+            address_mode: self.get_address_mode(),
+            allow_untagged: self.allow_untagged,
+        }
+    }
+    pub fn get_audit_options(&self) -> AuditOptions {
+        // Получение параметров проверки количества копий (по умолчанию - значения библиотеки)
+        let default = AuditOptions::default();
+        AuditOptions {
+            min_replicas: self.min_replicas.unwrap_or(default.min_replicas),
+            concurrency: self.probe_concurrency.unwrap_or(default.concurrency),
+            timeout: self
+                .probe_timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.timeout),
+            peers: self.peers.clone(),
+            broadcast: self.broadcast,
+            address_mode: self.get_address_mode(),
+        }
+    }
+    pub fn get_address_mode(&self) -> AddressMode {
+        // Адресация запросов: группа IPv6, если она указана, иначе широковещание IPv4
+        self.multicast
+            .map_or(AddressMode::Ipv4Broadcast, AddressMode::Ipv6Multicast)
+    }
+    pub fn get_manifests(&self) -> Vec<PathBuf> {
+        // Получение файлов метаданных для проверки: целевой файл и файлы --manifest
+        self.file
+            .iter()
+            .map(PathBuf::from)
+            .chain(self.manifest.iter().cloned())
+            .collect()
+    }
+    pub fn get_diff_manifest(&self) -> PathBuf {
+        // Получение файла метаданных для сравнения: первый --manifest или метаданные рядом с файлом
+        match self.manifest.first() {
+            Some(manifest) => manifest.clone(),
+            None => leafcommon::reed_solomon_scheme::manifest_path(self.get_file(), false),
+        }
+    }
+    pub fn get_send_retry(&self) -> SendRetry {
+        // Получение параметров повторных попыток отправки чанков (по умолчанию - значения библиотеки)
+        let default = SendRetry::default();
+        SendRetry {
+            max_retries: self.send_retries.unwrap_or(default.max_retries),
+            base_backoff: self
+                .send_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.base_backoff),
+        }
+    }
+    pub fn get_recovery_retry(&self) -> RecoveryRetry {
+        // Получение параметров повторных попыток получения чанков (по умолчанию - значения библиотеки)
+        let default = RecoveryRetry::default();
+        RecoveryRetry {
+            rounds: self.recovery_rounds.unwrap_or(default.rounds),
+            interval: self
+                .recovery_interval_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.interval),
+            deadline: self
+                .recovery_deadline_ms
+                .map(Duration::from_millis)
+                .or(default.deadline),
+        }
+    }
+    pub fn get_required_output(&self) -> Result<PathBuf, MissingOutputError> {
+        // Получение пути к файлу результата для действий, где целевого файла нет или он не подходит
+        // (файл метаданных каталога при отправке каталога, файл результата при получении по корневому хэшу)
+        self.output
+            .as_ref()
+            .map(PathBuf::from)
+            .ok_or(MissingOutputError)
+    }
+    pub fn get_output(&self) -> PathBuf {
+        // Получение аргумента пути к файлу результата (по умолчанию - целевой файл)
+        self.output
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.get_file())
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
 pub enum Action {
     // Перечисление, хранящее возможные варианты действий
-    Send,    // Действие по отправке файла
-    Receive, // Действие по получению файла
+    Send,           // Действие по отправке файла
+    Receive,        // Действие по получению файла
+    Repair,         // Действие по восстановлению избыточности файла в домене
+    Info,           // Действие по выводу статистики хранения файла
+    Discover,       // Действие по обнаружению серверов через серверы-затравки
+    Store,          // Действие по отправке файла под пользовательским ключом
+    Fetch,          // Действие по получению файла по пользовательскому ключу
+    SendDir, // Действие по отправке каталога целиком (метаданные сохраняются в файл --output)
+    RecvDir, // Действие по восстановлению каталога по файлу метаданных в каталог --output
+    RecoverOffline, // Действие по восстановлению файла из каталога чанков --chunks-dir
+    Ping,    // Действие по измерению времени ответа серверов и потерь (--count запросов)
+    PeerScores, // Действие по выводу оценок серверов из файла --peer-scores
+    Audit,   // Действие по проверке количества серверов, хранящих чанки файлов (--min-replicas)
+    Prepare, // Действие по подготовке отправки: зашифрованные чанки сохраняются в файл плана --output
+    SendPrepared, // Действие по отправке подготовленного плана, метаданные сохраняются в файл --output
+    Diff, // Действие по сравнению локального файла с метаданными отправленного (--manifest или <файл>.leaf)
+}
+
+fn parse_peer(s: &str) -> Result<SocketAddr, String> {
+    // Разрешение адреса сервера при запуске (host:port или ip:port)
+    s.to_socket_addrs()
+        .map_err(|e| format!("cannot resolve peer {}: {}", s, e))?
+        .next()
+        .ok_or_else(|| format!("peer {} has no addresses", s))
+}
+
+fn parse_manifest_format(s: &str) -> Result<ManifestFormat, String> {
+    // Разбор формата файла метаданных
+    match s {
+        "binary" => Ok(ManifestFormat::Binary),
+        "json" => Ok(ManifestFormat::Json),
+        _ => Err(format!("unknown manifest format {} (binary or json)", s)),
+    }
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    // Разбор прав доступа в восьмеричном виде ("640", "0640" или "0o640")
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|m| *m <= 0o7777)
+        .ok_or_else(|| format!("invalid file mode: {}", s))
 }
 
 pub fn load_args() -> Args {
@@ -41,20 +360,563 @@ pub fn load_args() -> Args {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = load_args(); // Получение аргументов командной строки
+    let mut args = load_args(); // Получение аргументов командной строки
+    if let Some(path) = &args.peer_scores {
+        args.scores = Some(Arc::new(PeerScores::load(path).await?)); // Оценки серверов прошлых запусков
+    }
+    if let Some(dir) = &args.default_home {
+        leafcommon::reed_solomon_scheme::set_default_app_dir(dir);
+    }
+    if let Some(threads) = args.crypto_threads {
+        leafcommon::reed_solomon_scheme::set_crypto_threads(threads); // До первого шифрования
+    }
 
     // Используем тот же пароль для шифрования шифровальщика
-    let path = &args.file;
-    match args.get_action() {
-        Action::Send => send_file(path).await,
-        Action::Receive => recv_file(path).await, // Если получение - вызываем функцию получения
+    let path = &args.get_file();
+    let password = args.get_password()?;
+    let result = match args.get_action() {
+        Action::Send | Action::Store if args.dry_run => {
+            dry_run(path, args.get_send_options()).await
+        }
+        Action::Send if args.root => {
+            send_file_rooted(path, args.get_send_options(), args.get_deadline()).await
+        }
+        Action::Send if args.segment_size.is_some() => {
+            let segment_size = args.segment_size.unwrap_or_default();
+            send_file_segmented(path, segment_size, args.get_send_options()).await
+        }
+        Action::Send if password.is_some() => {
+            let password = password.unwrap_or_default();
+            send_file_with_password(
+                path,
+                &password,
+                args.get_send_options(),
+                args.get_deadline(),
+            )
+            .await
+        }
+        Action::Send if args.pipeline => {
+            send_file_pipelined(path, args.get_send_options(), args.get_deadline()).await
+        }
+        Action::Send => send_file(path, args.get_send_options(), args.get_deadline()).await,
+        Action::Prepare => {
+            prepare_upload(path, args.get_required_output()?, args.get_send_options()).await
+        }
+        Action::SendPrepared => {
+            let manifest = args.get_required_output()?;
+            send_prepared(path, manifest, args.get_send_options(), args.get_deadline()).await
+        }
+        Action::Receive if args.root_hash.is_some() => {
+            recv_file_by_root(
+                args.root_hash.as_deref().unwrap_or_default(),
+                args.get_required_output()?,
+                args.get_output_attrs(),
+                args.get_recv_options(),
+                args.get_deadline(),
+            )
+            .await
+        }
+        Action::Receive if password.is_some() => {
+            recv_file_with_password(
+                path,
+                args.get_output(),
+                &password.unwrap_or_default(),
+                args.get_output_attrs(),
+                args.get_recv_options(),
+                args.get_deadline(),
+            )
+            .await
+        }
+        Action::Receive => {
+            recv_file(
+                path,
+                args.get_output(),
+                args.get_output_attrs(),
+                args.get_recv_options(),
+                args.get_deadline(),
+            )
+            .await
+        } // Если получение - вызываем функцию получения
+        Action::Repair => repair_file(path, password.as_deref(), args.get_send_options()).await,
+        Action::Info => file_info(path).await,
+        Action::Discover => discover(&args.seed).await,
+        Action::Ping => ping(args.count.unwrap_or(DEFAULT_PING_COUNT), &args.peers).await,
+        Action::Store => store_file(args.get_key()?, path, args.get_send_options()).await,
+        Action::Fetch => {
+            fetch_file(
+                args.get_key()?,
+                args.get_output(),
+                args.get_output_attrs(),
+                args.get_recv_options(),
+            )
+            .await
+        }
+        Action::SendDir => {
+            send_dir(path, args.get_required_output()?, args.get_send_options()).await
+        }
+        Action::RecvDir => recv_dir(path, args.get_output(), args.get_recv_options()).await,
+        Action::RecoverOffline => {
+            let chunks_dirs = args.get_chunks_dirs()?;
+            let conflict = match args.discard_conflicting {
+                true => ShardConflict::Discard,
+                false => ShardConflict::Reject,
+            };
+            recover_offline(path, chunks_dirs, args.get_required_output()?, conflict).await
+        }
+        Action::PeerScores => peer_scores(args.scores.as_deref()),
+        Action::Audit => {
+            let repair = args.repair.then(|| args.get_send_options()); // Параметры отправки восстановленных чанков
+            let options = args.get_audit_options();
+            audit(&args.get_manifests(), options, repair, password.as_deref()).await
+        }
+        Action::Diff => diff_file(path, args.get_diff_manifest()).await,
+    };
+    if let Some(scores) = &args.scores {
+        scores.save().await?; // Оценки сохраняются и после неудачной передачи
     }
+    result
+}
+
+async fn send_file(
+    path: impl AsRef<Path>,
+    options: SendOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::send_file_with_options(&path, &progress, &options, deadline)
+        .await?;
+    print_manifest_path(path, &options);
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+async fn prepare_upload(
+    path: impl AsRef<Path>,
+    plan: impl AsRef<Path>,
+    options: SendOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stats = leafcommon::reed_solomon_scheme::prepare_upload(path, &plan, &options).await?;
+    println!(
+        "Prepared {} data and {} recovery chunks ({} bytes) in {}",
+        stats.data_chunks,
+        stats.recovery_chunks,
+        stats.stored_size,
+        plan.as_ref().display()
+    );
+    println!("The plan contains encrypted file data, keep it private");
+    Ok(())
+}
+
+async fn send_prepared(
+    plan: impl AsRef<Path>,
+    manifest: impl AsRef<Path>,
+    options: SendOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::send_prepared(plan, &manifest, &progress, &options, deadline)
+        .await?;
+    println!("Metadata saved to {}", manifest.as_ref().display());
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+async fn send_file_pipelined(
+    path: impl AsRef<Path>,
+    options: SendOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::send_file_pipelined(&path, &progress, &options, deadline)
+        .await?;
+    print_manifest_path(path, &options);
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+async fn send_file_segmented(
+    path: impl AsRef<Path>,
+    segment_size: usize,
+    options: SendOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::send_file_segmented(&path, segment_size, &progress, &options)
+        .await?;
+    print_manifest_path(path, &options);
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+async fn send_file_with_password(
+    path: impl AsRef<Path>,
+    password: &[u8],
+    options: SendOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::send_file_with_password(
+        &path, password, &progress, &options, deadline,
+    )
+    .await?;
+    print_manifest_path(path, &options);
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+fn print_manifest_path(path: impl AsRef<Path>, options: &SendOptions) {
+    // Вывод пути к файлу метаданных, необходимому для получения файла
+    let manifest = leafcommon::reed_solomon_scheme::manifest_path(path, options.consume_source);
+    println!("Metadata saved to {}", manifest.display());
+}
+
+async fn send_file_rooted(
+    path: impl AsRef<Path>,
+    options: SendOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    let root =
+        leafcommon::reed_solomon_scheme::send_file_rooted(path, &progress, &options, deadline)
+            .await?;
+    println!("{}", root); // Корневой хэш - единственное, что нужно сохранить для получения файла
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+async fn dry_run(
+    path: impl AsRef<Path>,
+    options: SendOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let report = leafcommon::reed_solomon_scheme::dry_run(path, &options).await?;
+    println!(
+        "Data chunks: {}, recovery chunks: {}",
+        report.stats.data_chunks, report.stats.recovery_chunks
+    );
+    println!("Total size to send: {} bytes", report.stats.stored_size);
+    println!("Servers responding: {}", report.peers);
+    println!("Dry run: nothing was sent");
+    Ok(())
+}
+
+async fn recv_file(
+    path: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    attrs: OutputAttrs,
+    options: RecvOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::recv_file_with_deadline(
+        path, output, &progress, &attrs, &options, deadline,
+    )
+    .await?;
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+async fn recv_file_with_password(
+    path: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    password: &[u8],
+    attrs: OutputAttrs,
+    options: RecvOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::recv_file_with_password(
+        path, output, password, &progress, &attrs, &options, deadline,
+    )
+    .await?;
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+async fn recv_file_by_root(
+    root: &str,
+    output: impl AsRef<Path>,
+    attrs: OutputAttrs,
+    options: RecvOptions,
+    deadline: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    let started = Instant::now();
+    leafcommon::reed_solomon_scheme::recv_file_by_root(
+        root, output, &progress, &attrs, &options, deadline,
+    )
+    .await?;
+    print_stats(&progress.stats(started.elapsed()));
+    Ok(())
+}
+
+fn print_stats(stats: &OperationStats) {
+    // Вывод итогов операции в поток ошибок, чтобы не смешивать их с данными в стандартном выводе
+    eprintln!(
+        "Transferred {} bytes in {} chunks in {:.2} s ({:.0} bit/s), retries: {}",
+        stats.bytes,
+        stats.chunks,
+        stats.duration.as_secs_f64(),
+        stats.throughput_bps,
+        stats.retries
+    );
 }
 
-async fn send_file(path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
-    leafcommon::reed_solomon_scheme::send_file(path).await
+async fn send_dir(
+    dir: impl AsRef<Path>,
+    manifest: impl AsRef<Path>,
+    options: SendOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    leafcommon::reed_solomon_scheme::send_dir(dir, manifest, &progress, &options).await
 }
 
-async fn recv_file(path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
-    leafcommon::reed_solomon_scheme::recv_file(path).await
+async fn recv_dir(
+    manifest: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+    options: RecvOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = leafcommon::reed_solomon_scheme::Progress::default();
+    leafcommon::reed_solomon_scheme::recv_dir(manifest, target, &progress, &options).await
 }
+
+async fn recover_offline(
+    manifest: impl AsRef<Path>,
+    chunks_dirs: &[PathBuf],
+    output: impl AsRef<Path>,
+    conflict: ShardConflict,
+) -> Result<(), Box<dyn std::error::Error>> {
+    leafcommon::reed_solomon_scheme::recover_offline(manifest, chunks_dirs, output, conflict).await
+}
+
+async fn repair_file(
+    path: impl AsRef<Path>,
+    password: Option<&[u8]>,
+    options: SendOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Файл, отправленный в переносимом режиме, восстанавливается по паролю
+    let report = match password {
+        Some(password) => {
+            leafcommon::reed_solomon_scheme::repair_file_with_password(path, password, &options)
+                .await?
+        }
+        None => leafcommon::reed_solomon_scheme::repair_file(path, &options).await?,
+    };
+    println!(
+        "Lost data chunks: {}, lost recovery chunks: {}, chunks resent: {}",
+        report.lost_data, report.lost_recovery, report.resent
+    );
+    Ok(())
+}
+
+async fn file_info(path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let stats = leafcommon::reed_solomon_scheme::file_info(path).await?;
+    println!("Original size: {} bytes", stats.original_size);
+    println!("Stored size: {} bytes", stats.stored_size);
+    println!(
+        "Data chunks: {}, recovery chunks: {}",
+        stats.data_chunks, stats.recovery_chunks
+    );
+    println!("Chunks kept locally: {}", stats.local_chunks);
+    println!("Storage ratio: {:.2}", stats.storage_ratio());
+    Ok(())
+}
+
+async fn diff_file(
+    path: impl AsRef<Path>,
+    manifest: impl AsRef<Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let diff = leafcommon::reed_solomon_scheme::diff_file(path, manifest).await?;
+    for chunk in &diff.changed {
+        println!(
+            "Changed chunk {}: offset {}, {} bytes",
+            chunk.index, chunk.offset, chunk.len
+        );
+    }
+    println!(
+        "Local size: {} bytes, stored size: {} bytes",
+        diff.local_size, diff.stored_size
+    );
+    println!(
+        "Local chunks: {}, changed: {}, removed: {}",
+        diff.local_chunks,
+        diff.changed.len(),
+        diff.removed
+    );
+    match diff.is_identical() {
+        true => println!("File is identical to the stored version"),
+        false => println!("File differs from the stored version and needs re-upload"),
+    }
+    Ok(())
+}
+
+async fn discover(seeds: &[SocketAddr]) -> Result<(), Box<dyn std::error::Error>> {
+    let peers = leafcommon::discovery::discover(seeds, leafcommon::discovery::MAX_PEERS).await?;
+    for peer in peers.list() {
+        println!("{}", peer);
+    }
+    println!("Servers found: {}", peers.len());
+    Ok(())
+}
+
+async fn ping(count: usize, peers: &[SocketAddr]) -> Result<(), Box<dyn std::error::Error>> {
+    let stats = leafcommon::discovery::probe(count, peers).await?;
+    for (peer, s) in &stats {
+        print!(
+            "{}: {}/{} replies, {:.0}% loss",
+            peer,
+            s.replies,
+            s.sent,
+            s.loss()
+        );
+        if s.replies > 0 {
+            print!(
+                ", rtt min/avg/max = {:.3}/{:.3}/{:.3} ms",
+                s.min.as_secs_f64() * 1000.0,
+                s.avg().as_secs_f64() * 1000.0,
+                s.max.as_secs_f64() * 1000.0
+            );
+        }
+        println!();
+    }
+    println!(
+        "Servers answered: {}",
+        stats.values().filter(|s| s.replies > 0).count()
+    );
+    Ok(())
+}
+
+fn peer_scores(scores: Option<&PeerScores>) -> Result<(), Box<dyn std::error::Error>> {
+    let scores = scores.ok_or(MissingPeerScoresError)?;
+    for (peer, s) in scores.ranked() {
+        println!(
+            "{}: score {:.3}, {} ok / {} failed, latency {:.1} ms",
+            peer,
+            s.score(),
+            s.successes,
+            s.failures,
+            s.latency_ms
+        );
+    }
+    Ok(())
+}
+
+async fn audit(
+    manifests: &[PathBuf],
+    options: AuditOptions,
+    repair: Option<SendOptions>,
+    password: Option<&[u8]>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let report = leafcommon::reed_solomon_scheme::audit(manifests, &options).await?;
+    for chunk in &report.at_risk {
+        println!(
+            "{}: {} chunk {} ({}) has {} of {} replicas",
+            chunk.manifest.display(),
+            if chunk.recovery { "recovery" } else { "data" },
+            chunk.index,
+            chunk.hash,
+            chunk.replicas,
+            options.min_replicas
+        );
+    }
+    println!(
+        "Files checked: {}, chunks checked: {}, chunks at risk: {}",
+        report.files,
+        report.checked,
+        report.at_risk.len()
+    );
+    let files = report.files_needing_repair();
+    let Some(send_options) = repair else {
+        return match files.len() {
+            0 => Ok(()),
+            n => Err(Box::new(UnderReplicatedError(n))),
+        };
+    };
+    for path in files {
+        print!("{}: ", path.display());
+        repair_file(path, password, send_options.clone()).await?; // Восстановление избыточности файла по результатам проверки
+    }
+    Ok(())
+}
+
+async fn store_file(
+    key: &str,
+    path: impl AsRef<Path>,
+    options: SendOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    leafcommon::reed_solomon_scheme::store_file(key, path, &options).await
+}
+
+async fn fetch_file(
+    key: &str,
+    output: impl AsRef<Path>,
+    attrs: OutputAttrs,
+    options: RecvOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    leafcommon::reed_solomon_scheme::fetch_file(key, output, &attrs, &options).await
+}
+
+#[derive(Debug, Clone)]
+pub struct MissingKeyError; // Ошибка отсутствия пользовательского ключа
+
+impl std::fmt::Display for MissingKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The --key argument is required for this action")
+    }
+}
+
+impl std::error::Error for MissingKeyError {}
+
+#[derive(Debug, Clone)]
+pub struct MissingOutputError; // Ошибка отсутствия пути к файлу результата
+
+impl std::fmt::Display for MissingOutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The --output argument is required for this action")
+    }
+}
+
+impl std::error::Error for MissingOutputError {}
+
+#[derive(Debug, Clone)]
+pub struct MissingChunksDirError; // Ошибка отсутствия каталога с файлами чанков
+
+impl std::fmt::Display for MissingChunksDirError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The --chunks-dir argument is required for this action")
+    }
+}
+
+impl std::error::Error for MissingChunksDirError {}
+
+#[derive(Debug, Clone)]
+pub struct MissingPeerScoresError; // Ошибка отсутствия файла оценок серверов
+
+impl std::fmt::Display for MissingPeerScoresError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The --peer-scores argument is required for this action")
+    }
+}
+
+impl std::error::Error for MissingPeerScoresError {}
+
+#[derive(Debug, Clone)]
+pub struct UnderReplicatedError(pub usize); // Ошибка проверки: у файлов есть чанки с недостаточным количеством копий
+
+impl std::fmt::Display for UnderReplicatedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} file(s) have under-replicated chunks, run with --repair to restore them",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnderReplicatedError {}
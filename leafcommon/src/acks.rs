@@ -0,0 +1,142 @@
+use std::collections::HashMap; // Коллекции стандартной библиотеки
+use std::future::Future; // Трейт асинхронных операций стандартной библиотеки
+use std::io; // Ошибки ввода-вывода стандартной библиотеки
+use std::net::{IpAddr, SocketAddr}; // Структуры сетевых адресов
+use std::sync::Mutex; // Разделяемое между запросами состояние
+
+use tokio::net::UdpSocket; // Асинхронный UDP-сокет
+use tokio::sync::mpsc; // Канал для передачи ответов запросу
+
+use crate::message::Message; // Перечисление сообщений
+
+const MAX_UDP_PACKET_SIZE: usize = 65535; // Максимальный размер данных по UDP
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reply {
+    // Ответ сервера на отправку чанка
+    Ack(SocketAddr, u64), // SENDING_ACK: адрес сервера и его свободное место в байтах
+    Stored(SocketAddr),   // CONTENT_STORED: адрес сервера, сохранившего чанк
+}
+
+type Pending = HashMap<(String, u64), mpsc::UnboundedSender<Reply>>; // Ожидающие ответа отправки по хэшу и токену
+
+pub struct AckDemux<'a> {
+    // Распределитель подтверждений SENDING_ACK и CONTENT_STORED по отправкам, одновременно
+    // выполняющимся через один сокет. Подтверждение передается той отправке, которой оно адресовано,
+    // а не отбрасывается циклом ожидания другого чанка
+    socket: &'a UdpSocket,
+    localaddr: IpAddr, // Адрес узла: собственные пакеты не учитываются
+    pending: Mutex<Pending>,
+}
+
+impl<'a> AckDemux<'a> {
+    pub fn new(socket: &'a UdpSocket, localaddr: IpAddr) -> AckDemux<'a> {
+        AckDemux {
+            socket,
+            localaddr,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        self.socket // Сокет для отправки запросов
+    }
+
+    pub fn register(&self, hash: &str, token: u64) -> Replies<'_, 'a> {
+        // Регистрация отправки: ответы с этими хэшем и токеном доставляются в возвращаемую очередь
+        let (tx, rx) = mpsc::unbounded_channel();
+        let key = (hash.to_string(), token);
+        self.pending.lock().unwrap().insert(key.clone(), tx);
+        Replies {
+            demux: self,
+            key,
+            rx,
+        }
+    }
+
+    pub async fn run(&self) -> io::Error {
+        // Прием подтверждений и передача их зарегистрированным отправкам. Завершается только
+        // при ошибке сокета, поэтому выполняется вместе с отправками через select
+        let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+        loop {
+            let (sz, addr) = match self.socket.recv_from(&mut buf).await {
+                Ok(res) => res,
+                Err(e) => return e,
+            };
+            if addr.ip() == self.localaddr {
+                continue; // Проверяем, что мы не производим обмен сами с собой
+            }
+            let (key, reply) = match Message::from_slice(&buf[..sz]) {
+                Ok(Message::SendingAck(h, free, t)) => ((h, t), Reply::Ack(addr, free)),
+                Ok(Message::ContentStored(h, t)) => ((h, t), Reply::Stored(addr)),
+                _ => continue, // Некорректные и прочие сообщения пропускаются
+            };
+            if let Some(tx) = self.pending.lock().unwrap().get(&key) {
+                let _ = tx.send(reply);
+            } // Иначе - опоздавший ответ на завершенную отправку
+        }
+    }
+
+    pub async fn serve<T>(&self, operation: impl Future<Output = T>) -> Result<T, io::Error> {
+        // Выполнение отправок одновременно с распределением подтверждений на них
+        tokio::select! {
+            result = operation => Ok(result),
+            e = self.run() => Err(e),
+        }
+    }
+}
+
+pub struct Replies<'d, 'a> {
+    // Очередь ответов одной отправки, регистрация снимается при удалении очереди
+    demux: &'d AckDemux<'a>,
+    key: (String, u64),
+    rx: mpsc::UnboundedReceiver<Reply>,
+}
+
+impl Replies<'_, '_> {
+    pub async fn recv(&mut self) -> Option<Reply> {
+        self.rx.recv().await // Ожидание следующего ответа
+    }
+}
+
+impl Drop for Replies<'_, '_> {
+    fn drop(&mut self) {
+        self.demux.pending.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn acks_reach_their_own_request() {
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let to = client.local_addr().unwrap();
+        let demux = AckDemux::new(&client, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let mut a = demux.register("a", 1);
+        let mut b = demux.register("b", 2);
+
+        let result = demux
+            .serve(async {
+                // Подтверждение для b приходит раньше подтверждения для a
+                for m in [
+                    Message::SendingAck("b".to_string(), 20, 2),
+                    Message::SendingAck("a".to_string(), 10, 7), // Чужой токен
+                    Message::ContentStored("a".to_string(), 1),
+                ] {
+                    server.send_to(&m.into_bytes().unwrap(), to).await.unwrap();
+                }
+                (a.recv().await, b.recv().await)
+            })
+            .await
+            .unwrap();
+        let from = server.local_addr().unwrap();
+        assert_eq!(
+            result,
+            (Some(Reply::Stored(from)), Some(Reply::Ack(from, 20)))
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::path::Path; // Структура "сырого" файлового пути
+use std::time::SystemTime; // Время изменения файла
+
+use serde::{Deserialize, Serialize}; // Внешняя зависимость для (де)сериализации
+
+use errors::*; // Внутренние ошибки
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutputAttrs {
+    // Права доступа и владелец, устанавливаемые восстановленному файлу (None - не изменять)
+    pub mode: Option<u32>, // Права доступа в формате chmod (например, 0o640)
+    pub uid: Option<u32>,  // Идентификатор пользователя-владельца
+    pub gid: Option<u32>,  // Идентификатор группы-владельца
+}
+
+impl OutputAttrs {
+    pub fn is_empty(&self) -> bool {
+        self.mode.is_none() && self.uid.is_none() && self.gid.is_none()
+    }
+
+    #[cfg(unix)]
+    pub fn apply(&self, path: impl AsRef<Path>) -> Result<(), ApplyingAttrsError> {
+        // Установка прав доступа и владельца файла
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = path.as_ref();
+        if self.uid.is_some() || self.gid.is_some() {
+            std::os::unix::fs::chown(path, self.uid, self.gid).map_err(|e| {
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    ApplyingAttrsError(format!(
+                        "not enough privileges to change owner of {} (root or CAP_CHOWN is required)",
+                        path.display()
+                    ))
+                } else {
+                    ApplyingAttrsError(e.to_string())
+                }
+            })?;
+        }
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .map_err(|e| ApplyingAttrsError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _path: impl AsRef<Path>) -> Result<(), ApplyingAttrsError> {
+        // На других платформах права в формате Unix не поддерживаются
+        if self.is_empty() {
+            return Ok(());
+        }
+        Err(ApplyingAttrsError(String::from(
+            "file mode and owner are supported only on Unix",
+        )))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SourceAttrs {
+    // Атрибуты исходного файла, сохраняемые в метаданных для точного восстановления
+    pub len: u64,          // Размер файла
+    pub mtime: SystemTime, // Время последнего изменения
+    pub mode: Option<u32>, // Права доступа (только в Unix-системах)
+}
+
+impl SourceAttrs {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, ApplyingAttrsError> {
+        // Чтение атрибутов исходного файла
+        let metadata = std::fs::metadata(path).map_err(|e| ApplyingAttrsError(e.to_string()))?;
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        Ok(SourceAttrs {
+            len: metadata.len(),
+            mtime: metadata
+                .modified()
+                .map_err(|e| ApplyingAttrsError(e.to_string()))?,
+            mode,
+        })
+    }
+
+    pub fn restore(&self, path: impl AsRef<Path>) -> Result<(), ApplyingAttrsError> {
+        // Восстановление атрибутов файла после записи его содержимого. Права доступа
+        // восстанавливаются только в Unix-системах, на других платформах они пропускаются
+        let path = path.as_ref();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(path)
+            .map_err(|e| ApplyingAttrsError(e.to_string()))?;
+        let len = file
+            .metadata()
+            .map_err(|e| ApplyingAttrsError(e.to_string()))?
+            .len();
+        if len != self.len {
+            return Err(ApplyingAttrsError(format!(
+                "restored file {} has {} bytes, {} expected",
+                path.display(),
+                len,
+                self.len
+            )));
+        }
+        file.set_modified(self.mtime)
+            .map_err(|e| ApplyingAttrsError(e.to_string()))?;
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .map_err(|e| ApplyingAttrsError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+mod errors {
+    // Модуль с составными ошибками
+    use std::error::Error;
+    use std::fmt;
+
+    #[derive(Debug, Clone)]
+    pub struct ApplyingAttrsError(pub String); // Ошибка установки прав доступа и владельца
+
+    impl fmt::Display for ApplyingAttrsError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Error applying file attributes: {}", self.0)
+        }
+    }
+
+    impl Error for ApplyingAttrsError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn source_attrs_round_trip() {
+        let base = std::env::temp_dir().join(format!("leaf-attrs-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        let (source, restored) = (base.join("source"), base.join("restored"));
+        std::fs::write(&source, b"content").unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        std::fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let attrs = SourceAttrs::read(&source).unwrap();
+        std::fs::write(&restored, b"content").unwrap();
+        attrs.restore(&restored).unwrap();
+        assert_eq!(SourceAttrs::read(&restored).unwrap(), attrs);
+        assert_eq!(attrs.mtime, mtime);
+        #[cfg(unix)]
+        assert_eq!(attrs.mode, Some(0o640));
+
+        std::fs::write(&restored, b"truncated").unwrap();
+        assert!(attrs.restore(&restored).is_err()); // Размер не совпадает с исходным
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::{HashMap, HashSet}; // Коллекции стандартной библиотеки
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::net::{Ipv4Addr, SocketAddr}; // Структуры сетевых адресов
+use std::path::{Path, PathBuf}; // Структуры файлового пути
+use std::time::Duration; // Время ожидания ответов
+
+use tokio::net::UdpSocket; // Асинхронный UDP-сокет
+use tokio::time; // Ограничение времени ожидания ответов
+
+use crate::chunks::{ChunkHash, ChunksHashes, ReedSolomonChunksHashes}; // Хэши чанков файла
+use crate::message::Message; // Перечисление сообщений
+use crate::udp::{self, AddressMode}; // Отправка запросов серверам домена
+
+use consts::*; // Внутренний модуль с константами
+
+mod consts {
+    // Модуль с константами
+    use std::time::Duration;
+
+    pub const MAX_UDP_PACKET_SIZE: usize = 65535; // Максимальный размер данных по UDP
+    pub const DEFAULT_MIN_REPLICAS: usize = 1; // Минимальное количество копий чанка по умолчанию
+    pub const DEFAULT_PROBE_CONCURRENCY: usize = 64; // Количество хэшей, проверяемых одновременно
+    pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2); // Время ожидания ответов на одну группу запросов
+}
+
+pub use consts::{DEFAULT_MIN_REPLICAS, DEFAULT_PROBE_CONCURRENCY, DEFAULT_PROBE_TIMEOUT};
+
+#[derive(Clone, Debug)]
+pub struct AuditOptions {
+    // Параметры проверки количества копий чанков
+    pub min_replicas: usize, // Количество серверов, которые должны хранить каждый чанк
+    pub concurrency: usize,  // Количество хэшей, запрашиваемых одновременно
+    pub timeout: Duration,   // Время ожидания ответов на одну группу запросов
+    pub peers: Vec<SocketAddr>, // Опрашиваемые серверы (пустой список - широковещательно)
+    pub broadcast: Option<Ipv4Addr>, // Адрес широковещания подсети домена (None - 255.255.255.255)
+    pub address_mode: AddressMode, // Адресация запросов: широковещание IPv4 или группа IPv6
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        AuditOptions {
+            min_replicas: DEFAULT_MIN_REPLICAS,
+            concurrency: DEFAULT_PROBE_CONCURRENCY,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            peers: Vec::new(),
+            broadcast: None,
+            address_mode: AddressMode::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkAudit {
+    // Чанк, хранящийся на недостаточном количестве серверов
+    pub manifest: PathBuf, // Файл метаданных, в котором указан чанк
+    pub index: usize,      // Индекс чанка
+    pub recovery: bool,    // Является ли чанк восстановительным
+    pub hash: String,      // Хэш чанка
+    pub replicas: usize,   // Количество серверов, подтвердивших наличие чанка
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    // Результаты проверки количества копий чанков
+    pub files: usize,             // Количество проверенных файлов метаданных
+    pub checked: usize,           // Количество проверенных различных хэшей
+    pub at_risk: Vec<ChunkAudit>, // Чанки с количеством копий меньше требуемого
+}
+
+impl AuditReport {
+    pub fn is_ok(&self) -> bool {
+        self.at_risk.is_empty() // Все чанки хранятся на достаточном количестве серверов
+    }
+
+    pub fn files_needing_repair(&self) -> Vec<PathBuf> {
+        // Файлы метаданных, для которых нужно восстановление избыточности, без повторов
+        let mut seen = HashSet::new();
+        self.at_risk
+            .iter()
+            .filter(|c| seen.insert(&c.manifest))
+            .map(|c| c.manifest.clone())
+            .collect()
+    }
+}
+
+pub async fn audit(
+    manifests: &[impl AsRef<Path>],
+    options: &AuditOptions,
+) -> Result<AuditReport, Box<dyn Error>> {
+    // Проверка количества серверов, хранящих чанки файлов. Чанки не извлекаются: серверы только
+    // подтверждают их наличие, поэтому проверка не нарушает хранение. Один хэш, встреченный
+    // в нескольких файлах, запрашивается один раз
+    let mut chunks = Vec::new(); // Файл, индекс, признак восстановительного чанка и хэш
+    for path in manifests {
+        let hashes = ReedSolomonChunksHashes::load_from(path).await?;
+        for index in 0..hashes.len() {
+            for (recovery, hash) in [
+                (false, hashes.get_data_hash(index)),
+                (true, hashes.get_recv_hash(index)),
+            ] {
+                chunks.push((path.as_ref(), index, recovery, hash.get_value()));
+            }
+        }
+    }
+    let mut unique: Vec<&str> = Vec::new();
+    let mut seen = HashSet::new();
+    for (_, _, _, hash) in &chunks {
+        if seen.insert(hash.as_str()) {
+            unique.push(hash);
+        }
+    }
+
+    let mode = options.address_mode;
+    let broadcast = mode.domain_addr(options.broadcast, &options.peers)?;
+    let socket = udp::bind(&mode.client_addr(0).to_string(), udp::DEFAULT_BUF_SIZE)?;
+    let mut replicas: HashMap<String, HashSet<SocketAddr>> = HashMap::new();
+    for batch in unique.chunks(options.concurrency.max(1)) {
+        replicas.extend(probe(&socket, batch, options, broadcast).await?);
+    }
+    let checked = unique.len();
+
+    let at_risk = chunks
+        .into_iter()
+        .filter_map(|(path, index, recovery, hash)| {
+            let count = replicas.get(&hash).map_or(0, HashSet::len);
+            (count < options.min_replicas).then(|| ChunkAudit {
+                manifest: path.to_path_buf(),
+                index,
+                recovery,
+                hash,
+                replicas: count,
+            })
+        })
+        .collect();
+    Ok(AuditReport {
+        files: manifests.len(),
+        checked,
+        at_risk,
+    })
+}
+
+async fn probe(
+    socket: &UdpSocket,
+    batch: &[&str],
+    options: &AuditOptions,
+    broadcast: Option<SocketAddr>,
+) -> Result<HashMap<String, HashSet<SocketAddr>>, Box<dyn Error>> {
+    // Запрос наличия группы чанков и сбор подтверждений от различных серверов до истечения
+    // времени ожидания. Если серверы указаны, ожидание завершается, как только ответили все
+    let mut replicas: HashMap<String, HashSet<SocketAddr>> = batch
+        .iter()
+        .map(|h| (h.to_string(), HashSet::new()))
+        .collect();
+    for hash in batch {
+        let req = Message::HasReq(hash.to_string()).into_bytes()?;
+        udp::send_to_domain(socket, &req, &options.peers, broadcast).await?;
+    }
+    let complete = |r: &HashMap<String, HashSet<SocketAddr>>| {
+        !options.peers.is_empty() && r.values().all(|s| s.len() >= options.peers.len())
+    };
+    let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+    let deadline = time::Instant::now() + options.timeout;
+    while !complete(&replicas) {
+        let Ok(res) = time::timeout_at(deadline, socket.recv_from(&mut buf)).await else {
+            break;
+        };
+        let (sz, addr) = res?;
+        if let Ok(Message::HasAck(h)) = Message::from_slice(&buf[..sz]) {
+            if let Some(peers) = replicas.get_mut(&h) {
+                peers.insert(addr); // Ответы на хэши других групп не учитываются
+            }
+        }
+    }
+    Ok(replicas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn holding_server(held: &[&str]) -> SocketAddr {
+        // Сервер для проверки: подтверждает наличие указанных чанков
+        let held: HashSet<String> = held.iter().map(|h| h.to_string()).collect();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                let Ok(Message::HasReq(h)) = Message::from_slice(&buf[..sz]) else {
+                    continue;
+                };
+                if held.contains(&h) {
+                    let ack = Message::HasAck(h).into_bytes().unwrap();
+                    socket.send_to(&ack, from).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn audit_flags_under_replicated_chunks() {
+        let manifest = br#"{"data":[{"value":"a","size":1},{"value":"b","size":1}],"recv":[{"value":"c","size":1},{"value":"a","size":1}]}"#;
+        let path = std::env::temp_dir().join(format!("leaf-audit-{}", rand::random::<u64>()));
+        ReedSolomonChunksHashes::from_bytes(manifest)
+            .unwrap()
+            .save_to(&path)
+            .await
+            .unwrap();
+
+        // Чанк a хранится на двух серверах, b - на одном, c - ни на одном
+        let options = AuditOptions {
+            min_replicas: 2,
+            concurrency: 2,
+            timeout: Duration::from_millis(300),
+            peers: vec![
+                holding_server(&["a", "b"]).await,
+                holding_server(&["a"]).await,
+            ],
+            ..AuditOptions::default()
+        };
+        let report = audit(&[&path], &options).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.checked, 3);
+        let flagged: Vec<_> = report
+            .at_risk
+            .iter()
+            .map(|c| (c.index, c.recovery, c.hash.as_str(), c.replicas))
+            .collect();
+        assert_eq!(flagged, vec![(0, true, "c", 0), (1, false, "b", 1)]);
+        assert_eq!(report.files_needing_repair(), vec![path]);
+    }
+}
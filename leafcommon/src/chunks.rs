@@ -1,34 +1,76 @@
 #![allow(refining_impl_trait)] // Разрешение на уточнение типов в реализациях трейтов
 
+use std::collections::{HashMap, HashSet}; // Коллекции стандартной библиотеки
 use std::error::Error; // Трейт ошибок стандартной библиотеки
 use std::future::Future; // Трейт асинхронных операций стандартной библиотеки
-use std::net::IpAddr; // Перечисление с типами IP-адресов
+use std::net::{IpAddr, Ipv4Addr, SocketAddr}; // Структуры сетевых адресов
 use std::path::Path; // Структура "сырого" файлового пути
+use std::sync::atomic::{AtomicUsize, Ordering}; // Атомарные счетчики
+use std::sync::Arc; // Шифровальщик и хэш-вычислитель, разделяемые со стадией шифрования
 use std::time::Duration; // Структура с длительностью ожидания
 
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _}; // BASE64-кодек
+use futures::future; // Одновременное выполнение отправок чанков
+use futures::stream::{self, StreamExt}; // Одновременное выполнение запросов чанков
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize}; // Трейты (де)сериализации
 use tokio::fs; // Асинхронные операции с файловой системой
+use tokio::io::{self, AsyncWriteExt}; // Асинхронный стандартный вывод
 use tokio::net::UdpSocket; // Асинхронный UDP-сокет
+use tokio::sync::mpsc; // Канал между стадиями шифрования и отправки
 use tokio::time; // Асинхронное ожидание
 
-use crate::crypto::{hash::streebog, Encryptor}; // Трейты шифровальщика и хэш-вычислителя
-use crate::message::Message; // Перечисление сообщений
-use crate::shards::reed_solomon; // Трейт разделителя секрета
+use crate::acks::{AckDemux, Reply}; // Распределитель подтверждений по одновременным отправкам
+use crate::attrs::SourceAttrs; // Атрибуты исходного файла
+use crate::crypto::{DecryptionError, EncryptionError, Encryptor, FileKeyMaterial, Hasher}; // Трейты шифровальщика и хэш-вычислителя
+use crate::demux::Demux; // Распределитель ответов по одновременным запросам
+use crate::journal::RecvJournal; // Журнал полученных чанков для продолжения получения
+use crate::message::{Message, MAX_META_SIZE}; // Перечисление сообщений
+use crate::pool; // Пул потоков шифрования, отдельный от потоков tokio
+use crate::registry::Algorithms; // Описание реализаций шифрования и хэширования
+use crate::reputation::{self, PeerLoad, PeerScores}; // Оценки надежности серверов
+use crate::shards::reed_solomon::{self, Backend, Chunker, PartialShards, ShardConflict}; // Трейт разделителя секрета
+use crate::udp::{self, AddressMode}; // Создание UDP-сокета
 
 use consts::*; // Внутренние константы
+pub use consts::{DEFAULT_ACK_WINDOW, DEFAULT_MAX_MANIFEST_SIZE, KEYED_HASH_MARK, STDOUT_PATH}; // Константы, используемые за пределами модуля
+pub use errors::ManifestTooLargeError; // Ошибка превышения размера списка чанков в метаданных
 use errors::*; // Внутренние ошибки
+pub use errors::{ManifestTagError, UntaggedManifestError}; // Ошибки проверки тега, общие для всех видов метаданных
 
 mod consts {
-    pub const CLIENT_ADDR: &str = "0.0.0.0:0";
-    pub const BROADCAST_ADDR: &str = "255.255.255.255:62092"; // Широковещательный адрес локальной сети с портом
+    use std::time::Duration; // Структура с длительностью ожидания
+
+    pub const LOCAL_SERVER_ADDR: &str = "127.0.0.1:62092"; // Адрес сервера, запущенного на том же узле
     pub const MAX_UDP_PACKET_SIZE: usize = 65535; // Максимальный размер данных по UDP
+    pub const STDOUT_PATH: &str = "-"; // Путь, означающий запись в стандартный вывод
+    pub const TMP_SUFFIX: &str = ".tmp"; // Суффикс временного файла при замене файла метаданных
+    pub const JSON_MAGIC: u8 = b'{'; // Первый символ метаданных в формате JSON
+    pub const KEY_HASH_PREFIX: &str = "leaf-key:"; // Префикс пользовательских ключей при вычислении хэша
+    pub const CONTENT_DIGEST_PREFIX: &[u8] = b"leaf-content:"; // Префикс данных дайджеста открытого содержимого чанка
+    pub const KEYED_HASH_MARK: &str = "key-"; // Префикс хэшей чанков, адресуемых ключом: их содержимое не соответствует хэшу
+    pub const RECV_TIMEOUT: Duration = Duration::from_secs(10); // Время ожидания чанка из домена
+    pub const SEND_TIMEOUT: Duration = Duration::from_secs(4); // Время ожидания подтверждения на одну попытку записи локальной копии
+    pub const SEND_ATTEMPTS: usize = 3; // Количество попыток записи локальной копии
+    pub const DEFAULT_SEND_RETRIES: usize = 2; // Количество повторных отправок запроса или данных чанка по умолчанию
+    pub const DEFAULT_SEND_BACKOFF: Duration = Duration::from_secs(1); // Время ожидания ответа на первую попытку по умолчанию (удваивается с каждой следующей)
+    pub const MAX_BACKOFF_DOUBLINGS: usize = 16; // Количество удвоений времени ожидания, после которого оно не растет
+    pub const MAX_SENDING_ACKS: usize = 16; // Количество подтверждений SENDING_ACK, после которого сбор прекращается
+    pub const MAX_REBINDS: usize = 3; // Количество повторных определений адреса узла за одну отправку файла
+    pub const DEFAULT_ACK_WINDOW: Duration = Duration::from_millis(50); // Время сбора подтверждений после первого
+    pub const HEDGE_DELAY: Duration = Duration::from_millis(500); // Время ожидания чанка данных до запроса восстановительного
+    pub const DEFAULT_RECOVERY_ROUNDS: usize = 2; // Количество повторных попыток получения недостающих чанков по умолчанию
+    pub const DEFAULT_RECOVERY_INTERVAL: Duration = Duration::from_secs(2); // Время ожидания перед повторной попыткой по умолчанию
+    pub const DEFAULT_RECV_CONCURRENCY: usize = 8; // Количество одновременно запрашиваемых чанков по умолчанию
+    pub const DEFAULT_SEND_CONCURRENCY: usize = 1; // Количество одновременно отправляемых чанков по умолчанию
+    pub const PIPELINE_WINDOW: usize = 32; // Количество чанков, шифруемых стадией шифрования за один раз
+    pub const PIPELINE_DEPTH: usize = 2; // Количество зашифрованных окон, ожидающих отправки (ограничивает занятую память)
+    pub const DEFAULT_MAX_MANIFEST_SIZE: usize = 64 * 1024 * 1024; // Размер списка чанков в метаданных по умолчанию (64 МиБ), больше которого файл не отправляется
 }
 
 pub trait ChunkHash<V, S> {
     // Трейт хэша одного чанка
-    fn from_chunk(chunk: &[u8]) -> Self
+    fn from_chunk(chunk: &[u8], hasher: &dyn Hasher) -> Self
     where
         Self: Sized; // Метод получения хэша из чанка
     fn get_value(&self) -> V; // Получение значения хэша
@@ -40,15 +82,21 @@ pub struct ReedSolomonChunkHash {
     // Структура хэша чанка, полученного по Риду-Соломону
     value: String, // Значение хэша
     size: usize,   // Размер изначального чанка
+    #[serde(default)]
+    local: bool, // Копия чанка сохранена на сервере узла-отправителя (в старых метаданных отсутствует)
+    #[serde(default)]
+    digest: Option<String>, // Дайджест открытого содержимого на ключе шифровальщика (в старых метаданных отсутствует)
 }
 
 impl ChunkHash<String, usize> for ReedSolomonChunkHash {
-    fn from_chunk(chunk: &[u8]) -> Self {
-        let value = streebog::calc_hash(chunk); // Вычисление хэша
+    fn from_chunk(chunk: &[u8], hasher: &dyn Hasher) -> Self {
+        let value = hasher.calc_hash(chunk); // Вычисление хэша
         ReedSolomonChunkHash {
             // Создание объекта структуры
             value,
             size: chunk.len(),
+            local: false,
+            digest: None,
         }
     }
 
@@ -63,20 +111,9 @@ impl ChunkHash<String, usize> for ReedSolomonChunkHash {
 
 pub trait Chunk<V, S, H> {
     // Трейт чанка
-    fn encrypt(&mut self, encryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>>; // Метод шифрования чанка
-    fn decrypt(&mut self, decryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>>; // Метод дешифрования чанка
-    fn update_hash(&mut self) -> Result<(), Box<dyn Error>>; // Метод обновления хэш-суммы чанка
-    fn send(
-        self,
-        socket: &UdpSocket,
-        localaddr: IpAddr,
-    ) -> impl Future<Output = Result<impl ChunkHash<V, S>, Box<dyn Error>>>; // Метод отправки чанка в сеть
-    fn recv(
-        socket: &UdpSocket,
-        hash: impl ChunkHash<V, S>,
-    ) -> impl Future<Output = Result<Self, Box<dyn Error>>>
-    where
-        Self: Sized; // Метод получения чанка из сети
+    fn encrypt(&mut self, encryptor: &dyn Encryptor) -> Result<(), EncryptionError>; // Метод шифрования чанка
+    fn decrypt(&mut self, decryptor: &dyn Encryptor) -> Result<(), DecryptionError>; // Метод дешифрования чанка
+    fn update_hash(&mut self, hasher: &dyn Hasher) -> Result<(), Box<dyn Error>>; // Метод обновления хэш-суммы чанка
 }
 
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
@@ -84,334 +121,3245 @@ pub struct ReedSolomonChunk {
     // Структура чанка по Риду-Соломону
     value: Vec<u8>,                     // Данные
     hash: Option<ReedSolomonChunkHash>, // Хэш чанка (при создании равен None)
+    #[serde(default)]
+    meta: Option<Vec<u8>>, // Метаданные, сохраняемые сервером вместе с чанком
+    #[serde(default)]
+    digest: Option<String>, // Дайджест открытого содержимого, вычисленный перед шифрованием
 }
 
 impl Chunk<String, usize, String> for ReedSolomonChunk {
-    fn encrypt(&mut self, encryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>> {
-        self.value = encryptor.encrypt_chunk(&self.value); // Переписываем значение на созданное шифровальщиком
+    fn encrypt(&mut self, encryptor: &dyn Encryptor) -> Result<(), EncryptionError> {
+        self.value = encryptor.encrypt_chunk(&self.value)?; // Переписываем значение на созданное шифровальщиком
         Ok(())
     }
 
-    fn decrypt(&mut self, decryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>> {
+    fn decrypt(&mut self, decryptor: &dyn Encryptor) -> Result<(), DecryptionError> {
         self.value = decryptor.decrypt_chunk(&self.value)?; // Переписываем значение на созданное дешифровальщиком
         Ok(())
     }
 
-    fn update_hash(&mut self) -> Result<(), Box<dyn Error>> {
-        self.hash = Some(ReedSolomonChunkHash::from_chunk(&self.value)); // Получаем значение хэша в Some
+    fn update_hash(&mut self, hasher: &dyn Hasher) -> Result<(), Box<dyn Error>> {
+        self.hash = Some(self.hashed(hasher)); // Получаем значение хэша в Some
         Ok(())
     }
+}
 
-    async fn send(
-        self,
-        socket: &UdpSocket,
-        localaddr: IpAddr,
-    ) -> Result<ReedSolomonChunkHash, Box<dyn Error>> {
-        let req: Vec<u8> =
-            Message::SendingReq(self.hash.clone().unwrap().get_value()).into_bytes()?; // Формируем сообщение SENDING_REQ и преобразуем его в поток байт
-        socket.send_to(&req, BROADCAST_ADDR).await?; // Отправляем сообщение в широковещательный домен
-        let mut ack = [0u8; MAX_UDP_PACKET_SIZE]; // Создаем буфер для получения ответа
-        while let Ok((sz, addr)) =
-            time::timeout(Duration::from_secs(10), socket.recv_from(&mut ack)).await?
-        // Ожидаем ответ в течение 5 секунд
-        {
-            let ack = Message::from_bytes(ack[..sz].to_vec())?; // Формируем сообщение из полученных данных
-            if !localaddr.eq(&addr.ip()) {
-                // Проверяем, что мы не производим обмен сами с собой
-                if let Message::SendingAck(h) = ack {
-                    // Если сообщение имеет тип SENDING_ACK
-                    if h.eq(&self.hash.clone().unwrap().get_value()) {
-                        let content: Vec<u8> = Message::ContentFilled(
-                            self.hash.clone().unwrap().get_value(),
-                            self.value,
-                        )
-                        .into_bytes()?;
-                        socket.send_to(&content, addr).await?;
-                        return Ok(self.hash.unwrap());
+impl ReedSolomonChunk {
+    fn hashed(&self, hasher: &dyn Hasher) -> ReedSolomonChunkHash {
+        // Хэш чанка вместе с дайджестом содержимого, вычисленным при шифровании
+        ReedSolomonChunkHash {
+            digest: self.digest.clone(),
+            ..ReedSolomonChunkHash::from_chunk(&self.value, hasher)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)] // Параметры домена и выбора сервера
+    async fn send_to_peer(
+        &self,
+        demux: &AckDemux<'_>,
+        ack_window: Duration,
+        retry: &SendRetry,
+        peers: &[SocketAddr],
+        broadcast: Option<SocketAddr>,
+        scores: Option<&PeerScores>,
+        load: Option<&PeerLoad>,
+        progress: &Progress,
+    ) -> Result<(ReedSolomonChunkHash, SocketAddr), Box<dyn Error>> {
+        // Отправка чанка серверу с наибольшим свободным местом (среди серверов с лучшими оценками
+        // scores, если они ведутся, и не занятых load), возвращает хэш и адрес сервера. Запрос
+        // отправляется широковещательно (на адрес broadcast, если он указан) или каждому из серверов peers
+        let hash = self
+            .hash
+            .clone()
+            .ok_or(SendingChunkError(String::from("Hash is not calculated")))?;
+        // Токен одинаков для всех попыток отправки чанка: подтверждения на предыдущие попытки
+        // принимаются, а сервер распознает повторно полученные данные
+        let token: u64 = rand::random();
+        let req: Vec<u8> = Message::SendingReq(hash.get_value(), token).into_bytes()?; // Формируем сообщение SENDING_REQ и преобразуем его в поток байт
+        let mut replies = demux.register(&hash.get_value(), token); // Ответы на эту отправку
+        let mut candidates: Vec<(SocketAddr, u64)> = Vec::new(); // Серверы, подтвердившие отправку, и их свободное место
+        for attempt in 0..=retry.max_retries {
+            if attempt > 0 {
+                progress.add_retry();
+            }
+            udp::send_to_domain(demux.socket(), &req, peers, broadcast).await?; // Запрос отправляется заново при каждой попытке
+            let mut deadline = time::Instant::now() + retry.backoff(attempt);
+            while let Ok(Some(reply)) = time::timeout_at(deadline, replies.recv()).await {
+                let Reply::Ack(addr, free) = reply else {
+                    continue; // Пропускаем сообщения, не являющиеся SENDING_ACK
+                };
+                if free < self.value.len() as u64 {
+                    continue; // У сервера недостаточно места
+                }
+                if candidates.is_empty() {
+                    // После первого подтверждения ждем остальные в течение окна сбора
+                    deadline = deadline.min(time::Instant::now() + ack_window);
+                }
+                candidates.push((addr, free));
+                if candidates.len() >= MAX_SENDING_ACKS {
+                    break; // В плотном домене остальные подтверждения не обрабатываются
+                }
+            }
+            if !candidates.is_empty() {
+                break;
+            }
+        }
+        let timeout = || SendingChunkError(String::from("Timeout"));
+        let slot = match load {
+            Some(load) => Some(
+                load.reserve(scores, &candidates)
+                    .await
+                    .ok_or_else(timeout)?,
+            ),
+            None => None,
+        }; // Место передачи занято до подтверждения сохранения или отказа
+        let addr = match &slot {
+            Some(slot) => slot.addr,
+            None => {
+                reputation::place(scores, &candidates)
+                    .ok_or_else(timeout)?
+                    .0
+            }
+        };
+        let content = match &self.meta {
+            Some(m) => {
+                Message::ContentFilledMeta(hash.get_value(), self.value.clone(), token, m.clone())
+            }
+            None => Message::ContentFilled(hash.get_value(), self.value.clone(), token),
+        };
+        let content: Vec<u8> = content.into_bytes()?;
+        // Чанк считается отправленным только после подтверждения сохранения: сервер сообщает
+        // о нем, записав данные. Повторно полученные данные той же отправки сервер не сохраняет дважды
+        let started = time::Instant::now();
+        for attempt in 0..=retry.max_retries {
+            if attempt > 0 {
+                progress.add_retry();
+            }
+            demux.socket().send_to(&content, addr).await?; // Отправляем данные выбранному серверу
+            let deadline = time::Instant::now() + retry.backoff(attempt);
+            while let Ok(Some(reply)) = time::timeout_at(deadline, replies.recv()).await {
+                if reply == Reply::Stored(addr) {
+                    if let Some(s) = scores {
+                        s.success(addr, started.elapsed());
                     }
+                    return Ok((hash, addr));
                 }
             }
         }
-        Err(Box::new(SendingChunkError(String::from("Timeout"))))
+        if let Some(s) = scores {
+            s.failure(addr); // Сервер согласился принять чанк, но не сохранил его
+        }
+        Err(Box::new(SendingChunkError(format!(
+            "Server {} did not confirm saving the chunk",
+            addr
+        ))))
     }
 
-    async fn recv(
-        socket: &UdpSocket,
-        hash: impl ChunkHash<String, usize>,
-    ) -> Result<ReedSolomonChunk, Box<dyn Error>> {
-        let req: Vec<u8> = Message::RetrievingReq(hash.get_value()).into_bytes()?; // Создание запроса на получение
-        socket.send_to(&req, BROADCAST_ADDR).await?; // Отправка сообщения на широковещательный адрес
-        let mut content = [0u8; MAX_UDP_PACKET_SIZE]; // Буфер для приема сообщения
-        if let Ok((sz, _)) =
-            time::timeout(Duration::from_secs(10), socket.recv_from(&mut content)).await?
-        {
-            let content = Message::from_bytes(content[..sz].to_vec())?; // Проверка корректности сообщения
-            if let Message::ContentFilled(h, d) = content {
-                // Проверка типа сообщения
-                if h.eq(&hash.get_value()) {
-                    // Проверка равенства хэш-сумм
-                    if d.len() == hash.get_size() {
-                        // Проверка равенства размеров блока данных
-                        return Ok(ReedSolomonChunk {
-                            value: d,
-                            hash: None,
-                        }); // Возврат данных
-                    }
-                    return Err(Box::new(ReceivingChunkError(String::from(
-                        "Blocks sizes mismatch",
-                    )))); // Ошибка несоответствия размеров блока
+    async fn store_local(&self, demux: &AckDemux<'_>) -> Result<(), Box<dyn Error>> {
+        // Явная запись копии чанка на сервер этого же узла. Сервер отбрасывает собственные
+        // SENDING_REQ и CONTENT_FILLED, поэтому копия передается отдельным сообщением на loopback
+        let hash = self
+            .hash
+            .as_ref()
+            .ok_or(SendingChunkError(String::from("Hash is not calculated")))?;
+        let token: u64 = rand::random();
+        let req = Message::StoreLocal(hash.get_value(), self.value.clone(), token).into_bytes()?;
+        let mut replies = demux.register(&hash.get_value(), token);
+        for _ in 0..SEND_ATTEMPTS {
+            demux.socket().send_to(&req, LOCAL_SERVER_ADDR).await?;
+            let deadline = time::Instant::now() + SEND_TIMEOUT;
+            while let Ok(Some(reply)) = time::timeout_at(deadline, replies.recv()).await {
+                if matches!(reply, Reply::Ack(..)) {
+                    return Ok(()); // Сервер узла подтвердил запись копии
+                }
+            }
+        }
+        Err(Box::new(SendingChunkError(String::from(
+            "Local server did not confirm the local copy",
+        ))))
+    }
+
+    pub async fn send_keyed(
+        key: &str,
+        value: Vec<u8>,
+        hasher: &dyn Hasher,
+        options: &SendOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка чанка, адресуемого хэшем пользовательского ключа, а не своего содержимого
+        Self::send_single(key_hash(key, hasher), value, None, options).await
+    }
+
+    pub async fn send_root(
+        value: Vec<u8>,
+        hasher: &dyn Hasher,
+        options: &SendOptions,
+    ) -> Result<String, Box<dyn Error>> {
+        // Отправка одиночного чанка, адресуемого хэшем своего содержимого. Возвращает хэш чанка
+        Self::send_with_meta(value, None, hasher, options).await
+    }
+
+    pub async fn send_with_meta(
+        value: Vec<u8>,
+        meta: Option<Vec<u8>>,
+        hasher: &dyn Hasher,
+        options: &SendOptions,
+    ) -> Result<String, Box<dyn Error>> {
+        // Отправка одиночного чанка, адресуемого хэшем содержимого, с метаданными (не более
+        // MAX_META_SIZE байт), которые можно получить без самого чанка. Возвращает хэш чанка
+        if let Some(size) = meta.as_ref().map(Vec::len).filter(|&s| s > MAX_META_SIZE) {
+            return Err(Box::new(MetaTooLargeError(size)));
+        }
+        let hash = hasher.calc_hash(&value);
+        Self::send_single(hash.clone(), value, meta, options).await?;
+        Ok(hash)
+    }
+
+    async fn send_single(
+        hash: String,
+        value: Vec<u8>,
+        meta: Option<Vec<u8>>,
+        options: &SendOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка одиночного чанка под указанным хэшем с адресацией и повторами, как у чанков файла
+        let mode = options.address_mode;
+        let broadcast = mode.domain_addr(options.broadcast, &options.peers)?;
+        let socket = ReedSolomonChunks::client_socket_on(mode, options.source_port, options.dscp)?;
+        let chunk = ReedSolomonChunk {
+            hash: Some(ReedSolomonChunkHash {
+                value: hash,
+                size: value.len(),
+                local: false,
+                digest: None,
+            }),
+            value,
+            meta,
+            digest: None,
+        };
+        let demux = AckDemux::new(&socket, ReedSolomonChunks::local_addr()?);
+        let progress = Progress::default();
+        let send = chunk.send_to_peer(
+            &demux,
+            options.ack_window,
+            &options.retry,
+            &options.peers,
+            broadcast,
+            options.scores.as_deref(),
+            None,
+            &progress,
+        );
+        demux.serve(send).await??;
+        Ok(())
+    }
+
+    pub async fn recv_meta(hash: &str, options: &RecvOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Получение метаданных чанка по хэшу с адресацией запроса, как при получении чанков.
+        // Чанк остается на сервере
+        let mode = options.address_mode;
+        let broadcast = mode.domain_addr(options.broadcast, &options.peers)?;
+        let socket = ReedSolomonChunks::client_socket_on(mode, options.source_port, options.dscp)?;
+        let req = Message::MetaReq(hash.to_string()).into_bytes()?;
+        let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+        udp::send_to_domain(&socket, &req, &options.peers, broadcast).await?;
+        let deadline = time::Instant::now() + RECV_TIMEOUT;
+        while let Ok(res) = time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (sz, _) = res?;
+            if let Ok(Message::MetaAck(h, meta)) = Message::from_bytes(buf[..sz].to_vec()) {
+                if h == hash {
+                    return Ok(meta);
                 }
             }
-            return Err(Box::new(ReceivingChunkError(String::from(
-                "Hash is incorrect",
-            )))); // Ошибка несоответствия хэш-сумм
         }
-        Err(Box::new(ReceivingChunkError(String::from("Timeout")))) // Ошибка таймаута
+        Err(Box::new(ReceivingChunkError(format!(
+            "No metadata received for {}",
+            hash
+        ))))
+    }
+
+    pub async fn recv_root(hash: &str, options: &RecvOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Получение одиночного чанка по хэшу его содержимого, размер чанка заранее неизвестен
+        let demux = ReedSolomonChunks::domain_demux(options)?;
+        let request = demux.request(hash, None, RECV_TIMEOUT);
+        ReedSolomonChunks::with_demux(&demux, request).await?
+    }
+
+    pub async fn recv_keyed(
+        key: &str,
+        hasher: &dyn Hasher,
+        options: &RecvOptions,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Получение чанка по пользовательскому ключу, размер чанка заранее неизвестен
+        let demux = ReedSolomonChunks::domain_demux(options)?;
+        let hash = key_hash(key, hasher);
+        let legacy = legacy_key_hash(key, hasher);
+        let request = async {
+            // Размер чанка по ключу заранее неизвестен. Чанки, отправленные до появления
+            // пометки ключевых хэшей, запрашиваются по старому хэшу
+            match demux.request(&hash, None, RECV_TIMEOUT).await {
+                Ok(data) => Ok(data),
+                Err(_) => demux.request(&legacy, None, RECV_TIMEOUT).await,
+            }
+        };
+        ReedSolomonChunks::with_demux(&demux, request).await?
+    }
+}
+
+type SendBatch = Vec<(bool, ReedSolomonChunk)>; // Чанки для отправки с признаком восстановительного чанка
+
+fn encrypt_checked(
+    chunk: &mut ReedSolomonChunk,
+    encryptor: &dyn Encryptor,
+    deterministic: bool,
+) -> Result<(), EncryptionError> {
+    // Шифрование чанка с проверкой, что его размер изменился только на постоянную добавку шифровальщика.
+    // В режиме dedup чанк шифруется детерминированно, иначе повторы не дали бы одинаковых хэшей
+    let len = chunk.value.len();
+    chunk.digest = content_digest(encryptor, &chunk.value); // Хэш шифротекста со случайным вектором не позволяет сравнивать содержимое
+    match deterministic {
+        true => chunk.value = encryptor.encrypt_chunk_deterministic(&chunk.value)?,
+        false => chunk.encrypt(encryptor)?,
     }
+    if chunk.value.len() != len + encryptor.overhead() {
+        // Блоки разной длины нельзя восстановить кодом Рида-Соломона
+        return Err(EncryptionError(format!(
+            "Encryption changed chunk size from {} to {} bytes, split the file for the encryptor block size",
+            len,
+            chunk.value.len()
+        )));
+    }
+    Ok(())
+}
+
+fn is_address_change(e: &(dyn Error + 'static)) -> bool {
+    // Признак ошибки отправки, вызванной сменой сетевого интерфейса или адреса узла
+    e.downcast_ref::<io::Error>().is_some_and(|e| {
+        matches!(
+            e.kind(),
+            io::ErrorKind::AddrNotAvailable
+                | io::ErrorKind::NetworkUnreachable
+                | io::ErrorKind::NetworkDown
+                | io::ErrorKind::HostUnreachable
+        )
+    })
+}
+
+fn key_hash(key: &str, hasher: &dyn Hasher) -> String {
+    // Хэш пользовательского ключа, под которым хранится чанк. Пометка позволяет серверу отличить
+    // такой чанк от адресуемых содержимым (например, при проверке целостности хранилища)
+    format!("{}{}", KEYED_HASH_MARK, legacy_key_hash(key, hasher))
+}
+
+fn legacy_key_hash(key: &str, hasher: &dyn Hasher) -> String {
+    // Хэш пользовательского ключа без пометки. Префикс отделяет пространство ключей от хэшей содержимого
+    hasher.calc_hash(format!("{}{}", KEY_HASH_PREFIX, key).as_bytes())
 }
 
 pub trait Chunks<H> {
     // Трейт для набора чанков
-    fn from_file(path: impl AsRef<Path>) -> impl Future<Output = Result<Self, Box<dyn Error>>>
-    where
-        Self: Sized; // Получение чанков из файла
     fn into_file(self, path: impl AsRef<Path>) -> impl Future<Output = Result<(), Box<dyn Error>>>; // Восстановление файла
-    fn encrypt(&mut self, encryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>>; // Шифрование
-    fn decrypt(&mut self, decryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>>; // Дешифрование
-    fn update_hashes(&mut self) -> Result<(), Box<dyn Error>>; // Обновление хэш-сумм
-    fn send(self) -> impl Future<Output = Result<H, Box<dyn Error>>>; // Отправка в домен
-    fn recv(hashes: H) -> impl Future<Output = Result<Self, Box<dyn Error>>>
-    where
-        Self: Sized; // Получение из домена
+    fn encrypt(&mut self, encryptor: &dyn Encryptor) -> Result<(), Box<dyn Error>>; // Шифрование
+    fn decrypt(&mut self, decryptor: &dyn Encryptor) -> Result<(), Box<dyn Error>>; // Дешифрование
+    fn update_hashes(&mut self, hasher: &dyn Hasher) -> Result<(), Box<dyn Error>>; // Обновление хэш-сумм
+    fn send(self, progress: &Progress) -> impl Future<Output = Result<H, Box<dyn Error>>>; // Отправка в домен
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ReedSolomonChunks {
-    // Чанки Рида-Соломона
-    data: Vec<ReedSolomonChunk>,
-    recv: Vec<ReedSolomonChunk>,
+    // Чанки Рида-Соломона (None - чанк не был получен из домена)
+    data: Vec<Option<ReedSolomonChunk>>,
+    recv: Vec<Option<ReedSolomonChunk>>,
+    backend: Backend,     // Реализация кода Рида-Соломона, которой получены чанки
+    original_size: usize, // Размер исходного файла
+    #[serde(default)]
+    chunker: Chunker, // Способ разбиения файла на блоки
+    #[serde(skip, default = "default_ack_window")]
+    ack_window: Duration, // Время сбора подтверждений при отправке
+    #[serde(skip)]
+    keep_local: bool, // Сохранять ли копию каждого чанка на сервере этого же узла
+    #[serde(skip)]
+    dedup: bool, // Отправлять ли одинаковые чанки один раз
+    #[serde(skip)]
+    source_port: Option<u16>, // Порт клиентского сокета при отправке (None - выбирается системой)
+    #[serde(skip)]
+    dscp: Option<u8>, // Маркировка DSCP пакетов клиентского сокета (None - без маркировки)
+    #[serde(skip)]
+    send_concurrency: usize, // Количество одновременно отправляемых чанков (0 и 1 - по одному)
+    #[serde(skip)]
+    retry: SendRetry, // Повторные попытки отправки чанка
+    #[serde(skip)]
+    max_per_peer: usize, // Количество чанков, одновременно отправляемых одному серверу (0 - без ограничения)
+    #[serde(skip)]
+    peers: Vec<SocketAddr>, // Серверы, которым отправляются запросы (пустой список - широковещательно)
+    #[serde(skip)]
+    broadcast: Option<Ipv4Addr>, // Адрес широковещания подсети домена (None - ограниченное широковещание)
+    #[serde(skip)]
+    address_mode: AddressMode, // Адресация запросов: широковещание IPv4 или группа IPv6
+    #[serde(skip)]
+    scores: Option<Arc<PeerScores>>, // Оценки серверов, учитываемые при выборе сервера для чанка (None - не ведутся)
 }
 
-impl Chunks<ReedSolomonChunksHashes> for ReedSolomonChunks {
-    async fn from_file(path: impl AsRef<Path>) -> Result<ReedSolomonChunks, Box<dyn Error>> {
-        let content = fs::read(path).await?; // Чтение файла
-        let (data, recv) = reed_solomon::split(content)?; // Формирование чанков
-        Ok(ReedSolomonChunks {
-            data: data
-                .par_iter()
-                .map(|x| ReedSolomonChunk {
-                    value: x.clone(),
-                    hash: None,
-                })
-                .collect::<Vec<_>>(),
-            recv: recv
-                .par_iter()
-                .map(|x| ReedSolomonChunk {
-                    value: x.clone(),
-                    hash: None,
-                })
-                .collect::<Vec<_>>(),
-        })
-    }
+fn default_ack_window() -> Duration {
+    DEFAULT_ACK_WINDOW
+}
 
-    async fn into_file(self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
-        let data = self
-            .data
-            .par_iter()
-            .map(|x| x.value.clone())
-            .collect::<Vec<_>>();
-        let recv = self
-            .recv
-            .par_iter()
-            .map(|x| x.value.clone())
-            .collect::<Vec<_>>(); // Получение чанков
+#[derive(Default, Debug)]
+pub struct Progress {
+    // Счетчики хода передачи чанков, могут читаться из других задач
+    done: AtomicUsize,    // Количество обработанных чанков
+    total: AtomicUsize,   // Общее количество чанков
+    bytes: AtomicUsize,   // Объем переданных данных чанков
+    retries: AtomicUsize, // Количество повторных попыток (повторные отправки и раунды повторного получения)
+}
 
-        let content = reed_solomon::recover(data, recv)?; // Восстановление данных
-        fs::write(path, content).await?; // Запись в файл
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OperationStats {
+    // Итоги отправки или получения файла
+    pub duration: Duration,  // Длительность операции
+    pub bytes: usize,        // Объем переданных данных чанков
+    pub chunks: usize,       // Количество обработанных чанков
+    pub retries: usize,      // Количество повторных попыток
+    pub throughput_bps: f64, // Средняя скорость передачи, бит/с
+}
 
-        Ok(())
+impl Progress {
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed) // Получение количества обработанных чанков
     }
 
-    fn encrypt(&mut self, encryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>> {
-        self.data
-            .iter_mut()
-            .chain(self.recv.iter_mut())
-            .try_for_each(|c| c.encrypt(encryptor)) // Шифрование
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed) // Получение общего количества чанков
     }
 
-    fn decrypt(&mut self, decryptor: &Box<dyn Encryptor>) -> Result<(), Box<dyn Error>> {
-        self.data
-            .iter_mut()
-            .chain(self.recv.iter_mut())
-            .try_for_each(|c| c.decrypt(decryptor)) // Дешифрование
+    fn add_total(&self, n: usize) {
+        self.total.fetch_add(n, Ordering::Relaxed); // Увеличение общего количества чанков
     }
 
-    fn update_hashes(&mut self) -> Result<(), Box<dyn Error>> {
-        self.data
-            .iter_mut()
-            .chain(self.recv.iter_mut())
-            .try_for_each(|c| c.update_hash()) // Обновление хэшей
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed) // Получение объема переданных данных
     }
 
-    async fn send(self) -> Result<ReedSolomonChunksHashes, Box<dyn Error>> {
-        let localaddr = pnet::datalink::interfaces()
-            .par_iter()
-            .find_first(|i| !i.is_loopback() && !i.ips.is_empty())
-            .map_or(
-                Err(SendingChunkError(String::from("No interface found"))),
-                |x| Ok(x),
-            )?
-            .ips
-            .first()
-            .map_or(Err(SendingChunkError(String::from("No IP found"))), |x| {
-                Ok(x)
-            })?
-            .ip(); // IP-адрес машины
-
-        let socket = UdpSocket::bind(CLIENT_ADDR).await?;
-        socket.set_broadcast(true)?; // Создание сокета
-
-        let (mut data_hashes, mut recv_hashes): (
-            Vec<ReedSolomonChunkHash>,
-            Vec<ReedSolomonChunkHash>,
-        ) = (
-            Vec::with_capacity(self.data.len()),
-            Vec::with_capacity(self.recv.len()),
-        );
+    pub fn retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed) // Получение количества повторных попыток
+    }
 
-        for c in self.data {
-            data_hashes.push(c.send(&socket, localaddr).await?);
+    pub fn stats(&self, duration: Duration) -> OperationStats {
+        // Итоги операции, длительность которой измерена вызывающим
+        let secs = duration.as_secs_f64();
+        OperationStats {
+            duration,
+            bytes: self.bytes(),
+            chunks: self.done(),
+            retries: self.retries(),
+            throughput_bps: match secs > 0.0 {
+                true => self.bytes() as f64 * 8.0 / secs,
+                false => 0.0,
+            },
         }
-        for c in self.recv {
-            recv_hashes.push(c.send(&socket, localaddr).await?);
+    }
+
+    fn advance(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed); // Учет очередного обработанного чанка
+    }
+
+    fn add_bytes(&self, n: usize) {
+        self.bytes.fetch_add(n, Ordering::Relaxed); // Учет переданных данных
+    }
+
+    fn add_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed); // Учет повторной попытки
+    }
+}
+
+pub async fn with_deadline<T>(
+    deadline: Option<Duration>,
+    progress: &Progress,
+    operation: impl Future<Output = Result<T, Box<dyn Error>>>,
+) -> Result<T, Box<dyn Error>> {
+    // Ограничение времени выполнения всей операции: по истечении времени незавершенные
+    // операции с чанками отменяются (их сокеты закрываются вместе с прерванной операцией)
+    let Some(deadline) = deadline else {
+        return operation.await;
+    };
+    match time::timeout(deadline, operation).await {
+        Ok(result) => result,
+        Err(_) => Err(Box::new(DeadlineExceededError {
+            completed: progress.done(),
+            pending: progress.total().saturating_sub(progress.done()),
+        })),
+    }
+}
+
+pub fn decode_manifest(content: Vec<u8>) -> Result<(Vec<u8>, ManifestFormat), Box<dyn Error>> {
+    // Получение JSON из файла метаданных любого формата. Формат определяется по первому
+    // символу: '{', которым начинается JSON, в Base64 не встречается
+    if content.trim_ascii_start().first() == Some(&JSON_MAGIC) {
+        return Ok((content, ManifestFormat::Json));
+    }
+    Ok((BASE64.decode(content.trim_ascii())?, ManifestFormat::Binary))
+}
+
+pub async fn write_replacing(path: impl AsRef<Path>, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    // Запись файла через временный файл рядом с ним: прежнее содержимое (например, исходный файл,
+    // заменяемый метаданными) заменяется только после того, как новое полностью записано на диск
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(TMP_SUFFIX);
+    let mut file = fs::File::create(&tmp).await?;
+    file.write_all(data).await?;
+    file.sync_all().await?;
+    drop(file);
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunksStats {
+    // Статистика хранения файла в домене, заполняется при отправке
+    pub original_size: usize,   // Размер исходного файла
+    pub stored_size: usize,     // Суммарный размер отправленных чанков
+    pub data_chunks: usize,     // Количество чанков данных
+    pub recovery_chunks: usize, // Количество восстановительных чанков
+    #[serde(default)]
+    pub local_chunks: usize, // Количество чанков, копия которых сохранена на сервере узла-отправителя
+}
+
+impl ChunksStats {
+    pub fn storage_ratio(&self) -> f64 {
+        // Отношение объема хранимых данных к размеру исходного файла
+        if self.original_size == 0 {
+            return 0.0;
         }
+        self.stored_size as f64 / self.original_size as f64
+    }
+}
 
-        Ok(ReedSolomonChunksHashes {
-            data: data_hashes,
-            recv: recv_hashes,
-        })
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErasureParams {
+    // Параметры кода Рида-Соломона, без которых полученные чанки нельзя собрать в файл. Хранятся
+    // в метаданных и (при отправке с корневым хэшем) отдельным чанком-заголовком в домене
+    pub backend: Backend,       // Реализация кода Рида-Соломона
+    pub chunker: Chunker,       // Способ разбиения файла на блоки
+    pub original_size: usize,   // Размер исходного файла
+    pub data_chunks: usize,     // Количество чанков данных
+    pub recovery_chunks: usize, // Количество восстановительных чанков
+    pub chunk_size: usize,      // Размер наибольшего чанка данных
+}
+
+#[derive(Clone, Debug)]
+pub struct RecoveryRetry {
+    // Параметры повторных попыток получения чанков, если полученных не хватает для восстановления файла
+    pub rounds: usize,              // Максимальное количество повторных попыток
+    pub interval: Duration,         // Время ожидания перед очередной попыткой
+    pub deadline: Option<Duration>, // Максимальное общее время повторных попыток (None - без ограничения)
+}
+
+impl Default for RecoveryRetry {
+    fn default() -> Self {
+        RecoveryRetry {
+            rounds: DEFAULT_RECOVERY_ROUNDS,
+            interval: DEFAULT_RECOVERY_INTERVAL,
+            deadline: None,
+        }
     }
+}
 
-    async fn recv(hashes: ReedSolomonChunksHashes) -> Result<ReedSolomonChunks, Box<dyn Error>> {
-        let socket = UdpSocket::bind(CLIENT_ADDR).await?;
-        socket.set_broadcast(true)?; // Создание сокета
-        let mut data = Vec::with_capacity(hashes.len());
-        let mut non_received_data_indexes = Vec::with_capacity(hashes.len());
-        for i in 0..hashes.len() {
-            data.push(match ReedSolomonChunk::recv(&socket, hashes.get_data_hash(i)).await {
-                Ok(d) => d, // Получение чанка
-                Err(e) => {
-                    eprintln!("Error receiving data chunk ({}), trying to receive a recovering one...", e.to_string());
-                    non_received_data_indexes.push(i);
-                    ReedSolomonChunk {
-                        value: vec![0u8; hashes.get_data_hash(i).get_size()],
-                        hash: None,
-                    }
-                }, // Запись пустого чанка и запись его индекса
-            });
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SendRetry {
+    // Параметры повторных попыток отправки чанка. Запрос SENDING_REQ (и затем данные чанка)
+    // отправляется заново, если ответа нет; время ожидания ответа удваивается с каждой попыткой,
+    // поэтому одна потерянная датаграмма задерживает отправку ненадолго, а перегруженный домен
+    // не засыпается повторными запросами
+    pub max_retries: usize, // Количество повторных отправок после первой попытки
+    pub base_backoff: Duration, // Время ожидания ответа на первую попытку
+}
+
+impl Default for SendRetry {
+    fn default() -> Self {
+        SendRetry {
+            max_retries: DEFAULT_SEND_RETRIES,
+            base_backoff: DEFAULT_SEND_BACKOFF,
         }
-        let mut recv = Vec::with_capacity(hashes.len());
-        let mut is_all_recovery_received = true;
-        for i in 0..hashes.len() {
-            if !is_all_recovery_received {
-                // Если блок не получен - выходим и завершаем
-                break;
-            }
-            if !non_received_data_indexes.contains(&i) {
-                recv.push(ReedSolomonChunk {
-                    value: vec![0u8; hashes.get_recv_hash(i).get_size()],
-                    hash: None,
-                });
-            }
-            recv.push(
-                match ReedSolomonChunk::recv(&socket, hashes.get_recv_hash(i)).await {
-                    Ok(d) => d, // Получение данных
-                    Err(_) => {
-                        is_all_recovery_received = false; // Устанавливаем флаг
-                        ReedSolomonChunk {
-                            value: vec![0u8; hashes.get_recv_hash(i).get_size()],
-                            hash: None,
-                        } // Запись пустого чанка
-                    }
-                },
-            )
+    }
+}
+
+impl SendRetry {
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        // Время ожидания ответа на попытку с номером attempt (с нуля)
+        self.base_backoff
+            .saturating_mul(1 << attempt.min(MAX_BACKOFF_DOUBLINGS))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SendOptions {
+    // Параметры отправки чанков в домен
+    pub ack_window: Duration, // Время сбора подтверждений от серверов
+    pub keep_local: bool,     // Сохранять ли копию каждого чанка на сервере этого же узла
+    // Отправлять одинаковые чанки файла один раз, ссылаясь в метаданных на один хэш. В этом режиме
+    // вектор шифрования вычисляется по содержимому чанка, поэтому одинаковые чанки дают одинаковые
+    // хэши; без него каждый чанк шифруется со случайным вектором и повтор хранится отдельной
+    // копией. Включается только явно: серверы видят, что чанков меньше, чем индексов, т.е. узнают
+    // о повторах в файле, а все ссылки на общий чанк теряются вместе с ним
+    pub dedup: bool,
+    pub source_port: Option<u16>, // Фиксированный порт клиентского сокета для правил межсетевого экрана (None - выбирается системой)
+    pub dscp: Option<u8>, // Класс обслуживания DSCP (0-63) отправляемых пакетов для QoS (None - без маркировки)
+    pub send_concurrency: usize, // Количество чанков, отправляемых одновременно через один сокет (подтверждения распределяются по хэшу и токену)
+    pub retry: SendRetry,        // Повторные попытки отправки чанка без ответа
+    pub max_per_peer: usize, // Количество чанков, одновременно отправляемых одному серверу, чтобы лучший сервер не получал всю партию (0 - без ограничения)
+    pub chunker: Chunker, // Способ разбиения файла на блоки (разбиение по содержимому сохраняет хэши неизмененных частей файла)
+    pub preserve_attrs: bool, // Сохранять ли в метаданных размер, время изменения и права доступа исходного файла
+    pub peers: Vec<SocketAddr>, // Серверы для сетей без широковещания: запросы отправляются каждому из них (пустой список - широковещательно)
+    pub consume_source: bool, // Заменять ли исходный файл метаданными (иначе метаданные записываются рядом с ним)
+    pub manifest_format: ManifestFormat, // Формат файла метаданных
+    pub broadcast: Option<Ipv4Addr>, // Адрес широковещания подсети домена для узлов с несколькими интерфейсами (None - 255.255.255.255)
+    pub address_mode: AddressMode, // Адресация запросов без указанных серверов: широковещание IPv4 (по умолчанию) или группа IPv6
+    pub scores: Option<Arc<PeerScores>>, // Оценки серверов: обновляются по результатам отправки и учитываются при выборе сервера
+    pub max_chunks_per_file: usize, // Количество чанков файла (или сегмента), больше которого файл не отправляется
+    pub max_manifest_size: usize, // Размер списка чанков в метаданных файла (или сегмента) в байтах, больше которого файл не отправляется
+    pub embed_erasure_params: bool, // Сохранять ли при отправке с корневым хэшем параметры кода отдельным чанком-заголовком
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        SendOptions {
+            ack_window: DEFAULT_ACK_WINDOW,
+            keep_local: false,
+            dedup: false,
+            source_port: None,
+            dscp: None,
+            send_concurrency: DEFAULT_SEND_CONCURRENCY,
+            max_per_peer: 0,
+            retry: SendRetry::default(),
+            peers: Vec::new(),
+            chunker: Chunker::default(),
+            preserve_attrs: false,
+            consume_source: false,
+            manifest_format: ManifestFormat::default(),
+            broadcast: None,
+            address_mode: AddressMode::default(),
+            scores: None,
+            max_chunks_per_file: reed_solomon::DEFAULT_MAX_CHUNKS,
+            max_manifest_size: DEFAULT_MAX_MANIFEST_SIZE,
+            embed_erasure_params: false,
         }
-        if !is_all_recovery_received {
-            return Err(Box::new(ReceivingChunkError(String::from(
-                "Could not receive both data and recovery chunks",
-            ))));
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecvOptions {
+    // Параметры получения чанков из домена
+    pub concurrency: usize,   // Количество одновременно запрашиваемых чанков
+    pub retry: RecoveryRetry, // Повторные попытки получения недостающих чанков
+    pub source_port: Option<u16>, // Фиксированный порт клиентского сокета (None - выбирается системой)
+    pub dscp: Option<u8>, // Класс обслуживания DSCP (0-63) отправляемых запросов для QoS (None - без маркировки)
+    pub fail_on_wrong_size: bool, // Считать ли чанк неполученным при первом ответе неверного размера (иначе ответ отбрасывается и ожидаются другие серверы)
+    pub quorum: usize, // Количество серверов, которые должны прислать одинаковую копию чанка (1 - принимается первая копия)
+    pub peers: Vec<SocketAddr>, // Серверы, у которых запрашиваются чанки (пустой список - широковещательно)
+    pub verify: bool, // Проверять ли хэш-суммы полученных чанков перед восстановлением файла
+    pub scores: Option<Arc<PeerScores>>, // Оценки серверов: обновляются по полученным ответам, серверы peers опрашиваются по убыванию оценки
+    pub resume: bool, // Вести ли журнал полученных чанков, чтобы прерванное получение продолжилось с того же места
+    pub broadcast: Option<Ipv4Addr>, // Адрес широковещания подсети домена для узлов с несколькими интерфейсами (None - 255.255.255.255)
+    pub address_mode: AddressMode, // Адресация запросов без указанных серверов: широковещание IPv4 (по умолчанию) или группа IPv6
+    pub allow_untagged: bool, // Принимать ли метаданные без тега (отправленные до появления тегов) с предупреждением
+}
+
+impl Default for RecvOptions {
+    fn default() -> Self {
+        RecvOptions {
+            concurrency: DEFAULT_RECV_CONCURRENCY,
+            retry: RecoveryRetry::default(),
+            source_port: None,
+            dscp: None,
+            peers: Vec::new(),
+            fail_on_wrong_size: false,
+            quorum: 1,
+            verify: false,
+            resume: false,
+            scores: None,
+            broadcast: None,
+            address_mode: AddressMode::default(),
+            allow_untagged: false,
         }
-        Ok(ReedSolomonChunks { data, recv })
     }
 }
 
-pub trait ChunksHashes<H> {
-    fn save_to(self, path: impl AsRef<Path>) -> impl Future<Output = Result<(), Box<dyn Error>>>; // Сохранение метаданных
-    fn load_from(path: impl AsRef<Path>) -> impl Future<Output = Result<Self, Box<dyn Error>>>
-    where
-        Self: Sized; // Чтение метаданных
-    fn len(&self) -> usize; // Количество чанков
-    fn get_data_hash(&self, index: usize) -> H; // Получение данных
-    fn get_recv_hash(&self, index: usize) -> H; // Получение восстановительных данных
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkSendResult {
+    // Результат отправки одного чанка
+    pub index: usize,             // Индекс чанка
+    pub recovery: bool,           // Является ли чанк восстановительным
+    pub peer: Option<SocketAddr>, // Сервер, которому отправлен чанк (None - отправка не удалась)
+    pub error: Option<String>,    // Описание ошибки отправки
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct ReedSolomonChunksHashes {
-    data: Vec<ReedSolomonChunkHash>,
-    recv: Vec<ReedSolomonChunkHash>,
+impl ChunkSendResult {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none() // Проверка успешности отправки
+    }
 }
 
-impl ChunksHashes<ReedSolomonChunkHash> for ReedSolomonChunksHashes {
-    async fn save_to(self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
-        let data = BASE64.encode(serde_json::to_vec(&self)?); // Сериализация
-        fs::write(path, &data).await?; // Запись в файл
-        Ok(())
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepairReport {
+    // Сведения о восстановлении избыточности файла
+    pub lost_data: usize,     // Количество не полученных из домена чанков данных
+    pub lost_recovery: usize, // Количество не полученных из домена восстановительных чанков
+    pub resent: usize,        // Количество повторно отправленных в домен чанков
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkDiff {
+    // Участок локального файла, чанка которого нет в метаданных
+    pub index: usize,  // Индекс чанка данных в локальном файле
+    pub offset: usize, // Смещение участка в файле
+    pub len: usize,    // Длина участка
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDiff {
+    // Результат сравнения локального файла с метаданными отправленного
+    pub local_size: usize,       // Размер локального файла
+    pub stored_size: usize,      // Размер отправленного файла по метаданным
+    pub local_chunks: usize,     // Количество чанков данных локального файла
+    pub changed: Vec<ChunkDiff>, // Участки с новым или измененным содержимым
+    pub removed: usize, // Количество чанков данных из метаданных, которых в локальном файле больше нет
+}
+
+impl FileDiff {
+    pub fn is_identical(&self) -> bool {
+        // Совпадает ли файл с отправленным (повторная отправка не нужна)
+        self.changed.is_empty() && self.removed == 0 && self.local_size == self.stored_size
     }
+}
 
-    async fn load_from(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
-        let content = fs::read(path).await?; // Чтение из файла
-        let obj = serde_json::from_slice(&BASE64.decode(&content)?)?; // Десериализация
-        Ok(obj)
+impl ReedSolomonChunks {
+    pub async fn diff(
+        path: impl AsRef<Path>,
+        hashes: &ReedSolomonChunksHashes,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<FileDiff, Box<dyn Error>> {
+        // Сравнение локального файла с метаданными без обращения к домену: файл разбивается
+        // тем же способом, чанки шифруются, а их дайджесты содержимого ищутся среди дайджестов
+        // чанков данных в метаданных. При разбиении по содержимому неизмененные участки сохраняют хэши
+        // и после вставок; при разбиении на блоки постоянного размера изменение размера файла
+        // меняет размер всех блоков
+        let mut chunks = Self::from_file_chunked(
+            &path,
+            encryptor,
+            hashes.chunker,
+            reed_solomon::DEFAULT_MAX_CHUNKS,
+        )
+        .await?;
+        let mut offset = 0;
+        let regions: Vec<(usize, usize)> = chunks
+            .data
+            .iter()
+            .flatten()
+            .map(|c| {
+                let len = reed_solomon::content_len(&c.value, chunks.chunker);
+                let len = len.min(chunks.original_size.saturating_sub(offset)); // Дополнение в конце файла
+                offset += len;
+                (offset - len, len)
+            })
+            .collect();
+        chunks.encrypt(encryptor)?;
+        chunks.update_hashes(hasher)?;
+
+        // Со случайным вектором неизмененный чанк шифруется в другой шифротекст, поэтому хэши
+        // шифротекста сравниваются только для метаданных без дайджестов (старых или отправленных
+        // шифровальщиком без тегов)
+        let by_digest = hashes.data.iter().all(|h| h.digest.is_some());
+        let key = |h: &ReedSolomonChunkHash| match by_digest {
+            true => h.digest.clone().unwrap_or_default(),
+            false => h.value.clone(),
+        };
+        let stored: HashSet<String> = hashes.data.iter().map(key).collect();
+        let local: Vec<String> = chunks
+            .data
+            .iter()
+            .flatten()
+            .filter_map(|c| c.hash.as_ref().map(key))
+            .collect();
+        let present: HashSet<&String> = local.iter().collect();
+        let changed = local
+            .iter()
+            .zip(regions)
+            .enumerate()
+            .filter(|(_, (h, _))| !stored.contains(*h))
+            .map(|(index, (_, (offset, len)))| ChunkDiff { index, offset, len })
+            .collect();
+        Ok(FileDiff {
+            local_size: chunks.original_size,
+            stored_size: hashes.stats.original_size,
+            local_chunks: local.len(),
+            changed,
+            removed: stored.iter().filter(|h| !present.contains(*h)).count(),
+        })
     }
 
-    fn len(&self) -> usize {
-        self.data.len() // Количество чанков одинаково
+    pub async fn from_file_chunked(
+        path: impl AsRef<Path>,
+        encryptor: &dyn Encryptor,
+        chunker: Chunker,
+        max_chunks: usize,
+    ) -> Result<ReedSolomonChunks, Box<dyn Error>> {
+        // Получение чанков файла, разбитого на блоки указанным способом
+        let content = fs::read(path).await?;
+        Self::from_bytes_chunked(
+            content,
+            Backend::preferred(),
+            encryptor.block_size(),
+            chunker,
+            max_chunks,
+        )
     }
 
-    fn get_data_hash(&self, index: usize) -> ReedSolomonChunkHash {
-        self.data[index].clone() // Получение хэша по индексу
+    #[cfg(test)]
+    pub fn from_bytes(
+        content: Vec<u8>,
+        backend: Backend,
+        cipher_block: usize,
+    ) -> Result<ReedSolomonChunks, Box<dyn Error>> {
+        // Получение чанков из данных в памяти с ограничением числа чанков по умолчанию; рабочие
+        // пути передают ограничение из параметров отправки в from_bytes_chunked
+        Self::from_bytes_chunked(
+            content,
+            backend,
+            cipher_block,
+            Chunker::Fixed,
+            reed_solomon::DEFAULT_MAX_CHUNKS,
+        )
     }
 
-    fn get_recv_hash(&self, index: usize) -> ReedSolomonChunkHash {
-        self.recv[index].clone() // Получение хэша по индексу
+    pub fn from_bytes_chunked(
+        content: Vec<u8>,
+        backend: Backend,
+        cipher_block: usize,
+        chunker: Chunker,
+        max_chunks: usize,
+    ) -> Result<ReedSolomonChunks, Box<dyn Error>> {
+        // Получение чанков из данных в памяти: файл, который разбился бы больше чем на max_chunks
+        // чанков, отклоняется до формирования блоков
+        let original_size = content.len();
+        let (data, recv) =
+            reed_solomon::split_with(content, backend, cipher_block, chunker, max_chunks)?; // Формирование чанков
+        Ok(Self::from_shards(
+            data,
+            recv,
+            backend,
+            original_size,
+            chunker,
+        ))
     }
-}
 
-mod errors {
-    // Модуль с составными ошибками
-    use std::error::Error;
-    use std::fmt;
-    use std::fmt::{Display, Formatter};
+    pub fn into_bytes(self) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Восстановление данных в памяти
+        let (backend, original_size, chunker) = (self.backend, self.original_size, self.chunker);
+        let (data, recv) = self.into_shards(); // Получение чанков
+        reed_solomon::recover(data, recv, backend, original_size, chunker) // Восстановление данных
+    }
 
-    #[derive(Debug, Clone)]
-    pub struct SendingChunkError(pub String); // Ошибка отправки данных
+    pub fn check_manifest_size(
+        &self,
+        hasher: &dyn Hasher,
+        max_size: usize,
+    ) -> Result<(), ManifestTooLargeError> {
+        // Проверка размера списка чанков в метаданных до отправки: метаданные, которые нельзя
+        // обработать или сохранить в домене, не создаются. Для чанков с вычисленными хэшами
+        // размер точный, для остальных (при конвейерной отправке) оценивается по длине хэша
+        // пустых данных: хэши одного вычислителя имеют одинаковую длину
+        let sample = hasher.calc_hash(&[]);
+        let entry_size =
+            |hash: &ReedSolomonChunkHash| serde_json::to_vec(hash).map_or(0, |v| v.len());
+        let size = self
+            .data
+            .iter()
+            .chain(&self.recv)
+            .flatten()
+            .map(|c| match &c.hash {
+                Some(h) => entry_size(h),
+                None => entry_size(&ReedSolomonChunkHash {
+                    value: sample.clone(),
+                    size: c.value.len(),
+                    local: false,
+                    digest: None,
+                }),
+            })
+            .sum();
+        match size > max_size {
+            true => Err(ManifestTooLargeError(size, max_size)),
+            false => Ok(()),
+        }
+    }
 
-    impl Display for SendingChunkError {
-        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-            write!(f, "Error sending chunk: {}", self.0)
+    pub fn stats(&self) -> ChunksStats {
+        // Статистика хранения, которая получится при отправке чанков (без обращения к домену)
+        let size = |x: &Vec<Option<ReedSolomonChunk>>| {
+            x.iter().flatten().map(|c| c.value.len()).sum::<usize>()
+        };
+        ChunksStats {
+            original_size: self.original_size,
+            stored_size: size(&self.data) + size(&self.recv),
+            data_chunks: self.data.len(),
+            recovery_chunks: self.recv.len(),
+            local_chunks: 0,
         }
     }
 
-    impl Error for SendingChunkError {}
+    pub fn manifest_tag(
+        &self,
+        encryptor: &dyn Encryptor,
+    ) -> Result<Option<String>, SendingChunkError> {
+        // Тег списка чанков, который получат метаданные после отправки (хэши должны быть вычислены)
+        let hashes = |x: &[Option<ReedSolomonChunk>]| {
+            x.iter()
+                .map(|c| c.as_ref().and_then(|c| c.hash.clone()))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(SendingChunkError(String::from("Hash is not calculated")))
+        };
+        let (data, recv) = (hashes(&self.data)?, hashes(&self.recv)?);
+        let message = tag_message(self.original_size, [&data, &recv]);
+        Ok(encryptor.authenticate(&message).map(hex::encode))
+    }
 
-    #[derive(Debug, Clone)]
-    pub struct ReceivingChunkError(pub String); // Ошибка получения данных
+    pub fn with_ack_window(mut self, ack_window: Duration) -> ReedSolomonChunks {
+        // Установка времени сбора подтверждений при отправке
+        self.ack_window = ack_window;
+        self
+    }
 
-    impl Display for ReceivingChunkError {
-        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-            write!(f, "Error receiving chunk: {}", self.0)
+    pub fn with_options(self, options: &SendOptions) -> ReedSolomonChunks {
+        // Установка параметров отправки
+        let mut chunks = self.with_ack_window(options.ack_window);
+        chunks.keep_local = options.keep_local;
+        chunks.dedup = options.dedup;
+        chunks.source_port = options.source_port;
+        chunks.dscp = options.dscp;
+        chunks.send_concurrency = options.send_concurrency;
+        chunks.max_per_peer = options.max_per_peer;
+        chunks.retry = options.retry.clone();
+        chunks.peers = options.peers.clone();
+        chunks.broadcast = options.broadcast;
+        chunks.address_mode = options.address_mode;
+        chunks.scores = options.scores.clone();
+        chunks
+    }
+
+    fn from_shards(
+        data: Vec<Vec<u8>>,
+        recv: Vec<Vec<u8>>,
+        backend: Backend,
+        original_size: usize,
+        chunker: Chunker,
+    ) -> ReedSolomonChunks {
+        // Формирование набора чанков из блоков
+        let into_chunks = |x: Vec<Vec<u8>>| {
+            x.into_par_iter()
+                .map(|value| {
+                    Some(ReedSolomonChunk {
+                        value,
+                        hash: None,
+                        meta: None,
+                        digest: None,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        Self::from_chunks(
+            into_chunks(data),
+            into_chunks(recv),
+            backend,
+            original_size,
+            chunker,
+        )
+    }
+
+    fn from_chunks(
+        data: Vec<Option<ReedSolomonChunk>>,
+        recv: Vec<Option<ReedSolomonChunk>>,
+        backend: Backend,
+        original_size: usize,
+        chunker: Chunker,
+    ) -> ReedSolomonChunks {
+        // Формирование набора чанков с параметрами отправки по умолчанию (см. with_options)
+        ReedSolomonChunks {
+            data,
+            recv,
+            backend,
+            original_size,
+            chunker,
+            ack_window: DEFAULT_ACK_WINDOW,
+            keep_local: false,
+            dedup: false,
+            source_port: None,
+            dscp: None,
+            send_concurrency: DEFAULT_SEND_CONCURRENCY,
+            max_per_peer: 0,
+            retry: SendRetry::default(),
+            peers: Vec::new(),
+            broadcast: None,
+            address_mode: AddressMode::default(),
+            scores: None,
         }
     }
 
-    impl Error for ReceivingChunkError {}
+    fn into_shards(self) -> (PartialShards, PartialShards) {
+        // Получение блоков из набора чанков
+        let into_values = |x: Vec<Option<ReedSolomonChunk>>| {
+            x.into_par_iter()
+                .map(|c| c.map(|c| c.value))
+                .collect::<Vec<_>>()
+        };
+        (into_values(self.data), into_values(self.recv))
+    }
+
+    fn par_chunks_mut(&mut self) -> impl ParallelIterator<Item = &mut ReedSolomonChunk> {
+        // Параллельный итератор по всем полученным чанкам
+        self.data
+            .par_iter_mut()
+            .chain(self.recv.par_iter_mut())
+            .flatten()
+    }
+
+    fn local_addr() -> Result<IpAddr, SendingChunkError> {
+        // Получение IP-адреса машины, чтобы не принимать подтверждения от самого себя
+        Ok(pnet::datalink::interfaces()
+            .par_iter()
+            .find_first(|i| !i.is_loopback() && !i.ips.is_empty())
+            .ok_or(SendingChunkError(String::from("No interface found")))?
+            .ips
+            .first()
+            .ok_or(SendingChunkError(String::from("No IP found")))?
+            .ip())
+    }
+
+    fn client_socket_on(
+        mode: AddressMode,
+        port: Option<u16>,
+        dscp: Option<u8>,
+    ) -> Result<UdpSocket, Box<dyn Error>> {
+        // Создание клиентского сокета семейства адресов mode. Занятый фиксированный порт - ошибка:
+        // замена его случайным нарушила бы правила межсетевого экрана
+        let Some(port) = port else {
+            let addr = mode.client_addr(0).to_string();
+            return Ok(udp::bind_marked(&addr, udp::DEFAULT_BUF_SIZE, dscp)?);
+        };
+        let addr = mode.client_addr(port).to_string();
+        udp::bind_marked(&addr, udp::DEFAULT_BUF_SIZE, dscp).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AddrInUse => Box::new(SourcePortBusyError(port)) as Box<dyn Error>,
+            _ => Box::new(e),
+        })
+    }
+
+    fn domain_demux(options: &RecvOptions) -> Result<Demux, Box<dyn Error>> {
+        // Распределитель ответов на сокете с адресацией и проверками получения options
+        let mode = options.address_mode;
+        let broadcast = mode.domain_addr(options.broadcast, &options.peers)?;
+        let socket = Self::client_socket_on(mode, options.source_port, options.dscp)?;
+        Ok(Demux::new(socket)
+            .with_discard_wrong_size(!options.fail_on_wrong_size)
+            .with_quorum(options.quorum)
+            .with_peers(options.peers.clone())
+            .with_scores(options.scores.clone())
+            .with_broadcast(broadcast))
+    }
+
+    async fn with_demux<T>(
+        demux: &Demux,
+        operation: impl Future<Output = T>,
+    ) -> Result<T, Box<dyn Error>> {
+        // Выполнение запросов чанков одновременно с распределением ответов на них
+        tokio::select! {
+            result = operation => Ok(result),
+            e = demux.run() => Err(Box::new(e)),
+        }
+    }
+
+    async fn fetch(
+        demux: &Demux,
+        hashes: impl ExactSizeIterator<Item = ReedSolomonChunkHash>,
+        progress: &Progress,
+        concurrency: usize,
+    ) -> Vec<Option<ReedSolomonChunk>> {
+        // Получение чанков из домена (до concurrency одновременно), не полученные чанки записываются как None
+        progress.add_total(hashes.len());
+        let mut chunks = vec![None; hashes.len()];
+        let mut fetched = stream::iter(hashes.enumerate())
+            .map(|(i, h)| async move { (i, Self::fetch_one(demux, h).await) })
+            .buffer_unordered(concurrency.max(1));
+        while let Some((i, c)) = fetched.next().await {
+            chunks[i] = c; // Ответы приходят в произвольном порядке
+            progress.advance();
+        }
+        chunks
+    }
+
+    async fn fetch_one(demux: &Demux, hash: ReedSolomonChunkHash) -> Option<ReedSolomonChunk> {
+        // Получение одного чанка из домена, ошибка выводится и заменяется на None
+        let value = hash.get_value();
+        let size = Some(hash.get_size());
+        let result = demux
+            .request(&value, size, RECV_TIMEOUT)
+            .await
+            .and_then(|d| {
+                if d.len() != hash.get_size() {
+                    let e = ReceivingChunkError(String::from("Blocks sizes mismatch"));
+                    return Err(Box::new(e) as Box<dyn Error>); // Ошибка несоответствия размеров блока
+                }
+                Ok(d)
+            });
+        match result {
+            Ok(d) => Some(ReedSolomonChunk {
+                value: d,
+                hash: None,
+                meta: None,
+                digest: None,
+            }),
+            Err(e) => {
+                eprintln!("Error receiving chunk {}: {}", value, e);
+                None
+            }
+        }
+    }
+
+    async fn fetch_hedged(
+        demux: &Demux,
+        data_hash: ReedSolomonChunkHash,
+        recv_hash: ReedSolomonChunkHash,
+    ) -> (Option<ReedSolomonChunk>, Option<ReedSolomonChunk>, bool) {
+        // Получение чанка данных с подстраховкой: если он не пришел за HEDGE_DELAY, параллельно
+        // запрашивается восстановительный чанк с тем же индексом (он из той же группы и заменяет
+        // чанк данных при восстановлении), используется пришедший первым.
+        // Возвращает чанк данных, восстановительный чанк и признак запроса восстановительного
+        let data = Self::fetch_one(demux, data_hash);
+        tokio::pin!(data);
+        match time::timeout(HEDGE_DELAY, &mut data).await {
+            Ok(Some(c)) => return (Some(c), None, false), // Чанк данных пришел вовремя
+            Ok(None) => return (None, Self::fetch_one(demux, recv_hash).await, true), // Чанк данных не получен
+            Err(_) => {} // Чанк данных задерживается
+        }
+        let recovery = Self::fetch_one(demux, recv_hash);
+        tokio::pin!(recovery);
+        tokio::select! {
+            d = &mut data => match d {
+                Some(c) => (Some(c), None, true),
+                None => (None, recovery.await, true),
+            },
+            r = &mut recovery => match r {
+                Some(c) => (None, Some(c), true),
+                None => (data.await, None, true),
+            },
+        }
+    }
+
+    pub async fn repair(
+        hashes: ReedSolomonChunksHashes,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+        options: &SendOptions,
+    ) -> Result<(ReedSolomonChunksHashes, RepairReport), Box<dyn Error>> {
+        Self::rekey(hashes, encryptor, encryptor, hasher, options).await
+    }
+
+    pub async fn rekey(
+        hashes: ReedSolomonChunksHashes,
+        decryptor: &dyn Encryptor,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+        options: &SendOptions,
+    ) -> Result<(ReedSolomonChunksHashes, RepairReport), Box<dyn Error>> {
+        // Восстановление избыточности файла: получение всех доступных чанков, восстановление
+        // потерянных и повторная отправка в домен. Сервер удаляет чанк при выдаче, поэтому
+        // в домен заново отправляются все чанки, а не только потерянные. Чанки расшифровываются
+        // decryptor и шифруются encryptor, при разных ключах файл перешифровывается без повторного
+        // разбиения, старые копии чанков удаляются серверами при выдаче. Чанки запрашиваются
+        // и отправляются с адресацией options
+        let mode = options.address_mode;
+        let broadcast = mode.domain_addr(options.broadcast, &options.peers)?;
+        let socket = Self::client_socket_on(mode, options.source_port, options.dscp)?;
+        let demux = Demux::new(socket)
+            .with_shared(hashes.shared_hashes())
+            .with_peers(options.peers.clone())
+            .with_scores(options.scores.clone())
+            .with_broadcast(broadcast);
+        let progress = Progress::default();
+        let data_hashes = (0..hashes.len()).map(|i| hashes.get_data_hash(i));
+        let recv_hashes = (0..hashes.len()).map(|i| hashes.get_recv_hash(i));
+        let fetched = Self::with_demux(&demux, async {
+            let data = Self::fetch(&demux, data_hashes, &progress, DEFAULT_RECV_CONCURRENCY).await;
+            let recv = Self::fetch(&demux, recv_hashes, &progress, DEFAULT_RECV_CONCURRENCY).await;
+            (data, recv)
+        });
+        let (data, recv) = fetched.await?;
+        drop(demux);
+        let mut chunks = Self::from_chunks(
+            data,
+            recv,
+            hashes.backend,
+            hashes.stats.original_size,
+            hashes.chunker,
+        );
+
+        let lost_data = chunks.data.iter().filter(|x| x.is_none()).count();
+        let lost_recovery = chunks.recv.iter().filter(|x| x.is_none()).count();
+
+        chunks.decrypt(decryptor)?;
+        let backend = chunks.backend;
+        let (original_size, chunker) = (chunks.original_size, chunks.chunker);
+        let (data, recv) = chunks.into_shards();
+        let data = reed_solomon::reconstruct(data, recv, backend)?; // Восстановление блоков данных
+        let recv = reed_solomon::encode(&data, backend)?; // Повторное вычисление восстановительных блоков
+
+        let mut chunks =
+            Self::from_shards(data, recv, backend, original_size, chunker).with_options(options);
+        chunks.dedup = hashes.dedup; // Режим отправки сохраняется
+        chunks.encrypt(encryptor)?;
+        chunks.update_hashes(hasher)?;
+        let resent = chunks.data.len() + chunks.recv.len();
+        // Атрибуты исходного файла, параметры шифрования паролем, реализации и формат метаданных
+        // сохраняются: без них файл, отправленный в переносимом режиме, нельзя было бы получить
+        let ReedSolomonChunksHashes {
+            source_attrs,
+            key_material,
+            algorithms,
+            format,
+            ..
+        } = hashes;
+        let mut hashes = chunks.send(&progress).await?;
+        hashes.attach_tag(encryptor); // Новый список чанков заверяется ключом, которым они зашифрованы
+        hashes.source_attrs = source_attrs;
+        hashes.key_material = key_material;
+        hashes.algorithms = algorithms;
+        hashes.format = format;
+
+        Ok((
+            hashes,
+            RepairReport {
+                lost_data,
+                lost_recovery,
+                resent,
+            },
+        ))
+    }
+
+    pub async fn send_detailed(
+        self,
+        progress: &Progress,
+    ) -> Result<(ReedSolomonChunksHashes, Vec<ChunkSendResult>), Box<dyn Error>> {
+        // Отправка всех чанков с результатом по каждому: ошибка отправки одного чанка не прерывает
+        // отправку остальных, хэши не отправленных чанков также записываются в метаданные
+        self.send_all(progress, false).await
+    }
+
+    pub async fn send_pipelined(
+        mut self,
+        encryptor: Arc<dyn Encryptor + Send + Sync>,
+        hasher: Arc<dyn Hasher + Send + Sync>,
+        progress: &Progress,
+    ) -> Result<ReedSolomonChunksHashes, Box<dyn Error>> {
+        // Шифрование, хэширование и отправка чанков одновременно. Стадия шифрования в отдельном
+        // потоке обрабатывает окна по PIPELINE_WINDOW чанков параллельно и передает их стадии
+        // отправки через ограниченный канал: сеть не простаивает во время шифрования, а процессор -
+        // во время ожидания подтверждений. Результат тот же, что у encrypt, update_hashes и send
+        let counts = (self.data.len(), self.recv.len());
+        let dedup = self.dedup;
+        let mut chunks = self.take_chunks()?.into_iter();
+        let (tx, rx) = mpsc::channel(PIPELINE_DEPTH);
+        let encrypt = tokio::task::spawn_blocking(move || loop {
+            let mut window: SendBatch = chunks.by_ref().take(PIPELINE_WINDOW).collect();
+            if window.is_empty() {
+                break;
+            }
+            let result = pool::crypto_pool()
+                .install(|| {
+                    window.par_iter_mut().try_for_each(|(_, c)| {
+                        encrypt_checked(c, encryptor.as_ref(), dedup).map_err(|e| e.to_string())?;
+                        c.update_hash(hasher.as_ref()).map_err(|e| e.to_string())
+                    })
+                })
+                .map(|()| window);
+            let failed = result.is_err();
+            if tx.blocking_send(result).is_err() || failed {
+                break; // Стадия отправки завершилась или чанк не зашифрован
+            }
+        });
+        let sent = self.send_stream(counts, rx, progress, true).await;
+        encrypt.await?;
+        let (hashes, results) = sent?;
+        match results.into_iter().find_map(|r| r.error) {
+            Some(e) => Err(Box::new(SendingChunkError(e))),
+            None => Ok(hashes),
+        }
+    }
+
+    fn take_chunks(&mut self) -> Result<SendBatch, SendingChunkError> {
+        // Извлечение всех чанков для отправки: сначала чанки данных, затем восстановительные
+        let data = std::mem::take(&mut self.data)
+            .into_iter()
+            .map(|c| (false, c));
+        let recv = std::mem::take(&mut self.recv).into_iter();
+        data.chain(recv.map(|c| (true, c)))
+            .map(|(recovery, c)| {
+                c.map(|c| (recovery, c))
+                    .ok_or(SendingChunkError(String::from("Chunk is missing")))
+            })
+            .collect()
+    }
+
+    async fn send_all(
+        mut self,
+        progress: &Progress,
+        stop_on_error: bool,
+    ) -> Result<(ReedSolomonChunksHashes, Vec<ChunkSendResult>), Box<dyn Error>> {
+        // Отправка уже зашифрованных чанков одним окном
+        let counts = (self.data.len(), self.recv.len());
+        let (tx, rx) = mpsc::channel(1);
+        tx.send(Ok(self.take_chunks()?)).await?;
+        drop(tx);
+        self.send_stream(counts, rx, progress, stop_on_error).await
+    }
+
+    async fn send_stream(
+        &self,
+        (data_len, recv_len): (usize, usize),
+        mut windows: mpsc::Receiver<Result<SendBatch, String>>,
+        progress: &Progress,
+        stop_on_error: bool,
+    ) -> Result<(ReedSolomonChunksHashes, Vec<ChunkSendResult>), Box<dyn Error>> {
+        // Отправка окон чанков по мере их поступления (data_len и recv_len - количество чанков
+        // данных и восстановительных во всех окнах)
+        let mut localaddr = Self::local_addr()?; // IP-адрес машины
+        let mode = self.address_mode;
+        let broadcast = mode.domain_addr(self.broadcast, &self.peers)?; // Адрес проверяется до отправки первого чанка
+        let mut socket = Self::client_socket_on(mode, self.source_port, self.dscp)?; // Создание сокета
+        let mut rebinds = 0; // Количество повторных определений адреса узла
+
+        let mut hashes = (Vec::with_capacity(data_len), Vec::with_capacity(recv_len));
+        let mut results = Vec::with_capacity(data_len + recv_len);
+        let mut stored_size = 0;
+        let mut placed: HashMap<String, (SocketAddr, bool)> = HashMap::new(); // Отправленные хэши (в режиме dedup)
+        let mut i = 0; // Номер очередного чанка среди всех чанков
+
+        progress.add_total(data_len + recv_len);
+        let (ack_window, peers, keep_local) = (self.ack_window, &self.peers, self.keep_local);
+        let retry = &self.retry;
+        let scores = self.scores.as_deref();
+
+        // Чанки отправляются партиями по send_concurrency одновременно через один сокет, подтверждения
+        // распределяются по отправкам AckDemux. Результаты учитываются в порядке чанков
+        let concurrency = self.send_concurrency.max(1);
+        let load = (self.max_per_peer > 0).then(|| PeerLoad::new(self.max_per_peer));
+        let load = load.as_ref();
+        'batches: while let Some(window) = windows.recv().await {
+            let window = window.map_err(EncryptionError)?;
+            for batch in window.chunks(concurrency) {
+                // В режиме dedup повтор хэша, уже отправленного или отправляемого в этой партии, не отправляется
+                let mut in_flight = HashSet::new();
+                let duplicate: Vec<bool> = batch
+                    .iter()
+                    .map(|(_, c)| {
+                        let value = c.hash.as_ref().map(|h| h.get_value()).unwrap_or_default();
+                        self.dedup && (placed.contains_key(&value) || !in_flight.insert(value))
+                    })
+                    .collect();
+                let mut locals: Vec<Option<Result<(), String>>> = vec![None; batch.len()]; // Результаты записи локальных копий
+                let mut sent: Vec<Option<Result<SocketAddr, Box<dyn Error>>>> =
+                    (0..batch.len()).map(|_| None).collect();
+                loop {
+                    let demux = AckDemux::new(&socket, localaddr);
+                    let todo: Vec<usize> = (0..batch.len())
+                        .filter(|&j| !duplicate[j] && sent[j].is_none())
+                        .collect();
+                    let sends = todo.iter().map(|&j| {
+                        let (c, local_done) = (&batch[j].1, locals[j].clone());
+                        let demux = &demux;
+                        async move {
+                            // Локальная копия записывается до отправки, пока данные чанка еще не переданы
+                            let local = match (local_done, keep_local) {
+                                (Some(local), _) => local,
+                                (None, true) => {
+                                    c.store_local(demux).await.map_err(|e| e.to_string())
+                                }
+                                (None, false) => Ok(()),
+                            };
+                            let sent = match &local {
+                                Ok(()) => c
+                                    .send_to_peer(
+                                        demux, ack_window, retry, peers, broadcast, scores, load,
+                                        progress,
+                                    )
+                                    .await
+                                    .map(|(_, peer)| peer),
+                                Err(e) => Err(e.clone().into()),
+                            };
+                            (local, sent)
+                        }
+                    });
+                    let outcomes = match demux.serve(future::join_all(sends)).await {
+                        Ok(outcomes) => outcomes,
+                        Err(e) => todo
+                            .iter()
+                            .map(|_| {
+                                let e = io::Error::new(e.kind(), e.to_string()); // Ошибка сокета - у каждой отправки
+                                (Ok(()), Err(Box::new(e) as Box<dyn Error>))
+                            })
+                            .collect(),
+                    };
+                    let mut changed = None;
+                    for (&j, (local, result)) in todo.iter().zip(outcomes) {
+                        locals[j] = Some(local);
+                        match result {
+                            Err(e) if is_address_change(e.as_ref()) && rebinds < MAX_REBINDS => {
+                                changed = Some(e.to_string()); // Чанк будет отправлен повторно
+                            }
+                            result => sent[j] = Some(result),
+                        }
+                    }
+                    let Some(e) = changed else {
+                        break;
+                    };
+                    // Адрес узла сменился (переподключение, VPN) - определяем его заново
+                    // и пересоздаем сокет, после чего повторяем отправку неотправленных чанков
+                    drop(demux);
+                    rebinds += 1;
+                    eprintln!(
+                        "Network changed ({}), re-resolving local address ({}/{})...",
+                        e, rebinds, MAX_REBINDS
+                    );
+                    localaddr = Self::local_addr()?;
+                    socket = Self::client_socket_on(mode, self.source_port, self.dscp)?;
+                    progress.add_retry();
+                }
+
+                for (j, (recovery, c)) in batch.iter().enumerate() {
+                    let recovery = *recovery;
+                    let mut hash = c
+                        .hash
+                        .clone()
+                        .ok_or(SendingChunkError(String::from("Hash is not calculated")))?;
+                    let index = if recovery { i - data_len } else { i };
+                    let result = if duplicate[j] {
+                        // Такой же чанк уже отправлен - в метаданных достаточно сослаться на его хэш
+                        let placed = placed.get(&hash.value).copied();
+                        hash.local = placed.is_some_and(|(_, local)| local);
+                        ChunkSendResult {
+                            index,
+                            recovery,
+                            peer: placed.map(|(peer, _)| peer),
+                            error: match placed {
+                                Some(_) => None,
+                                None => Some(String::from("Identical chunk was not sent")),
+                            },
+                        }
+                    } else {
+                        hash.local = keep_local && locals[j].as_ref().is_some_and(|l| l.is_ok());
+                        let sent = sent[j]
+                            .take()
+                            .unwrap_or_else(|| Err("Chunk was not sent".into()));
+                        match sent {
+                            Ok(peer) => {
+                                stored_size += hash.get_size();
+                                progress.add_bytes(hash.get_size());
+                                placed.insert(hash.get_value(), (peer, hash.local));
+                                ChunkSendResult {
+                                    index,
+                                    recovery,
+                                    peer: Some(peer),
+                                    error: None,
+                                }
+                            }
+                            Err(e) => ChunkSendResult {
+                                index,
+                                recovery,
+                                peer: None,
+                                error: Some(e.to_string()),
+                            },
+                        }
+                    };
+                    if recovery {
+                        hashes.1.push(hash);
+                    } else {
+                        hashes.0.push(hash);
+                    }
+                    progress.advance();
+                    i += 1;
+                    let failed = !result.succeeded();
+                    results.push(result);
+                    if failed && stop_on_error {
+                        break 'batches;
+                    }
+                }
+            }
+        }
+
+        let (data, recv) = hashes;
+        let stats = ChunksStats {
+            original_size: self.original_size,
+            stored_size,
+            data_chunks: data.len(),
+            recovery_chunks: recv.len(),
+            local_chunks: data.iter().chain(&recv).filter(|h| h.local).count(),
+        };
+        let hashes = ReedSolomonChunksHashes {
+            data,
+            recv,
+            backend: self.backend,
+            stats,
+            dedup: self.dedup,
+            chunker: self.chunker,
+            source_attrs: None,
+            key_material: None,
+            algorithms: None,
+            tag: None,
+            format: ManifestFormat::default(),
+        };
+        Ok((hashes, results))
+    }
+
+    pub async fn recv_with_options(
+        hashes: &ReedSolomonChunksHashes,
+        progress: &Progress,
+        options: &RecvOptions,
+    ) -> Result<ReedSolomonChunks, Box<dyn Error>> {
+        Self::recv_with_journal(hashes, progress, options, None).await
+    }
+
+    pub async fn recv_with_journal(
+        hashes: &ReedSolomonChunksHashes,
+        progress: &Progress,
+        options: &RecvOptions,
+        journal: Option<&mut RecvJournal>,
+    ) -> Result<ReedSolomonChunks, Box<dyn Error>> {
+        // Получение с журналом: чанки, записанные в журнал ранее, не запрашиваются, а полученные
+        // записываются в него по мере получения. Все запросы выполняются через один сокет, ответы распределяются по хэшам
+        let demux = Self::domain_demux(options)?.with_shared(hashes.shared_hashes());
+        let fetch = Self::fetch_all(&demux, hashes, progress, options, journal);
+        let (data, recv) = Self::with_demux(&demux, fetch).await??;
+        Ok(Self::from_chunks(
+            data,
+            recv,
+            hashes.backend,
+            hashes.stats.original_size,
+            hashes.chunker,
+        ))
+    }
+
+    pub fn verify(
+        &self,
+        hashes: &ReedSolomonChunksHashes,
+        hasher: &dyn Hasher,
+    ) -> Result<(), ChunkHashMismatchError> {
+        // Проверка хэш-сумм полученных чанков, параллельно по всем чанкам. Проверка прекращается
+        // при первом несовпадении, а в ошибке указывается несовпавший чанк с наименьшим индексом,
+        // поэтому результат не зависит от порядка выполнения
+        let mismatch = pool::run_crypto(|| {
+            (0..hashes.len())
+                .into_par_iter()
+                .flat_map_iter(|i| [(i, false), (i, true)])
+                .find_first(|&(i, recovery)| {
+                    let (chunk, expected) = match recovery {
+                        false => (&self.data[i], hashes.get_data_hash(i)),
+                        true => (&self.recv[i], hashes.get_recv_hash(i)),
+                    };
+                    chunk
+                        .as_ref()
+                        .is_some_and(|c| hasher.calc_hash(&c.value) != expected.get_value())
+                })
+        });
+        match mismatch {
+            Some((index, recovery)) => Err(ChunkHashMismatchError { index, recovery }),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn from_dirs(
+        hashes: ReedSolomonChunksHashes,
+        dirs: &[impl AsRef<Path>],
+        hasher: &dyn Hasher,
+        conflict: ShardConflict,
+    ) -> Result<ReedSolomonChunks, Box<dyn Error>> {
+        // Получение чанков из каталогов с файлами чанков без обращения к домену (например, с дисков
+        // нескольких серверов, хранивших копии одних чанков). Имена файлов не учитываются (на сервере
+        // файлы названы по UUID): хэш каждого файла вычисляется заново, поэтому используются только
+        // файлы, содержимое которых совпадает с хэшем в метаданных. Копии одного чанка из разных
+        // каталогов объединяются, различающиеся копии обрабатываются согласно conflict
+        let mut wanted: HashMap<String, Vec<(bool, usize, usize)>> = HashMap::new(); // Хэш - индексы и размеры чанков
+        for i in 0..hashes.len() {
+            for (recovery, h) in [
+                (false, hashes.get_data_hash(i)),
+                (true, hashes.get_recv_hash(i)),
+            ] {
+                wanted
+                    .entry(h.get_value())
+                    .or_default()
+                    .push((recovery, i, h.get_size()));
+            }
+        }
+        let mut copies = Vec::new();
+        for dir in dirs {
+            let mut entries = fs::read_dir(dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if !entry.file_type().await?.is_file() {
+                    continue;
+                }
+                let value = fs::read(entry.path()).await?;
+                let Some(places) = wanted.get(&hasher.calc_hash(&value)) else {
+                    continue;
+                };
+                for &(recovery, index, size) in places {
+                    if value.len() == size {
+                        copies.push((recovery, index, value.clone()));
+                    }
+                }
+            }
+        }
+        let (data, recv) = reed_solomon::collect_shards(hashes.len(), copies, conflict)?;
+        let into_chunks = |x: PartialShards| -> Vec<Option<ReedSolomonChunk>> {
+            x.into_iter()
+                .map(|v| {
+                    v.map(|value| ReedSolomonChunk {
+                        value,
+                        hash: None,
+                        meta: None,
+                        digest: None,
+                    })
+                })
+                .collect()
+        };
+        let (data, recv) = (into_chunks(data), into_chunks(recv));
+        let short = reed_solomon::shortfall(&data, &recv);
+        if short > 0 {
+            return Err(Box::new(InsufficientRecoveryError(short)));
+        }
+        Ok(Self::from_chunks(
+            data,
+            recv,
+            hashes.backend,
+            hashes.stats.original_size,
+            hashes.chunker,
+        ))
+    }
+
+    async fn fetch_all(
+        demux: &Demux,
+        hashes: &ReedSolomonChunksHashes,
+        progress: &Progress,
+        options: &RecvOptions,
+        mut journal: Option<&mut RecvJournal>,
+    ) -> Result<(Vec<Option<ReedSolomonChunk>>, Vec<Option<ReedSolomonChunk>>), Box<dyn Error>>
+    {
+        let retry = &options.retry;
+        let concurrency = options.concurrency.max(1);
+        let count = hashes.len();
+        progress.add_total(count); // Каждый индекс учитывается один раз, какой бы чанк ни был получен
+        let mut data = vec![None; count];
+        let mut recv = vec![None; count];
+        if let Some(j) = journal.as_deref_mut() {
+            let (d, r) = j.restore().await?; // Чанки, полученные до прерывания
+            let chunk = |value| ReedSolomonChunk {
+                value,
+                hash: None,
+                meta: None,
+                digest: None,
+            };
+            data = d.into_iter().map(|v| v.map(chunk)).collect();
+            recv = r.into_iter().map(|v| v.map(chunk)).collect();
+        }
+        let restored: Vec<bool> = (0..count)
+            .map(|i| data[i].is_some() || recv[i].is_some())
+            .collect();
+        restored
+            .iter()
+            .filter(|&&r| r)
+            .for_each(|_| progress.advance());
+        let mut requested = restored.clone(); // Запрашивался ли восстановительный чанк
+        let mut fetched = stream::iter((0..count).filter(|&i| !restored[i]))
+            .map(|i| async move {
+                let (d, r, req) =
+                    Self::fetch_hedged(demux, hashes.get_data_hash(i), hashes.get_recv_hash(i))
+                        .await;
+                (i, d, r, req)
+            })
+            .buffer_unordered(concurrency);
+        while let Some((i, d, r, req)) = fetched.next().await {
+            Self::journal_chunk(journal.as_deref_mut(), false, i, &d).await;
+            Self::journal_chunk(journal.as_deref_mut(), true, i, &r).await;
+            data[i] = d; // Чанки размещаются по индексам, т.к. приходят в произвольном порядке
+            recv[i] = r;
+            requested[i] = req;
+            progress.advance();
+        }
+
+        if (0..count).any(|i| data[i].is_none() && recv[i].is_none()) {
+            // Для части индексов не получено ни одного чанка - запрашиваем остальные восстановительные
+            eprintln!("Some data chunks were not received, trying to receive recovering ones...");
+            let rest: Vec<usize> = (0..count).filter(|&i| !requested[i]).collect();
+            let rest_hashes = rest.iter().map(|&i| hashes.get_recv_hash(i));
+            let chunks = Self::fetch(demux, rest_hashes, progress, concurrency).await;
+            for (i, c) in rest.into_iter().zip(chunks) {
+                Self::journal_chunk(journal.as_deref_mut(), true, i, &c).await;
+                recv[i] = c;
+            }
+        }
+
+        // Повторные попытки: запрашиваются только недостающие чанки групп, которые нельзя восстановить
+        let deadline = retry.deadline.map(|d| time::Instant::now() + d);
+        let mut round = 0;
+        loop {
+            let short = reed_solomon::shortfall(&data, &recv);
+            if short == 0 {
+                break;
+            }
+            let expired = deadline.is_some_and(|d| time::Instant::now() + retry.interval > d);
+            if round >= retry.rounds || expired {
+                return Err(Box::new(InsufficientRecoveryError(short)));
+            }
+            round += 1;
+            progress.add_retry();
+            eprintln!(
+                "{} chunks short for recovery, retrying ({}/{})...",
+                short, round, retry.rounds
+            );
+            time::sleep(retry.interval).await;
+
+            for g in reed_solomon::groups(count) {
+                if reed_solomon::shortfall(&data[g.clone()], &recv[g.clone()]) == 0 {
+                    continue; // Группа восстанавливается из уже полученных чанков
+                }
+                let missing_data: Vec<usize> = g.clone().filter(|&i| data[i].is_none()).collect();
+                let chunks = Self::fetch(
+                    demux,
+                    missing_data.iter().map(|&i| hashes.get_data_hash(i)),
+                    progress,
+                    concurrency,
+                )
+                .await;
+                for (i, c) in missing_data.into_iter().zip(chunks) {
+                    Self::journal_chunk(journal.as_deref_mut(), false, i, &c).await;
+                    data[i] = c;
+                }
+                let missing_recv: Vec<usize> = g.filter(|&i| recv[i].is_none()).collect();
+                let chunks = Self::fetch(
+                    demux,
+                    missing_recv.iter().map(|&i| hashes.get_recv_hash(i)),
+                    progress,
+                    concurrency,
+                )
+                .await;
+                for (i, c) in missing_recv.into_iter().zip(chunks) {
+                    Self::journal_chunk(journal.as_deref_mut(), true, i, &c).await;
+                    recv[i] = c;
+                }
+            }
+        }
+        let received = data.iter().chain(&recv).flatten().map(|c| c.value.len());
+        progress.add_bytes(received.sum());
+        Ok((data, recv))
+    }
+
+    async fn journal_chunk(
+        journal: Option<&mut RecvJournal>,
+        recovery: bool,
+        index: usize,
+        chunk: &Option<ReedSolomonChunk>,
+    ) {
+        // Запись полученного чанка в журнал. Ошибка записи не прерывает получение: чанк уже
+        // изъят сервером и нужен для восстановления файла, теряется лишь возможность продолжения
+        if let (Some(j), Some(c)) = (journal, chunk) {
+            if let Err(e) = j.record(recovery, index, &c.value).await {
+                eprintln!("Error writing chunk {} to receive journal: {}", index, e);
+            }
+        }
+    }
+}
+
+impl Chunks<ReedSolomonChunksHashes> for ReedSolomonChunks {
+    async fn into_file(self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let content = self.into_bytes()?; // Восстановление данных
+        if path.as_ref() == Path::new(STDOUT_PATH) {
+            let mut stdout = io::stdout(); // Запись "как есть", без преобразования переводов строк
+            stdout.write_all(&content).await?;
+            stdout.flush().await?;
+        } else {
+            fs::write(path, content).await?; // Запись в файл
+        }
+
+        Ok(())
+    }
+
+    fn encrypt(&mut self, encryptor: &dyn Encryptor) -> Result<(), Box<dyn Error>> {
+        // Шифрование, как и дешифрование и хэширование, выполняется в пуле потоков шифрования
+        let dedup = self.dedup;
+        pool::run_crypto(|| {
+            self.par_chunks_mut()
+                .try_for_each(|c| encrypt_checked(c, encryptor, dedup))
+        })?;
+        Ok(())
+    }
+
+    fn decrypt(&mut self, decryptor: &dyn Encryptor) -> Result<(), Box<dyn Error>> {
+        pool::run_crypto(|| {
+            self.par_chunks_mut().try_for_each(|c| c.decrypt(decryptor)) // Дешифрование
+        })?;
+        Ok(())
+    }
+
+    fn update_hashes(&mut self, hasher: &dyn Hasher) -> Result<(), Box<dyn Error>> {
+        pool::run_crypto(|| {
+            self.par_chunks_mut().for_each(|c| {
+                c.hash = Some(c.hashed(hasher));
+                // Обновление хэшей
+            })
+        });
+        Ok(())
+    }
+
+    async fn send(self, progress: &Progress) -> Result<ReedSolomonChunksHashes, Box<dyn Error>> {
+        let (hashes, results) = self.send_all(progress, true).await?;
+        match results.into_iter().find_map(|r| r.error) {
+            Some(e) => Err(Box::new(SendingChunkError(e))),
+            None => Ok(hashes),
+        }
+    }
+}
+
+pub trait ChunksHashes<H> {
+    fn save_to(self, path: impl AsRef<Path>) -> impl Future<Output = Result<(), Box<dyn Error>>>; // Сохранение метаданных
+    fn load_from(path: impl AsRef<Path>) -> impl Future<Output = Result<Self, Box<dyn Error>>>
+    where
+        Self: Sized; // Чтение метаданных
+    fn len(&self) -> usize; // Количество чанков
+    fn get_data_hash(&self, index: usize) -> H; // Получение данных
+    fn get_recv_hash(&self, index: usize) -> H; // Получение восстановительных данных
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReedSolomonChunksHashes {
+    data: Vec<ReedSolomonChunkHash>,
+    recv: Vec<ReedSolomonChunkHash>,
+    #[serde(default)]
+    backend: Backend, // Реализация кода Рида-Соломона (в старых метаданных отсутствует)
+    #[serde(default)]
+    stats: ChunksStats, // Статистика хранения (в старых метаданных отсутствует)
+    #[serde(default)]
+    dedup: bool, // Одинаковые чанки отправлены один раз (в старых метаданных отсутствует)
+    #[serde(default)]
+    chunker: Chunker, // Способ разбиения файла на блоки (в старых метаданных отсутствует)
+    #[serde(default)]
+    source_attrs: Option<SourceAttrs>, // Атрибуты исходного файла (только при их сохранении)
+    #[serde(default)]
+    key_material: Option<FileKeyMaterial>, // Параметры шифрования файла паролем (только в переносимом режиме)
+    #[serde(default)]
+    algorithms: Option<Algorithms>, // Реализации шифрования и хэширования (только при отправке через набор реализаций)
+    #[serde(default)]
+    tag: Option<String>, // Тег аутентификации списка чанков ключом шифровальщика (в старых метаданных отсутствует)
+    #[serde(skip)]
+    format: ManifestFormat, // Формат файла метаданных (при загрузке - формат прочитанного файла)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ManifestFormat {
+    // Формат файла метаданных
+    #[default]
+    Binary, // JSON в Base64: компактная запись одной строкой
+    Json, // Форматированный JSON: удобен для просмотра, сравнения версий и хранения в git
+}
+
+impl ReedSolomonChunksHashes {
+    pub fn stats(&self) -> &ChunksStats {
+        &self.stats // Получение статистики хранения файла
+    }
+
+    pub fn erasure_params(&self) -> ErasureParams {
+        ErasureParams {
+            backend: self.backend,
+            chunker: self.chunker,
+            original_size: self.stats.original_size,
+            data_chunks: self.data.len(),
+            recovery_chunks: self.recv.len(),
+            chunk_size: self.data.iter().map(|h| h.size).max().unwrap_or(0),
+        }
+    }
+
+    pub fn params_missing(&self) -> bool {
+        // Утеряны ли параметры кода в метаданных: поля со значениями по умолчанию не сходятся
+        // со списками чанков
+        !self.data.is_empty() && self.stats.data_chunks != self.data.len()
+    }
+
+    pub fn apply_erasure_params(
+        &mut self,
+        params: &ErasureParams,
+    ) -> Result<(), ErasureParamsMismatchError> {
+        // Восстановление параметров кода из заголовка. Заголовок должен относиться к этим же
+        // чанкам: количество чанков и размер наибольшего из них совпадают
+        let own = self.erasure_params();
+        if (own.data_chunks, own.recovery_chunks, own.chunk_size)
+            != (
+                params.data_chunks,
+                params.recovery_chunks,
+                params.chunk_size,
+            )
+        {
+            return Err(ErasureParamsMismatchError);
+        }
+        self.backend = params.backend;
+        self.chunker = params.chunker;
+        self.stats.original_size = params.original_size;
+        self.stats.data_chunks = params.data_chunks;
+        self.stats.recovery_chunks = params.recovery_chunks;
+        self.stats.stored_size = self.data.iter().chain(&self.recv).map(|h| h.size).sum();
+        Ok(())
+    }
+
+    pub fn source_attrs(&self) -> Option<&SourceAttrs> {
+        self.source_attrs.as_ref() // Получение сохраненных атрибутов исходного файла
+    }
+
+    pub fn set_source_attrs(&mut self, attrs: SourceAttrs) {
+        self.source_attrs = Some(attrs);
+    }
+
+    pub fn algorithms(&self) -> Option<&Algorithms> {
+        self.algorithms.as_ref() // Получение описания реализаций, использованных при отправке
+    }
+
+    pub fn set_algorithms(&mut self, algorithms: Algorithms) {
+        self.algorithms = Some(algorithms);
+    }
+
+    #[cfg(feature = "gost")]
+    pub fn key_material(&self) -> Option<&FileKeyMaterial> {
+        self.key_material.as_ref() // Получение параметров шифрования файла паролем
+    }
+
+    #[cfg(feature = "gost")]
+    pub fn set_key_material(&mut self, material: FileKeyMaterial) {
+        self.key_material = Some(material);
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(serde_json::to_vec(self)?) // Сериализация метаданных
+    }
+
+    pub fn set_format(&mut self, format: ManifestFormat) {
+        self.format = format; // Формат, в котором метаданные будут сохранены
+    }
+
+    pub fn tag_message(&self) -> Vec<u8> {
+        tag_message(self.stats.original_size, [&self.data, &self.recv])
+    }
+
+    pub fn attach_tag(&mut self, encryptor: &dyn Encryptor) {
+        // Заверение списка чанков тегом, если шифровальщик вычисляет теги
+        self.tag = encryptor.authenticate(&self.tag_message()).map(hex::encode);
+    }
+
+    pub fn set_tag(&mut self, tag: Option<String>) {
+        self.tag = tag; // Тег, вычисленный заранее (при подготовке отправки)
+    }
+
+    pub fn verify_tag(
+        &self,
+        encryptor: &dyn Encryptor,
+        allow_untagged: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        // Проверка тега до получения чанков: метаданные с подмененными ссылками на чанки (каждый
+        // из которых по отдельности соответствует своему хэшу) отвергаются. Метаданные без тега
+        // отвергаются так же: иначе подмененный список достаточно было бы сохранить без тега
+        check_tag(
+            encryptor,
+            &self.tag_message(),
+            self.tag.as_deref(),
+            allow_untagged,
+        )
+    }
+
+    fn shared_hashes(&self) -> HashSet<String> {
+        // Хэши, на которые ссылаются несколько индексов (только в режиме dedup: иначе повторы хранятся отдельными копиями)
+        if !self.dedup {
+            return HashSet::new();
+        }
+        let mut seen = HashSet::new();
+        self.data
+            .iter()
+            .chain(&self.recv)
+            .filter(|h| !seen.insert(&h.value))
+            .map(|h| h.get_value())
+            .collect()
+    }
+
+    pub fn from_bytes(content: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(content)?) // Десериализация метаданных
+    }
+}
+
+fn content_digest(encryptor: &dyn Encryptor, content: &[u8]) -> Option<String> {
+    // Дайджест открытого содержимого чанка: тег шифровальщика с отдельным префиксом, чтобы
+    // дайджест чанка нельзя было выдать за тег метаданных. Без ключа содержимое не проверить
+    let message = [CONTENT_DIGEST_PREFIX, content].concat();
+    encryptor.authenticate(&message).map(hex::encode)
+}
+
+pub fn check_tag(
+    encryptor: &dyn Encryptor,
+    message: &[u8],
+    tag: Option<&str>,
+    allow_untagged: bool,
+) -> Result<(), Box<dyn Error>> {
+    // Проверка тега метаданных (файла, индекса сегментов или каталога) над данными message.
+    // Метаданные, отправленные до появления тегов, принимаются с предупреждением только при allow_untagged
+    let Some(expected) = encryptor.authenticate(message) else {
+        return Ok(()); // Шифровальщик не вычисляет теги
+    };
+    let Some(tag) = tag else {
+        if !allow_untagged {
+            return Err(Box::new(UntaggedManifestError));
+        }
+        eprintln!("Warning: metadata has no authentication tag, chunk list is not verified");
+        return Ok(());
+    };
+    let tag = hex::decode(tag).map_err(|_| ManifestTagError)?;
+    // Сравнение за постоянное время: время проверки не выдает совпавшую часть тега
+    let diff = tag.iter().zip(&expected).fold(0, |d, (a, b)| d | (a ^ b));
+    match tag.len() == expected.len() && diff == 0 {
+        true => Ok(()),
+        false => Err(Box::new(ManifestTagError)),
+    }
+}
+
+fn tag_message(original_size: usize, lists: [&[ReedSolomonChunkHash]; 2]) -> Vec<u8> {
+    // Данные, заверяемые тегом: размер файла и упорядоченные хэши и размеры всех чанков.
+    // Длины списков и хэшей включены, чтобы разные списки не давали одинаковых данных
+    let mut message = (original_size as u64).to_be_bytes().to_vec();
+    for list in lists {
+        message.extend((list.len() as u64).to_be_bytes());
+        for h in list {
+            message.extend((h.value.len() as u64).to_be_bytes());
+            message.extend(h.value.as_bytes());
+            message.extend((h.size as u64).to_be_bytes());
+        }
+    }
+    message
+}
+
+impl ChunksHashes<ReedSolomonChunkHash> for ReedSolomonChunksHashes {
+    async fn save_to(self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let data = match self.format {
+            ManifestFormat::Binary => BASE64.encode(serde_json::to_vec(&self)?).into_bytes(), // Сериализация
+            ManifestFormat::Json => serde_json::to_vec_pretty(&self)?,
+        };
+        write_replacing(path, &data).await // Запись в файл
+    }
+
+    async fn load_from(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let (content, format) = decode_manifest(fs::read(path).await?)?; // Чтение из файла
+        let mut obj: ReedSolomonChunksHashes = serde_json::from_slice(&content)?; // Десериализация
+        obj.format = format; // Метаданные, измененные после загрузки, сохраняются в прежнем формате
+        Ok(obj)
+    }
+
+    fn len(&self) -> usize {
+        self.data.len() // Количество чанков одинаково
+    }
+
+    fn get_data_hash(&self, index: usize) -> ReedSolomonChunkHash {
+        self.data[index].clone() // Получение хэша по индексу
+    }
+
+    fn get_recv_hash(&self, index: usize) -> ReedSolomonChunkHash {
+        self.recv[index].clone() // Получение хэша по индексу
+    }
+}
+
+mod errors {
+    // Модуль с составными ошибками
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::{Display, Formatter};
+
+    #[derive(Debug, Clone)]
+    pub struct SendingChunkError(pub String); // Ошибка отправки данных
+
+    impl Display for SendingChunkError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "Error sending chunk: {}", self.0)
+        }
+    }
+
+    impl Error for SendingChunkError {}
+
+    #[derive(Debug, Clone)]
+    pub struct ReceivingChunkError(pub String); // Ошибка получения данных
+
+    impl Display for ReceivingChunkError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "Error receiving chunk: {}", self.0)
+        }
+    }
+
+    impl Error for ReceivingChunkError {}
+
+    #[derive(Debug, Clone)]
+    pub struct DeadlineExceededError {
+        // Ошибка истечения времени, отведенного на всю операцию
+        pub completed: usize, // Количество обработанных чанков
+        pub pending: usize,   // Количество необработанных чанков
+    }
+
+    impl Display for DeadlineExceededError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Operation deadline exceeded: {} chunks completed, {} pending",
+                self.completed, self.pending
+            )
+        }
+    }
+
+    impl Error for DeadlineExceededError {}
+
+    #[derive(Debug, Clone)]
+    pub struct ChunkHashMismatchError {
+        // Ошибка проверки полученного чанка: хэш-сумма содержимого не совпадает с метаданными
+        pub index: usize,   // Индекс чанка
+        pub recovery: bool, // Является ли чанк восстановительным
+    }
+
+    impl Display for ChunkHashMismatchError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            let kind = match self.recovery {
+                true => "recovery",
+                false => "data",
+            };
+            write!(f, "Hash mismatch in received {} chunk {}", kind, self.index)
+        }
+    }
+
+    impl Error for ChunkHashMismatchError {}
+
+    #[derive(Debug, Clone)]
+    pub struct ManifestTooLargeError(pub usize, pub usize); // Ошибка превышения размера списка чанков в метаданных: размер и ограничение
+
+    impl Display for ManifestTooLargeError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "File metadata would take {} bytes, at most {} allowed: increase the chunk size or send the file in segments (their metadata is stored in the domain)",
+                self.0, self.1
+            )
+        }
+    }
+
+    impl Error for ManifestTooLargeError {}
+
+    #[derive(Debug, Clone)]
+    pub struct ManifestTagError; // Ошибка проверки тега метаданных: список чанков изменен или ключ неверен
+
+    impl Display for ManifestTagError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Metadata authentication tag does not match: chunk list was modified or the key is wrong"
+            )
+        }
+    }
+
+    impl Error for ManifestTagError {}
+
+    #[derive(Debug, Clone)]
+    pub struct UntaggedManifestError; // Ошибка метаданных без тега при шифровальщике, вычисляющем теги
+
+    impl Display for UntaggedManifestError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Metadata has no authentication tag; metadata sent before tags is accepted only when explicitly allowed"
+            )
+        }
+    }
+
+    impl Error for UntaggedManifestError {}
+
+    #[derive(Debug, Clone)]
+    pub struct ErasureParamsMismatchError; // Ошибка заголовка с параметрами кода, относящегося к другому списку чанков
+
+    impl Display for ErasureParamsMismatchError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Erasure header does not match the chunk list of the file"
+            )
+        }
+    }
+
+    impl Error for ErasureParamsMismatchError {}
+
+    #[derive(Debug, Clone)]
+    pub struct InsufficientRecoveryError(pub usize); // Ошибка нехватки полученных чанков для восстановления файла
+
+    impl Display for InsufficientRecoveryError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Not enough chunks received to recover the file: {} chunks short",
+                self.0
+            )
+        }
+    }
+
+    impl Error for InsufficientRecoveryError {}
+
+    #[derive(Debug, Clone)]
+    pub struct SourcePortBusyError(pub u16); // Ошибка занятости фиксированного порта клиента
+
+    impl Display for SourcePortBusyError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Source port {} is already in use (another transfer may be running)",
+                self.0
+            )
+        }
+    }
+
+    impl Error for SourcePortBusyError {}
+
+    #[derive(Debug, Clone)]
+    pub struct MetaTooLargeError(pub usize); // Ошибка превышения размера метаданных чанка
+
+    impl Display for MetaTooLargeError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Chunk metadata is {} bytes, at most {} allowed",
+                self.0,
+                crate::message::MAX_META_SIZE
+            )
+        }
+    }
+
+    impl Error for MetaTooLargeError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prepared::PreparedUpload;
+
+    struct LenHasher; // Хэш-вычислитель для проверки: хэш - сумма байтов чанка
+
+    impl Hasher for LenHasher {
+        fn calc_hash(&self, chunk: &[u8]) -> String {
+            chunk.iter().map(|&b| b as u64).sum::<u64>().to_string()
+        }
+    }
+
+    fn manifest(chunks: &ReedSolomonChunks) -> ReedSolomonChunksHashes {
+        // Метаданные чанков с вычисленными хэшами без отправки в домен
+        let hash = |x: &Vec<Option<ReedSolomonChunk>>| -> Vec<ReedSolomonChunkHash> {
+            x.iter()
+                .flatten()
+                .map(|c| c.hash.clone().unwrap())
+                .collect()
+        };
+        ReedSolomonChunksHashes {
+            data: hash(&chunks.data),
+            recv: hash(&chunks.recv),
+            backend: chunks.backend,
+            stats: chunks.stats(),
+            dedup: false,
+            chunker: chunks.chunker,
+            source_attrs: None,
+            key_material: None,
+            algorithms: None,
+            tag: None,
+            format: ManifestFormat::default(),
+        }
+    }
+
+    #[test]
+    fn verify_reports_lowest_mismatching_chunk() {
+        let content: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16).unwrap();
+        chunks.update_hashes(&LenHasher).unwrap();
+        let hashes = manifest(&chunks);
+        assert!(hashes.len() > 100);
+        assert!(chunks.verify(&hashes, &LenHasher).is_ok());
+
+        for i in [90, 40] {
+            chunks.recv[i].as_mut().unwrap().value[0] ^= 1; // Повреждаем восстановительные чанки
+        }
+        chunks.data[40] = None; // Неполученный чанк не считается несовпадением
+        let err = chunks.verify(&hashes, &LenHasher).unwrap_err();
+        assert_eq!((err.index, err.recovery), (40, true));
+    }
+
+    struct SlowXor(Duration); // Шифровальщик для проверки: XOR с задержкой, имитирующей дорогое шифрование
+
+    impl Encryptor for SlowXor {
+        fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+            std::thread::sleep(self.0);
+            Ok(chunk.iter().map(|b| b ^ 0x5a).collect())
+        }
+
+        fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, crate::crypto::DecryptionError> {
+            Ok(chunk.iter().map(|b| b ^ 0x5a).collect())
+        }
+    }
+
+    struct Tagging(u8); // Шифровальщик для проверки тегов: тег - простая сумма данных с ключом
+
+    impl Encryptor for Tagging {
+        fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+            Ok(chunk.to_vec())
+        }
+
+        fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, crate::crypto::DecryptionError> {
+            Ok(chunk.to_vec())
+        }
+
+        fn authenticate(&self, message: &[u8]) -> Option<Vec<u8>> {
+            let sum = message.iter().fold(self.0 as u64, |s, &b| {
+                s.wrapping_mul(31).wrapping_add(b as u64)
+            });
+            Some(sum.to_be_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn oversized_manifest_is_rejected_before_sending() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16).unwrap();
+        let estimated = chunks.check_manifest_size(&FnvHasher, 0).unwrap_err(); // Хэши еще не вычислены
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let exact = chunks.check_manifest_size(&FnvHasher, 0).unwrap_err();
+        assert_eq!(estimated.0, exact.0); // Хэши одного вычислителя имеют одинаковую длину
+        assert!(exact.0 <= manifest(&chunks).to_bytes().unwrap().len());
+
+        assert!(chunks.check_manifest_size(&FnvHasher, exact.0).is_ok());
+        let err = chunks
+            .check_manifest_size(&FnvHasher, exact.0 - 1)
+            .unwrap_err();
+        assert_eq!(err.1, exact.0 - 1);
+        assert!(err.to_string().contains("increase the chunk size"));
+        assert!(chunks
+            .check_manifest_size(&FnvHasher, SendOptions::default().max_manifest_size)
+            .is_ok());
+    }
+
+    #[test]
+    fn swapped_chunk_references_fail_tag_verification() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16).unwrap();
+        chunks.update_hashes(&LenHasher).unwrap();
+        let mut hashes = manifest(&chunks);
+        hashes.attach_tag(&Tagging(1));
+        assert!(hashes.verify_tag(&Tagging(1), false).is_ok());
+        assert!(hashes.verify_tag(&Tagging(2), false).is_err()); // Другой ключ
+
+        let restored = ReedSolomonChunksHashes::from_bytes(&hashes.to_bytes().unwrap()).unwrap();
+        assert!(restored.verify_tag(&Tagging(1), false).is_ok()); // Тег сохраняется в метаданных
+
+        hashes.data.swap(0, 1); // Подмена ссылок на чанки
+        assert!(hashes.verify_tag(&Tagging(1), true).is_err());
+        hashes.tag = None; // Метаданные без тега принимаются только явно
+        let err = hashes.verify_tag(&Tagging(1), false).unwrap_err();
+        assert!(err.is::<UntaggedManifestError>());
+        assert!(hashes.verify_tag(&Tagging(1), true).is_ok());
+        assert!(hashes.verify_tag(&SlowXor(Duration::ZERO), false).is_ok()); // Шифровальщик без тегов
+    }
+
+    #[tokio::test]
+    async fn manifest_format_is_detected_on_load() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16).unwrap();
+        chunks.update_hashes(&LenHasher).unwrap();
+        let path = std::env::temp_dir().join(format!("leaf-manifest-{}", rand::random::<u64>()));
+
+        let mut hashes = manifest(&chunks);
+        hashes.set_format(ManifestFormat::Json);
+        hashes.clone().save_to(&path).await.unwrap();
+        let text = String::from_utf8(fs::read(&path).await.unwrap()).unwrap();
+        assert!(text.starts_with("{\n")); // Форматированный JSON без Base64
+        let loaded = ReedSolomonChunksHashes::load_from(&path).await.unwrap();
+        assert_eq!(loaded.format, ManifestFormat::Json);
+        assert_eq!(loaded.to_bytes().unwrap(), hashes.to_bytes().unwrap());
+
+        hashes.set_format(ManifestFormat::Binary);
+        hashes.clone().save_to(&path).await.unwrap();
+        let loaded = ReedSolomonChunksHashes::load_from(&path).await.unwrap();
+        assert_eq!(loaded.format, ManifestFormat::Binary);
+        assert_eq!(loaded.to_bytes().unwrap(), hashes.to_bytes().unwrap());
+        fs::remove_file(&path).await.unwrap();
+    }
+
+    #[test]
+    fn erasure_header_restores_lost_manifest_params() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes_chunked(
+            content,
+            Backend::preferred(),
+            16,
+            Chunker::ContentDefined,
+            reed_solomon::DEFAULT_MAX_CHUNKS,
+        )
+        .unwrap();
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let mut hashes = manifest(&chunks);
+        hashes.attach_tag(&Tagging(3));
+        let header = hashes.erasure_params();
+        assert!(!hashes.params_missing());
+
+        // Метаданные без параметров кода: поля получают значения по умолчанию
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&hashes.to_bytes().unwrap()).unwrap();
+        for field in ["backend", "stats", "chunker"] {
+            json.as_object_mut().unwrap().remove(field);
+        }
+        let mut damaged =
+            ReedSolomonChunksHashes::from_bytes(&serde_json::to_vec(&json).unwrap()).unwrap();
+        assert!(damaged.params_missing());
+        assert_ne!(damaged.erasure_params(), header);
+        assert!(damaged.verify_tag(&Tagging(3), false).is_err()); // Размер файла входит в тег
+
+        let foreign = ErasureParams {
+            data_chunks: header.data_chunks + 1,
+            ..header
+        };
+        assert!(damaged.apply_erasure_params(&foreign).is_err());
+        damaged.apply_erasure_params(&header).unwrap();
+        assert_eq!(damaged.erasure_params(), header);
+        assert!(!damaged.params_missing());
+        assert!(damaged.verify_tag(&Tagging(3), false).is_ok());
+    }
+
+    #[tokio::test]
+    async fn diff_reports_only_modified_regions() {
+        let mut state = 7u32;
+        let content: Vec<u8> = (0..300_000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("leaf-diff-{}", rand::random::<u64>()));
+        fs::write(&path, &content).await.unwrap();
+        let mut chunks = ReedSolomonChunks::from_file_chunked(
+            &path,
+            &Tagging(0),
+            Chunker::ContentDefined,
+            reed_solomon::DEFAULT_MAX_CHUNKS,
+        )
+        .await
+        .unwrap();
+        chunks.encrypt(&Tagging(0)).unwrap();
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let hashes = manifest(&chunks);
+
+        let diff = ReedSolomonChunks::diff(&path, &hashes, &Tagging(0), &FnvHasher)
+            .await
+            .unwrap();
+        assert!(diff.is_identical());
+        assert_eq!(diff.local_chunks, hashes.len());
+
+        // Изменение нескольких байтов в середине файла затрагивает только содержащий их участок
+        let mut modified = content.clone();
+        modified[150_000..150_010].fill(0);
+        fs::write(&path, &modified).await.unwrap();
+        let diff = ReedSolomonChunks::diff(&path, &hashes, &Tagging(0), &FnvHasher)
+            .await
+            .unwrap();
+        fs::remove_file(&path).await.unwrap();
+        assert!(!diff.is_identical());
+        assert!(!diff.changed.is_empty() && diff.changed.len() < diff.local_chunks);
+        assert!(diff.removed >= 1);
+        let first = diff.changed.first().unwrap();
+        let last = diff.changed.last().unwrap();
+        assert!(first.offset <= 150_000 && last.offset + last.len >= 150_010);
+    }
+
+    #[cfg(feature = "gost")]
+    #[tokio::test]
+    async fn diff_with_kuznechik_compares_content() {
+        use crate::crypto::hash::streebog::StreebogHasher;
+        use crate::crypto::KuznechikEncryptor;
+
+        let (encryptor, _) = KuznechikEncryptor::with_password(b"secret").await.unwrap();
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let path = std::env::temp_dir().join(format!("leaf-diff-{}", rand::random::<u64>()));
+        fs::write(&path, &content).await.unwrap();
+        let mut chunks = ReedSolomonChunks::from_file_chunked(
+            &path,
+            &encryptor,
+            Chunker::Fixed,
+            reed_solomon::DEFAULT_MAX_CHUNKS,
+        )
+        .await
+        .unwrap();
+        chunks.encrypt(&encryptor).unwrap();
+        chunks.update_hashes(&StreebogHasher).unwrap();
+        let hashes =
+            ReedSolomonChunksHashes::from_bytes(&manifest(&chunks).to_bytes().unwrap()).unwrap(); // Дайджесты сохраняются в метаданных
+
+        // Повторное шифрование дает другие векторы и хэши, но не другие дайджесты
+        let diff = ReedSolomonChunks::diff(&path, &hashes, &encryptor, &StreebogHasher)
+            .await
+            .unwrap();
+        assert!(diff.is_identical());
+
+        let mut modified = content.clone();
+        modified[50_000] ^= 1;
+        fs::write(&path, &modified).await.unwrap();
+        let diff = ReedSolomonChunks::diff(&path, &hashes, &encryptor, &StreebogHasher)
+            .await
+            .unwrap();
+        fs::remove_file(&path).await.unwrap();
+        assert_eq!(diff.changed.len(), 1);
+        let changed = &diff.changed[0];
+        assert!(changed.offset <= 50_000 && changed.offset + changed.len > 50_000);
+    }
+
+    async fn reply_server(
+        reply: impl FnMut(Message) -> Option<Message> + Send + 'static,
+    ) -> SocketAddr {
+        reply_server_on("127.0.0.1:0", reply).await
+    }
+
+    async fn reply_server_on(
+        bind: &str,
+        mut reply: impl FnMut(Message) -> Option<Message> + Send + 'static,
+    ) -> SocketAddr {
+        // Сервер для проверки: на каждое сообщение отвечает результатом reply (None - без ответа)
+        let socket = UdpSocket::bind(bind).await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                let Some(r) = Message::from_slice(&buf[..sz]).ok().and_then(&mut reply) else {
+                    continue;
+                };
+                let _ = socket.send_to(&r.into_bytes().unwrap(), from).await;
+            }
+        });
+        addr
+    }
+
+    async fn fake_server() -> SocketAddr {
+        // Сервер для проверки: подтверждает любой запрос на отправку и сохранение любого чанка
+        reply_server(|m| match m {
+            Message::SendingReq(h, t) => Some(Message::SendingAck(h, u64::MAX, t)),
+            Message::ContentFilled(h, _, t) => Some(Message::ContentStored(h, t)),
+            _ => None,
+        })
+        .await
+    }
+
+    struct FnvHasher; // Хэш-вычислитель для проверки без совпадений хэшей разных чанков
+
+    impl Hasher for FnvHasher {
+        fn calc_hash(&self, chunk: &[u8]) -> String {
+            let hash = chunk.iter().fold(0xcbf29ce484222325u64, |h, &b| {
+                (h ^ b as u64).wrapping_mul(0x100000001b3)
+            });
+            format!("{:016x}", hash)
+        }
+    }
+
+    async fn storing_server(
+        chunks: &ReedSolomonChunks,
+    ) -> (SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        // Сервер для проверки: как и настоящий, выдает каждый чанк только один раз.
+        // Возвращает адрес и счетчик выданных чанков
+        let mut stored: HashMap<String, Vec<u8>> = chunks
+            .data
+            .iter()
+            .chain(&chunks.recv)
+            .flatten()
+            .map(|c| (c.hash.as_ref().unwrap().get_value(), c.value.clone()))
+            .collect();
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+        let addr = reply_server(move |m| {
+            let Message::RetrievingReq(h) = m else {
+                return None;
+            };
+            let d = stored.remove(&h)?;
+            counter.fetch_add(1, Ordering::Relaxed);
+            Some(Message::ContentFilled(h, d, 0))
+        })
+        .await;
+        (addr, served)
+    }
+
+    async fn loaded_server(free: u64) -> (SocketAddr, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        // Сервер для проверки: подтверждает сохранение с задержкой. Возвращает адрес,
+        // наибольшее количество одновременно сохраняемых чанков и количество сохраненных
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let addr = socket.local_addr().unwrap();
+        let (peak, stored) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (max, count) = (peak.clone(), stored.clone());
+        let active = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(async move {
+            let mut saving = HashSet::new();
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                match Message::from_slice(&buf[..sz]) {
+                    Ok(Message::SendingReq(h, t)) => {
+                        let ack = Message::SendingAck(h, free, t).into_bytes().unwrap();
+                        let _ = socket.send_to(&ack, from).await;
+                    }
+                    Ok(Message::ContentFilled(h, _, t)) if saving.insert(h.clone()) => {
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        max.fetch_max(now, Ordering::SeqCst);
+                        let (socket, active, count) =
+                            (socket.clone(), active.clone(), count.clone());
+                        tokio::spawn(async move {
+                            time::sleep(Duration::from_millis(30)).await;
+                            active.fetch_sub(1, Ordering::SeqCst);
+                            count.fetch_add(1, Ordering::SeqCst);
+                            let stored = Message::ContentStored(h, t).into_bytes().unwrap();
+                            let _ = socket.send_to(&stored, from).await;
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        });
+        (addr, peak, stored)
+    }
+
+    async fn lossy_server() -> SocketAddr {
+        // Сервер для проверки: первый запрос на отправку каждого чанка "теряется"
+        let mut dropped = HashSet::new();
+        reply_server(move |m| match m {
+            Message::SendingReq(h, _) if dropped.insert(h.clone()) => None,
+            Message::SendingReq(h, t) => Some(Message::SendingAck(h, u64::MAX, t)),
+            Message::ContentFilled(h, _, t) => Some(Message::ContentStored(h, t)),
+            _ => None,
+        })
+        .await
+    }
+
+    async fn domain_server() -> SocketAddr {
+        domain_server_on("127.0.0.1:0").await
+    }
+
+    async fn domain_server_on(bind: &str) -> SocketAddr {
+        // Сервер для проверки: сохраняет любой чанк и, как настоящий, выдает его только один раз
+        let mut stored: HashMap<String, Vec<u8>> = HashMap::new();
+        reply_server_on(bind, move |m| match m {
+            Message::SendingReq(h, t) => Some(Message::SendingAck(h, u64::MAX, t)),
+            Message::ContentFilled(h, d, t) => {
+                stored.insert(h.clone(), d);
+                Some(Message::ContentStored(h, t))
+            }
+            Message::RetrievingReq(h) => stored.remove(&h).map(|d| Message::ContentFilled(h, d, 0)),
+            _ => None,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn single_chunks_use_given_peers() {
+        // Чанки по ключу и корневые чанки отправляются и запрашиваются у серверов peers
+        let server = domain_server().await;
+        let send = SendOptions {
+            ack_window: Duration::from_millis(1),
+            peers: vec![server],
+            ..SendOptions::default()
+        };
+        let recv = RecvOptions {
+            peers: vec![server],
+            ..RecvOptions::default()
+        };
+        ReedSolomonChunk::send_keyed("notes", vec![1, 2, 3], &FnvHasher, &send)
+            .await
+            .unwrap();
+        let keyed = ReedSolomonChunk::recv_keyed("notes", &FnvHasher, &recv).await;
+        assert_eq!(keyed.unwrap(), [1, 2, 3]);
+        let root = ReedSolomonChunk::send_root(vec![4, 5], &FnvHasher, &send)
+            .await
+            .unwrap();
+        assert_eq!(
+            ReedSolomonChunk::recv_root(&root, &recv).await.unwrap(),
+            [4, 5]
+        );
+    }
+
+    #[tokio::test]
+    async fn single_chunks_follow_address_mode() {
+        // В режиме IPv6 чанки по ключу отправляются и запрашиваются через сокет IPv6
+        let mode = AddressMode::Ipv6Multicast("ff02::4c46".parse().unwrap());
+        let server = domain_server_on("[::1]:0").await;
+        let send = SendOptions {
+            ack_window: Duration::from_millis(1),
+            peers: vec![server],
+            address_mode: mode,
+            ..SendOptions::default()
+        };
+        let recv = RecvOptions {
+            peers: vec![server],
+            address_mode: mode,
+            ..RecvOptions::default()
+        };
+        ReedSolomonChunk::send_keyed("notes", vec![1, 2, 3], &FnvHasher, &send)
+            .await
+            .unwrap();
+        let keyed = ReedSolomonChunk::recv_keyed("notes", &FnvHasher, &recv).await;
+        assert_eq!(keyed.unwrap(), [1, 2, 3]);
+
+        let ipv4 = SendOptions {
+            peers: vec![fake_server().await],
+            ..send
+        };
+        let sent = ReedSolomonChunk::send_root(vec![4, 5], &FnvHasher, &ipv4).await;
+        assert!(sent.is_err()); // Сервер IPv4 в режиме IPv6
+    }
+
+    #[cfg(feature = "gost")]
+    #[tokio::test]
+    async fn repair_keeps_password_key_material() {
+        use crate::crypto::KuznechikEncryptor;
+
+        let server = domain_server().await;
+        let options = SendOptions {
+            ack_window: Duration::from_millis(1),
+            peers: vec![server],
+            ..SendOptions::default()
+        };
+        let (encryptor, material) = KuznechikEncryptor::with_password(b"secret").await.unwrap();
+        let content: Vec<u8> = (0..50_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content.clone(), Backend::preferred(), 16)
+            .unwrap()
+            .with_options(&options);
+        chunks.encrypt(&encryptor).unwrap();
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let mut hashes = chunks.send(&Progress::default()).await.unwrap();
+        hashes.set_key_material(material.clone());
+        hashes.set_format(ManifestFormat::Json);
+
+        let (repaired, _) = ReedSolomonChunks::repair(hashes, &encryptor, &FnvHasher, &options)
+            .await
+            .unwrap();
+        assert_eq!(repaired.key_material(), Some(&material));
+        assert!(repaired.verify_tag(&encryptor, false).is_ok()); // Новый список чанков заверен
+        assert_eq!(repaired.format, ManifestFormat::Json);
+
+        // Файл получается по паролю и параметрам из новых метаданных
+        let decryptor = KuznechikEncryptor::from_key_material(b"secret", &material)
+            .await
+            .unwrap();
+        let recv = RecvOptions {
+            peers: vec![server],
+            ..RecvOptions::default()
+        };
+        let mut received =
+            ReedSolomonChunks::recv_with_options(&repaired, &Progress::default(), &recv)
+                .await
+                .unwrap();
+        received.decrypt(&decryptor).unwrap();
+        assert_eq!(received.into_bytes().unwrap(), content);
+    }
+
+    #[cfg(feature = "gost")]
+    #[tokio::test]
+    async fn dedup_sends_repeated_chunks_once_with_kuznechik() {
+        use crate::crypto::hash::streebog::StreebogHasher;
+        use crate::crypto::KuznechikEncryptor;
+
+        let (encryptor, _) = KuznechikEncryptor::with_password(b"secret").await.unwrap();
+        let content = vec![42u8; 100_000]; // Все чанки данных одинаковы
+        let mut stored = Vec::new();
+        for dedup in [false, true] {
+            let (server, _, count) = loaded_server(u64::MAX).await;
+            let options = SendOptions {
+                ack_window: Duration::from_millis(1),
+                peers: vec![server],
+                dedup,
+                ..SendOptions::default()
+            };
+            let mut chunks =
+                ReedSolomonChunks::from_bytes(content.clone(), Backend::preferred(), 16)
+                    .unwrap()
+                    .with_options(&options);
+            chunks.encrypt(&encryptor).unwrap();
+            chunks.update_hashes(&StreebogHasher).unwrap();
+            let total = chunks.stats().data_chunks + chunks.stats().recovery_chunks;
+            let hashes = chunks.send(&Progress::default()).await.unwrap();
+            let unique: HashSet<&str> = hashes
+                .data
+                .iter()
+                .chain(&hashes.recv)
+                .map(|h| h.value.as_str())
+                .collect();
+            assert_eq!(hashes.data.len() + hashes.recv.len(), total); // Ссылки на все индексы сохраняются
+            assert_eq!(count.load(Ordering::SeqCst), unique.len());
+            let data: HashSet<&str> = hashes.data.iter().map(|h| h.value.as_str()).collect();
+            stored.push((unique.len(), data.len(), total));
+        }
+        // Со случайными векторами одинаковые чанки различаются, в режиме dedup - совпадают
+        // (последний чанк данных может отличаться дополнением)
+        assert_eq!(stored[0].0, stored[0].2);
+        assert!(stored[1].1 <= 2);
+        assert!(stored[1].0 < stored[1].2);
+    }
+
+    #[tokio::test]
+    async fn chunk_meta_is_requested_from_given_peers() {
+        // Сервер для проверки отвечает метаданными на любой запрос
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                if let Ok(Message::MetaReq(h)) = Message::from_slice(&buf[..sz]) {
+                    let reply = Message::MetaAck(h, b"text/plain".to_vec());
+                    let _ = socket.send_to(&reply.into_bytes().unwrap(), from).await;
+                }
+            }
+        });
+        let options = RecvOptions {
+            peers: vec![server],
+            ..RecvOptions::default()
+        };
+        let meta = ReedSolomonChunk::recv_meta("hash", &options).await.unwrap();
+        assert_eq!(meta, b"text/plain");
+
+        let ipv6 = RecvOptions {
+            address_mode: AddressMode::Ipv6Multicast("ff02::4c46".parse().unwrap()),
+            ..options
+        };
+        assert!(ReedSolomonChunk::recv_meta("hash", &ipv6).await.is_err()); // Сервер IPv4 в режиме IPv6
+    }
+
+    #[tokio::test]
+    async fn lost_sending_ack_is_retried_with_backoff() {
+        let retry = SendRetry {
+            max_retries: 2,
+            base_backoff: Duration::from_millis(50),
+        };
+        assert_eq!(retry.backoff(2), Duration::from_millis(200));
+
+        let content: Vec<u8> = (0..20_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for (max_retries, sent) in [(0, false), (2, true)] {
+            let options = SendOptions {
+                ack_window: Duration::from_millis(1),
+                peers: vec![lossy_server().await],
+                retry: SendRetry {
+                    max_retries,
+                    ..retry.clone()
+                },
+                ..SendOptions::default()
+            };
+            let mut chunks =
+                ReedSolomonChunks::from_bytes(content.clone(), Backend::preferred(), 16)
+                    .unwrap()
+                    .with_options(&options);
+            chunks.update_hashes(&FnvHasher).unwrap();
+            let total = chunks.stats().data_chunks + chunks.stats().recovery_chunks;
+            let progress = Progress::default();
+            assert_eq!(chunks.send(&progress).await.is_ok(), sent);
+            if sent {
+                assert_eq!(progress.retries(), total); // Каждый запрос отправлен повторно один раз
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn per_peer_cap_spreads_parallel_send() {
+        let (big, big_peak, big_stored) = loaded_server(u64::MAX).await;
+        let (small, small_peak, small_stored) = loaded_server(1 << 40).await;
+        let options = SendOptions {
+            ack_window: Duration::from_millis(20),
+            peers: vec![big, small],
+            send_concurrency: 8,
+            max_per_peer: 2,
+            ..SendOptions::default()
+        };
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16)
+            .unwrap()
+            .with_options(&options);
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let total = chunks.stats().data_chunks + chunks.stats().recovery_chunks;
+        chunks.send(&Progress::default()).await.unwrap();
+
+        // Без ограничения все чанки получил бы сервер с наибольшим свободным местом
+        assert!(big_peak.load(Ordering::SeqCst) <= 2);
+        assert!(small_peak.load(Ordering::SeqCst) <= 2);
+        assert!(small_stored.load(Ordering::SeqCst) > 0);
+        let stored = big_stored.load(Ordering::SeqCst) + small_stored.load(Ordering::SeqCst);
+        assert_eq!(stored, total);
+    }
+
+    #[tokio::test]
+    async fn interrupted_receive_resumes_from_journal() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut chunks =
+            ReedSolomonChunks::from_bytes(content.clone(), Backend::preferred(), 16).unwrap();
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let hashes = manifest(&chunks);
+        let (server, served) = storing_server(&chunks).await;
+        let options = RecvOptions {
+            peers: vec![server],
+            resume: true,
+            ..RecvOptions::default()
+        };
+        let output = std::env::temp_dir().join(format!("leaf-resume-{}", rand::random::<u64>()));
+
+        // Получение прерывается (будущее удаляется, как при завершении процесса) на середине
+        let progress = Progress::default();
+        let mut journal = RecvJournal::open(&output, &hashes).await.unwrap();
+        let halfway = async {
+            while progress.done() < hashes.len() / 2 {
+                time::sleep(Duration::from_millis(1)).await;
+            }
+        };
+        tokio::select! {
+            _ = ReedSolomonChunks::recv_with_journal(&hashes, &progress, &options, Some(&mut journal)) => {
+                panic!("receive finished before interruption")
+            }
+            _ = halfway => {}
+        }
+        drop(journal);
+        let before = served.load(Ordering::Relaxed);
+
+        // Повторный запуск: выданные сервером чанки берутся из журнала
+        let mut journal = RecvJournal::open(&output, &hashes).await.unwrap();
+        let received = ReedSolomonChunks::recv_with_journal(
+            &hashes,
+            &Progress::default(),
+            &options,
+            Some(&mut journal),
+        )
+        .await
+        .unwrap();
+        assert!(served.load(Ordering::Relaxed) - before < hashes.len()); // Запрошена только часть чанков
+        assert_eq!(received.into_bytes().unwrap(), content);
+        journal.finish().await.unwrap();
+        let mut part = output.into_os_string();
+        part.push(".part");
+        assert!(!Path::new(&part).exists());
+    }
+
+    async fn slow_data_server(
+        chunks: &ReedSolomonChunks,
+        delay: Duration,
+    ) -> (SocketAddr, Arc<std::sync::Mutex<HashMap<String, usize>>>) {
+        // Сервер для проверки: выдает каждый чанк один раз, чанки данных - с задержкой delay.
+        // Возвращает адрес и количество запросов каждого хэша
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let addr = socket.local_addr().unwrap();
+        let data: HashSet<String> = chunks
+            .data
+            .iter()
+            .flatten()
+            .map(|c| c.hash.as_ref().unwrap().get_value())
+            .collect();
+        let mut stored: HashMap<String, Vec<u8>> = chunks
+            .data
+            .iter()
+            .chain(&chunks.recv)
+            .flatten()
+            .map(|c| (c.hash.as_ref().unwrap().get_value(), c.value.clone()))
+            .collect();
+        let requests = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                let Ok(Message::RetrievingReq(h)) = Message::from_slice(&buf[..sz]) else {
+                    continue;
+                };
+                *counter.lock().unwrap().entry(h.clone()).or_insert(0) += 1;
+                let slow = if data.contains(&h) {
+                    delay
+                } else {
+                    Duration::ZERO
+                };
+                let Some(d) = stored.remove(&h) else {
+                    continue;
+                };
+                let reply = Message::ContentFilled(h, d, 0).into_bytes().unwrap();
+                let socket = socket.clone();
+                tokio::spawn(async move {
+                    time::sleep(slow).await;
+                    let _ = socket.send_to(&reply, from).await;
+                });
+            }
+        });
+        (addr, requests)
+    }
+
+    #[tokio::test]
+    async fn slow_data_chunks_are_replaced_by_recovering_ones() {
+        let content: Vec<u8> = (0..160u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut chunks =
+            ReedSolomonChunks::from_bytes(content.clone(), Backend::preferred(), 16).unwrap();
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let hashes = manifest(&chunks);
+        let (server, requests) = slow_data_server(&chunks, HEDGE_DELAY * 2).await;
+        let options = RecvOptions {
+            peers: vec![server],
+            ..RecvOptions::default()
+        };
+
+        let progress = Progress::default();
+        let received = ReedSolomonChunks::recv_with_journal(&hashes, &progress, &options, None)
+            .await
+            .unwrap();
+        assert_eq!(received.into_bytes().unwrap(), content);
+        assert_eq!(progress.total(), hashes.len()); // Каждый индекс учтен один раз
+        assert_eq!(progress.done(), hashes.len());
+        assert_eq!(progress.retries(), 0);
+        let recovering: usize = chunks.recv.iter().flatten().map(|c| c.value.len()).sum();
+        assert_eq!(progress.bytes(), recovering); // Файл собран только из восстановительных чанков
+        let requests = requests.lock().unwrap();
+        for i in 0..hashes.len() {
+            // Восстановительный чанк запрошен подстраховкой, ни один чанк не запрошен повторно
+            assert_eq!(requests.get(&hashes.get_data_hash(i).get_value()), Some(&1));
+            assert_eq!(requests.get(&hashes.get_recv_hash(i).get_value()), Some(&1));
+        }
+    }
+
+    async fn send_both_ways(
+        size: u32,
+        delay: Duration,
+    ) -> (
+        ReedSolomonChunksHashes,
+        Duration,
+        ReedSolomonChunksHashes,
+        Duration,
+    ) {
+        // Отправка одних и тех же данных последовательными проходами и конвейером
+        let options = SendOptions {
+            ack_window: Duration::from_millis(1),
+            peers: vec![fake_server().await],
+            ..SendOptions::default()
+        };
+        let content: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let chunks = || {
+            ReedSolomonChunks::from_bytes(content.clone(), Backend::preferred(), 16)
+                .unwrap()
+                .with_options(&options)
+        };
+
+        let started = time::Instant::now();
+        let mut sequential = chunks();
+        sequential.encrypt(&SlowXor(delay)).unwrap();
+        sequential.update_hashes(&LenHasher).unwrap();
+        let expected = sequential.send(&Progress::default()).await.unwrap();
+        let sequential = started.elapsed();
+
+        let started = time::Instant::now();
+        let progress = Progress::default();
+        let hashes = chunks()
+            .send_pipelined(Arc::new(SlowXor(delay)), Arc::new(LenHasher), &progress)
+            .await
+            .unwrap();
+        assert_eq!(progress.done(), progress.total());
+        (expected, sequential, hashes, started.elapsed())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn network_tasks_run_during_encryption() {
+        // Шифрование выполняется в задаче единственного потока tokio: если бы его задачи не
+        // передавались другому потоку, таймер не срабатывал бы до конца шифрования
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        let ticker = tokio::spawn(async move {
+            loop {
+                time::sleep(Duration::from_millis(1)).await;
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let encrypted = tokio::spawn(async move {
+            let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+            let mut chunks =
+                ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16).unwrap();
+            let during = ticks.load(Ordering::Relaxed);
+            chunks.encrypt(&SlowXor(Duration::from_millis(5))).unwrap();
+            ticks.load(Ordering::Relaxed) - during
+        });
+        let during = encrypted.await.unwrap();
+        ticker.abort();
+        assert!(during > 10, "only {} ticks during encryption", during);
+    }
+
+    #[tokio::test]
+    async fn pipelined_send_matches_sequential_send() {
+        let (expected, _, hashes, _) = send_both_ways(100_000, Duration::ZERO).await;
+        let values = |h: &ReedSolomonChunksHashes| -> Vec<String> {
+            h.data
+                .iter()
+                .chain(&h.recv)
+                .map(|c| c.get_value())
+                .collect()
+        };
+        assert_eq!(values(&hashes), values(&expected)); // Те же чанки в том же порядке
+        assert_eq!(hashes.stats, expected.stats);
+    }
+
+    #[tokio::test]
+    async fn prepared_upload_is_sent_without_encryptor() {
+        let base = std::env::temp_dir().join(format!("leaf-plan-{}", rand::random::<u64>()));
+        let (source, plan) = (base.with_extension("src"), base.with_extension("plan"));
+        let content: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&source, &content).unwrap();
+        let options = SendOptions {
+            ack_window: Duration::from_millis(1),
+            peers: vec![fake_server().await],
+            ..SendOptions::default()
+        };
+        let encryptor = Tagging(7);
+
+        // Отправка тех же чанков обычным способом
+        let mut chunks = ReedSolomonChunks::from_file_chunked(
+            &source,
+            &encryptor,
+            Chunker::Fixed,
+            reed_solomon::DEFAULT_MAX_CHUNKS,
+        )
+        .await
+        .unwrap()
+        .with_options(&options);
+        chunks.encrypt(&encryptor).unwrap();
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let mut expected = chunks.send(&Progress::default()).await.unwrap();
+        expected.attach_tag(&encryptor);
+
+        let prepared = PreparedUpload::prepare(&source, &options, &encryptor, &FnvHasher)
+            .await
+            .unwrap();
+        prepared.save_to(&plan).await.unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&plan).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600); // Зашифрованные данные доступны только владельцу
+        }
+        // План отправляется дважды (в разные домены) без шифровальщика
+        for _ in 0..2 {
+            let prepared = PreparedUpload::load_from(&plan).await.unwrap();
+            let hashes = prepared.send(&Progress::default(), &options).await.unwrap();
+            assert_eq!(hashes.to_bytes().unwrap(), expected.to_bytes().unwrap());
+            assert!(hashes.verify_tag(&encryptor, false).is_ok());
+        }
+        std::fs::write(&plan, b"not a plan").unwrap();
+        assert!(PreparedUpload::load_from(&plan).await.is_err());
+        std::fs::remove_file(&source).unwrap();
+        std::fs::remove_file(&plan).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "benchmark: cargo test -p leafcommon bench -- --ignored --nocapture"]
+    async fn bench_pipelined_send() {
+        // Шифрование 2 мс на чанк: при конвейере оно выполняется во время ожидания подтверждений
+        let (expected, sequential, _, pipelined) =
+            send_both_ways(1_000_000, Duration::from_millis(2)).await;
+        println!(
+            "{} chunks: sequential {:?}, pipelined {:?}",
+            expected.data.len() + expected.recv.len(),
+            sequential,
+            pipelined
+        );
+        assert!(pipelined < sequential);
+    }
+
+    #[cfg(feature = "gost")]
+    #[test]
+    #[ignore = "benchmark: cargo test -p leafcommon bench -- --ignored --nocapture"]
+    fn bench_parallel_verify() {
+        // Проверка хэш-сумм "Стрибога" всех чанков файла 16 Мб по очереди и параллельно
+        use crate::crypto::hash::streebog::StreebogHasher;
+
+        let content: Vec<u8> = (0..16_000_000u32).map(|i| (i % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16).unwrap();
+        chunks.update_hashes(&StreebogHasher).unwrap();
+        let hashes = manifest(&chunks);
+
+        let started = time::Instant::now();
+        for (chunk, hash) in chunks.data.iter().zip(&hashes.data) {
+            let chunk = chunk.as_ref().unwrap();
+            assert_eq!(StreebogHasher.calc_hash(&chunk.value), hash.get_value());
+        }
+        for (chunk, hash) in chunks.recv.iter().zip(&hashes.recv) {
+            let chunk = chunk.as_ref().unwrap();
+            assert_eq!(StreebogHasher.calc_hash(&chunk.value), hash.get_value());
+        }
+        let sequential = started.elapsed();
+
+        let started = time::Instant::now();
+        chunks.verify(&hashes, &StreebogHasher).unwrap();
+        let parallel = started.elapsed();
+        println!(
+            "{} chunks: sequential {:?}, parallel {:?}",
+            hashes.data.len() + hashes.recv.len(),
+            sequential,
+            parallel
+        );
+        if std::thread::available_parallelism().map_or(1, |n| n.get()) > 1 {
+            assert!(parallel < sequential); // На одном ядре выигрыша нет
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "benchmark: cargo test -p leafcommon bench -- --ignored --nocapture"]
+    async fn bench_concurrent_recv() {
+        // Сервер отвечает на каждый запрос через 2 мс, имитируя задержку сети
+        let content: Vec<u8> = (0..2_000_000u32).map(|i| (i % 251) as u8).collect();
+        let mut chunks = ReedSolomonChunks::from_bytes(content, Backend::preferred(), 16).unwrap();
+        chunks.update_hashes(&FnvHasher).unwrap();
+        let hashes = manifest(&chunks);
+        let stored: Arc<HashMap<String, Vec<u8>>> = Arc::new(
+            chunks
+                .data
+                .iter()
+                .chain(&chunks.recv)
+                .flatten()
+                .map(|c| (c.hash.as_ref().unwrap().get_value(), c.value.clone()))
+                .collect(),
+        );
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let server = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                let Ok(Message::RetrievingReq(h)) = Message::from_slice(&buf[..sz]) else {
+                    continue;
+                };
+                let (socket, stored) = (socket.clone(), stored.clone());
+                tokio::spawn(async move {
+                    time::sleep(Duration::from_millis(2)).await;
+                    if let Some(d) = stored.get(&h) {
+                        let reply = Message::ContentFilled(h, d.clone(), 0)
+                            .into_bytes()
+                            .unwrap();
+                        let _ = socket.send_to(&reply, from).await;
+                    }
+                });
+            }
+        });
+
+        let mut elapsed = Vec::new();
+        for concurrency in [1, 16] {
+            let options = RecvOptions {
+                concurrency,
+                peers: vec![server],
+                ..RecvOptions::default()
+            };
+            let started = time::Instant::now();
+            ReedSolomonChunks::recv_with_options(&hashes, &Progress::default(), &options)
+                .await
+                .unwrap();
+            elapsed.push(started.elapsed());
+        }
+        println!(
+            "{} chunks: K=1 {:?}, K=16 {:?}",
+            hashes.data.len() + hashes.recv.len(),
+            elapsed[0],
+            elapsed[1]
+        );
+        assert!(elapsed[1] < elapsed[0]);
+    }
 }
@@ -1,18 +1,33 @@
 #![allow(dead_code)]
 
-use std::path::PathBuf; // Зависимость стандартной библиотеки для использования структуры по работе с файловыми путями
+use std::error::Error; // Зависимость стандартной библиотеки для работы с трейтом ошибок
+use std::future::Future; // Зависимость стандартной библиотеки для работы с асинхронными операциями
+#[cfg(feature = "gost")]
+use std::path::{Path, PathBuf}; // Зависимость стандартной библиотеки для использования структуры по работе с файловыми путями
+#[cfg(feature = "gost")]
+use std::sync::OnceLock; // Однократно задаваемый каталог по умолчанию
 
 use argon2::Argon2; // Внешняя зависимость для создания ключа из гаммы, соли и пароля
+#[cfg(feature = "gost")]
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _}; // Внешняя зависимость для кодирования и декодирования по алгоритму Base64
-use kuznyechik::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+#[cfg(feature = "gost")]
+use kuznyechik::cipher::{BlockDecrypt, BlockEncrypt, KeyInit}; // Внешние зависимости для работы с симметричным шифром "Кузнечик (ГОСТ Р 34.12-2015)"
+#[cfg(feature = "gost")]
 use kuznyechik::{Block, Key, Kuznyechik};
+#[cfg(feature = "gost")]
 use rand::{rngs::OsRng, Rng}; // Внешняя зависимость для генерации псевдослучайных последовательностей
 use serde::{Deserialize, Serialize}; // Внешняя зависимость для сериализации и десериализации структур
-use tokio::fs; // Внешняя зависимость для асинхронной работы c файловой системой // Внешние зависимости для работы с симметричным шифром "Кузнечик (ГОСТ Р 34.12-2015)"
+#[cfg(feature = "gost")]
+use tokio::fs; // Внешняя зависимость для асинхронной работы c файловой системой
 
+#[cfg(feature = "gost")]
 use consts::*; // Внутренняя зависимость модуля констант
 use errors::*; // Внутренняя зависимость модуля для использования собственных типов ошибок
+pub use errors::{DecryptionError, EncryptionError}; // Ошибки, возвращаемые реализациями трейта шифровальщика
+#[cfg(feature = "gost")]
+pub use errors::{MissingKeyMaterialError, PasswordRequiredError}; // Ошибки работы с файлом, отправленным по паролю
 
+#[cfg(feature = "gost")]
 mod consts {
     #[cfg(target_os = "windows")]
     pub const HOME_DIR_VAR: &str = "USERPROFILE";
@@ -20,10 +35,46 @@ mod consts {
     #[cfg(target_os = "linux")]
     pub const HOME_DIR_VAR: &str = "HOME";
 
+    pub const LEAF_HOME_VAR: &str = "LEAF_HOME"; // Переменная окружения с каталогом приложения (приоритетнее домашнего каталога)
     pub const APP_DIR: &str = ".leaf";
     pub const METADATA_PATH: &str = "metadata.bin";
+    pub const TAG_KEY_LABEL: &[u8] = b"leaf-manifest-tag"; // Метка ключа тега метаданных: ключ не совпадает с ключом шифрования
+    pub const BLOCK_SIZE: usize = 16; // Размер блока "Кузнечика", 128 бит
+    pub const NONCE_SIZE: usize = 16; // Размер одноразового вектора, добавляемого в начало зашифрованного чанка
+    pub const MAC_SIZE: usize = 32; // Размер имитовставки HMAC по "Стрибогу", добавляемой в конец зашифрованного чанка
+    pub const MAC_KEY_LABEL: &[u8] = b"leaf-chunk-mac"; // Метка ключа имитовставок чанков: ключ не совпадает с ключами шифрования и тегов
+    pub const NONCE_KEY_LABEL: &[u8] = b"leaf-chunk-nonce"; // Метка ключа векторов детерминированного шифрования (режим dedup)
 }
 
+#[cfg(feature = "gost")]
+static DEFAULT_APP_DIR: OnceLock<PathBuf> = OnceLock::new(); // Каталог приложения, если домашний каталог не определен
+
+#[cfg(feature = "gost")]
+pub fn set_default_app_dir(path: impl Into<PathBuf>) -> bool {
+    // Задание каталога приложения на случай, когда ни LEAF_HOME, ни домашний каталог не определены
+    // (служебные учетные записи, минимальные контейнеры). Задается один раз до создания шифровальщика,
+    // возвращает false, если каталог уже был задан
+    DEFAULT_APP_DIR.set(path.into()).is_ok()
+}
+
+#[cfg(feature = "gost")]
+fn resolve_app_dir(
+    var: impl Fn(&str) -> Option<String>,
+    default: Option<&Path>,
+) -> Result<PathBuf, HomeDirError> {
+    // Определение каталога приложения: LEAF_HOME, затем домашний каталог пользователя, затем
+    // каталог по умолчанию. Пустые значения переменных окружения не учитываются
+    let var = |name: &str| var(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    if let Some(dir) = var(LEAF_HOME_VAR) {
+        return Ok(dir);
+    }
+    if let Some(home) = var(HOME_DIR_VAR) {
+        return Ok(home.join(APP_DIR));
+    }
+    default.map(Path::to_path_buf).ok_or(HomeDirError)
+}
+
+#[cfg(feature = "gost")]
 #[derive(Serialize, Deserialize)] // Использование сериализации и десериализации для данной структуры
 struct EncryptionMetadata {
     // Структура для хранения гаммы и соли для использования в шифровании "Кузнечиком"
@@ -32,22 +83,158 @@ struct EncryptionMetadata {
     token: Vec<u8>, // Закодированный по Base64 токен
 }
 
-pub trait Encryptor {
-    // Трейт для структур, реализующих шифрование
-    fn encrypt_chunk(&self, chunk: &[u8]) -> Vec<u8>; // Прототип метода шифрования массива данных
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FileKeyMaterial {
+    // Параметры шифрования отдельного файла, хранимые в его метаданных: файл расшифровывается
+    // на любой машине по паролю, без общего файла метаданных пользователя
+    pub salt: Vec<u8>,  // Соль для вычисления ключа из пароля
+    pub gamma: Vec<u8>, // Гамма, зашифрованная ключом, вычисленным из пароля
+}
+
+pub trait Encryptor: Sync {
+    // Трейт для структур, реализующих шифрование (чанки шифруются параллельно в пуле потоков)
+    fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError>; // Прототип метода шифрования массива данных
+    fn encrypt_chunk_deterministic(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.encrypt_chunk(chunk) // Шифрование, при котором одинаковые данные дают одинаковый шифротекст (для режима dedup)
+    }
     fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, DecryptionError>; // Прототип метода дешифрования массива данных
+    fn block_size(&self) -> usize {
+        1 // Размер блока шифра: данные такой кратности шифруются без дополнения
+    }
+    fn overhead(&self) -> usize {
+        0 // Количество байт, которое шифрование добавляет к каждому чанку (например, одноразовый вектор)
+    }
+    fn authenticate(&self, _message: &[u8]) -> Option<Vec<u8>> {
+        None // Тег аутентификации данных ключом шифровальщика (None - шифровальщик не вычисляет теги)
+    }
+}
+
+pub trait Hasher: Sync {
+    // Трейт для структур, реализующих вычисление хэш-сумм чанков
+    fn calc_hash(&self, chunk: &[u8]) -> String; // Прототип метода вычисления хэш-суммы в шестнадцатеричном виде
+}
+
+pub trait KeyProvider {
+    // Трейт источника ключа шифрования (локальное вычисление, KMS, HSM и т.п.)
+    fn get_key(&self, salt: &[u8]) -> impl Future<Output = Result<[u8; 32], Box<dyn Error>>>; // Прототип метода получения 256-битного ключа по соли
+}
+
+pub struct Argon2KeyProvider {
+    // Источник ключа по умолчанию: вычисление ключа из токена и соли по Argon2
+    token: Vec<u8>, // Токен из файла с метаданными
 }
 
+impl Argon2KeyProvider {
+    pub fn new(token: Vec<u8>) -> Self {
+        Argon2KeyProvider { token }
+    }
+}
+
+impl KeyProvider for Argon2KeyProvider {
+    async fn get_key(&self, salt: &[u8]) -> Result<[u8; 32], Box<dyn Error>> {
+        let config = Argon2::default(); // Создание конфигурации для создания ключа шифрования
+        let mut key = [0u8; 32]; // Создаем буфер для ключа
+        config
+            .hash_password_into(&self.token, salt, &mut key)
+            .map_err(|e| InitializationError(e.to_string()))?; // Создаем ключ и записываем его в буфер
+        Ok(key)
+    }
+}
+
+#[cfg(feature = "gost")]
 pub struct KuznechikEncryptor {
     // Структура, реализующая шифрование по ГОСТ Р 34.12-2015 "Кузнечик"
     cipher: Kuznyechik,     // Ключ шифрования
     gamma: Vec<u8>,         // Гамма для шифрования
     metadata_path: PathBuf, // Путь к файлу с метаданными
+    tag_key: [u8; 32],      // Ключ тегов метаданных файлов, производный от ключа шифрования
+    mac_key: [u8; 32],      // Ключ имитовставок чанков, производный от ключа шифрования
+    nonce_key: [u8; 32],    // Ключ векторов, вычисляемых по содержимому чанка в режиме dedup
 }
 
+#[cfg(feature = "gost")]
 impl KuznechikEncryptor {
     pub async fn new() -> Result<Self, InitializationError> {
-        // Метод инициализации полей структуры гаммой и ключом
+        // Метод инициализации полей структуры гаммой и ключом, вычисленным из токена метаданных
+        let (metadata_path, gamma, salt, token) = Self::init_metadata().await?;
+        Self::from_parts(metadata_path, gamma, &salt, &Argon2KeyProvider::new(token)).await
+    }
+
+    pub async fn with_key_provider(
+        provider: &impl KeyProvider,
+    ) -> Result<Self, InitializationError> {
+        // Метод инициализации с ключом из внешнего источника, гамма и соль по-прежнему берутся из метаданных
+        let (metadata_path, gamma, salt, _) = Self::init_metadata().await?;
+        Self::from_parts(metadata_path, gamma, &salt, provider).await
+    }
+
+    pub async fn with_password(
+        password: &[u8],
+    ) -> Result<(Self, FileKeyMaterial), InitializationError> {
+        // Метод создания шифратора отдельного файла: новые гамма и соль, ключ вычисляется из пароля.
+        // Возвращаемые параметры сохраняются в метаданных файла, общий файл метаданных не используется
+        let mut gamma = vec![0u8; 16];
+        let mut salt = vec![0u8; 32];
+        OsRng.fill(&mut gamma[..]);
+        OsRng.fill(&mut salt[..]);
+        let provider = Argon2KeyProvider::new(password.to_vec());
+        let encryptor = Self::from_parts(PathBuf::new(), gamma.clone(), &salt, &provider).await?;
+
+        let mut block = Block::clone_from_slice(&gamma);
+        encryptor.cipher.encrypt_block(&mut block); // Гамма шифруется ключом без наложения самой гаммы
+        let material = FileKeyMaterial {
+            salt,
+            gamma: block.to_vec(),
+        };
+        Ok((encryptor, material))
+    }
+
+    pub async fn from_key_material(
+        password: &[u8],
+        material: &FileKeyMaterial,
+    ) -> Result<Self, InitializationError> {
+        // Метод создания шифратора по паролю и параметрам шифрования из метаданных файла
+        if material.gamma.len() != 16 {
+            return Err(InitializationError(String::from(
+                "Invalid encrypted gamma length",
+            )));
+        }
+        let provider = Argon2KeyProvider::new(password.to_vec());
+        let mut encryptor =
+            Self::from_parts(PathBuf::new(), Vec::new(), &material.salt, &provider).await?;
+        let mut block = Block::clone_from_slice(&material.gamma);
+        encryptor.cipher.decrypt_block(&mut block);
+        encryptor.gamma = block.to_vec();
+        Ok(encryptor)
+    }
+
+    async fn from_parts(
+        metadata_path: PathBuf,
+        gamma: Vec<u8>,
+        salt: &[u8],
+        provider: &impl KeyProvider,
+    ) -> Result<Self, InitializationError> {
+        // Метод создания шифратора из гаммы и ключа, полученного от источника
+        let key = provider
+            .get_key(salt)
+            .await
+            .map_err(|e| InitializationError(e.to_string()))?;
+
+        let cipher_key = Key::from_slice(&key); // Создаем объект ключа шифрования из буфера
+        let cipher = Kuznyechik::new(cipher_key); // Создаем объект шифратора
+
+        Ok(Self {
+            cipher,
+            gamma,
+            metadata_path,
+            tag_key: hash::streebog::hmac(&key, TAG_KEY_LABEL),
+            mac_key: hash::streebog::hmac(&key, MAC_KEY_LABEL),
+            nonce_key: hash::streebog::hmac(&key, NONCE_KEY_LABEL),
+        }) // Создаем и возвращаем новый экземпляр структуры
+    }
+
+    async fn init_metadata() -> Result<(PathBuf, Vec<u8>, Vec<u8>, Vec<u8>), InitializationError> {
+        // Метод чтения метаданных (гамма, соль, токен) или их создания при первом запуске
         let metadata_path = Self::get_metadata_path().await?; // Получаем путь до файла с метаданными при помощи метода
 
         let (gamma, salt, token) = if metadata_path.exists() {
@@ -83,30 +270,18 @@ impl KuznechikEncryptor {
             (gamma, salt, token)
         };
 
-        let config = Argon2::default(); // Создание конфигурации для создания ключа шифрования
-        let mut key = vec![0u8; 32]; // Создаем буфер для ключа
-        config
-            .hash_password_into(&token, &salt, &mut key)
-            .map_err(|e| InitializationError(e.to_string()))?; // Создаем ключ и записываем его в буфер
-
-        let cipher_key = Key::from_slice(&key); // Создаем объект ключа шифрования из буфера
-        let cipher = Kuznyechik::new(&cipher_key); // Создаем объект шифратора
-
-        Ok(Self {
-            cipher,
-            gamma,
-            metadata_path,
-        }) // Создаем и возвращаем новый экземпляр структуры
+        Ok((metadata_path, gamma, salt, token))
     }
 
     async fn get_metadata_path() -> Result<PathBuf, InitializationError> {
         // Метод получения пути файла с метаданными
-        let base_path = PathBuf::from(
-            std::env::var(HOME_DIR_VAR).map_err(|e| InitializationError(e.to_string()))?,
-        ); // Получаем полный путь до директории с конфигурациями приложений в домашнем каталоге пользователя
+        let app_dir = resolve_app_dir(
+            |name| std::env::var(name).ok(),
+            DEFAULT_APP_DIR.get().map(PathBuf::as_path),
+        )
+        .map_err(|e| InitializationError(e.to_string()))?; // Получаем путь до директории нашего приложения
 
         // Создаем директорию нашего приложения
-        let app_dir = base_path.join(APP_DIR);
         fs::create_dir_all(&app_dir)
             .await
             .map_err(|e| InitializationError(e.to_string()))?;
@@ -116,12 +291,12 @@ impl KuznechikEncryptor {
 
     async fn load_metadata(path: &PathBuf) -> Result<EncryptionMetadata, InitializationError> {
         // Метод получения данных из файла метаданных
-        Ok(serde_json::from_slice(
+        serde_json::from_slice(
             &fs::read(path)
                 .await
                 .map_err(|e| InitializationError(e.to_string()))?,
         )
-        .map_err(|e| InitializationError(e.to_string()))?) // Десериализуем прочитанный JSON-текст в структуру и возвращаем его
+        .map_err(|e| InitializationError(e.to_string())) // Десериализуем прочитанный JSON-текст в структуру и возвращаем его
     }
 
     async fn save_metadata(
@@ -129,12 +304,12 @@ impl KuznechikEncryptor {
         metadata: &EncryptionMetadata,
     ) -> Result<(), InitializationError> {
         // Метод записи данных в файл метаданных
-        Ok(fs::write(
+        fs::write(
             path,
             &serde_json::to_vec(metadata).map_err(|e| InitializationError(e.to_string()))?, // Сериализуем объект в JSON-текст с пробельными символами
         )
         .await
-        .map_err(|e| InitializationError(e.to_string()))?) // Записываем текст в файл
+        .map_err(|e| InitializationError(e.to_string())) // Записываем текст в файл
     }
 
     pub async fn regenerate_gamma_and_token(&mut self) -> Result<(), GammaRegenerationError> {
@@ -161,77 +336,149 @@ impl KuznechikEncryptor {
     }
 }
 
+#[cfg(feature = "gost")]
 impl Encryptor for KuznechikEncryptor {
     // Блок реализации трейта для структуры
-    fn encrypt_chunk(&self, chunk: &[u8]) -> Vec<u8> {
-        // Метод шифрования данных на месте
-        let mut padded_data = chunk.to_vec(); // Копируем данные в новую переменную
-        while padded_data.len() % 16 != 0 {
-            // Выравниваем данные по 16 байт
-            padded_data.push(0);
-        }
-
-        let mut result = Vec::with_capacity(padded_data.len()); // Создаем буфер для зашифрованных данных
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
 
-        for c in padded_data.chunks(16) {
-            // Для каждого блока по 16 байт
-            let mut block = [0u8; 16]; // Выделяем место для блока
-            block.copy_from_slice(c); // Копируем блок во временный буфер
+    fn overhead(&self) -> usize {
+        NONCE_SIZE + MAC_SIZE // Зашифрованный чанк на 48 байт длиннее: одноразовый вектор в начале и имитовставка в конце
+    }
 
-            for (b, g) in block.iter_mut().zip(self.gamma.iter()) {
-                *b ^= g; // Выполняем XOR для блока с гаммой
-            }
+    fn authenticate(&self, message: &[u8]) -> Option<Vec<u8>> {
+        Some(hash::streebog::hmac(&self.tag_key, message).to_vec()) // HMAC по "Стрибогу" (Р 50.1.113-2016)
+    }
 
-            let mut block = Block::clone_from_slice(&block); // Создаем структуру блока, с которой умеет работать экземпляр шифратора
-            self.cipher.encrypt_block(&mut block); // Выполняем шифрование
-            result.extend_from_slice(&block); // Записываем защифрованные данные в конец результирующего буфера
-        }
+    fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        // Метод шифрования данных в режиме гаммирования (счетчика). Для каждого чанка выбирается
+        // новый случайный одноразовый вектор, поэтому одинаковые блоки и чанки шифруются по-разному.
+        // Вектор и шифротекст заверяются имитовставкой: искаженный чанк не расшифровывается, даже
+        // если его хэш совпал с хэшем из метаданных
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill(&mut nonce[..]);
+        self.seal(chunk, &nonce)
+    }
 
-        result
+    fn encrypt_chunk_deterministic(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        // Шифрование для режима dedup: вектор - HMAC содержимого чанка на отдельном ключе, поэтому
+        // одинаковые чанки дают одинаковый шифротекст и хэш, а разные - разные векторы. Повтор
+        // вектора раскрывает только совпадение чанков целиком, ради которого режим и включается
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&hash::streebog::hmac(&self.nonce_key, chunk)[..NONCE_SIZE]);
+        self.seal(chunk, &nonce)
     }
 
     fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, DecryptionError> {
-        // Метод дешифрования данных на месте
-        // Если данные не выравнены по 16 байт, то возвращаем ошибку
-        if chunk.len() % 16 != 0 {
+        // Метод дешифрования данных: после проверки имитовставки гамма вычисляется по одноразовому
+        // вектору из начала чанка
+        // Если данные пусты, не содержат блоков между вектором и имитовставкой или не выравнены по 16 байт, то возвращаем ошибку
+        if chunk.is_empty() {
+            return Err(DecryptionError(String::from("Chunk is empty")));
+        }
+        if chunk.len() <= NONCE_SIZE + MAC_SIZE
+            || !(chunk.len() - MAC_SIZE).is_multiple_of(BLOCK_SIZE)
+        {
             return Err(DecryptionError(String::from(
                 "Invalid encrypted data length",
             )));
         }
 
-        let mut result = Vec::with_capacity(chunk.len()); // Создаем буфер для хранения дешифрованных данных
+        let (sealed, mac) = chunk.split_at(chunk.len() - MAC_SIZE);
+        let expected = hash::streebog::hmac(&self.mac_key, sealed);
+        let diff = expected.iter().zip(mac).fold(0u8, |d, (a, b)| d | (a ^ b)); // Сравнение без раннего выхода: время не зависит от совпавших байт
+        if diff != 0 {
+            return Err(DecryptionError(String::from("MAC mismatch")));
+        }
+        let (nonce, data) = sealed.split_at(NONCE_SIZE);
+        let mut result = data.to_vec();
+        self.apply_keystream(nonce, &mut result);
+        Ok(result)
+    }
+}
 
-        for c in chunk.chunks(16) {
-            // Для каждого блока по 16 байт
-            let mut block = [0u8; 16]; // Создаем временный буфер для блока
-            block.copy_from_slice(c); // Записываем данные во временный буфер
+#[cfg(feature = "gost")]
+impl KuznechikEncryptor {
+    fn seal(&self, chunk: &[u8], nonce: &[u8; NONCE_SIZE]) -> Result<Vec<u8>, EncryptionError> {
+        // Шифрование чанка с указанным вектором и добавление имитовставки
+        // Пустой чанк не шифруется, чтобы его нельзя было спутать с отсутствующим
+        if chunk.is_empty() {
+            return Err(EncryptionError(String::from("Chunk is empty")));
+        }
 
-            let mut block = Block::clone_from_slice(&block); // Создаем объект блока для дешифратора
-            self.cipher.decrypt_block(&mut block); // Дешифруем данные
+        let mut result = nonce.to_vec(); // Буфер начинается с одноразового вектора
+        result.extend_from_slice(chunk);
+        result.resize(NONCE_SIZE + chunk.len().next_multiple_of(BLOCK_SIZE), 0); // Выравниваем данные по 16 байт
 
-            for (b, g) in block.iter_mut().zip(self.gamma.iter()) {
-                *b ^= g; // Выполняем XOR с гаммой для блока
-            }
+        let (nonce, data) = result.split_at_mut(NONCE_SIZE);
+        self.apply_keystream(nonce, data);
+        let mac = hash::streebog::hmac(&self.mac_key, &result);
+        result.extend_from_slice(&mac);
+        Ok(result)
+    }
 
-            result.extend_from_slice(&block); // Записываем дешифрованные данные в конец общего буфера
+    fn apply_keystream(&self, nonce: &[u8], data: &mut [u8]) {
+        // Наложение гаммы шифра: блоки счетчика, начальное значение которого - одноразовый вектор,
+        // сложенный с гаммой пользователя, шифруются "Кузнечиком" и складываются с данными по XOR.
+        // Шифрование и дешифрование совпадают
+        let mut start = [0u8; BLOCK_SIZE];
+        start.copy_from_slice(nonce);
+        for (s, g) in start.iter_mut().zip(self.gamma.iter()) {
+            *s ^= g;
+        }
+        let start = u128::from_be_bytes(start);
+        let mut keystream: Vec<Block> = (0..data.len().div_ceil(BLOCK_SIZE) as u128)
+            .map(|i| Block::from(start.wrapping_add(i).to_be_bytes()))
+            .collect();
+        self.cipher.encrypt_blocks(&mut keystream); // Блоки счетчика шифруются одним вызовом
+        for (b, k) in data.iter_mut().zip(keystream.iter().flatten()) {
+            *b ^= k;
         }
-
-        Ok(result)
     }
 }
 
+#[cfg(feature = "gost")]
 pub mod hash {
     pub mod streebog {
         use streebog::digest::Update;
         use streebog::Digest;
 
-        pub fn calc_hash(chunk: &[u8]) -> String {
-            let mut hasher = streebog::Streebog256::new(); // Создаем новый объект хэшера
-            Update::update(&mut hasher, chunk); // Передаем хэшеру данные для вычисления
-            let hash = hasher.clone().finalize(); // Вычисляем хэш-сумму для отданных данных
-            let hash = hash.to_vec(); // Переводим в тип вектора
-            hex::encode(hash)
+        use crate::crypto::Hasher;
+
+        pub struct StreebogHasher; // Структура, реализующая хэширование по ГОСТ Р 34.11-2012 "Стрибог"
+
+        impl Hasher for StreebogHasher {
+            fn calc_hash(&self, chunk: &[u8]) -> String {
+                let mut hasher = streebog::Streebog256::new(); // Создаем новый объект хэшера
+                Update::update(&mut hasher, chunk); // Передаем хэшеру данные для вычисления
+                let hash = hasher.clone().finalize(); // Вычисляем хэш-сумму для отданных данных
+                let hash = hash.to_vec(); // Переводим в тип вектора
+                hex::encode(hash)
+            }
+        }
+
+        pub fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+            // HMAC_GOSTR3411_2012_256 (Р 50.1.113-2016): ключ дополняется до размера блока хэш-функции
+            let mut block = [0u8; BLOCK_SIZE];
+            if key.len() > BLOCK_SIZE {
+                block[..32].copy_from_slice(&streebog::Streebog256::digest(key));
+            } else {
+                block[..key.len()].copy_from_slice(key);
+            }
+            let pad = |byte: u8| block.map(|b| b ^ byte);
+            let mut inner = streebog::Streebog256::new();
+            Update::update(&mut inner, &pad(IPAD));
+            Update::update(&mut inner, message);
+            let mut outer = streebog::Streebog256::new();
+            Update::update(&mut outer, &pad(OPAD));
+            Update::update(&mut outer, &inner.finalize());
+            outer.finalize().into()
         }
+
+        const BLOCK_SIZE: usize = 64; // Размер блока "Стрибога", 512 бит
+        const IPAD: u8 = 0x36;
+        const OPAD: u8 = 0x5c;
     }
 }
 
@@ -240,6 +487,18 @@ mod errors {
     use std::error::Error;
     use std::fmt; // Зависимость стандартной библиотеки для отображения данных на экране
 
+    #[derive(Debug, Clone)]
+    pub struct EncryptionError(pub String); // Ошибка шифрования данных
+
+    impl fmt::Display for EncryptionError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            // Метод отображения сведений об ошибке на экране
+            write!(f, "Error during encryption chunk: {}", self.0)
+        }
+    }
+
+    impl Error for EncryptionError {}
+
     #[derive(Debug, Clone)]
     pub struct DecryptionError(pub String); // Ошибка дешифрования данных
 
@@ -264,6 +523,52 @@ mod errors {
 
     impl Error for InitializationError {}
 
+    #[cfg(feature = "gost")]
+    #[derive(Debug, Clone)]
+    pub struct MissingKeyMaterialError; // Ошибка отсутствия параметров шифрования паролем в метаданных файла
+
+    #[cfg(feature = "gost")]
+    impl fmt::Display for MissingKeyMaterialError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "File was not sent with a password: no key material in metadata"
+            )
+        }
+    }
+
+    #[cfg(feature = "gost")]
+    impl Error for MissingKeyMaterialError {}
+
+    #[cfg(feature = "gost")]
+    #[derive(Debug, Clone)]
+    pub struct PasswordRequiredError; // Ошибка обработки файла, отправленного по паролю, без пароля
+
+    #[cfg(feature = "gost")]
+    impl fmt::Display for PasswordRequiredError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "File was sent with a password: the password is required")
+        }
+    }
+
+    #[cfg(feature = "gost")]
+    impl Error for PasswordRequiredError {}
+
+    #[derive(Debug, Clone)]
+    pub struct HomeDirError; // Ошибка определения каталога приложения
+
+    impl fmt::Display for HomeDirError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            // Метод отображения сведений об ошибке на экране
+            write!(
+                f,
+                "Cannot determine the application directory: set LEAF_HOME or the home directory"
+            )
+        }
+    }
+
+    impl Error for HomeDirError {}
+
     #[derive(Debug, Clone)]
     pub struct GammaRegenerationError(pub String); // Ошибка регенерации гаммы
 
@@ -276,3 +581,139 @@ mod errors {
 
     impl Error for GammaRegenerationError {}
 }
+
+#[cfg(all(test, feature = "gost"))]
+mod tests {
+    // Модуль юнит-тестирования
+    use super::*;
+
+    fn encryptor() -> KuznechikEncryptor {
+        KuznechikEncryptor {
+            cipher: Kuznyechik::new(Key::from_slice(&[7u8; 32])),
+            gamma: (0..16).collect(),
+            metadata_path: PathBuf::new(),
+            tag_key: [9u8; 32],
+            mac_key: [3u8; 32],
+            nonce_key: [5u8; 32],
+        }
+    }
+
+    #[test]
+    fn hmac_matches_reference_vector() {
+        // Контрольный пример Р 50.1.113-2016 (RFC 7836) для HMAC_GOSTR3411_2012_256
+        let key: Vec<u8> = (0..32).collect();
+        let message = hex::decode("0126bdb87800af214341456563780100").unwrap();
+        assert_eq!(
+            hex::encode(hash::streebog::hmac(&key, &message)),
+            "a1aa5f7de402d7b3d323f2991c8d4534013137010a83754fd0af6d7cd4922ed9"
+        );
+    }
+
+    #[tokio::test]
+    async fn file_key_material_restores_encryptor_by_password() {
+        let (encryptor, material) = KuznechikEncryptor::with_password(b"secret").await.unwrap();
+        let encrypted = encryptor.encrypt_chunk(&[1, 2, 3]).unwrap();
+
+        let restored = KuznechikEncryptor::from_key_material(b"secret", &material)
+            .await
+            .unwrap();
+        assert_eq!(restored.decrypt_chunk(&encrypted).unwrap()[..3], [1, 2, 3]);
+        let wrong = KuznechikEncryptor::from_key_material(b"wrong", &material)
+            .await
+            .unwrap();
+        assert!(wrong.decrypt_chunk(&encrypted).is_err()); // Имитовставка не сходится с ключом другого пароля
+    }
+
+    #[test]
+    fn empty_chunk_is_rejected() {
+        let encryptor = encryptor();
+        assert!(encryptor.encrypt_chunk(&[]).is_err());
+        assert!(encryptor.decrypt_chunk(&[]).is_err());
+    }
+
+    #[test]
+    fn single_byte_chunk_round_trip() {
+        let encryptor = encryptor();
+        let encrypted = encryptor.encrypt_chunk(&[42]).unwrap();
+        assert_eq!(encrypted.len(), NONCE_SIZE + 16 + MAC_SIZE);
+        let decrypted = encryptor.decrypt_chunk(&encrypted).unwrap();
+        assert_eq!(decrypted[0], 42);
+        assert!(decrypted[1..].iter().all(|&b| b == 0)); // Дополнение до размера блока
+    }
+
+    #[test]
+    fn identical_chunks_encrypt_differently() {
+        let encryptor = encryptor();
+        let chunk = [5u8; 64]; // Четыре одинаковых блока
+        let (first, second) = (
+            encryptor.encrypt_chunk(&chunk).unwrap(),
+            encryptor.encrypt_chunk(&chunk).unwrap(),
+        );
+        assert_ne!(first, second); // Одноразовые векторы различаются
+        assert_ne!(first[NONCE_SIZE..][..16], first[NONCE_SIZE..][16..32]); // Одинаковые блоки не совпадают и внутри чанка
+        assert_eq!(encryptor.decrypt_chunk(&first).unwrap(), chunk);
+        assert_eq!(encryptor.decrypt_chunk(&second).unwrap(), chunk);
+        assert!(encryptor
+            .decrypt_chunk(&first[..NONCE_SIZE + MAC_SIZE])
+            .is_err()); // Вектор и имитовставка без данных
+    }
+
+    #[test]
+    fn deterministic_encryption_depends_only_on_content() {
+        let encryptor = encryptor();
+        let chunk = [5u8; 64];
+        let first = encryptor.encrypt_chunk_deterministic(&chunk).unwrap();
+        assert_eq!(
+            first,
+            encryptor.encrypt_chunk_deterministic(&chunk).unwrap()
+        ); // Режим dedup: повтор дает тот же шифротекст
+        assert_ne!(
+            first,
+            encryptor.encrypt_chunk_deterministic(&[6u8; 64]).unwrap()
+        );
+        assert_ne!(first[NONCE_SIZE..][..16], first[NONCE_SIZE..][16..32]);
+        assert_eq!(encryptor.decrypt_chunk(&first).unwrap(), chunk); // Дешифруется как обычный чанк
+    }
+
+    #[test]
+    fn tampered_chunk_is_not_decrypted() {
+        let encryptor = encryptor();
+        let encrypted = encryptor.encrypt_chunk(&[1u8; 40]).unwrap();
+        for i in [0, NONCE_SIZE, NONCE_SIZE + 20, encrypted.len() - 1] {
+            // Искажение одного байта вектора, шифротекста или имитовставки
+            let mut tampered = encrypted.clone();
+            tampered[i] ^= 1;
+            let e = encryptor.decrypt_chunk(&tampered).unwrap_err();
+            assert_eq!(e.0, "MAC mismatch");
+        }
+        assert_eq!(
+            encryptor.decrypt_chunk(&encrypted).unwrap()[..40],
+            [1u8; 40]
+        );
+    }
+
+    #[test]
+    fn app_dir_falls_back_from_leaf_home_to_home_to_default() {
+        let vars = |leaf: &'static str, home: &'static str| {
+            move |name: &str| match name {
+                LEAF_HOME_VAR => Some(leaf.to_string()),
+                HOME_DIR_VAR => Some(home.to_string()),
+                _ => None,
+            }
+        };
+        let default = Path::new("/srv/leaf");
+        assert_eq!(
+            resolve_app_dir(vars("/opt/leaf", "/home/u"), Some(default)).unwrap(),
+            PathBuf::from("/opt/leaf")
+        );
+        assert_eq!(
+            resolve_app_dir(vars("", "/home/u"), Some(default)).unwrap(),
+            Path::new("/home/u").join(APP_DIR)
+        );
+        assert_eq!(
+            resolve_app_dir(vars("", ""), Some(default)).unwrap(),
+            default
+        );
+        assert!(resolve_app_dir(|_| None, None).is_err());
+    }
+}
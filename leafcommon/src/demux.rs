@@ -0,0 +1,429 @@
+use std::collections::{HashMap, HashSet}; // Коллекции стандартной библиотеки
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::io; // Ошибки ввода-вывода стандартной библиотеки
+use std::net::SocketAddr; // Адреса серверов, приславших копии
+use std::sync::{Arc, Mutex}; // Разделяемое между запросами состояние
+use std::time::Duration; // Структура с длительностью ожидания
+
+use tokio::net::UdpSocket; // Асинхронный UDP-сокет
+use tokio::sync::oneshot; // Канал для передачи одного ответа
+use tokio::time; // Асинхронное ожидание
+
+use crate::message::Message; // Перечисление сообщений
+use crate::reputation::PeerScores; // Оценки надежности серверов
+use crate::udp; // Отправка запросов серверам домена
+
+use consts::*; // Внутренние константы
+use errors::*; // Внутренние ошибки
+
+mod consts {
+    pub const MAX_UDP_PACKET_SIZE: usize = 65535; // Максимальный размер данных по UDP
+}
+
+type Waiters = HashMap<String, Vec<oneshot::Sender<Vec<u8>>>>; // Ожидающие ответа запросы по хэшам
+type Votes = HashMap<String, Vec<(SocketAddr, Vec<u8>)>>; // Полученные от разных серверов копии чанков по хэшам
+
+pub struct Demux {
+    // Распределитель ответов CONTENT_FILLED по запросам, одновременно выполняющимся через один сокет
+    socket: UdpSocket,                                // Общий сокет запросов
+    waiting: Mutex<Waiters>,                          // Ожидающие ответа запросы
+    shared: HashSet<String>, // Хэши, на которые ссылаются несколько индексов файла (хранятся в домене в одном экземпляре)
+    cache: Mutex<HashMap<String, Vec<u8>>>, // Полученные данные общих хэшей
+    sizes: Mutex<HashMap<String, usize>>, // Ожидаемые размеры запрошенных чанков
+    mismatched: Mutex<HashSet<String>>, // Хэши, для которых были отброшены ответы неверного размера
+    discard_wrong_size: bool, // Отбрасывать ли ответы неверного размера, продолжая ждать другие серверы
+    quorum: usize, // Количество серверов, приславших одинаковую копию, необходимое для принятия чанка
+    votes: Mutex<Votes>, // Копии чанков, еще не набравшие кворум
+    disagreed: Mutex<HashSet<String>>, // Хэши, для которых серверы прислали различающиеся копии
+    peers: Vec<SocketAddr>, // Серверы, которым отправляются запросы (пустой список - широковещательно)
+    broadcast: Option<SocketAddr>, // Адрес широковещания подсети (None - ограниченное широковещание)
+    scores: Option<Arc<PeerScores>>, // Оценки серверов, обновляемые по ответам (None - не ведутся)
+    requested: Mutex<HashMap<String, time::Instant>>, // Время отправки запросов по хэшам (для оценки времени ответа)
+}
+
+impl Demux {
+    pub fn new(socket: UdpSocket) -> Demux {
+        Demux {
+            socket,
+            waiting: Mutex::new(HashMap::new()),
+            shared: HashSet::new(),
+            cache: Mutex::new(HashMap::new()),
+            sizes: Mutex::new(HashMap::new()),
+            mismatched: Mutex::new(HashSet::new()),
+            discard_wrong_size: true,
+            quorum: 1,
+            votes: Mutex::new(HashMap::new()),
+            disagreed: Mutex::new(HashSet::new()),
+            peers: Vec::new(),
+            broadcast: None,
+            scores: None,
+            requested: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_peers(mut self, peers: Vec<SocketAddr>) -> Demux {
+        // Установка серверов для сетей без широковещания: запрос отправляется каждому из них
+        self.peers = peers;
+        self
+    }
+
+    pub fn with_scores(mut self, scores: Option<Arc<PeerScores>>) -> Demux {
+        // Установка оценок серверов: серверы peers опрашиваются по убыванию оценки, а оценки
+        // обновляются по ответам (принятая копия - успех, копия неверного размера - неудача)
+        if let Some(s) = &scores {
+            s.order(&mut self.peers);
+        }
+        self.scores = scores;
+        self
+    }
+
+    pub fn with_broadcast(mut self, broadcast: Option<SocketAddr>) -> Demux {
+        // Установка адреса направленного широковещания подсети, проверенного udp::directed_broadcast
+        self.broadcast = broadcast;
+        self
+    }
+
+    pub fn with_quorum(mut self, quorum: usize) -> Demux {
+        // Установка кворума: чанк принимается, только когда quorum разных серверов прислали
+        // побайтно одинаковые копии. Защищает от серверов, подменяющих содержимое под чужим хэшем
+        self.quorum = quorum.max(1);
+        self
+    }
+
+    pub fn with_discard_wrong_size(mut self, discard: bool) -> Demux {
+        // Установка реакции на ответ неверного размера: при false он передается запросу как есть
+        // (и запрос завершается ошибкой), при true отбрасывается, а запрос ждет ответа других серверов
+        self.discard_wrong_size = discard;
+        self
+    }
+
+    pub fn with_shared(mut self, shared: HashSet<String>) -> Demux {
+        // Установка общих хэшей: сервер выдает такой чанк один раз, поэтому полученные данные
+        // запоминаются и передаются всем запросам с этим хэшем, в том числе последующим
+        self.shared = shared;
+        self
+    }
+
+    pub async fn request(
+        &self,
+        hash: &str,
+        size: Option<usize>,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Запрос чанка по хэшу (size - ожидаемый размер, если известен), ответ доставляется методом run
+        if let Some(data) = self.cache.lock().unwrap().get(hash) {
+            return Ok(data.clone()); // Общий чанк уже получен
+        }
+        if let Some(size) = size {
+            self.sizes.lock().unwrap().insert(hash.to_string(), size);
+        }
+        let (tx, rx) = oneshot::channel();
+        self.waiting
+            .lock()
+            .unwrap()
+            .entry(hash.to_string())
+            .or_default()
+            .push(tx);
+        let req = Message::RetrievingReq(hash.to_string()).into_bytes()?;
+        if self.scores.is_some() {
+            self.requested
+                .lock()
+                .unwrap()
+                .insert(hash.to_string(), time::Instant::now());
+        }
+        udp::send_to_domain(&self.socket, &req, &self.peers, self.broadcast).await?;
+        match time::timeout(timeout, rx).await {
+            Ok(Ok(data)) => Ok(data),
+            _ => {
+                self.forget(hash);
+                self.requested.lock().unwrap().remove(hash);
+                let votes = self.votes.lock().unwrap().remove(hash).unwrap_or_default();
+                if self.disagreed.lock().unwrap().contains(hash) {
+                    return Err(Box::new(DisagreementError(hash.to_string())));
+                }
+                if !votes.is_empty() {
+                    let e = QuorumNotReachedError(hash.to_string(), votes.len(), self.quorum);
+                    return Err(Box::new(e));
+                }
+                if self.mismatched.lock().unwrap().contains(hash) {
+                    return Err(Box::new(WrongSizeError(hash.to_string())));
+                }
+                Err(Box::new(RequestTimeoutError(hash.to_string())))
+            }
+        }
+    }
+
+    pub async fn run(&self) -> io::Error {
+        // Прием ответов и передача каждого первому ожидающему запросу с тем же хэшем.
+        // Завершается только при ошибке сокета, поэтому выполняется вместе с запросами через select
+        let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+        loop {
+            let (sz, addr) = match self.socket.recv_from(&mut buf).await {
+                Ok(res) => res,
+                Err(e) => return e,
+            };
+            let Ok(Message::ContentFilled(hash, data, _)) = Message::from_bytes(buf[..sz].to_vec())
+            else {
+                continue; // Некорректные и прочие сообщения пропускаются
+            };
+            let expected = self.sizes.lock().unwrap().get(&hash).copied();
+            if self.discard_wrong_size && expected.is_some_and(|s| s != data.len()) {
+                // Поврежденная копия на одном сервере не должна прерывать получение: ждем другие
+                eprintln!(
+                    "Discarding chunk {} of wrong size {} from {}",
+                    hash,
+                    data.len(),
+                    addr
+                );
+                self.mismatched.lock().unwrap().insert(hash);
+                if let Some(s) = &self.scores {
+                    s.failure(addr);
+                }
+                continue;
+            }
+            let Some(data) = self.vote(&hash, addr, data) else {
+                continue; // Кворум еще не набран
+            };
+            if let Some(s) = &self.scores {
+                if let Some(sent) = self.requested.lock().unwrap().remove(&hash) {
+                    s.success(addr, sent.elapsed()); // Учитывается сервер, копия которого принята
+                }
+            }
+            let mut waiting = self.waiting.lock().unwrap();
+            if self.shared.contains(&hash) {
+                // Общий чанк запоминается, даже если его запросы уже отменены
+                for s in waiting.remove(&hash).unwrap_or_default() {
+                    let _ = s.send(data.clone());
+                }
+                self.cache.lock().unwrap().insert(hash, data);
+                continue;
+            }
+            let Some(senders) = waiting.get_mut(&hash) else {
+                continue; // Ответ на отмененный или уже выполненный запрос
+            };
+            senders.retain(|s| !s.is_closed()); // Отмененные запросы (например, проигравшие подстраховку)
+            if !senders.is_empty() {
+                let _ = senders.remove(0).send(data);
+            }
+            if senders.is_empty() {
+                waiting.remove(&hash);
+            }
+        }
+    }
+
+    fn vote(&self, hash: &str, addr: SocketAddr, data: Vec<u8>) -> Option<Vec<u8>> {
+        // Учет копии чанка от сервера. Возвращает содержимое, как только его прислали quorum
+        // разных серверов. Повторные копии от того же сервера не учитываются
+        if self.quorum <= 1 {
+            return Some(data);
+        }
+        let mut votes = self.votes.lock().unwrap();
+        let copies = votes.entry(hash.to_string()).or_default();
+        if copies.iter().any(|(a, _)| *a == addr) {
+            return None;
+        }
+        if copies.iter().any(|(_, d)| *d != data) {
+            eprintln!(
+                "Servers disagree on the content of chunk {} ({})",
+                hash, addr
+            );
+            self.disagreed.lock().unwrap().insert(hash.to_string());
+        }
+        let agreeing = copies.iter().filter(|(_, d)| *d == data).count() + 1;
+        if agreeing < self.quorum {
+            copies.push((addr, data));
+            return None;
+        }
+        votes.remove(hash);
+        Some(data)
+    }
+
+    fn forget(&self, hash: &str) {
+        // Удаление запросов, которые больше не ожидают ответа
+        let mut waiting = self.waiting.lock().unwrap();
+        if let Some(senders) = waiting.get_mut(hash) {
+            senders.retain(|s| !s.is_closed());
+            if senders.is_empty() {
+                waiting.remove(hash);
+            }
+        }
+    }
+}
+
+mod errors {
+    // Модуль с составными ошибками
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::{Display, Formatter};
+
+    #[derive(Debug, Clone)]
+    pub struct RequestTimeoutError(pub String); // Ошибка таймаута запроса чанка
+
+    impl Display for RequestTimeoutError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "Timeout waiting for chunk {}", self.0)
+        }
+    }
+
+    impl Error for RequestTimeoutError {}
+
+    #[derive(Debug, Clone)]
+    pub struct WrongSizeError(pub String); // Ошибка получения только ответов неверного размера
+
+    impl Display for WrongSizeError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "Only chunks of wrong size were received for {}", self.0)
+        }
+    }
+
+    impl Error for WrongSizeError {}
+
+    #[derive(Debug, Clone)]
+    pub struct QuorumNotReachedError(pub String, pub usize, pub usize); // Ошибка недостаточного количества одинаковых копий
+
+    impl Display for QuorumNotReachedError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Only {} of {} required copies were received for {}",
+                self.1, self.2, self.0
+            )
+        }
+    }
+
+    impl Error for QuorumNotReachedError {}
+
+    #[derive(Debug, Clone)]
+    pub struct DisagreementError(pub String); // Ошибка получения различающихся копий без кворума
+
+    impl Display for DisagreementError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Servers returned different content for {} and no copy reached the quorum",
+                self.0
+            )
+        }
+    }
+
+    impl Error for DisagreementError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn responder(data: Vec<u8>, copies: usize) -> SocketAddr {
+        // Сервер для проверки: на каждый запрос чанка отвечает copies одинаковыми копиями data
+        delayed_responder(data, copies, Duration::ZERO).await
+    }
+
+    async fn delayed_responder(data: Vec<u8>, copies: usize, delay: Duration) -> SocketAddr {
+        // Сервер для проверки, отвечающий с задержкой delay
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+            while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                let Ok(Message::RetrievingReq(hash)) = Message::from_bytes(buf[..sz].to_vec())
+                else {
+                    continue;
+                };
+                let reply = Message::ContentFilled(hash, data.clone(), 0);
+                time::sleep(delay).await;
+                for _ in 0..copies {
+                    let _ = socket
+                        .send_to(&reply.clone().into_bytes().unwrap(), from)
+                        .await;
+                }
+            }
+        });
+        addr
+    }
+
+    async fn polling(peers: Vec<SocketAddr>) -> Demux {
+        // Распределитель запросов, опрашивающий указанные серверы
+        Demux::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()).with_peers(peers)
+    }
+
+    async fn fetch(demux: &Demux, size: Option<usize>) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Запрос чанка одновременно с распределением ответов на него
+        tokio::select! {
+            result = demux.request("hash", size, Duration::from_millis(300)) => result,
+            e = demux.run() => Err(Box::new(e)),
+        }
+    }
+
+    #[tokio::test]
+    async fn chunk_is_accepted_when_quorum_agrees() {
+        let peers = vec![
+            responder(vec![1, 2, 3], 1).await,
+            responder(vec![1, 2, 3], 1).await,
+            responder(vec![1, 2, 3], 1).await,
+        ];
+        let demux = polling(peers.clone()).await.with_quorum(3);
+        assert_eq!(fetch(&demux, None).await.unwrap(), [1, 2, 3]);
+
+        let peers = vec![peers[0], responder(vec![9, 9, 9], 1).await, peers[1]];
+        let demux = polling(peers).await.with_quorum(2);
+        assert_eq!(fetch(&demux, None).await.unwrap(), [1, 2, 3]); // Подмененная копия в меньшинстве
+    }
+
+    #[tokio::test]
+    async fn repeated_copies_from_one_server_are_counted_once() {
+        let demux = polling(vec![responder(vec![1, 2, 3], 3).await])
+            .await
+            .with_quorum(2);
+        let e = fetch(&demux, None).await.unwrap_err();
+        let e = e.downcast_ref::<QuorumNotReachedError>().unwrap();
+        assert_eq!((e.1, e.2), (1, 2));
+
+        let peers = vec![
+            responder(vec![1, 2, 3], 3).await,
+            responder(vec![1, 2, 3], 1).await,
+        ];
+        let demux = polling(peers).await.with_quorum(2);
+        assert_eq!(fetch(&demux, None).await.unwrap(), [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn different_copies_without_quorum_are_rejected() {
+        let peers = vec![
+            responder(vec![1, 2, 3], 1).await,
+            responder(vec![9, 9, 9], 1).await,
+        ];
+        let demux = polling(peers).await.with_quorum(2);
+        let e = fetch(&demux, None).await.unwrap_err();
+        assert!(e.is::<DisagreementError>());
+    }
+
+    #[tokio::test]
+    async fn wrong_size_copy_is_skipped_for_correct_one() {
+        let delay = Duration::from_millis(50);
+        let peers = vec![
+            responder(vec![1, 2], 1).await,
+            delayed_responder(vec![1, 2, 3], 1, delay).await,
+        ];
+        let demux = polling(peers.clone()).await;
+        assert_eq!(fetch(&demux, Some(3)).await.unwrap(), [1, 2, 3]);
+
+        let demux = polling(peers).await.with_discard_wrong_size(false);
+        assert_eq!(fetch(&demux, Some(3)).await.unwrap(), [1, 2]); // Первый ответ передается как есть
+    }
+
+    #[tokio::test]
+    async fn wrong_size_is_reported_after_timeout() {
+        let demux = polling(vec![responder(vec![1, 2], 1).await]).await;
+        let started = time::Instant::now();
+        let e = fetch(&demux, Some(3)).await.unwrap_err();
+        assert!(e.is::<WrongSizeError>());
+        assert!(started.elapsed() >= Duration::from_millis(300)); // Ответы других серверов ожидаются до таймаута
+
+        let demux = polling(vec![responder(vec![1, 2, 3], 1).await]).await;
+        let e = fetch(&demux, Some(4)).await.unwrap_err();
+        assert!(e.is::<WrongSizeError>());
+        let silent = polling(vec![responder(vec![1, 2, 3], 0).await]).await;
+        let e = fetch(&silent, Some(3)).await.unwrap_err();
+        assert!(e.is::<RequestTimeoutError>()); // Без ответов - обычный таймаут
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, VecDeque}; // Очередь адресов для опроса
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::net::SocketAddr; // Структура сетевого адреса
+use std::time::Duration; // Структура с длительностью ожидания
+use tokio::time; // Асинхронное ожидание
+
+use crate::message::Message; // Перечисление сообщений
+use crate::udp; // Создание UDP-сокета
+
+use consts::*; // Внутренние константы
+
+mod consts {
+    use std::time::Duration; // Структура с длительностью ожидания
+
+    pub const CLIENT_ADDR: &str = "0.0.0.0:0";
+    pub const BROADCAST_ADDR: &str = "255.255.255.255:62092"; // Широковещательный адрес локальной сети с портом
+    pub const MAX_UDP_PACKET_SIZE: usize = 65535; // Максимальный размер данных по UDP
+    pub const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2); // Время ожидания ответа одного узла
+    pub const PROBE_TIMEOUT: Duration = Duration::from_secs(1); // Время ожидания ответов на один пробный запрос
+}
+
+pub const SERVER_PORT: u16 = 62092; // Порт, на котором работают серверы
+pub const MAX_PEERS: usize = 32; // Количество адресов в ответе на запрос обнаружения по умолчанию
+
+#[derive(Clone, Debug)]
+pub struct PeerSet {
+    // Множество известных серверов без повторов, ограниченное по размеру
+    peers: Vec<SocketAddr>, // Адреса в порядке добавления
+    limit: usize,           // Максимальное количество адресов
+}
+
+impl PeerSet {
+    pub fn new(limit: usize) -> Self {
+        PeerSet {
+            peers: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    pub fn insert(&mut self, addr: SocketAddr) -> bool {
+        // Добавление адреса, возвращает true, если адрес новый и поместился
+        if self.peers.len() >= self.limit || self.peers.contains(&addr) {
+            return false;
+        }
+        self.peers.push(addr);
+        true
+    }
+
+    pub fn extend(&mut self, addrs: impl IntoIterator<Item = SocketAddr>) {
+        // Добавление нескольких адресов
+        for addr in addrs {
+            self.insert(addr);
+        }
+    }
+
+    pub fn list(&self) -> Vec<SocketAddr> {
+        self.peers.clone() // Получение списка адресов
+    }
+
+    pub fn list_except(&self, addr: SocketAddr) -> Vec<SocketAddr> {
+        // Получение списка адресов без указанного (обычно - без запрашивающего узла)
+        self.peers.iter().copied().filter(|p| *p != addr).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len() // Количество известных адресов
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty() // Проверка отсутствия известных адресов
+    }
+}
+
+pub async fn ping(window: Duration, limit: usize) -> Result<PeerSet, Box<dyn Error>> {
+    // Быстрая проверка доступности домена: широковещательный запрос обнаружения, ответившие
+    // в течение указанного времени серверы считаются доступными
+    let socket = udp::bind(CLIENT_ADDR, udp::DEFAULT_BUF_SIZE)?;
+    let mut peers = PeerSet::new(limit);
+    let mut buf = [0u8; MAX_UDP_PACKET_SIZE];
+
+    socket
+        .send_to(&Message::DiscoveryReq.into_bytes()?, BROADCAST_ADDR)
+        .await?;
+    let deadline = time::Instant::now() + window;
+    while let Ok(res) = time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (sz, addr) = res?;
+        if let Ok(Message::DiscoveryAck(_)) = Message::from_bytes(buf[..sz].to_vec()) {
+            peers.insert(addr);
+        }
+    }
+    Ok(peers)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerLatency {
+    // Результат пробных запросов к одному серверу
+    pub sent: usize,    // Количество отправленных запросов
+    pub replies: usize, // Количество полученных ответов
+    pub min: Duration,  // Минимальное время ответа
+    pub max: Duration,  // Максимальное время ответа
+    total: Duration,    // Суммарное время ответов
+}
+
+impl PeerLatency {
+    fn record(&mut self, rtt: Duration) {
+        // Учет одного ответа
+        self.min = match self.replies {
+            0 => rtt,
+            _ => self.min.min(rtt),
+        };
+        self.max = self.max.max(rtt);
+        self.total += rtt;
+        self.replies += 1;
+    }
+
+    pub fn avg(&self) -> Duration {
+        // Среднее время ответа (нулевое при отсутствии ответов)
+        match self.replies {
+            0 => Duration::ZERO,
+            n => self.total / n as u32,
+        }
+    }
+
+    pub fn loss(&self) -> f64 {
+        // Доля потерянных запросов, %
+        if self.sent == 0 {
+            return 0.0;
+        }
+        100.0 * (self.sent - self.replies) as f64 / self.sent as f64
+    }
+}
+
+pub async fn probe(
+    count: usize,
+    peers: &[SocketAddr],
+) -> Result<BTreeMap<SocketAddr, PeerLatency>, Box<dyn Error>> {
+    // Измерение времени ответа и потерь: count раз отправляется запрос обнаружения (широковещательно
+    // или каждому из peers) и замеряется время первого ответа каждого сервера. Каждый запрос
+    // отправляется с нового сокета, чтобы опоздавшие ответы не учитывались в следующем замере.
+    // Серверы с отключенной обработкой discovery_req не отвечают и в результат не попадают
+    let mut stats: BTreeMap<SocketAddr, PeerLatency> =
+        peers.iter().map(|p| (*p, PeerLatency::default())).collect();
+    let mut buf = [0u8; MAX_UDP_PACKET_SIZE];
+    let req = Message::DiscoveryReq.into_bytes()?;
+    for round in 0..count {
+        let socket = udp::bind(CLIENT_ADDR, udp::DEFAULT_BUF_SIZE)?;
+        let started = time::Instant::now();
+        udp::send_to_domain(&socket, &req, peers, None).await?;
+        let mut answered = Vec::new();
+        let deadline = started + PROBE_TIMEOUT;
+        while let Ok(res) = time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (sz, addr) = res?;
+            if answered.contains(&addr) || !(peers.is_empty() || peers.contains(&addr)) {
+                continue;
+            }
+            if let Ok(Message::DiscoveryAck(_)) = Message::from_slice(&buf[..sz]) {
+                answered.push(addr);
+                let peer = stats.entry(addr).or_insert_with(|| PeerLatency {
+                    sent: round, // Сервер, ответивший впервые, не ответил на предыдущие запросы
+                    ..PeerLatency::default()
+                });
+                peer.record(started.elapsed());
+            }
+        }
+        stats.values_mut().for_each(|p| p.sent += 1);
+    }
+    Ok(stats)
+}
+
+pub async fn discover(seeds: &[SocketAddr], limit: usize) -> Result<PeerSet, Box<dyn Error>> {
+    // Обнаружение серверов через узлы-затравки: каждый ответивший узел сообщает известные ему адреса,
+    // которые также опрашиваются, пока не будут опрошены все узлы или не будет достигнут предел
+    let socket = udp::bind(CLIENT_ADDR, udp::DEFAULT_BUF_SIZE)?;
+    let mut peers = PeerSet::new(limit);
+    let mut queue: VecDeque<SocketAddr> = seeds.iter().copied().collect();
+    let mut asked = Vec::new();
+    let mut buf = [0u8; MAX_UDP_PACKET_SIZE];
+
+    while let Some(peer) = queue.pop_front() {
+        if asked.contains(&peer) || asked.len() >= limit {
+            continue;
+        }
+        asked.push(peer);
+        socket
+            .send_to(&Message::DiscoveryReq.into_bytes()?, peer)
+            .await?;
+
+        let deadline = time::Instant::now() + DISCOVERY_TIMEOUT;
+        while let Ok(res) = time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (sz, addr) = res?;
+            if addr != peer {
+                continue; // Ответ от другого (ранее опрошенного) узла
+            }
+            if let Ok(Message::DiscoveryAck(known)) = Message::from_bytes(buf[..sz].to_vec()) {
+                peers.insert(peer); // Узел ответил - он работает
+                queue.extend(known.into_iter().take(limit));
+                break;
+            }
+        }
+    }
+    Ok(peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_counts_loss_and_rtt() {
+        let mut peer = PeerLatency {
+            sent: 4,
+            ..PeerLatency::default()
+        };
+        peer.record(Duration::from_millis(30));
+        peer.record(Duration::from_millis(10));
+        peer.record(Duration::from_millis(20));
+        assert_eq!(peer.min, Duration::from_millis(10));
+        assert_eq!(peer.max, Duration::from_millis(30));
+        assert_eq!(peer.avg(), Duration::from_millis(20));
+        assert_eq!(peer.loss(), 25.0);
+        assert_eq!(PeerLatency::default().loss(), 0.0);
+    }
+}
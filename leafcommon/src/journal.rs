@@ -0,0 +1,155 @@
+use std::io::SeekFrom; // Позиция записи чанка в файле
+use std::path::{Path, PathBuf}; // Структуры файлового пути
+
+use tokio::fs; // Асинхронные операции с файловой системой
+use tokio::io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt}; // Запись и чтение по смещению
+
+use crate::chunks::{ChunkHash, ChunksHashes, ReedSolomonChunkHash, ReedSolomonChunksHashes}; // Хэши и размеры ожидаемых чанков
+
+use consts::*; // Внутренний модуль с константами
+
+mod consts {
+    // Модуль с константами
+    pub const PART_SUFFIX: &str = ".part"; // Суффикс файла с полученными чанками
+    pub const DONE_SUFFIX: &str = ".done"; // Суффикс списка записанных чанков
+    pub const DATA_MARK: &str = "d"; // Отметка чанка данных в списке
+    pub const RECOVERY_MARK: &str = "r"; // Отметка восстановительного чанка в списке
+}
+
+struct Slot {
+    // Место чанка в файле полученных чанков
+    hash: String, // Ожидаемый хэш чанка
+    offset: u64,  // Смещение чанка в файле
+    size: usize,  // Размер чанка
+}
+
+pub struct RecvJournal {
+    // Журнал получения файла. Сервер выдает чанк только один раз, поэтому полученные чанки
+    // записываются рядом с файлом результата: после прерывания получение продолжается с того же
+    // места, а уже полученные чанки не запрашиваются повторно. Каждому чанку выделено место
+    // в файле .part, а в список .part.done после записи чанка дописывается строка с его индексом
+    // и хэшем. Строки с чужим хэшем (журнал другого файла) и недописанная последняя строка
+    // не учитываются
+    part: fs::File,
+    done: fs::File,
+    part_path: PathBuf,
+    done_path: PathBuf,
+    data: Vec<Slot>, // Места чанков данных
+    recv: Vec<Slot>, // Места восстановительных чанков
+}
+
+type Restored = (Vec<Option<Vec<u8>>>, Vec<Option<Vec<u8>>>); // Чанки данных и восстановительные чанки по индексам
+
+impl RecvJournal {
+    pub async fn open(
+        output: impl AsRef<Path>,
+        hashes: &ReedSolomonChunksHashes,
+    ) -> io::Result<RecvJournal> {
+        // Открытие журнала получения в файл output (существующий журнал сохраняется)
+        let mut part_path = output.as_ref().as_os_str().to_owned();
+        part_path.push(PART_SUFFIX);
+        let part_path = PathBuf::from(part_path);
+        let mut done_path = part_path.as_os_str().to_owned();
+        done_path.push(DONE_SUFFIX);
+        let done_path = PathBuf::from(done_path);
+
+        let mut offset = 0;
+        let mut slot = |hash: ReedSolomonChunkHash| {
+            let s = Slot {
+                hash: hash.get_value(),
+                offset,
+                size: hash.get_size(),
+            };
+            offset += s.size as u64;
+            s
+        };
+        let data = (0..hashes.len())
+            .map(|i| slot(hashes.get_data_hash(i)))
+            .collect();
+        let recv = (0..hashes.len())
+            .map(|i| slot(hashes.get_recv_hash(i)))
+            .collect();
+
+        let part = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&part_path)
+            .await?;
+        let done = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&done_path)
+            .await?;
+        Ok(RecvJournal {
+            part,
+            done,
+            part_path,
+            done_path,
+            data,
+            recv,
+        })
+    }
+
+    pub async fn restore(&mut self) -> io::Result<Restored> {
+        // Чтение чанков, записанных до прерывания получения
+        let mut list = String::new();
+        fs::File::open(&self.done_path)
+            .await?
+            .read_to_string(&mut list)
+            .await?;
+        let complete = list.rfind('\n').map_or("", |end| &list[..end]); // Последняя строка могла быть недописана
+
+        let mut data = vec![None; self.data.len()];
+        let mut recv = vec![None; self.recv.len()];
+        for line in complete.lines() {
+            let mut fields = line.split_whitespace();
+            let (mark, index, hash) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(m), Some(i), Some(h)) => (m, i, h),
+                _ => continue,
+            };
+            let (slots, chunks) = match mark {
+                DATA_MARK => (&self.data, &mut data),
+                RECOVERY_MARK => (&self.recv, &mut recv),
+                _ => continue,
+            };
+            let index = match index.parse::<usize>() {
+                Ok(i) if i < slots.len() && slots[i].hash == hash => i,
+                _ => continue, // Строка относится к другому файлу
+            };
+            let slot = &slots[index];
+            let mut value = vec![0; slot.size];
+            self.part.seek(SeekFrom::Start(slot.offset)).await?;
+            self.part.read_exact(&mut value).await?;
+            chunks[index] = Some(value);
+        }
+        Ok((data, recv))
+    }
+
+    pub async fn record(&mut self, recovery: bool, index: usize, value: &[u8]) -> io::Result<()> {
+        // Запись полученного чанка. Строка в списке дописывается только после записи самого чанка,
+        // поэтому при прерывании между ними чанк лишь считается неполученным
+        let (slots, mark) = match recovery {
+            false => (&self.data, DATA_MARK),
+            true => (&self.recv, RECOVERY_MARK),
+        };
+        let slot = match slots.get(index) {
+            Some(s) if s.size == value.len() => s,
+            _ => return Ok(()), // Чанк неверного размера не сохраняется
+        };
+        self.part.seek(SeekFrom::Start(slot.offset)).await?;
+        self.part.write_all(value).await?;
+        self.part.flush().await?;
+        let line = format!("{} {} {}\n", mark, index, slot.hash);
+        self.done.write_all(line.as_bytes()).await?;
+        self.done.flush().await
+    }
+
+    pub async fn finish(self) -> io::Result<()> {
+        // Удаление журнала после записи файла результата
+        drop((self.part, self.done));
+        fs::remove_file(&self.done_path).await?;
+        fs::remove_file(&self.part_path).await
+    }
+}
@@ -1,36 +1,1294 @@
 pub mod message;
 pub use message::Message;
 
+pub mod udp;
+
+pub mod discovery;
+
+mod acks;
+mod attrs;
+mod audit;
 mod crypto;
 mod shards;
 
 mod chunks;
+mod demux;
+mod journal;
+mod pool;
+mod prepared;
+mod registry;
+mod reputation;
+mod segments;
+
+mod transfers;
+mod tree;
+pub use transfers::{LimitPolicy, TransferKind, TransferRegistry, TransferStats, TransferStatus};
 
 pub mod reed_solomon_scheme {
-    use super::chunks::{Chunks, ChunksHashes, ReedSolomonChunks, ReedSolomonChunksHashes};
-    use super::crypto::{Encryptor, KuznechikEncryptor};
+    // Высокоуровневые операции с файлами. Функции с суффиксом _using принимают шифровальщик
+    // и хэш-вычислитель пользователя, остальные используют реализации по ГОСТ (признак gost)
+    use super::chunks::{
+        with_deadline, write_replacing, Chunks, ChunksHashes, ReedSolomonChunk, ReedSolomonChunks,
+        ReedSolomonChunksHashes, STDOUT_PATH,
+    };
+    #[cfg(feature = "gost")]
+    use super::crypto::KuznechikEncryptor;
+    #[cfg(feature = "gost")]
+    pub use super::crypto::{Argon2KeyProvider, KeyProvider};
+    use super::discovery;
+    use super::journal::RecvJournal;
+    use super::segments::{self, Segment, SegmentIndex};
+    use super::shards::reed_solomon::Backend;
+    pub use super::shards::reed_solomon::{
+        Chunker, ShardConflict, TooManyChunksError, DEFAULT_MAX_CHUNKS,
+    };
+    use super::tree::{self, DirEntry, DirManifest, EntryKind, WalkEntry};
+
+    pub use super::attrs::{OutputAttrs, SourceAttrs};
+    pub use super::audit::{
+        audit, AuditOptions, AuditReport, ChunkAudit, DEFAULT_MIN_REPLICAS,
+        DEFAULT_PROBE_CONCURRENCY, DEFAULT_PROBE_TIMEOUT,
+    };
+    pub use super::chunks::{
+        ChunkDiff, ChunkSendResult, ChunksStats, ErasureParams, FileDiff, ManifestFormat,
+        ManifestTooLargeError, OperationStats, Progress, RecoveryRetry, RecvOptions, RepairReport,
+        SendOptions, SendRetry, DEFAULT_ACK_WINDOW, DEFAULT_MAX_MANIFEST_SIZE, KEYED_HASH_MARK,
+    };
+    #[cfg(feature = "gost")]
+    pub use super::crypto::hash::streebog::{hmac as streebog_hmac, StreebogHasher};
+    #[cfg(feature = "gost")]
+    pub use super::crypto::set_default_app_dir;
+    pub use super::crypto::{DecryptionError, EncryptionError, Encryptor, FileKeyMaterial, Hasher};
+    #[cfg(feature = "gost")]
+    pub use super::crypto::{MissingKeyMaterialError, PasswordRequiredError};
+    pub use super::pool::{default_crypto_threads, set_crypto_threads};
+    pub use super::prepared::PreparedUpload;
+    pub use super::registry::{Algorithms, Registry, UnknownAlgorithmError};
+    pub use super::reputation::{PeerScore, PeerScores};
+    pub use super::udp::AddressMode;
 
     use std::error::Error;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use std::time::Duration;
+    use tokio::fs;
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+    const PING_WINDOW: Duration = Duration::from_secs(1); // Время ожидания ответов серверов при проверке домена
+    const ERASURE_HEADER_PREFIX: &str = "leaf-params:"; // Префикс ключа чанка-заголовка с параметрами кода
+    const MANIFEST_SUFFIX: &str = ".leaf"; // Суффикс файла метаданных, записываемого рядом с исходным файлом
+
+    pub fn manifest_path(path: impl AsRef<Path>, consume_source: bool) -> PathBuf {
+        // Путь файла метаданных отправленного файла. По умолчанию исходный файл не изменяется,
+        // а метаданные записываются рядом с ним; с consume_source исходный файл заменяется метаданными
+        let path = path.as_ref();
+        if consume_source {
+            return path.to_path_buf();
+        }
+        let mut name = path.as_os_str().to_owned();
+        name.push(MANIFEST_SUFFIX);
+        PathBuf::from(name)
+    }
+
+    #[cfg(feature = "gost")]
+    async fn gost() -> Result<(KuznechikEncryptor, StreebogHasher), Box<dyn Error>> {
+        // Создание шифровальщика и хэш-вычислителя по умолчанию
+        Ok((KuznechikEncryptor::new().await?, StreebogHasher))
+    }
 
+    #[cfg(feature = "gost")]
     pub async fn send_file(path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
-        let encryptor: Box<dyn Encryptor> = Box::new(KuznechikEncryptor::new().await?);
+        send_file_with_progress(path, &Progress::default()).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_with_progress(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+    ) -> Result<(), Box<dyn Error>> {
+        send_file_with_ack_window(path, progress, DEFAULT_ACK_WINDOW).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_with_ack_window(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        ack_window: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        send_file_with_deadline(path, progress, ack_window, None).await
+    }
 
-        let mut chunks = ReedSolomonChunks::from_file(&path).await?; // Получаем чанки
-        chunks.encrypt(&encryptor)?; // Шифруем их
-        chunks.update_hashes()?; // Обновляем их хэш-суммы
-        let hashes = chunks.send().await?; // Отправляем чанки в домен и получаем назад их хэш-суммы
-        hashes.save_to(path).await?; // Сохраняем хэш-суммы в целевом файле
+    #[cfg(feature = "gost")]
+    pub async fn send_file_with_deadline(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        ack_window: Duration,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let options = SendOptions {
+            ack_window,
+            ..SendOptions::default()
+        };
+        send_file_with_options(path, progress, &options, operation_deadline).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_with_options(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        send_file_using(
+            path,
+            progress,
+            options,
+            operation_deadline,
+            &encryptor,
+            &hasher,
+        )
+        .await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_with_password(
+        path: impl AsRef<Path>,
+        password: &[u8],
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Переносимый режим: файл шифруется собственной гаммой и ключом из пароля, а соль и
+        // зашифрованная паролем гамма сохраняются в метаданных файла вместо общего файла метаданных
+        let (encryptor, material) = KuznechikEncryptor::with_password(password).await?;
+        let send = send_chunks(&path, progress, options, &encryptor, &StreebogHasher);
+        let mut hashes = with_deadline(operation_deadline, progress, send).await?;
+        hashes.set_key_material(material);
+        hashes.set_format(options.manifest_format);
+        hashes
+            .save_to(manifest_path(path, options.consume_source))
+            .await?;
         Ok(())
     }
 
-    pub async fn recv_file(path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
-        let decryptor: Box<dyn Encryptor> = Box::new(KuznechikEncryptor::new().await?);
+    pub async fn send_file_using(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        let send = send_chunks(&path, progress, options, encryptor, hasher);
+        let mut hashes = with_deadline(operation_deadline, progress, send).await?; // Отправляем чанки в домен и получаем назад их хэш-суммы
 
-        let hashes = ReedSolomonChunksHashes::load_from(&path).await?; // Получаем хэш-суммы из файла
-        let mut chunks = ReedSolomonChunks::recv(hashes).await?; // Получаем чанки по хэшам
-        chunks.decrypt(&decryptor)?; // Расшифровываем чанки
-        chunks.into_file(path).await?; // Восстанавливаем из них содержимое и записываем его в целевой файл
+        // Сохраняем хэш-суммы: исходный файл заменяется только при consume_source и только после
+        // успешной отправки и полной записи метаданных
+        hashes.set_format(options.manifest_format);
+        hashes
+            .save_to(manifest_path(path, options.consume_source))
+            .await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn prepare_upload(
+        path: impl AsRef<Path>,
+        plan: impl AsRef<Path>,
+        options: &SendOptions,
+    ) -> Result<ChunksStats, Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        prepare_upload_using(path, plan, options, &encryptor, &hasher).await
+    }
+
+    pub async fn prepare_upload_using(
+        path: impl AsRef<Path>,
+        plan: impl AsRef<Path>,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<ChunksStats, Box<dyn Error>> {
+        // Подготовка отправки без обращения к домену: зашифрованные чанки сохраняются в файл плана
+        let prepared = PreparedUpload::prepare(path, options, encryptor, hasher).await?;
+        prepared.save_to(plan).await?;
+        Ok(prepared.stats())
+    }
+
+    pub async fn send_prepared(
+        plan: impl AsRef<Path>,
+        manifest: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка подготовленного плана в домен: ключ шифрования не нужен, метаданные
+        // записываются в файл manifest
+        let prepared = PreparedUpload::load_from(plan).await?;
+        let send = prepared.send(progress, options);
+        let mut hashes = with_deadline(operation_deadline, progress, send).await?;
+        hashes.set_format(options.manifest_format);
+        hashes.save_to(manifest).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_pipelined(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        send_file_pipelined_using(
+            path,
+            progress,
+            options,
+            operation_deadline,
+            Arc::new(encryptor),
+            Arc::new(hasher),
+        )
+        .await
+    }
+
+    pub async fn send_file_pipelined_using(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+        encryptor: Arc<dyn Encryptor + Send + Sync>,
+        hasher: Arc<dyn Hasher + Send + Sync>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка файла с шифрованием чанков одновременно с отправкой уже зашифрованных.
+        // Шифровальщик и хэш-вычислитель разделяются с потоком шифрования, поэтому передаются в Arc
+        let send = async {
+            let source_attrs = match options.preserve_attrs {
+                true => Some(SourceAttrs::read(&path)?),
+                false => None,
+            };
+            let chunks = ReedSolomonChunks::from_file_chunked(
+                &path,
+                encryptor.as_ref(),
+                options.chunker,
+                options.max_chunks_per_file,
+            )
+            .await?
+            .with_options(options);
+            chunks.check_manifest_size(hasher.as_ref(), options.max_manifest_size)?; // Хэши еще не вычислены, размер оценивается
+            let tagger = encryptor.clone();
+            let mut hashes = chunks.send_pipelined(encryptor, hasher, progress).await?;
+            hashes.attach_tag(tagger.as_ref());
+            if let Some(attrs) = source_attrs {
+                hashes.set_source_attrs(attrs);
+            }
+            Ok::<_, Box<dyn Error>>(hashes)
+        };
+        let mut hashes = with_deadline(operation_deadline, progress, send).await?;
+        hashes.set_format(options.manifest_format);
+        hashes
+            .save_to(manifest_path(path, options.consume_source))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn send_file_with_registry(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+        registry: &Registry,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка файла основными реализациями набора: их имена сохраняются в метаданных файла
+        let algorithms = registry.primary()?;
+        let (encryptor, hasher) = registry.select(&algorithms)?;
+        let send = send_chunks(&path, progress, options, encryptor, hasher);
+        let mut hashes = with_deadline(operation_deadline, progress, send).await?;
+        hashes.set_algorithms(algorithms);
+        hashes.set_format(options.manifest_format);
+        hashes
+            .save_to(manifest_path(path, options.consume_source))
+            .await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_rooted(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<String, Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        send_file_rooted_using(
+            path,
+            progress,
+            options,
+            operation_deadline,
+            &encryptor,
+            &hasher,
+        )
+        .await
+    }
+
+    pub async fn send_file_rooted_using(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        operation_deadline: Option<Duration>,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<String, Box<dyn Error>> {
+        // Отправка файла вместе с метаданными: метаданные сохраняются в домене, а не в целевом
+        // файле, и файл адресуется единственным корневым хэшем. Целевой файл не изменяется
+        let send = async {
+            let hashes = send_chunks(&path, progress, options, encryptor, hasher).await?; // Отправляем чанки файла
+            let pointer = send_manifest(&hashes, progress, options, encryptor, hasher).await?; // Отправляем чанки метаданных
+            let root = ReedSolomonChunk::send_root(pointer, hasher, options).await?; // Отправляем метаданные метаданных одним чанком
+            if options.embed_erasure_params {
+                send_erasure_header(&root, &hashes, options, encryptor, hasher).await?;
+            }
+            Ok(root)
+        };
+        with_deadline(operation_deadline, progress, send).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_segmented(
+        path: impl AsRef<Path>,
+        segment_size: usize,
+        progress: &Progress,
+        options: &SendOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        send_file_segmented_using(path, segment_size, progress, options, &encryptor, &hasher).await
+    }
+
+    pub async fn send_file_segmented_using(
+        path: impl AsRef<Path>,
+        segment_size: usize,
+        progress: &Progress,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка большого файла сегментами не больше segment_size байт: каждый сегмент отправляется
+        // как отдельный файл, его метаданные сохраняются в домене, а в файл метаданных
+        // (см. manifest_path) записывается индекс сегментов. Получение такого файла выполняется обычным recv_file
+        let source_attrs = match options.preserve_attrs {
+            true => Some(SourceAttrs::read(&path)?),
+            false => None,
+        };
+        let content = fs::read(&path).await?;
+        let mut index = SegmentIndex {
+            segment_size,
+            original_size: content.len() as u64,
+            segments: Vec::new(),
+            source_attrs,
+            tag: None,
+        };
+        for range in segments::ranges(content.len(), segment_size)? {
+            let mut chunks = ReedSolomonChunks::from_bytes_chunked(
+                content[range.clone()].to_vec(),
+                Backend::preferred(),
+                encryptor.block_size(),
+                options.chunker,
+                options.max_chunks_per_file,
+            )?
+            .with_options(options); // Получаем чанки сегмента
+            chunks.encrypt(encryptor)?;
+            chunks.update_hashes(hasher)?;
+            chunks.check_manifest_size(hasher, options.max_manifest_size)?;
+            let mut hashes = chunks.send(progress).await?; // Отправляем чанки сегмента
+            hashes.attach_tag(encryptor);
+            index.segments.push(Segment {
+                offset: range.start as u64,
+                len: range.len(),
+                pointer: send_manifest(&hashes, progress, options, encryptor, hasher).await?, // Отправляем метаданные сегмента
+            });
+        }
+        index.attach_tag(encryptor); // Заверяем индекс ключом шифровальщика
+        index
+            .save_to(manifest_path(path, options.consume_source))
+            .await // Сохраняем индекс сегментов
+    }
+
+    async fn recv_segments(
+        index: SegmentIndex,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        options: &RecvOptions,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Получение файла по индексу сегментов: сегменты получаются и записываются по порядку
+        index.verify_tag(decryptor, options.allow_untagged)?; // Проверяем, что сегменты не подменены
+        let mut out: Box<dyn AsyncWrite + Unpin> = match output.as_ref() == Path::new(STDOUT_PATH) {
+            true => Box::new(tokio::io::stdout()),
+            false => Box::new(fs::File::create(&output).await?),
+        };
+        for segment in &index.segments {
+            let hashes = recv_manifest(&segment.pointer, progress, options, decryptor).await?; // Получаем метаданные сегмента
+            hashes.verify_tag(decryptor, options.allow_untagged)?;
+            let mut chunks =
+                ReedSolomonChunks::recv_with_options(&hashes, progress, options).await?;
+            if options.verify {
+                chunks.verify(&hashes, hasher)?;
+            }
+            chunks.decrypt(decryptor)?;
+            out.write_all(&chunks.into_bytes()?).await?; // Записываем содержимое сегмента
+        }
+        out.flush().await?;
+        drop(out);
+        restore_source_attrs(output, index.source_attrs.as_ref())
+    }
+
+    async fn send_chunks(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<ReedSolomonChunksHashes, Box<dyn Error>> {
+        let source_attrs = match options.preserve_attrs {
+            true => Some(SourceAttrs::read(&path)?), // Атрибуты читаются до замены файла метаданными
+            false => None,
+        };
+        let mut chunks = ReedSolomonChunks::from_file_chunked(
+            &path,
+            encryptor,
+            options.chunker,
+            options.max_chunks_per_file,
+        )
+        .await?
+        .with_options(options); // Получаем чанки
+        chunks.encrypt(encryptor)?; // Шифруем их
+        chunks.update_hashes(hasher)?; // Обновляем их хэш-суммы
+        chunks.check_manifest_size(hasher, options.max_manifest_size)?; // Проверяем размер метаданных до отправки
+        let mut hashes = chunks.send(progress).await?; // Отправляем чанки в домен и получаем назад их хэш-суммы
+        hashes.attach_tag(encryptor); // Заверяем список чанков ключом шифровальщика
+        if let Some(attrs) = source_attrs {
+            hashes.set_source_attrs(attrs);
+        }
+        Ok(hashes)
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_dir(
+        dir: impl AsRef<Path>,
+        manifest: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        send_dir_using(dir, manifest, progress, options, &encryptor, &hasher).await
+    }
+
+    pub async fn send_dir_using(
+        dir: impl AsRef<Path>,
+        manifest: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка каталога целиком: каждый файл отправляется отдельно, а метаданные всех файлов
+        // сохраняются в одном файле метаданных каталога. Исходные файлы не изменяются.
+        // Каталоги (в том числе пустые) и символические ссылки сохраняются только в метаданных
+        let dir = dir.as_ref();
+        let mut entries = Vec::new();
+        for (path, entry) in tree::walk(dir).await? {
+            let kind = match entry {
+                WalkEntry::Dir => EntryKind::Dir,
+                WalkEntry::Symlink(target) => EntryKind::Symlink(target),
+                WalkEntry::File => EntryKind::File(Box::new(
+                    send_chunks(dir.join(&path), progress, options, encryptor, hasher).await?,
+                )),
+            };
+            entries.push(DirEntry { path, kind });
+        }
+        let mut dir_manifest = DirManifest { entries, tag: None };
+        dir_manifest.attach_tag(encryptor); // Заверяем метаданные каталога ключом шифровальщика
+        dir_manifest.save_to(manifest).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file_detailed(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+    ) -> Result<Vec<ChunkSendResult>, Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        send_file_detailed_using(path, progress, options, &encryptor, &hasher).await
+    }
+
+    pub async fn send_file_detailed_using(
+        path: impl AsRef<Path>,
+        progress: &Progress,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<Vec<ChunkSendResult>, Box<dyn Error>> {
+        // Отправка файла с результатом по каждому чанку. Метаданные сохраняются и при частичной
+        // отправке: вызывающий решает, достаточно ли избыточности или нужно повторить отправку,
+        // поэтому исходный файл не заменяется метаданными независимо от consume_source
+        let mut chunks = ReedSolomonChunks::from_file_chunked(
+            &path,
+            encryptor,
+            options.chunker,
+            options.max_chunks_per_file,
+        )
+        .await?
+        .with_options(options);
+        chunks.encrypt(encryptor)?;
+        chunks.update_hashes(hasher)?;
+        chunks.check_manifest_size(hasher, options.max_manifest_size)?;
+        let (mut hashes, results) = chunks.send_detailed(progress).await?;
+        hashes.attach_tag(encryptor); // Заверяем список чанков ключом шифровальщика
+        hashes.set_format(options.manifest_format);
+        hashes.save_to(manifest_path(path, false)).await?;
+        Ok(results)
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct DryRunReport {
+        // Результаты проверки отправки файла без передачи данных
+        pub stats: ChunksStats, // Статистика хранения, которая получится при отправке
+        pub peers: usize,       // Количество ответивших серверов домена
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn dry_run(
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+    ) -> Result<DryRunReport, Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        dry_run_using(path, options, &encryptor, &hasher).await
+    }
+
+    pub async fn dry_run_using(
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<DryRunReport, Box<dyn Error>> {
+        // Выполнение локальных этапов отправки (чтение, шифрование, хэширование) и проверка доступности домена
+        // Файл разбивается так же, как при отправке с теми же параметрами, поэтому превышение
+        // ограничения числа чанков обнаруживается до отправки
+        let mut chunks = ReedSolomonChunks::from_file_chunked(
+            &path,
+            encryptor,
+            options.chunker,
+            options.max_chunks_per_file,
+        )
+        .await?;
+        chunks.encrypt(encryptor)?;
+        chunks.update_hashes(hasher)?;
+        chunks.check_manifest_size(hasher, options.max_manifest_size)?;
+        let peers = discovery::ping(PING_WINDOW, discovery::MAX_PEERS).await?; // Проверяем, отвечают ли серверы
+        Ok(DryRunReport {
+            stats: chunks.stats(),
+            peers: peers.len(),
+        })
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        recv_file_with_progress(path, output, &Progress::default()).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file_with_progress(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+    ) -> Result<(), Box<dyn Error>> {
+        recv_file_with_attrs(path, output, progress, &OutputAttrs::default()).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file_with_attrs(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        attrs: &OutputAttrs,
+    ) -> Result<(), Box<dyn Error>> {
+        recv_file_with_retry(path, output, progress, attrs, &RecoveryRetry::default()).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file_with_retry(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        attrs: &OutputAttrs,
+        retry: &RecoveryRetry,
+    ) -> Result<(), Box<dyn Error>> {
+        let options = RecvOptions {
+            retry: retry.clone(),
+            ..RecvOptions::default()
+        };
+        recv_file_with_deadline(path, output, progress, attrs, &options, None).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file_with_deadline(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (decryptor, hasher) = gost().await?;
+        recv_file_using(
+            path,
+            output,
+            progress,
+            attrs,
+            options,
+            operation_deadline,
+            &decryptor,
+            &hasher,
+        )
+        .await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file_with_password(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        password: &[u8],
+        progress: &Progress,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Получение файла, отправленного в переносимом режиме: шифровальщик восстанавливается
+        // по паролю и параметрам из метаданных файла
+        let recv = async {
+            let hashes = ReedSolomonChunksHashes::load_from(&path).await?;
+            let material = hashes.key_material().ok_or(MissingKeyMaterialError)?;
+            let decryptor = KuznechikEncryptor::from_key_material(password, material).await?;
+            recv_chunks(
+                hashes,
+                &output,
+                progress,
+                options,
+                &decryptor,
+                &StreebogHasher,
+            )
+            .await
+        };
+        with_deadline(operation_deadline, progress, recv).await?;
+        apply_attrs(output, attrs)
+    }
+
+    pub async fn recv_file_with_registry(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+        operation_deadline: Option<Duration>,
+        registry: &Registry,
+    ) -> Result<(), Box<dyn Error>> {
+        // Получение файла реализациями, указанными в его метаданных. Для метаданных без описания
+        // реализаций (отправленных без набора, индексов сегментов) используются основные реализации набора
+        let stored = ReedSolomonChunksHashes::load_from(&path)
+            .await
+            .ok()
+            .and_then(|h| h.algorithms().cloned());
+        let algorithms = match stored {
+            Some(algorithms) => algorithms,
+            None => registry.primary()?,
+        };
+        let (decryptor, hasher) = registry.select(&algorithms)?;
+        recv_file_using(
+            path,
+            output,
+            progress,
+            attrs,
+            options,
+            operation_deadline,
+            decryptor,
+            hasher,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)] // Параметры получения и реализации шифрования и хэширования
+    pub async fn recv_file_using(
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+        operation_deadline: Option<Duration>,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        let recv = async {
+            if let Some(index) = SegmentIndex::load_from(&path).await? {
+                // Файл отправлен сегментами
+                return recv_segments(index, &output, progress, options, decryptor, hasher).await;
+            }
+            let hashes = ReedSolomonChunksHashes::load_from(&path).await?; // Получаем хэш-суммы из файла
+            recv_chunks(hashes, &output, progress, options, decryptor, hasher).await
+        };
+        with_deadline(operation_deadline, progress, recv).await?;
+        apply_attrs(output, attrs) // Устанавливаем права доступа и владельца файла результата
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file_by_root(
+        root: &str,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+        operation_deadline: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (decryptor, hasher) = gost().await?;
+        recv_file_by_root_using(
+            root,
+            output,
+            progress,
+            attrs,
+            options,
+            operation_deadline,
+            &decryptor,
+            &hasher,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn recv_file_by_root_using(
+        root: &str,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+        operation_deadline: Option<Duration>,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Получение файла по корневому хэшу: сначала из домена получаются метаданные, затем чанки файла
+        let recv = async {
+            let pointer = ReedSolomonChunk::recv_root(root, options).await?; // Получаем метаданные метаданных
+            let mut hashes =
+                recv_manifest(&pointer, &Progress::default(), options, decryptor).await?; // Получаем метаданные файла
+            if hashes.params_missing() {
+                // Параметры кода в метаданных утеряны: берем их из заголовка, сохраненного при отправке
+                let params = recv_erasure_header(root, options, decryptor, hasher).await?;
+                hashes.apply_erasure_params(&params)?;
+            }
+            recv_chunks(hashes, &output, progress, options, decryptor, hasher).await
+        };
+        with_deadline(operation_deadline, progress, recv).await?;
+        apply_attrs(output, attrs)
+    }
+
+    async fn recv_chunks(
+        hashes: ReedSolomonChunksHashes,
+        output: impl AsRef<Path>,
+        progress: &Progress,
+        options: &RecvOptions,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        hashes.verify_tag(decryptor, options.allow_untagged)?; // Проверяем, что список чанков не подменен
+        let mut journal = match options.resume && output.as_ref() != Path::new(STDOUT_PATH) {
+            true => Some(RecvJournal::open(&output, &hashes).await?), // Журнал для продолжения прерванного получения
+            false => None,
+        };
+        let mut chunks =
+            ReedSolomonChunks::recv_with_journal(&hashes, progress, options, journal.as_mut())
+                .await?; // Получаем чанки по хэшам
+        if options.verify {
+            chunks.verify(&hashes, hasher)?; // Проверяем хэш-суммы до восстановления файла
+        }
+        chunks.decrypt(decryptor)?; // Расшифровываем чанки
+        match journal {
+            Some(journal) => {
+                // Файл результата заменяется целиком, после чего журнал больше не нужен
+                write_replacing(&output, &chunks.into_bytes()?).await?;
+                journal.finish().await?;
+            }
+            None => chunks.into_file(&output).await?, // Восстанавливаем из них содержимое и записываем его в файл результата
+        }
+        restore_source_attrs(output, hashes.source_attrs())
+    }
+
+    fn restore_source_attrs(
+        output: impl AsRef<Path>,
+        attrs: Option<&SourceAttrs>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Восстановление сохраненных атрибутов исходного файла (кроме стандартного вывода)
+        match attrs {
+            Some(attrs) if output.as_ref() != Path::new(STDOUT_PATH) => Ok(attrs.restore(output)?),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_dir(
+        manifest: impl AsRef<Path>,
+        target: impl AsRef<Path>,
+        progress: &Progress,
+        options: &RecvOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let (decryptor, hasher) = gost().await?;
+        recv_dir_using(manifest, target, progress, options, &decryptor, &hasher).await
+    }
+
+    pub async fn recv_dir_using(
+        manifest: impl AsRef<Path>,
+        target: impl AsRef<Path>,
+        progress: &Progress,
+        options: &RecvOptions,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Восстановление каталога по метаданным каталога. Символические ссылки воссоздаются
+        // только в Unix-системах, в остальных они пропускаются
+        let target = target.as_ref();
+        fs::create_dir_all(target).await?;
+        let dir_manifest = DirManifest::load_from(manifest).await?;
+        dir_manifest.verify_tag(decryptor, options.allow_untagged)?; // Проверяем метаданные до создания файлов
+        for entry in dir_manifest.entries {
+            let path = tree::target_path(target, &entry.path)?;
+            match entry.kind {
+                EntryKind::Dir => fs::create_dir_all(&path).await?,
+                EntryKind::File(hashes) => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent).await?;
+                    }
+                    recv_chunks(*hashes, &path, progress, options, decryptor, hasher).await?;
+                }
+                #[cfg(unix)]
+                EntryKind::Symlink(link) => fs::symlink(link, &path).await?,
+                #[cfg(not(unix))]
+                EntryKind::Symlink(_) => {}
+            }
+        }
         Ok(())
     }
+
+    fn apply_attrs(output: impl AsRef<Path>, attrs: &OutputAttrs) -> Result<(), Box<dyn Error>> {
+        // Установка прав доступа и владельца восстановленного файла (кроме стандартного вывода)
+        if attrs.is_empty() || output.as_ref() == Path::new(STDOUT_PATH) {
+            return Ok(());
+        }
+        Ok(attrs.apply(output)?)
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn store_file(
+        key: &str,
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        store_file_using(key, path, options, &encryptor, &hasher).await
+    }
+
+    pub async fn store_file_using(
+        key: &str,
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка файла под пользовательским ключом. Метаданные файла отправляются в домен
+        // как обычный файл, а их собственные (небольшие) метаданные - одним чанком под хэшем ключа
+        let progress = Progress::default();
+
+        let hashes = send_chunks(&path, &progress, options, encryptor, hasher).await?; // Отправляем чанки файла
+
+        let pointer = send_manifest(&hashes, &progress, options, encryptor, hasher).await?; // Отправляем чанки метаданных
+        ReedSolomonChunk::send_keyed(key, pointer, hasher, options).await // Отправляем метаданные метаданных под ключом
+    }
+
+    async fn send_manifest(
+        hashes: &ReedSolomonChunksHashes,
+        progress: &Progress,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Отправка метаданных файла в домен как обычного содержимого. Возвращает зашифрованные
+        // метаданные метаданных, которые помещаются в один чанк. Метаданные отправляются с теми
+        // же параметрами, что и чанки файла, и подчиняются тем же ограничениям числа чанков и размера метаданных
+        let mut chunks = ReedSolomonChunks::from_bytes_chunked(
+            hashes.to_bytes()?,
+            Backend::preferred(),
+            encryptor.block_size(),
+            Chunker::Fixed,
+            options.max_chunks_per_file,
+        )?
+        .with_options(options); // Получаем чанки метаданных
+        chunks.encrypt(encryptor)?;
+        chunks.update_hashes(hasher)?;
+        chunks.check_manifest_size(hasher, options.max_manifest_size)?;
+        let mut hashes = chunks.send(progress).await?; // Отправляем чанки метаданных
+        hashes.attach_tag(encryptor);
+        Ok(encryptor.encrypt_chunk(&hashes.to_bytes()?)?) // Шифруем метаданные метаданных
+    }
+
+    fn erasure_header_key(root: &str) -> String {
+        format!("{}{}", ERASURE_HEADER_PREFIX, root) // Ключ заголовка, производный от корневого хэша
+    }
+
+    async fn send_erasure_header(
+        root: &str,
+        hashes: &ReedSolomonChunksHashes,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Отправка параметров кода отдельным небольшим чанком под ключом, производным от корневого
+        // хэша: файл восстанавливается по корневому хэшу, даже если эти поля метаданных утеряны
+        let header = encryptor.encrypt_chunk(&serde_json::to_vec(&hashes.erasure_params())?)?;
+        ReedSolomonChunk::send_keyed(&erasure_header_key(root), header, hasher, options).await
+    }
+
+    async fn recv_erasure_header(
+        root: &str,
+        options: &RecvOptions,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<ErasureParams, Box<dyn Error>> {
+        let header =
+            ReedSolomonChunk::recv_keyed(&erasure_header_key(root), hasher, options).await?;
+        let header = decryptor.decrypt_chunk(&header)?;
+        let header_len = header.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1); // Удаляем дополнение шифрования
+        Ok(serde_json::from_slice(&header[..header_len])?)
+    }
+
+    async fn recv_manifest(
+        pointer: &[u8],
+        progress: &Progress,
+        options: &RecvOptions,
+        decryptor: &dyn Encryptor,
+    ) -> Result<ReedSolomonChunksHashes, Box<dyn Error>> {
+        // Получение метаданных файла по зашифрованным метаданным метаданных
+        let pointer = decryptor.decrypt_chunk(pointer)?;
+        let pointer_len = pointer.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1); // Удаляем дополнение шифрования
+        let hashes = ReedSolomonChunksHashes::from_bytes(&pointer[..pointer_len])?;
+        hashes.verify_tag(decryptor, options.allow_untagged)?;
+
+        let mut chunks = ReedSolomonChunks::recv_with_options(&hashes, progress, options).await?; // Получаем чанки метаданных
+        chunks.decrypt(decryptor)?;
+        ReedSolomonChunksHashes::from_bytes(&chunks.into_bytes()?)
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn fetch_file(
+        key: &str,
+        output: impl AsRef<Path>,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let (decryptor, hasher) = gost().await?;
+        fetch_file_using(key, output, attrs, options, &decryptor, &hasher).await
+    }
+
+    pub async fn fetch_file_using(
+        key: &str,
+        output: impl AsRef<Path>,
+        attrs: &OutputAttrs,
+        options: &RecvOptions,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Получение файла по пользовательскому ключу
+        let progress = Progress::default();
+
+        let pointer = ReedSolomonChunk::recv_keyed(key, hasher, options).await?; // Получаем метаданные метаданных
+        let hashes = recv_manifest(&pointer, &progress, options, decryptor).await?; // Получаем метаданные файла
+        recv_chunks(hashes, &output, &progress, options, decryptor, hasher).await?; // Получаем чанки файла
+        apply_attrs(output, attrs)
+    }
+
+    pub async fn send_chunk_with_meta(
+        data: Vec<u8>,
+        meta: Vec<u8>,
+        hasher: &dyn Hasher,
+        options: &SendOptions,
+    ) -> Result<String, Box<dyn Error>> {
+        // Отправка одиночного чанка с небольшими метаданными (например, типом содержимого),
+        // которые можно получить функцией chunk_meta без получения чанка. Возвращает хэш чанка
+        ReedSolomonChunk::send_with_meta(data, Some(meta), hasher, options).await
+    }
+
+    pub async fn chunk_meta(hash: &str, options: &RecvOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+        ReedSolomonChunk::recv_meta(hash, options).await // Получение метаданных чанка, чанк остается в домене
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recover_offline(
+        manifest: impl AsRef<Path>,
+        chunks_dirs: &[impl AsRef<Path>],
+        output: impl AsRef<Path>,
+        conflict: ShardConflict,
+    ) -> Result<(), Box<dyn Error>> {
+        let (decryptor, hasher) = gost().await?;
+        recover_offline_using(manifest, chunks_dirs, output, conflict, &decryptor, &hasher).await
+    }
+
+    pub async fn recover_offline_using(
+        manifest: impl AsRef<Path>,
+        chunks_dirs: &[impl AsRef<Path>],
+        output: impl AsRef<Path>,
+        conflict: ShardConflict,
+        decryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<(), Box<dyn Error>> {
+        // Восстановление файла по метаданным из каталогов с файлами чанков (например, с дисков
+        // вышедших из строя серверов) без обращения к домену
+        let hashes = ReedSolomonChunksHashes::load_from(&manifest).await?; // Получаем хэш-суммы из файла
+        let source_attrs = hashes.source_attrs().cloned();
+        let mut chunks =
+            ReedSolomonChunks::from_dirs(hashes, chunks_dirs, hasher, conflict).await?; // Находим чанки в каталогах
+        chunks.decrypt(decryptor)?; // Расшифровываем чанки
+        chunks.into_file(&output).await?; // Восстанавливаем из них содержимое файла
+        restore_source_attrs(output, source_attrs.as_ref())
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn diff_file(
+        path: impl AsRef<Path>,
+        manifest: impl AsRef<Path>,
+    ) -> Result<FileDiff, Box<dyn Error>> {
+        let (encryptor, hasher) = gost().await?;
+        diff_file_using(path, manifest, &encryptor, &hasher).await
+    }
+
+    pub async fn diff_file_using(
+        path: impl AsRef<Path>,
+        manifest: impl AsRef<Path>,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<FileDiff, Box<dyn Error>> {
+        // Сравнение локального файла с метаданными отправленного без обращения к домену
+        let hashes = ReedSolomonChunksHashes::load_from(manifest).await?;
+        ReedSolomonChunks::diff(path, &hashes, encryptor, hasher).await
+    }
+
+    pub async fn file_info(path: impl AsRef<Path>) -> Result<ChunksStats, Box<dyn Error>> {
+        let hashes = ReedSolomonChunksHashes::load_from(&path).await?; // Получаем хэш-суммы из файла
+        Ok(hashes.stats().clone()) // Статистика читается без обращения к домену
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn repair_file(
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+    ) -> Result<RepairReport, Box<dyn Error>> {
+        let hashes = ReedSolomonChunksHashes::load_from(&path).await?;
+        if hashes.key_material().is_some() {
+            return Err(Box::new(PasswordRequiredError)); // Файл зашифрован не общей гаммой
+        }
+        let (encryptor, hasher) = gost().await?;
+        repair_file_using(path, options, &encryptor, &hasher).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn repair_file_with_password(
+        path: impl AsRef<Path>,
+        password: &[u8],
+        options: &SendOptions,
+    ) -> Result<RepairReport, Box<dyn Error>> {
+        // Восстановление файла, отправленного в переносимом режиме: шифровальщик восстанавливается
+        // по паролю и параметрам из метаданных файла, параметры сохраняются в новых метаданных
+        let hashes = ReedSolomonChunksHashes::load_from(&path).await?;
+        let material = hashes.key_material().ok_or(MissingKeyMaterialError)?;
+        let encryptor = KuznechikEncryptor::from_key_material(password, material).await?;
+        repair_file_using(path, options, &encryptor, &StreebogHasher).await
+    }
+
+    pub async fn repair_file_using(
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<RepairReport, Box<dyn Error>> {
+        let hashes = ReedSolomonChunksHashes::load_from(&path).await?; // Получаем хэш-суммы из файла
+        let (hashes, report) =
+            ReedSolomonChunks::repair(hashes, encryptor, hasher, options).await?; // Восстанавливаем и заново отправляем чанки
+        hashes.save_to(path).await?; // Сохраняем новые хэш-суммы в целевом файле
+        Ok(report)
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn rekey_file(
+        path: impl AsRef<Path>,
+        old_key: &impl KeyProvider,
+        new_key: &impl KeyProvider,
+        options: &SendOptions,
+    ) -> Result<RepairReport, Box<dyn Error>> {
+        let hashes = ReedSolomonChunksHashes::load_from(&path).await?;
+        if hashes.key_material().is_some() {
+            return Err(Box::new(PasswordRequiredError)); // Ключ файла вычисляется из пароля, а не из источника ключа
+        }
+        let decryptor = KuznechikEncryptor::with_key_provider(old_key).await?;
+        let encryptor = KuznechikEncryptor::with_key_provider(new_key).await?;
+        rekey_file_using(path, options, &decryptor, &encryptor, &StreebogHasher).await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn rekey_file_with_password(
+        path: impl AsRef<Path>,
+        old_password: &[u8],
+        new_password: &[u8],
+        options: &SendOptions,
+    ) -> Result<RepairReport, Box<dyn Error>> {
+        // Смена пароля файла, отправленного в переносимом режиме: чанки перешифровываются новыми
+        // гаммой и ключом, в метаданные записываются новые параметры шифрования
+        let hashes = ReedSolomonChunksHashes::load_from(&path).await?;
+        let material = hashes.key_material().ok_or(MissingKeyMaterialError)?;
+        let decryptor = KuznechikEncryptor::from_key_material(old_password, material).await?;
+        let (encryptor, material) = KuznechikEncryptor::with_password(new_password).await?;
+        let (mut hashes, report) =
+            ReedSolomonChunks::rekey(hashes, &decryptor, &encryptor, &StreebogHasher, options)
+                .await?;
+        hashes.set_key_material(material);
+        hashes.save_to(path).await?; // Сохраняем новые хэш-суммы в целевом файле
+        Ok(report)
+    }
+
+    pub async fn rekey_file_using(
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+        decryptor: &dyn Encryptor,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<RepairReport, Box<dyn Error>> {
+        // Перешифрование файла новым ключом при компрометации старого. Затрагивает каждый чанк
+        // файла (получение, перешифрование и повторная отправка), поэтому выполняется только
+        // при действительной необходимости смены ключа
+        let hashes = ReedSolomonChunksHashes::load_from(&path).await?; // Получаем хэш-суммы из файла
+        let (hashes, report) =
+            ReedSolomonChunks::rekey(hashes, decryptor, encryptor, hasher, options).await?; // Перешифровываем и заново отправляем чанки
+        hashes.save_to(path).await?; // Сохраняем новые хэш-суммы в целевом файле
+        Ok(report)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::crypto::DecryptionError;
+        use crate::Message;
+        use tokio::net::UdpSocket;
+
+        struct Plain; // Шифровальщик для проверки: данные не изменяются
+
+        impl Encryptor for Plain {
+            fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+                Ok(chunk.to_vec())
+            }
+
+            fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+                Ok(chunk.to_vec())
+            }
+        }
+
+        struct FnvHasher; // Хэш-вычислитель для проверки без совпадений хэшей разных чанков
+
+        impl Hasher for FnvHasher {
+            fn calc_hash(&self, chunk: &[u8]) -> String {
+                let hash = chunk.iter().fold(0xcbf29ce484222325u64, |h, &b| {
+                    (h ^ b as u64).wrapping_mul(0x100000001b3)
+                });
+                format!("{:016x}", hash)
+            }
+        }
+
+        async fn acking_server() -> std::net::SocketAddr {
+            // Сервер для проверки: подтверждает отправку и сохранение любого чанка
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = socket.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 65535];
+                while let Ok((sz, from)) = socket.recv_from(&mut buf).await {
+                    let reply = match Message::from_slice(&buf[..sz]) {
+                        Ok(Message::SendingReq(h, t)) => Message::SendingAck(h, u64::MAX, t),
+                        Ok(Message::ContentFilled(h, _, t)) => Message::ContentStored(h, t),
+                        _ => continue,
+                    };
+                    let _ = socket.send_to(&reply.into_bytes().unwrap(), from).await;
+                }
+            });
+            addr
+        }
+
+        #[tokio::test]
+        async fn source_is_replaced_only_after_successful_send() {
+            let path = std::env::temp_dir().join(format!("leaf-source-{}", rand::random::<u64>()));
+            let content: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+            fs::write(&path, &content).await.unwrap();
+            let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // Сервер, не отвечающий на запросы
+            let mut options = SendOptions {
+                peers: vec![silent.local_addr().unwrap()],
+                consume_source: true,
+                ack_window: Duration::from_millis(10),
+                ..SendOptions::default()
+            };
+
+            let deadline = Some(Duration::from_millis(300));
+            let progress = Progress::default();
+            let sent = send_file_using(&path, &progress, &options, deadline, &Plain, &FnvHasher);
+            assert!(sent.await.is_err());
+            assert_eq!(fs::read(&path).await.unwrap(), content); // Неудачная отправка не затрагивает файл
+            assert!(!manifest_path(&path, false).exists());
+
+            options.peers = vec![acking_server().await];
+            options.consume_source = false;
+            let progress = Progress::default();
+            send_file_using(&path, &progress, &options, None, &Plain, &FnvHasher)
+                .await
+                .unwrap();
+            assert_eq!(fs::read(&path).await.unwrap(), content); // Метаданные записаны рядом
+            let beside = manifest_path(&path, false);
+            let kept = ReedSolomonChunksHashes::load_from(&beside).await.unwrap();
+
+            options.consume_source = true;
+            let progress = Progress::default();
+            send_file_using(&path, &progress, &options, None, &Plain, &FnvHasher)
+                .await
+                .unwrap();
+            let manifest = ReedSolomonChunksHashes::load_from(&path).await.unwrap(); // Файл заменен метаданными
+            assert_eq!(manifest.len(), kept.len());
+            fs::remove_file(&beside).await.unwrap();
+            fs::remove_file(&path).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn oversized_manifest_is_neither_sent_nor_saved() {
+            let path = std::env::temp_dir().join(format!("leaf-source-{}", rand::random::<u64>()));
+            let content: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+            fs::write(&path, &content).await.unwrap();
+            let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let options = SendOptions {
+                peers: vec![silent.local_addr().unwrap()],
+                consume_source: true,
+                max_manifest_size: 100,
+                ..SendOptions::default()
+            };
+
+            let progress = Progress::default();
+            let sent = send_file_using(&path, &progress, &options, None, &Plain, &FnvHasher).await;
+            assert!(sent.unwrap_err().is::<ManifestTooLargeError>());
+            assert_eq!(progress.total(), 0); // Ни один чанк не отправлялся
+            assert_eq!(fs::read(&path).await.unwrap(), content);
+            let mut buf = [0u8; 16];
+            let wait = tokio::time::timeout(Duration::from_millis(50), silent.recv(&mut buf));
+            assert!(wait.await.is_err());
+            fs::remove_file(&path).await.unwrap();
+        }
+    }
 }
@@ -1,16 +1,43 @@
-use bincode::{deserialize, serialize};
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::io::Read; // Чтение распакованных данных с ограничением размера
+use std::net::SocketAddr; // Структура сетевого адреса
+
+use bincode::Options; // Внешняя зависимость для бинарной сериализации
 use serde::{Deserialize, Serialize}; // Внешняя зависимость для (де)сериализации
-use zstd::{decode_all, encode_all}; // Внешняя зависимость для сжатия gzip
+use zstd::{encode_all, Decoder}; // Внешняя зависимость для сжатия zstd
 
+pub use errors::MessageTooComplexError; // Ошибка сообщения, превышающего ограничения разбора
 use errors::*; // Внутренний модуль с составными ошибками
 
+pub const MAX_META_SIZE: usize = 256; // Максимальный размер метаданных, привязываемых к чанку
+pub const MAX_MESSAGE_SIZE: u64 = 128 * 1024; // Максимальный размер сообщения после распаковки (чанк с метаданными и заголовками)
+pub const MAX_LIST_LEN: usize = 1024; // Максимальное количество элементов списка в сообщении (адресов в DISCOVERY_ACK)
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum Message {
     // Тип сообщения
-    SendingReq(String), // Запрос на отправку данных клиентом, содержит только хэш-сумму
-    SendingAck(String), // Подтверждение на отправку от сервера, содержит только хэш-сумму
-    RetrievingReq(String), // Запрос на получение данных клиентом, содержит только хэш-сумму
-    ContentFilled(String, Vec<u8>), // Сообщение с данными, может быть отправлено клиентом или сервером, содержит хэш-сумму и данные
+    SendingReq(String, u64), // Запрос на отправку данных клиентом, содержит хэш-сумму и токен отправки
+    SendingAck(String, u64, u64), // Подтверждение на отправку от сервера, содержит хэш-сумму, свободное место сервера в байтах и токен запроса
+    RetrievingReq(String),        // Запрос на получение данных клиентом, содержит только хэш-сумму
+    ContentFilled(String, Vec<u8>, u64), // Сообщение с данными, может быть отправлено клиентом или сервером, содержит хэш-сумму, данные и токен отправки
+    DiscoveryReq,                        // Запрос списка известных серверов
+    DiscoveryAck(Vec<SocketAddr>),       // Ответ со списком известных серверу серверов
+    StoreLocal(String, Vec<u8>, u64), // Явная запись копии чанка на сервер того же узла (принимается только через loopback), подтверждается SENDING_ACK
+    ContentStored(String, u64), // Подтверждение сохранения данных сервером, содержит хэш-сумму и токен отправки
+    ContentFilledMeta(String, Vec<u8>, u64, Vec<u8>), // Данные чанка с метаданными (не более MAX_META_SIZE байт), подтверждается CONTENT_STORED
+    MetaReq(String), // Запрос метаданных чанка без его получения, содержит хэш-сумму
+    MetaAck(String, Vec<u8>), // Ответ с метаданными чанка, содержит хэш-сумму и метаданные
+    HasReq(String), // Запрос наличия чанка без его получения (сервер, хранящий чанк, отвечает HAS_ACK), содержит хэш-сумму
+    HasAck(String), // Подтверждение наличия чанка, содержит хэш-сумму
+}
+
+fn codec() -> impl Options {
+    // Параметры бинарного кодирования: целые фиксированной длины в сетевом порядке байт (big-endian),
+    // чтобы сообщение одинаково разбиралось узлами с любым порядком байт
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
+        .allow_trailing_bytes()
 }
 
 impl Message {
@@ -18,7 +45,8 @@ impl Message {
         // Метод перевода сообщения в двоичный формат
         encode_all(
             // Сжатие
-            serialize(&self) // Сериализация в бинарный вид
+            codec()
+                .serialize(&self) // Сериализация в бинарный вид
                 .map_err(|e| IntoBytesCastError(e.to_string()))?
                 .as_slice(),
             3,
@@ -26,12 +54,41 @@ impl Message {
         .map_err(|e| IntoBytesCastError(e.to_string()))
     }
 
-    pub fn from_bytes(value: Vec<u8>) -> Result<Message, FromBytesCastError> {
-        deserialize::<Message>(
-            // Десериализация
-            &decode_all(value.as_slice()).map_err(|e| FromBytesCastError(e.to_string()))?, // Декомпрессия
-        )
-        .map_err(|e| FromBytesCastError(e.to_string()))
+    pub fn from_bytes(value: Vec<u8>) -> Result<Message, Box<dyn Error + Send + Sync>> {
+        Self::from_slice(&value)
+    }
+
+    pub fn from_slice(value: &[u8]) -> Result<Message, Box<dyn Error + Send + Sync>> {
+        // Метод перевода сообщения из двоичного формата без передачи владения буфером. Сообщение
+        // приходит из сети, поэтому размер распакованных данных и длины списков ограничены до
+        // выделения памяти: иначе небольшой пакет мог бы развернуться в гигабайты. Вложенных
+        // структур сообщение не содержит, поэтому глубина разбора ограничена самим перечислением
+        let mut raw = Vec::new();
+        Decoder::new(value)
+            .map_err(|e| FromBytesCastError(e.to_string()))?
+            .take(MAX_MESSAGE_SIZE + 1)
+            .read_to_end(&mut raw)
+            .map_err(|e| FromBytesCastError(e.to_string()))?; // Декомпрессия
+        if raw.len() as u64 > MAX_MESSAGE_SIZE {
+            return Err(Box::new(MessageTooComplexError(format!(
+                "unpacked size exceeds {} bytes",
+                MAX_MESSAGE_SIZE
+            ))));
+        }
+        let message = codec()
+            .with_limit(MAX_MESSAGE_SIZE) // Длина строки или вектора больше сообщения отвергается до выделения памяти
+            .deserialize::<Message>(&raw)
+            .map_err(|e| FromBytesCastError(e.to_string()))?;
+        if let Message::DiscoveryAck(peers) = &message {
+            if peers.len() > MAX_LIST_LEN {
+                return Err(Box::new(MessageTooComplexError(format!(
+                    "{} addresses in a list of at most {}",
+                    peers.len(),
+                    MAX_LIST_LEN
+                ))));
+            }
+        }
+        Ok(message)
     }
 }
 
@@ -61,4 +118,54 @@ mod errors {
     }
 
     impl Error for FromBytesCastError {}
+
+    #[derive(Debug, Clone)]
+    pub struct MessageTooComplexError(pub String); // Тип ошибки сообщения, превышающего ограничения разбора
+
+    impl fmt::Display for MessageTooComplexError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Message rejected: {}", self.0)
+        }
+    }
+
+    impl Error for MessageTooComplexError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_are_encoded_big_endian() {
+        // Кодирование не зависит от порядка байт узла: длины и целые поля записываются в big-endian
+        let message = Message::SendingReq("ab".to_string(), 0x0102);
+        let raw = codec().serialize(&message).unwrap();
+        let mut expected = 0u32.to_be_bytes().to_vec(); // Номер варианта
+        expected.extend_from_slice(&2u64.to_be_bytes()); // Длина строки
+        expected.extend_from_slice(b"ab");
+        expected.extend_from_slice(&0x0102u64.to_be_bytes()); // Токен отправки
+        assert_eq!(raw, expected);
+
+        let parsed = Message::from_bytes(message.clone().into_bytes().unwrap()).unwrap();
+        assert!(parsed == message);
+    }
+
+    #[test]
+    fn oversized_and_crafted_messages_are_rejected() {
+        // Сжатый пакет, распаковывающийся больше допустимого размера
+        let bomb = encode_all(vec![0u8; MAX_MESSAGE_SIZE as usize * 4].as_slice(), 19).unwrap();
+        let e = Message::from_slice(&bomb).err().unwrap();
+        assert!(e.is::<MessageTooComplexError>());
+
+        // Длина строки, заявленная больше самого сообщения
+        let mut raw = 0u32.to_be_bytes().to_vec();
+        raw.extend_from_slice(&u64::MAX.to_be_bytes());
+        let crafted = encode_all(raw.as_slice(), 3).unwrap();
+        assert!(Message::from_slice(&crafted).is_err());
+
+        let peers = vec!["127.0.0.1:62092".parse().unwrap(); MAX_LIST_LEN + 1];
+        let long = Message::DiscoveryAck(peers).into_bytes().unwrap();
+        let e = Message::from_slice(&long).err().unwrap();
+        assert!(e.is::<MessageTooComplexError>());
+    }
 }
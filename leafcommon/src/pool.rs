@@ -0,0 +1,52 @@
+use std::sync::OnceLock; // Однократно создаваемый пул потоков
+use std::thread; // Количество ядер процессора
+
+use rayon::{ThreadPool, ThreadPoolBuilder}; // Пул потоков для вычислений
+use tokio::runtime::{Handle, RuntimeFlavor}; // Определение среды выполнения вызывающей задачи
+use tokio::task; // Передача задач других потоков среды выполнения
+
+static CRYPTO_POOL: OnceLock<ThreadPool> = OnceLock::new(); // Пул потоков шифрования и хэширования
+
+pub fn set_crypto_threads(threads: usize) -> bool {
+    // Задание количества потоков шифрования и хэширования. Задается один раз до первого шифрования,
+    // возвращает false, если пул уже создан или не может быть создан
+    match build(threads) {
+        Some(pool) => CRYPTO_POOL.set(pool).is_ok(),
+        None => false,
+    }
+}
+
+pub fn default_crypto_threads() -> usize {
+    // Количество потоков по умолчанию: на одно меньше количества ядер (но не меньше одного), чтобы
+    // ядро оставалось для потоков tokio, обрабатывающих сеть. Потоки пула заняты только во время
+    // шифрования, поэтому больше потоков, чем ядер, не ускоряет его
+    thread::available_parallelism()
+        .map_or(1, |n| n.get().saturating_sub(1))
+        .max(1)
+}
+
+fn build(threads: usize) -> Option<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads.max(1))
+        .thread_name(|i| format!("leaf-crypto-{}", i))
+        .build()
+        .ok()
+}
+
+pub fn crypto_pool() -> &'static ThreadPool {
+    CRYPTO_POOL.get_or_init(|| {
+        build(default_crypto_threads()).expect("unable to start encryption threads")
+    })
+}
+
+pub fn run_crypto<T: Send>(work: impl FnOnce() -> T + Send) -> T {
+    // Выполнение шифрования или хэширования в пуле потоков, отдельном от потоков tokio. Если
+    // вызов выполняется в потоке многопоточной среды tokio, ее задачи передаются другим потокам
+    // на время ожидания, поэтому сетевые задачи не простаивают. В однопоточной среде это
+    // невозможно: ожидание блокирует ее до завершения вычислений
+    let run = || crypto_pool().install(work);
+    match Handle::try_current().map(|h| h.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => task::block_in_place(run),
+        _ => run(),
+    }
+}
@@ -0,0 +1,128 @@
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::path::Path; // Структуры файлового пути
+
+use serde::{Deserialize, Serialize}; // Трейты (де)сериализации
+use tokio::fs; // Асинхронные операции с файловой системой
+use tokio::io::AsyncWriteExt; // Запись файла плана
+
+use crate::attrs::SourceAttrs; // Атрибуты исходного файла
+use crate::chunks::{
+    Chunks, ChunksStats, Progress, ReedSolomonChunks, ReedSolomonChunksHashes, SendOptions,
+}; // Чанки и параметры их отправки
+use crate::crypto::{Encryptor, Hasher}; // Шифрование и хэширование чанков
+
+use consts::*; // Внутренний модуль с константами
+use errors::*; // Внутренний модуль с ошибками
+
+mod consts {
+    // Модуль с константами
+    pub const PLAN_MAGIC: &[u8] = b"LEAFPLAN1\n"; // Начало файла плана отправки (с версией формата)
+    pub const TMP_SUFFIX: &str = ".tmp"; // Суффикс временного файла при записи плана
+    #[cfg(unix)]
+    pub const PLAN_MODE: u32 = 0o600; // Права доступа файла плана: только владелец
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PreparedUpload {
+    // Подготовленная отправка файла: зашифрованные чанки с вычисленными хэшами. Подготовка
+    // (чтение, шифрование, хэширование) выполняется один раз, а отправлять план можно позже
+    // и в любой домен без повторного шифрования. План содержит все данные файла в зашифрованном
+    // виде, поэтому записывается с правами только для владельца
+    chunks: ReedSolomonChunks,
+    tag: Option<String>, // Тег списка чанков, вычисленный ключом шифровальщика при подготовке
+    source_attrs: Option<SourceAttrs>, // Атрибуты исходного файла (только при их сохранении)
+}
+
+impl PreparedUpload {
+    pub async fn prepare(
+        path: impl AsRef<Path>,
+        options: &SendOptions,
+        encryptor: &dyn Encryptor,
+        hasher: &dyn Hasher,
+    ) -> Result<PreparedUpload, Box<dyn Error>> {
+        // Подготовка отправки файла. Из options учитываются только параметры разбиения
+        // (chunker, max_chunks_per_file, max_manifest_size) и preserve_attrs, остальные задаются при отправке
+        let source_attrs = match options.preserve_attrs {
+            true => Some(SourceAttrs::read(&path)?),
+            false => None,
+        };
+        let mut chunks = ReedSolomonChunks::from_file_chunked(
+            &path,
+            encryptor,
+            options.chunker,
+            options.max_chunks_per_file,
+        )
+        .await?;
+        chunks.encrypt(encryptor)?;
+        chunks.update_hashes(hasher)?;
+        chunks.check_manifest_size(hasher, options.max_manifest_size)?;
+        let tag = chunks.manifest_tag(encryptor)?; // Тег вычисляется сейчас: при отправке ключ не нужен
+        Ok(PreparedUpload {
+            chunks,
+            tag,
+            source_attrs,
+        })
+    }
+
+    pub fn stats(&self) -> ChunksStats {
+        self.chunks.stats() // Статистика хранения, которая получится при отправке
+    }
+
+    pub async fn send(
+        self,
+        progress: &Progress,
+        options: &SendOptions,
+    ) -> Result<ReedSolomonChunksHashes, Box<dyn Error>> {
+        // Отправка подготовленных чанков в домен, возвращает метаданные файла. План расходуется:
+        // для отправки в другой домен он заново читается из файла
+        let mut hashes = self.chunks.with_options(options).send(progress).await?;
+        hashes.set_tag(self.tag);
+        if let Some(attrs) = self.source_attrs {
+            hashes.set_source_attrs(attrs);
+        }
+        Ok(hashes)
+    }
+
+    pub async fn save_to(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        // Запись плана через временный файл, созданный сразу с правами только для владельца
+        let mut data = PLAN_MAGIC.to_vec();
+        data.extend(bincode::serialize(self)?);
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(TMP_SUFFIX);
+        let mut open = fs::OpenOptions::new();
+        open.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        open.mode(PLAN_MODE);
+        let mut file = open.open(&tmp).await?;
+        file.write_all(&data).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    pub async fn load_from(path: impl AsRef<Path>) -> Result<PreparedUpload, Box<dyn Error>> {
+        // Чтение плана, записанного save_to
+        let data = fs::read(&path).await?;
+        let content = data
+            .strip_prefix(PLAN_MAGIC)
+            .ok_or(PlanFormatError(path.as_ref().display().to_string()))?;
+        Ok(bincode::deserialize(content)?)
+    }
+}
+
+mod errors {
+    // Модуль с ошибками
+    use std::error::Error;
+    use std::fmt;
+
+    #[derive(Debug, Clone)]
+    pub struct PlanFormatError(pub String);
+    impl fmt::Display for PlanFormatError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} is not a prepared upload plan", self.0)
+        }
+    }
+    impl Error for PlanFormatError {}
+}
@@ -0,0 +1,172 @@
+#[cfg(feature = "gost")]
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+
+use serde::{Deserialize, Serialize}; // Трейты (де)сериализации
+
+use crate::crypto::{Encryptor, Hasher}; // Трейты шифровальщика и хэш-вычислителя
+
+pub use errors::UnknownAlgorithmError; // Ошибка выбора незарегистрированной реализации
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Algorithms {
+    // Описание реализаций, использованных при отправке файла (сохраняется в метаданных файла,
+    // чтобы при получении были выбраны те же реализации)
+    pub encryptor: String, // Имя шифровальщика
+    pub hasher: String,    // Имя хэш-вычислителя
+}
+
+#[derive(Default)]
+pub struct Registry {
+    // Набор реализаций шифрования и хэширования, доступных по именам. При отправке используются
+    // первые зарегистрированные реализации, при получении - указанные в метаданных файла
+    encryptors: Vec<(String, Box<dyn Encryptor>)>,
+    hashers: Vec<(String, Box<dyn Hasher>)>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn gost() -> Result<Registry, Box<dyn Error>> {
+        // Набор с реализациями по ГОСТ: "Кузнечик" и "Стрибог"
+        Ok(Registry::new()
+            .with_encryptor(
+                KUZNYECHIK,
+                Box::new(crate::crypto::KuznechikEncryptor::new().await?),
+            )
+            .with_hasher(
+                STREEBOG,
+                Box::new(crate::crypto::hash::streebog::StreebogHasher),
+            ))
+    }
+
+    pub fn with_encryptor(
+        mut self,
+        name: impl Into<String>,
+        encryptor: Box<dyn Encryptor>,
+    ) -> Self {
+        self.encryptors.push((name.into(), encryptor)); // Повторная регистрация имени заменяет прежнюю
+        self
+    }
+
+    pub fn with_hasher(mut self, name: impl Into<String>, hasher: Box<dyn Hasher>) -> Self {
+        self.hashers.push((name.into(), hasher));
+        self
+    }
+
+    pub fn encryptor(&self, name: &str) -> Result<&dyn Encryptor, UnknownAlgorithmError> {
+        // Поиск шифровальщика по имени (последняя регистрация имени приоритетнее)
+        self.encryptors
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, e)| e.as_ref())
+            .ok_or_else(|| UnknownAlgorithmError(name.to_string()))
+    }
+
+    pub fn hasher(&self, name: &str) -> Result<&dyn Hasher, UnknownAlgorithmError> {
+        // Поиск хэш-вычислителя по имени
+        self.hashers
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, h)| h.as_ref())
+            .ok_or_else(|| UnknownAlgorithmError(name.to_string()))
+    }
+
+    pub fn primary(&self) -> Result<Algorithms, UnknownAlgorithmError> {
+        // Реализации, используемые при отправке: первые зарегистрированные
+        match (self.encryptors.first(), self.hashers.first()) {
+            (Some((encryptor, _)), Some((hasher, _))) => Ok(Algorithms {
+                encryptor: encryptor.clone(),
+                hasher: hasher.clone(),
+            }),
+            _ => Err(UnknownAlgorithmError(String::from("<none registered>"))),
+        }
+    }
+
+    pub fn select(
+        &self,
+        algorithms: &Algorithms,
+    ) -> Result<(&dyn Encryptor, &dyn Hasher), UnknownAlgorithmError> {
+        // Выбор реализаций по описанию из метаданных файла
+        Ok((
+            self.encryptor(&algorithms.encryptor)?,
+            self.hasher(&algorithms.hasher)?,
+        ))
+    }
+}
+
+#[cfg(feature = "gost")]
+const KUZNYECHIK: &str = "kuznyechik-ctr-hmac"; // Имя шифровальщика по ГОСТ Р 34.12-2015 в режиме гаммирования с имитовставкой (файлы прежних форматов им не расшифровываются)
+#[cfg(feature = "gost")]
+const STREEBOG: &str = "streebog"; // Имя хэш-вычислителя по ГОСТ Р 34.11-2012
+
+mod errors {
+    // Модуль с составными ошибками
+    use std::error::Error;
+    use std::fmt;
+
+    #[derive(Debug, Clone)]
+    pub struct UnknownAlgorithmError(pub String); // Ошибка выбора незарегистрированной реализации
+
+    impl fmt::Display for UnknownAlgorithmError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Algorithm {} is not registered", self.0)
+        }
+    }
+
+    impl Error for UnknownAlgorithmError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{DecryptionError, EncryptionError};
+
+    struct Xor(u8); // Шифровальщик для проверки: XOR с постоянным байтом
+
+    impl Encryptor for Xor {
+        fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+            Ok(chunk.iter().map(|b| b ^ self.0).collect())
+        }
+
+        fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+            Ok(chunk.iter().map(|b| b ^ self.0).collect())
+        }
+    }
+
+    struct Len; // Хэш-вычислитель для проверки: хэш - длина чанка
+
+    impl Hasher for Len {
+        fn calc_hash(&self, chunk: &[u8]) -> String {
+            chunk.len().to_string()
+        }
+    }
+
+    #[test]
+    fn registry_selects_implementations_by_name() {
+        let registry = Registry::new()
+            .with_encryptor("xor1", Box::new(Xor(1)))
+            .with_encryptor("xor2", Box::new(Xor(2)))
+            .with_hasher("len", Box::new(Len));
+        let primary = registry.primary().unwrap();
+        assert_eq!(primary.encryptor, "xor1");
+
+        let stored = Algorithms {
+            encryptor: String::from("xor2"),
+            hasher: String::from("len"),
+        };
+        let (encryptor, _) = registry.select(&stored).unwrap();
+        assert_eq!(encryptor.encrypt_chunk(&[0]).unwrap(), vec![2]);
+
+        let unknown = Algorithms {
+            encryptor: String::from("aes"),
+            ..stored
+        };
+        assert!(registry.select(&unknown).is_err());
+        assert!(Registry::new().primary().is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap; // Коллекции стандартной библиотеки
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::io; // Ошибки ввода-вывода стандартной библиотеки
+use std::net::SocketAddr; // Адреса серверов
+use std::path::{Path, PathBuf}; // Структуры файлового пути
+use std::sync::Mutex; // Оценки, обновляемые одновременными отправками
+use std::time::Duration; // Время ответа сервера
+
+use serde::{Deserialize, Serialize}; // Трейты (де)сериализации
+use tokio::fs; // Асинхронные операции с файловой системой
+use tokio::sync::Notify; // Ожидание освобождения места у сервера
+
+use crate::chunks::write_replacing; // Замена файла оценок через временный файл
+
+use consts::*; // Внутренний модуль с константами
+
+mod consts {
+    // Модуль с константами
+    pub const PRIOR_SUCCESSES: f64 = 2.0; // Неизвестный сервер оценивается как выполнивший две передачи успешно: новые серверы не вытесняются известными
+    pub const LATENCY_SCALE_MS: f64 = 200.0; // Время ответа, при котором оценка сервера уменьшается вдвое
+    pub const LATENCY_WEIGHT: f64 = 0.2; // Вес нового измерения в скользящем среднем времени ответа
+    pub const SCORE_TOLERANCE: f64 = 0.1; // Разница оценок, в пределах которой серверы считаются равноценными
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PeerScore {
+    // Накопленные сведения о передачах одному серверу
+    pub successes: u64,  // Количество успешных передач
+    pub failures: u64,   // Количество неудачных передач
+    pub latency_ms: f64, // Скользящее среднее времени ответа, мс (0 - измерений не было)
+}
+
+impl PeerScore {
+    pub fn score(&self) -> f64 {
+        // Оценка сервера от 0 до 1: доля успешных передач, уменьшенная с ростом времени ответа
+        let total = (self.successes + self.failures) as f64;
+        let rate = (self.successes as f64 + PRIOR_SUCCESSES) / (total + PRIOR_SUCCESSES);
+        rate / (1.0 + self.latency_ms / LATENCY_SCALE_MS)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PeerScores {
+    // Оценки надежности серверов по результатам прошлых отправок и получений. Оценки сохраняются
+    // в небольшой файл JSON и учитываются при выборе сервера для чанка и порядке опроса серверов
+    path: Option<PathBuf>, // Файл оценок (None - оценки только в памяти)
+    peers: Mutex<HashMap<SocketAddr, PeerScore>>,
+}
+
+impl PeerScores {
+    pub fn new() -> PeerScores {
+        PeerScores::default() // Оценки без файла
+    }
+
+    pub async fn load(path: impl AsRef<Path>) -> Result<PeerScores, Box<dyn Error>> {
+        // Чтение оценок из файла (отсутствующий файл - оценок еще нет)
+        let path = path.as_ref().to_path_buf();
+        let peers = match fs::read(&path).await {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        Ok(PeerScores {
+            path: Some(path),
+            peers: Mutex::new(peers),
+        })
+    }
+
+    pub async fn save(&self) -> Result<(), Box<dyn Error>> {
+        // Запись оценок в файл, из которого они были прочитаны
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = serde_json::to_vec_pretty(&*self.peers.lock().unwrap())?;
+        write_replacing(path, &content).await
+    }
+
+    pub fn success(&self, peer: SocketAddr, latency: Duration) {
+        // Учет успешной передачи и времени ответа сервера
+        let mut peers = self.peers.lock().unwrap();
+        let score = peers.entry(peer).or_default();
+        let latency = latency.as_secs_f64() * 1000.0;
+        score.latency_ms = match score.successes {
+            0 => latency,
+            _ => score.latency_ms + LATENCY_WEIGHT * (latency - score.latency_ms),
+        };
+        score.successes += 1;
+    }
+
+    pub fn failure(&self, peer: SocketAddr) {
+        self.peers.lock().unwrap().entry(peer).or_default().failures += 1; // Учет неудачной передачи
+    }
+
+    pub fn score(&self, peer: SocketAddr) -> f64 {
+        let peers = self.peers.lock().unwrap();
+        peers.get(&peer).copied().unwrap_or_default().score() // Оценка сервера (неизвестный - наилучшая)
+    }
+
+    pub fn ranked(&self) -> Vec<(SocketAddr, PeerScore)> {
+        // Известные серверы по убыванию оценки
+        let mut peers: Vec<_> = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(a, s)| (*a, *s))
+            .collect();
+        peers.sort_by(|(a, x), (b, y)| y.score().total_cmp(&x.score()).then(a.cmp(b)));
+        peers
+    }
+
+    pub fn order(&self, peers: &mut [SocketAddr]) {
+        // Упорядочивание серверов по убыванию оценки: запросы первыми получают надежные серверы
+        peers.sort_by(|a, b| self.score(*b).total_cmp(&self.score(*a)));
+    }
+}
+
+pub fn place(
+    scores: Option<&PeerScores>,
+    candidates: &[(SocketAddr, u64)],
+) -> Option<(SocketAddr, u64)> {
+    // Выбор сервера для чанка среди подтвердивших отправку (адрес и свободное место): из серверов,
+    // оценка которых ниже наилучшей не более чем на SCORE_TOLERANCE, - с наибольшим свободным местом.
+    // Без оценок выбирается сервер с наибольшим свободным местом
+    let score = |addr| scores.map_or(1.0, |s| s.score(addr));
+    let best = candidates
+        .iter()
+        .map(|&(a, _)| score(a))
+        .fold(f64::MIN, f64::max);
+    candidates
+        .iter()
+        .filter(|&&(a, _)| score(a) >= best - SCORE_TOLERANCE)
+        .fold(None, |chosen, &(a, free)| match chosen {
+            Some((_, f)) if f >= free => chosen,
+            _ => Some((a, free)),
+        })
+}
+
+#[derive(Debug)]
+pub struct PeerLoad {
+    // Ограничение количества чанков, одновременно передаваемых одному серверу. Выбор сервера
+    // с наибольшим местом или лучшей оценкой отдает ему все чанки партии; с ограничением чанк
+    // получает лучший из подтвердивших серверов, у которого есть свободное место, а если заняты
+    // все, отправка ждет завершения одной из передач
+    cap: usize, // Количество одновременных передач одному серверу
+    active: Mutex<HashMap<SocketAddr, usize>>, // Текущее количество передач каждому серверу
+    freed: Notify, // Уведомление о завершении передачи
+}
+
+pub struct PeerSlot<'a> {
+    // Место передачи чанка серверу, освобождается при удалении
+    load: &'a PeerLoad,
+    pub addr: SocketAddr, // Выбранный сервер
+}
+
+impl PeerLoad {
+    pub fn new(cap: usize) -> PeerLoad {
+        PeerLoad {
+            cap: cap.max(1),
+            active: Mutex::new(HashMap::new()),
+            freed: Notify::new(),
+        }
+    }
+
+    pub async fn reserve(
+        &self,
+        scores: Option<&PeerScores>,
+        candidates: &[(SocketAddr, u64)],
+    ) -> Option<PeerSlot<'_>> {
+        // Выбор сервера среди подтвердивших отправку (как в place) с занятием места передачи.
+        // None - подтверждений не было
+        if candidates.is_empty() {
+            return None;
+        }
+        loop {
+            let freed = self.freed.notified(); // Создается до проверки: освобождение не пропускается
+            if let Some(addr) = self.try_reserve(scores, candidates) {
+                return Some(PeerSlot { load: self, addr });
+            }
+            freed.await;
+        }
+    }
+
+    fn try_reserve(
+        &self,
+        scores: Option<&PeerScores>,
+        candidates: &[(SocketAddr, u64)],
+    ) -> Option<SocketAddr> {
+        let mut active = self.active.lock().unwrap();
+        let free: Vec<_> = candidates
+            .iter()
+            .filter(|(a, _)| active.get(a).copied().unwrap_or(0) < self.cap)
+            .copied()
+            .collect();
+        let (addr, _) = place(scores, &free)?;
+        *active.entry(addr).or_default() += 1;
+        Some(addr)
+    }
+}
+
+impl Drop for PeerSlot<'_> {
+    fn drop(&mut self) {
+        let mut active = self.load.active.lock().unwrap();
+        if let Some(n) = active.get_mut(&self.addr) {
+            *n -= 1;
+        }
+        drop(active);
+        self.load.freed.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flaky_peer_is_not_chosen_for_placement() {
+        let (reliable, flaky, fresh): (SocketAddr, SocketAddr, SocketAddr) = (
+            "10.0.0.1:62092".parse().unwrap(),
+            "10.0.0.2:62092".parse().unwrap(),
+            "10.0.0.3:62092".parse().unwrap(),
+        );
+        let scores = PeerScores::new();
+        for _ in 0..10 {
+            scores.success(reliable, Duration::from_millis(5));
+            scores.failure(flaky);
+        }
+        scores.success(flaky, Duration::from_millis(5));
+        assert!(scores.score(flaky) < scores.score(reliable));
+
+        // У ненадежного сервера больше места, но выбирается надежный
+        let candidates = [(flaky, 1000), (reliable, 10)];
+        assert_eq!(place(Some(&scores), &candidates), Some((reliable, 10)));
+        assert_eq!(place(None, &candidates), Some((flaky, 1000)));
+        // Новый сервер не вытесняется известным надежным
+        let candidates = [(reliable, 10), (fresh, 20)];
+        assert_eq!(place(Some(&scores), &candidates), Some((fresh, 20)));
+
+        let mut peers = vec![flaky, reliable];
+        scores.order(&mut peers);
+        assert_eq!(peers, vec![reliable, flaky]);
+        assert_eq!(scores.ranked()[0].0, reliable);
+    }
+}
@@ -0,0 +1,187 @@
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::ops::Range; // Диапазон байтов сегмента
+use std::path::Path; // Структура файлового пути
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _}; // BASE64-кодек
+use serde::{Deserialize, Serialize}; // Трейты (де)сериализации
+use tokio::fs; // Асинхронные операции с файловой системой
+
+use crate::attrs::SourceAttrs; // Атрибуты исходного файла
+use crate::chunks::{check_tag, decode_manifest, write_replacing}; // Чтение метаданных любого формата и замена файла через временный файл
+use crate::crypto::Encryptor; // Трейт шифровальщика, вычисляющего теги
+
+use consts::*; // Внутренние константы
+pub use errors::SegmentSizeError; // Ошибка размера сегмента
+
+mod consts {
+    pub const TAG_PREFIX: &[u8] = b"leaf-segments:"; // Префикс данных тега индекса (тег индекса нельзя выдать за тег файла)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Segment {
+    // Сегмент файла: непрерывный диапазон байтов, отправленный как отдельный файл
+    pub offset: u64,      // Смещение сегмента в исходном файле
+    pub len: usize,       // Размер сегмента
+    pub pointer: Vec<u8>, // Зашифрованные метаданные метаданных сегмента (сами метаданные хранятся в домене)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SegmentIndex {
+    // Верхний уровень дерева метаданных большого файла. Метаданные каждого сегмента хранятся в домене,
+    // поэтому сегменты восстанавливаются независимо, а сам файл индекса остается небольшим
+    pub segment_size: usize, // Максимальный размер сегмента
+    pub original_size: u64,  // Размер исходного файла
+    pub segments: Vec<Segment>,
+    #[serde(default)]
+    pub source_attrs: Option<SourceAttrs>, // Атрибуты исходного файла (только при их сохранении)
+    #[serde(default)]
+    pub tag: Option<String>, // Тег аутентификации индекса ключом шифровальщика (в старых индексах отсутствует)
+}
+
+impl SegmentIndex {
+    fn tag_message(&self) -> Vec<u8> {
+        // Данные, заверяемые тегом: размеры, упорядоченные сегменты и атрибуты исходного файла
+        let mut message = TAG_PREFIX.to_vec();
+        message.extend((self.segment_size as u64).to_be_bytes());
+        message.extend(self.original_size.to_be_bytes());
+        message.extend((self.segments.len() as u64).to_be_bytes());
+        for s in &self.segments {
+            message.extend(s.offset.to_be_bytes());
+            message.extend((s.len as u64).to_be_bytes());
+            message.extend((s.pointer.len() as u64).to_be_bytes());
+            message.extend(&s.pointer);
+        }
+        if let Some(attrs) = &self.source_attrs {
+            message.extend(serde_json::to_vec(attrs).unwrap_or_default());
+        }
+        message
+    }
+
+    pub fn attach_tag(&mut self, encryptor: &dyn Encryptor) {
+        // Заверение индекса тегом: метаданные сегментов заверены отдельно, но без тега индекса
+        // сегменты можно было бы переставить, удалить или заменить сегментами другого файла
+        self.tag = encryptor.authenticate(&self.tag_message()).map(hex::encode);
+    }
+
+    pub fn verify_tag(
+        &self,
+        encryptor: &dyn Encryptor,
+        allow_untagged: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        check_tag(
+            encryptor,
+            &self.tag_message(),
+            self.tag.as_deref(),
+            allow_untagged,
+        )
+    }
+
+    pub async fn save_to(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let data = BASE64.encode(serde_json::to_vec(self)?); // Сериализация в том же виде, что и метаданные файла
+        write_replacing(path, data.as_bytes()).await
+    }
+
+    pub async fn load_from(path: impl AsRef<Path>) -> Result<Option<SegmentIndex>, Box<dyn Error>> {
+        // Чтение индекса сегментов. Обычные метаданные файла не содержат списка сегментов,
+        // поэтому для них возвращается None
+        let (content, _) = decode_manifest(fs::read(path).await?)?;
+        Ok(serde_json::from_slice(&content).ok())
+    }
+}
+
+pub fn ranges(len: usize, segment_size: usize) -> Result<Vec<Range<usize>>, SegmentSizeError> {
+    // Разбиение файла на сегменты не больше segment_size байт
+    if segment_size == 0 {
+        return Err(SegmentSizeError);
+    }
+    Ok((0..len)
+        .step_by(segment_size)
+        .map(|start| start..len.min(start + segment_size))
+        .collect())
+}
+
+mod errors {
+    // Модуль с составными ошибками
+    use std::error::Error;
+    use std::fmt;
+
+    #[derive(Debug, Clone)]
+    pub struct SegmentSizeError; // Ошибка нулевого размера сегмента
+
+    impl fmt::Display for SegmentSizeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Segment size must be greater than zero")
+        }
+    }
+
+    impl Error for SegmentSizeError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunks::{ManifestTagError, UntaggedManifestError};
+    use crate::crypto::{DecryptionError, EncryptionError};
+
+    struct Tagging(u8); // Шифровальщик для проверки тегов: тег - простая сумма данных с ключом
+
+    impl Encryptor for Tagging {
+        fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+            Ok(chunk.to_vec())
+        }
+
+        fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+            Ok(chunk.to_vec())
+        }
+
+        fn authenticate(&self, message: &[u8]) -> Option<Vec<u8>> {
+            let sum = message.iter().fold(self.0 as u64, |s, &b| {
+                s.wrapping_mul(31).wrapping_add(b as u64)
+            });
+            Some(sum.to_be_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn modified_segment_index_fails_tag_verification() {
+        let segment = |offset, pointer| Segment {
+            offset,
+            len: 4,
+            pointer,
+        };
+        let mut index = SegmentIndex {
+            segment_size: 4,
+            original_size: 8,
+            segments: vec![segment(0, vec![1, 2]), segment(4, vec![3, 4])],
+            source_attrs: None,
+            tag: None,
+        };
+        let path = std::env::temp_dir().join(format!("leaf-index-{}", rand::random::<u64>()));
+        index.attach_tag(&Tagging(1));
+        index.save_to(&path).await.unwrap();
+        let restored = SegmentIndex::load_from(&path).await.unwrap().unwrap();
+        assert!(restored.verify_tag(&Tagging(1), false).is_ok()); // Тег сохраняется в индексе
+        assert!(restored.verify_tag(&Tagging(2), false).is_err()); // Другой ключ
+        fs::remove_file(&path).await.unwrap();
+
+        let mut swapped = restored.clone();
+        swapped.segments.swap(0, 1);
+        let err = swapped.verify_tag(&Tagging(1), false).unwrap_err();
+        assert!(err.is::<ManifestTagError>());
+        let mut replaced = restored.clone();
+        replaced.segments[1].pointer = vec![5, 6];
+        assert!(replaced.verify_tag(&Tagging(1), false).is_err());
+
+        index.tag = None;
+        let err = index.verify_tag(&Tagging(1), false).unwrap_err();
+        assert!(err.is::<UntaggedManifestError>());
+        assert!(index.verify_tag(&Tagging(1), true).is_ok());
+    }
+
+    #[test]
+    fn ranges_cover_file_without_overlap() {
+        assert_eq!(ranges(10, 4).unwrap(), vec![0..4, 4..8, 8..10]);
+        assert_eq!(ranges(8, 4).unwrap(), vec![0..4, 4..8]);
+        assert!(ranges(8, 0).is_err());
+    }
+}
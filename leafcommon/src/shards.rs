@@ -1,12 +1,20 @@
 pub mod reed_solomon {
-    use std::cmp::{max, min};
+    use std::error::Error;
+    use std::ops::Range;
 
     use rayon::prelude::*;
+    #[cfg(feature = "rs-erasure")]
     use reed_solomon_erasure::{galois_8, ReedSolomon}; // Внешняя зависимость для создания блоков по схеме Рида-Соломона
+    use serde::{Deserialize, Serialize}; // Внешняя зависимость для (де)сериализации
 
+    pub use super::errors::TooManyChunksError; // Ошибка превышения количества чанков файла
     use super::errors::*;
+    pub use consts::DEFAULT_MAX_CHUNKS; // Ограничение количества чанков файла по умолчанию
     use consts::*;
 
+    #[cfg(not(any(feature = "rs-erasure", feature = "rs-simd")))]
+    compile_error!("at least one of the `rs-erasure` or `rs-simd` features must be enabled");
+
     mod consts {
         // Модуль с константами
         pub const MIN_BLOCK_SIZE: usize = 64; // Минимальный размер блока - 64 байта
@@ -14,22 +22,200 @@ pub mod reed_solomon {
         pub const GROWTH_FACTOR: f64 = 0.5_f64; // Коэффициент роста - 0.5
         pub const ALIGNMENT: usize = 64; // выравнивание по 64 бита
         pub const MAX_AMOUNT_OF_BLOCKS: usize = 128; // Максимальный размер блоков для разделения за одну итерацию
+        pub const CDC_MIN_SIZE: usize = 2 * 1024; // Минимальный размер блока при разбиении по содержимому
+        pub const CDC_MAX_SIZE: usize = 32 * 1024; // Максимальный размер блока при разбиении по содержимому
+        pub const CDC_MASK: u64 = (1 << 13) - 1; // Маска точки разреза (в среднем блок на 8 Кб длиннее минимального)
+        pub const PAD_MARKER: u8 = 0x80; // Байт, отделяющий содержимое блока от дополнения нулями
+        pub const DEFAULT_MAX_CHUNKS: usize = 1 << 20; // Количество чанков файла по умолчанию, не больше (блоки до 64 Кб - около 32 Гб данных)
+    }
+
+    pub type Shards = Vec<Vec<u8>>; // Набор блоков одного типа (данные или восстановительные)
+    pub type PartialShards = Vec<Option<Vec<u8>>>; // Набор блоков, часть из которых может отсутствовать
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum ShardConflict {
+        // Обработка различающихся копий одного блока при сборке блоков из нескольких источников
+        #[default]
+        Reject, // Различающиеся копии - ошибка
+        Discard, // Блок с различающимися копиями считается отсутствующим и восстанавливается по остальным
+    }
+
+    pub trait ErasureCoder {
+        // Трейт реализации кода Рида-Соломона
+        fn encode(&self, data: &[Vec<u8>], parity: &mut [Vec<u8>]) -> Result<(), Box<dyn Error>>; // Вычисление восстановительных блоков для группы блоков данных
+        fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<(), Box<dyn Error>>; // Восстановление недостающих блоков данных группы (первая половина - данные, вторая - восстановительные)
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+    pub enum Backend {
+        // Тип реализации кода Рида-Соломона, записывается в метаданные файла, т.к. блоки разных реализаций несовместимы
+        #[default]
+        Erasure, // reed-solomon-erasure, GF(2^8) (используется в метаданных без явного указания реализации)
+        Simd, // reed-solomon-simd, GF(2^16) с SIMD-ускорением
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+    pub enum Chunker {
+        // Способ разбиения файла на блоки данных, записывается в метаданные файла
+        #[default]
+        Fixed, // Блоки одинакового размера, зависящего от размера файла
+        // Границы блоков определяются скользящим хэшем содержимого: вставка байтов в начало
+        // файла меняет только соседние блоки, остальные блоки и их хэши сохраняются
+        ContentDefined,
     }
 
-    fn calc_block_size(file_size: usize) -> usize {
-        // Метод рассчета размера блока
+    const GEAR: [u64; 256] = gear_table(); // Таблица скользящего хэша разбиения по содержимому
+
+    const fn gear_table() -> [u64; 256] {
+        // Псевдослучайные значения для каждого байта (SplitMix64), одинаковые во всех сборках
+        let mut table = [0u64; 256];
+        let mut state: u64 = 0;
+        let mut i = 0;
+        while i < 256 {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            table[i] = z ^ (z >> 31);
+            i += 1;
+        }
+        table
+    }
+
+    impl Backend {
+        pub fn preferred() -> Backend {
+            // Реализация, выбираемая для новых файлов: SIMD, если она собрана, иначе портируемая
+            if cfg!(feature = "rs-simd") {
+                Backend::Simd
+            } else {
+                Backend::Erasure
+            }
+        }
+
+        fn coder(self) -> Result<Box<dyn ErasureCoder>, BackendUnavailableError> {
+            // Получение реализации кода по ее типу
+            match self {
+                #[cfg(feature = "rs-erasure")]
+                Backend::Erasure => Ok(Box::new(ErasureBackend)),
+                #[cfg(feature = "rs-simd")]
+                Backend::Simd => Ok(Box::new(SimdBackend)),
+                #[allow(unreachable_patterns)]
+                b => Err(BackendUnavailableError(b)),
+            }
+        }
+    }
+
+    #[cfg(feature = "rs-erasure")]
+    pub struct ErasureBackend; // Портируемая реализация на основе reed-solomon-erasure
+
+    #[cfg(feature = "rs-erasure")]
+    impl ErasureCoder for ErasureBackend {
+        fn encode(&self, data: &[Vec<u8>], parity: &mut [Vec<u8>]) -> Result<(), Box<dyn Error>> {
+            let encoder: ReedSolomon<galois_8::Field> = ReedSolomon::new(data.len(), parity.len())
+                .map_err(|e| DataSplittingError(e.to_string()))?;
+            encoder
+                .encode_sep(data, parity)
+                .map_err(|e| DataSplittingError(e.to_string()))?;
+            Ok(())
+        }
+
+        fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<(), Box<dyn Error>> {
+            let count = shards.len() / 2;
+            let decoder: ReedSolomon<galois_8::Field> =
+                ReedSolomon::new(count, count).map_err(|e| DataRecoveringError(e.to_string()))?;
+            decoder
+                .reconstruct_data(shards)
+                .map_err(|e| DataRecoveringError(e.to_string()))?;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "rs-simd")]
+    pub struct SimdBackend; // Реализация с SIMD-ускорением на основе reed-solomon-simd
+
+    #[cfg(feature = "rs-simd")]
+    impl ErasureCoder for SimdBackend {
+        fn encode(&self, data: &[Vec<u8>], parity: &mut [Vec<u8>]) -> Result<(), Box<dyn Error>> {
+            let recovery = reed_solomon_simd::encode(data.len(), parity.len(), data)
+                .map_err(|e| DataSplittingError(e.to_string()))?;
+            for (p, r) in parity.iter_mut().zip(recovery) {
+                *p = r;
+            }
+            Ok(())
+        }
+
+        fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<(), Box<dyn Error>> {
+            let count = shards.len() / 2;
+            let (data, parity) = shards.split_at(count);
+            let original = data
+                .iter()
+                .enumerate()
+                .filter_map(|(i, x)| x.as_ref().map(|x| (i, x)));
+            let recovery = parity
+                .iter()
+                .enumerate()
+                .filter_map(|(i, x)| x.as_ref().map(|x| (i, x)));
+            let restored = reed_solomon_simd::decode(count, count, original, recovery)
+                .map_err(|e| DataRecoveringError(e.to_string()))?;
+            for (i, x) in restored {
+                shards[i] = Some(x);
+            }
+            Ok(())
+        }
+    }
+
+    fn calc_block_size(file_size: usize, alignment: usize) -> usize {
+        // Метод рассчета размера блока, кратного alignment
         let bs = MIN_BLOCK_SIZE as f64
             * ((file_size as f64 / MIN_BLOCK_SIZE as f64).powf(GROWTH_FACTOR));
-        let bs = max(MIN_BLOCK_SIZE, min(bs as usize, MAX_BLOCK_SIZE));
-        let bs = ((bs + ALIGNMENT - 1) / ALIGNMENT) * ALIGNMENT;
-        bs
+        let bs = (bs as usize).clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
+        let bs = bs.div_ceil(alignment) * alignment;
+        if bs > MAX_BLOCK_SIZE {
+            bs - alignment // Выравнивание не должно приводить к превышению размера UDP-пакета
+        } else {
+            bs
+        }
+    }
+
+    fn alignment(cipher_block: usize) -> usize {
+        // Выравнивание блоков: кратно ALIGNMENT и размеру блока шифра (наименьшее общее кратное),
+        // поэтому шифрование не меняет размер блока и не требует дополнения
+        let (mut a, mut b) = (ALIGNMENT, cipher_block.max(1));
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        ALIGNMENT / a * cipher_block.max(1)
+    }
+
+    fn check_chunks(blocks: usize, max_chunks: usize) -> Result<(), TooManyChunksError> {
+        // Проверка количества чанков до формирования блоков: каждому блоку данных соответствует
+        // восстановительный, а каждый чанк - это отдельный запрос к домену и отдельный файл на сервере
+        let chunks = blocks.saturating_mul(2);
+        match chunks > max_chunks {
+            true => Err(TooManyChunksError(chunks, max_chunks)),
+            false => Ok(()),
+        }
     }
 
     pub fn split(
         secret: Vec<u8>,
-    ) -> Result<(Vec<Vec<u8>>, Vec<Vec<u8>>), Box<dyn std::error::Error>> {
-        // Метод разбиения файла на блоки
-        let block_size = calc_block_size(secret.len()); // Получение размера блока
+        backend: Backend,
+        cipher_block: usize,
+        max_chunks: usize,
+    ) -> Result<(Shards, Shards), Box<dyn Error>> {
+        // Метод разбиения файла на блоки, размер которых кратен размеру блока шифра cipher_block
+        if secret.is_empty() {
+            return Err(Box::new(DataSplittingError(String::from("File is empty"))));
+        }
+        let alignment = alignment(cipher_block);
+        if alignment > MAX_BLOCK_SIZE {
+            return Err(Box::new(DataSplittingError(format!(
+                "Cipher block size {} is too large",
+                cipher_block
+            ))));
+        }
+        let block_size = calc_block_size(secret.len(), alignment); // Получение размера блока
+        check_chunks(secret.len().div_ceil(block_size), max_chunks)?;
 
         let mut blocks = secret
             .par_iter()
@@ -42,37 +228,145 @@ pub mod reed_solomon {
             blocks[blocks_len - 1].append(&mut vec![0u8; block_size - last_block_size]);
         }
 
-        let mut parity = vec![vec![0u8; block_size]; blocks.len()];
-        let mut i = 0;
-        while i < blocks.len() {
-            let remaining_blocks = blocks.len() - i;
-            let block_size = remaining_blocks.min(MAX_AMOUNT_OF_BLOCKS); // определяем, сколько элементов обрабатывать
+        let parity = encode(&blocks, backend)?;
+        Ok((blocks, parity)) // Возврат структуры с блоками
+    }
 
-            let encoder: ReedSolomon<galois_8::Field> = ReedSolomon::new(block_size, block_size)
-                .map_err(|e| DataSplittingError(e.to_string()))?;
+    pub fn split_with(
+        secret: Vec<u8>,
+        backend: Backend,
+        cipher_block: usize,
+        chunker: Chunker,
+        max_chunks: usize,
+    ) -> Result<(Shards, Shards), Box<dyn Error>> {
+        // Метод разбиения файла на блоки указанным способом, не больше чем на max_chunks чанков
+        match chunker {
+            Chunker::Fixed => split(secret, backend, cipher_block, max_chunks),
+            Chunker::ContentDefined => split_by_content(secret, backend, cipher_block, max_chunks),
+        }
+    }
 
-            encoder
-                .encode_sep(
-                    &blocks[i..i + block_size],     // обрабатываем оставшиеся блоки
-                    &mut parity[i..i + block_size], // обрабатываем оставшиеся элементы для parity
-                )
-                .map_err(|e| DataSplittingError(e.to_string()))?;
+    fn split_by_content(
+        secret: Vec<u8>,
+        backend: Backend,
+        cipher_block: usize,
+        max_chunks: usize,
+    ) -> Result<(Shards, Shards), Box<dyn Error>> {
+        // Метод разбиения файла на блоки по точкам разреза скользящего хэша. Каждый блок
+        // дополняется маркером и нулями до кратного выравниванию размера
+        if secret.is_empty() {
+            return Err(Box::new(DataSplittingError(String::from("File is empty"))));
+        }
+        let alignment = alignment(cipher_block);
+        if CDC_MAX_SIZE + alignment > MAX_BLOCK_SIZE {
+            return Err(Box::new(DataSplittingError(format!(
+                "Cipher block size {} is too large",
+                cipher_block
+            ))));
+        }
+        let ranges = cut_points(&secret);
+        check_chunks(ranges.len(), max_chunks)?;
+        let blocks = ranges
+            .into_par_iter()
+            .map(|r| pad_block(&secret[r], alignment))
+            .collect::<Vec<_>>();
+        let parity = encode(&blocks, backend)?;
+        Ok((blocks, parity))
+    }
 
-            i += block_size; // увеличиваем i на количество обработанных блоков
+    fn cut_points(data: &[u8]) -> Vec<Range<usize>> {
+        // Границы блоков: первая позиция после CDC_MIN_SIZE, где скользящий хэш последних
+        // 64 байт удовлетворяет маске, но не дальше CDC_MAX_SIZE от начала блока
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while start < data.len() {
+            let end = data.len().min(start + CDC_MAX_SIZE);
+            let mut cut = end;
+            let mut hash: u64 = 0;
+            for (i, &b) in data.iter().enumerate().take(end).skip(start) {
+                hash = (hash << 1).wrapping_add(GEAR[b as usize]);
+                if i + 1 - start >= CDC_MIN_SIZE && hash & CDC_MASK == 0 {
+                    cut = i + 1;
+                    break;
+                }
+            }
+            ranges.push(start..cut);
+            start = cut;
         }
-        Ok((blocks, parity)) // Возврат структуры с блоками
+        ranges
     }
 
-    pub fn recover(
-        mut data: Vec<Vec<u8>>,
-        mut recv: Vec<Vec<u8>>,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        // Метод восстановления файла из блоков
-        let data_len = data.len();
+    fn pad_block(content: &[u8], alignment: usize) -> Vec<u8> {
+        // Дополнение блока маркером и нулями: дополнение отделимо от содержимого без сведений
+        // о его длине, даже если блок дополнен нулями еще раз (при восстановлении)
+        let mut block = Vec::with_capacity(content.len() + alignment);
+        block.extend_from_slice(content);
+        block.push(PAD_MARKER);
+        block.resize(block.len().div_ceil(alignment) * alignment, 0);
+        block
+    }
+
+    pub fn content_len(block: &[u8], chunker: Chunker) -> usize {
+        // Длина содержимого блока без дополнения (блоки постоянного размера дополнены только в конце файла)
+        match chunker {
+            Chunker::ContentDefined => unpad_block(block).map_or(block.len(), <[u8]>::len),
+            Chunker::Fixed => block.len(),
+        }
+    }
 
-        // Объединяем data и recovery в один массив для восстановления
-        data.append(&mut recv);
-        let full_data = data.par_iter().cloned().map(Some).collect::<Vec<_>>();
+    fn unpad_block(block: &[u8]) -> Result<&[u8], DataRecoveringError> {
+        // Отделение содержимого блока от дополнения
+        match block.iter().rposition(|&b| b != 0) {
+            Some(i) if block[i] == PAD_MARKER => Ok(&block[..i]),
+            _ => Err(DataRecoveringError(String::from(
+                "Block padding is corrupted",
+            ))),
+        }
+    }
+
+    pub fn encode(blocks: &[Vec<u8>], backend: Backend) -> Result<Shards, Box<dyn Error>> {
+        // Метод вычисления восстановительных блоков для блоков данных. Блоки группы разной длины
+        // (при разбиении по содержимому) кодируются дополненными нулями до самого длинного из них,
+        // восстановительные блоки группы имеют эту длину
+        let coder = backend.coder()?;
+        let mut parity = Vec::with_capacity(blocks.len());
+        for g in groups(blocks.len()) {
+            let group = &blocks[g];
+            let block_size = group.iter().map(Vec::len).max().unwrap_or(0);
+            let padded: Shards;
+            let group = if group.iter().all(|b| b.len() == block_size) {
+                group
+            } else {
+                padded = group
+                    .iter()
+                    .map(|b| {
+                        let mut b = b.clone();
+                        b.resize(block_size, 0);
+                        b
+                    })
+                    .collect();
+                &padded
+            };
+            let mut group_parity = vec![vec![0u8; block_size]; group.len()];
+            coder.encode(group, &mut group_parity)?;
+            parity.extend(group_parity);
+        }
+        Ok(parity)
+    }
+
+    pub fn reconstruct(
+        data: PartialShards,
+        recv: PartialShards,
+        backend: Backend,
+    ) -> Result<Shards, Box<dyn Error>> {
+        // Метод восстановления недостающих блоков данных при помощи восстановительных блоков
+        let coder = backend.coder()?;
+        let data_len = data.len();
+        if recv.len() != data_len {
+            return Err(Box::new(DataRecoveringError(String::from(
+                "Data and recovery blocks counts mismatch",
+            ))));
+        }
 
         let mut result = Vec::with_capacity(data_len);
 
@@ -82,39 +376,141 @@ pub mod reed_solomon {
             let remaining_blocks = data_len - i;
             let block_size = remaining_blocks.min(MAX_AMOUNT_OF_BLOCKS);
 
-            // // Создаем декодер для текущего набора блоков
-            let decoder: ReedSolomon<galois_8::Field> = ReedSolomon::new(block_size, block_size)
-                .map_err(|e| DataRecoveringError(e.to_string()))?;
             let mut curr_slice = Vec::with_capacity(block_size * 2);
-            let mut tmp_data = full_data[i..block_size + i].to_vec();
-            let mut tmp_recv = full_data[data_len + i..block_size + i + data_len].to_vec();
-            curr_slice.append(&mut tmp_data);
-            curr_slice.append(&mut tmp_recv);
-            decoder
-                .reconstruct_data(&mut curr_slice)
-                .map_err(|e| DataRecoveringError(e.to_string()))?;
+            curr_slice.extend_from_slice(&data[i..i + block_size]);
+            curr_slice.extend_from_slice(&recv[i..i + block_size]);
+            if curr_slice[..block_size].iter().any(Option::is_none) {
+                // Декодер вызывается только для групп с недостающими блоками данных. Ему передается
+                // ровно столько блоков, сколько нужно: все полученные блоки данных и недостающие
+                // до их количества первые восстановительные, лишние не используются и не влияют
+                // на дополнение. Блоки разной длины дополняются до самого длинного, после
+                // восстановления полученные блоки данных возвращаются к исходной длине
+                let mut needed = block_size - curr_slice[..block_size].iter().flatten().count();
+                for x in curr_slice[block_size..].iter_mut().filter(|x| x.is_some()) {
+                    match needed {
+                        0 => *x = None,
+                        _ => needed -= 1,
+                    }
+                }
+                let sizes: Vec<Option<usize>> = curr_slice
+                    .iter()
+                    .map(|x| x.as_ref().map(Vec::len))
+                    .collect();
+                let max_size = sizes.iter().flatten().copied().max().unwrap_or(0);
+                for x in curr_slice.iter_mut().flatten() {
+                    x.resize(max_size, 0);
+                }
+                coder.reconstruct(&mut curr_slice)?;
+                for (x, size) in curr_slice.iter_mut().zip(sizes) {
+                    if let (Some(x), Some(size)) = (x, size) {
+                        x.truncate(size);
+                    }
+                }
+            }
 
-            result.append(&mut curr_slice[..block_size].to_vec());
+            for x in curr_slice.into_iter().take(block_size) {
+                result.push(x.ok_or(DataRecoveringError(String::from(
+                    "Not enough blocks to recover data",
+                )))?);
+            }
             i += block_size;
         }
+        Ok(result)
+    }
 
-        // Извлекаем только блоки данных (без блоков восстановления)
-        let content = result
-            .par_iter()
-            .cloned()
-            .filter_map(|x| x)
-            .flatten()
-            .collect::<Vec<_>>();
-        // Удаление нулей в конце последовательности
-        let mut zeros_start_index: Option<isize> = None;
-        for i in content.len() - 1..0 {
-            if content[i] != 0 {
-                zeros_start_index = Some((i + 1) as isize);
-                break;
+    pub fn collect_shards(
+        count: usize,
+        copies: impl IntoIterator<Item = (bool, usize, Vec<u8>)>,
+        conflict: ShardConflict,
+    ) -> Result<(PartialShards, PartialShards), DataRecoveringError> {
+        // Сборка блоков из копий (признак восстановительного блока, индекс, содержимое), полученных
+        // в любом порядке и с повторами, например от нескольких серверов. Совпадающие копии одного
+        // индекса объединяются, различающиеся обрабатываются согласно conflict
+        let mut shards = (vec![None; count], vec![None; count]);
+        let mut conflicting = (vec![false; count], vec![false; count]);
+        for (recovery, index, value) in copies {
+            let (set, bad, kind) = match recovery {
+                false => (&mut shards.0, &mut conflicting.0, "data"),
+                true => (&mut shards.1, &mut conflicting.1, "recovery"),
+            };
+            let Some(slot) = set.get_mut(index) else {
+                return Err(DataRecoveringError(format!(
+                    "{} block index {} is out of range",
+                    kind, index
+                )));
+            };
+            match slot {
+                None => *slot = Some(value),
+                Some(v) if *v == value => {} // Повтор той же копии
+                Some(_) if conflict == ShardConflict::Reject => {
+                    return Err(DataRecoveringError(format!(
+                        "Copies of {} block {} differ",
+                        kind, index
+                    )));
+                }
+                Some(_) => bad[index] = true,
+            }
+        }
+        for (set, bad) in [
+            (&mut shards.0, &conflicting.0),
+            (&mut shards.1, &conflicting.1),
+        ] {
+            for (x, _) in set.iter_mut().zip(bad).filter(|(_, b)| **b) {
+                *x = None; // Ни одной из различающихся копий нельзя доверять
+            }
+        }
+        Ok(shards)
+    }
+
+    pub fn groups(count: usize) -> Vec<Range<usize>> {
+        // Диапазоны индексов групп блоков, восстанавливаемых независимо друг от друга
+        (0..count)
+            .step_by(MAX_AMOUNT_OF_BLOCKS)
+            .map(|i| i..count.min(i + MAX_AMOUNT_OF_BLOCKS))
+            .collect()
+    }
+
+    pub fn shortfall<T>(data: &[Option<T>], recv: &[Option<T>]) -> usize {
+        // Количество блоков, которых не хватает для восстановления всех групп
+        // (группе из n блоков данных достаточно любых n ее блоков)
+        groups(data.len())
+            .into_iter()
+            .map(|g| {
+                let present = data[g.clone()].iter().flatten().count()
+                    + recv
+                        .get(g.clone())
+                        .map_or(0, |r| r.iter().flatten().count());
+                g.len().saturating_sub(present)
+            })
+            .sum()
+    }
+
+    pub fn recover(
+        data: PartialShards,
+        recv: PartialShards,
+        backend: Backend,
+        original_size: usize,
+        chunker: Chunker,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        // Метод восстановления файла из блоков. Дополнение последнего блока отбрасывается
+        // по исходному размеру файла (0 - размер неизвестен, как в старых метаданных)
+        let result = reconstruct(data, recv, backend)?;
+        if chunker == Chunker::ContentDefined {
+            // Каждый блок дополнен отдельно
+            let mut content = Vec::with_capacity(original_size);
+            for block in &result {
+                content.extend_from_slice(unpad_block(block)?);
             }
+            return Ok(content);
         }
-        let content_len = zeros_start_index.map_or(content.len(), |x| x as usize);
-        let content = content[0..content_len].to_vec();
+
+        // Извлекаем только блоки данных (без блоков восстановления)
+        let mut content = result.into_par_iter().flatten().collect::<Vec<_>>();
+        let content_len = match original_size {
+            0 => content.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1), // Удаление нулей в конце последовательности
+            size => size.min(content.len()),
+        };
+        content.truncate(content_len);
         Ok(content)
     }
 }
@@ -154,18 +550,295 @@ mod errors {
     impl Error for DataRecoveringError {}
 
     #[derive(Debug, Clone)]
-    pub struct InitializationError(pub String); // Тип ошибки инициализации структуры разбиения файла
+    pub struct BackendUnavailableError(pub super::reed_solomon::Backend); // Тип ошибки отсутствия реализации кода Рида-Соломона в сборке
+
+    impl fmt::Display for BackendUnavailableError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            // Метод отображения сведений об ошибке на экране
+            write!(
+                f,
+                "Reed-Solomon backend {:?} is not enabled in this build",
+                self.0
+            )
+        }
+    }
+
+    impl Error for BackendUnavailableError {}
+
+    #[derive(Debug, Clone)]
+    pub struct TooManyChunksError(pub usize, pub usize); // Тип ошибки превышения количества чанков файла: количество и ограничение
 
-    impl fmt::Display for InitializationError {
+    impl fmt::Display for TooManyChunksError {
         fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
             // Метод отображения сведений об ошибке на экране
-            write!(f, "Error initializing data: {}", self.0)
+            write!(
+                f,
+                "File would be split into {} chunks, at most {} allowed: increase the chunk size (the fixed chunker makes chunks of up to 64 KiB) or send the file in segments",
+                self.0, self.1
+            )
         }
     }
 
-    impl Error for InitializationError {}
+    impl Error for TooManyChunksError {}
 }
 
 #[cfg(test)]
-mod tests { // Модуль юнит-тестирования
+mod tests {
+    // Модуль юнит-тестирования
+    use super::reed_solomon::{
+        self, Backend, Chunker, ShardConflict, TooManyChunksError, DEFAULT_MAX_CHUNKS,
+    };
+
+    #[test]
+    fn split_and_recover_round_trip() {
+        let secret = (1..=200_000u32)
+            .map(|x| (x % 251 + 1) as u8)
+            .collect::<Vec<_>>();
+        let (data, recv) =
+            reed_solomon::split(secret.clone(), Backend::preferred(), 1, DEFAULT_MAX_CHUNKS)
+                .unwrap();
+        assert_eq!(data.len(), recv.len());
+        let content = reed_solomon::recover(
+            data.into_iter().map(Some).collect(),
+            recv.into_iter().map(Some).collect(),
+            Backend::preferred(),
+            secret.len(),
+            Chunker::Fixed,
+        )
+        .unwrap();
+        assert_eq!(content, secret);
+    }
+
+    #[test]
+    fn split_aligns_blocks_to_cipher_block() {
+        let mut secret = vec![7u8; 10_000];
+        secret.extend([0u8; 5]); // Нули в конце файла сохраняются
+        for cipher_block in [1, 16, 48] {
+            let (data, recv) = reed_solomon::split(
+                secret.clone(),
+                Backend::preferred(),
+                cipher_block,
+                DEFAULT_MAX_CHUNKS,
+            )
+            .unwrap();
+            assert!(data
+                .iter()
+                .chain(&recv)
+                .all(|b| b.len() % cipher_block == 0 && b.len() % 64 == 0));
+            let content = reed_solomon::recover(
+                data.into_iter().map(Some).collect(),
+                recv.into_iter().map(Some).collect(),
+                Backend::preferred(),
+                secret.len(),
+                Chunker::Fixed,
+            )
+            .unwrap();
+            assert_eq!(content, secret);
+        }
+    }
+
+    #[test]
+    fn recover_missing_data_blocks() {
+        let secret = (1..=200_000u32)
+            .map(|x| (x % 251 + 1) as u8)
+            .collect::<Vec<_>>();
+        let (data, recv) =
+            reed_solomon::split(secret.clone(), Backend::preferred(), 16, DEFAULT_MAX_CHUNKS)
+                .unwrap();
+        let mut data = data.into_iter().map(Some).collect::<Vec<_>>();
+        let mut recv = recv.into_iter().map(Some).collect::<Vec<_>>();
+        data[0] = None;
+        data[3] = None;
+        recv[1] = None;
+        let content = reed_solomon::recover(
+            data.clone(),
+            recv,
+            Backend::preferred(),
+            secret.len(),
+            Chunker::Fixed,
+        )
+        .unwrap();
+        assert_eq!(content, secret);
+
+        let none = data.iter().map(|_| None).collect::<Vec<_>>();
+        assert!(reed_solomon::recover(
+            data,
+            none,
+            Backend::preferred(),
+            secret.len(),
+            Chunker::Fixed
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn redundant_shards_are_deduplicated_and_surplus_ignored() {
+        let secret = (1..=200_000u32)
+            .map(|x| (x % 251 + 1) as u8)
+            .collect::<Vec<_>>();
+        let (data, recv) =
+            reed_solomon::split(secret.clone(), Backend::preferred(), 16, DEFAULT_MAX_CHUNKS)
+                .unwrap();
+        let count = data.len();
+        assert!(count > 4);
+
+        // Копии от нескольких серверов: повторы, все восстановительные блоки и лишний
+        // восстановительный блок неверной длины, который не должен участвовать в восстановлении
+        let mut copies: Vec<(bool, usize, Vec<u8>)> = Vec::new();
+        for (i, d) in data.iter().enumerate().filter(|(i, _)| *i != 0 && *i != 3) {
+            copies.push((false, i, d.clone()));
+            copies.push((false, i, d.clone()));
+        }
+        for (i, r) in recv.iter().enumerate() {
+            let mut r = r.clone();
+            if i == 4 {
+                r.extend([0xff; 100]);
+            }
+            copies.push((true, i, r));
+        }
+        let (shards_data, shards_recv) =
+            reed_solomon::collect_shards(count, copies.clone(), ShardConflict::Reject).unwrap();
+        let content = reed_solomon::recover(
+            shards_data,
+            shards_recv,
+            Backend::preferred(),
+            secret.len(),
+            Chunker::Fixed,
+        )
+        .unwrap();
+        assert_eq!(content, secret);
+
+        // Различающиеся копии одного блока: ошибка либо восстановление без этого блока
+        copies.push((false, 1, vec![0; data[1].len()]));
+        assert!(
+            reed_solomon::collect_shards(count, copies.clone(), ShardConflict::Reject).is_err()
+        );
+        let (shards_data, shards_recv) =
+            reed_solomon::collect_shards(count, copies, ShardConflict::Discard).unwrap();
+        assert!(shards_data[1].is_none());
+        let content = reed_solomon::recover(
+            shards_data,
+            shards_recv,
+            Backend::preferred(),
+            secret.len(),
+            Chunker::Fixed,
+        )
+        .unwrap();
+        assert_eq!(content, secret);
+    }
+
+    #[test]
+    fn content_defined_blocks_survive_insertion() {
+        let mut state = 1u32;
+        let secret = (0..300_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect::<Vec<_>>();
+        let mut edited = vec![1u8, 2, 3];
+        edited.extend(&secret);
+        let split = |s: &[u8]| {
+            reed_solomon::split_with(
+                s.to_vec(),
+                Backend::preferred(),
+                16,
+                Chunker::ContentDefined,
+                DEFAULT_MAX_CHUNKS,
+            )
+            .unwrap()
+        };
+        let ((data, recv), (edited_data, _)) = (split(&secret), split(&edited));
+        let shared = edited_data.iter().filter(|b| data.contains(b)).count();
+        assert!(shared + 2 >= data.len()); // Меняются только блоки рядом со вставкой
+
+        let mut data = data.into_iter().map(Some).collect::<Vec<_>>();
+        data[1] = None;
+        let recv = recv.into_iter().map(Some).collect();
+        let content = reed_solomon::recover(
+            data,
+            recv,
+            Backend::preferred(),
+            secret.len(),
+            Chunker::ContentDefined,
+        )
+        .unwrap();
+        assert_eq!(content, secret);
+    }
+
+    #[test]
+    fn shortfall_counts_missing_blocks_per_group() {
+        let mut data = vec![Some(()); 130];
+        let mut recv = vec![Some(()); 130];
+        assert_eq!(reed_solomon::shortfall(&data, &recv), 0);
+        data[..128].fill(None); // Первая группа (128 блоков) теряет все блоки данных
+        recv[..3].fill(None); // и три восстановительных блока
+        data[128] = None; // Вторая группа (2 блока) теряет один блок - его заменяет восстановительный
+        assert_eq!(reed_solomon::shortfall(&data, &recv), 3);
+    }
+
+    #[test]
+    fn split_rejects_files_over_chunk_limit() {
+        let secret = vec![7u8; 1_000_000];
+        for chunker in [Chunker::Fixed, Chunker::ContentDefined] {
+            let split = |max_chunks| {
+                reed_solomon::split_with(
+                    secret.clone(),
+                    Backend::preferred(),
+                    16,
+                    chunker,
+                    max_chunks,
+                )
+            };
+            let (data, recv) = split(DEFAULT_MAX_CHUNKS).unwrap();
+            let chunks = data.len() + recv.len();
+            assert!(split(chunks).is_ok()); // Ровно на границе
+            let err = split(chunks - 1).unwrap_err();
+            let err = err.downcast_ref::<TooManyChunksError>().unwrap();
+            assert_eq!((err.0, err.1), (chunks, chunks - 1));
+        }
+    }
+
+    #[test]
+    fn split_rejects_empty_file() {
+        assert!(
+            reed_solomon::split(Vec::new(), Backend::preferred(), 16, DEFAULT_MAX_CHUNKS).is_err()
+        );
+    }
+
+    #[test]
+    #[ignore = "benchmark: cargo test -p leafcommon --all-features bench -- --ignored --nocapture"]
+    fn bench_backends_on_large_file() {
+        // Разбиение и восстановление файла 64 Мб каждой собранной реализацией кода
+        let secret = (0..64u32 << 20)
+            .map(|x| (x % 251 + 1) as u8)
+            .collect::<Vec<_>>();
+        for backend in [Backend::Erasure, Backend::Simd] {
+            let started = std::time::Instant::now();
+            let Ok((data, recv)) =
+                reed_solomon::split(secret.clone(), backend, 16, DEFAULT_MAX_CHUNKS)
+            else {
+                println!("{:?}: not built", backend);
+                continue;
+            };
+            let split = started.elapsed();
+
+            // Восстановление при потере каждого десятого блока данных
+            let mut data = data.into_iter().map(Some).collect::<Vec<_>>();
+            for block in data.iter_mut().step_by(10) {
+                *block = None;
+            }
+            let recv = recv.into_iter().map(Some).collect();
+            let started = std::time::Instant::now();
+            let content =
+                reed_solomon::recover(data, recv, backend, secret.len(), Chunker::Fixed).unwrap();
+            println!(
+                "{:?}: split {:?}, recover {:?}",
+                backend,
+                split,
+                started.elapsed()
+            );
+            assert_eq!(content, secret);
+        }
+    }
 }
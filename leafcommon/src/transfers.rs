@@ -0,0 +1,241 @@
+use std::collections::HashMap; // Ассоциативный массив стандартной библиотеки
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::future::Future; // Трейт асинхронных операций стандартной библиотеки
+#[cfg(feature = "gost")]
+use std::path::Path; // Структура "сырого" файлового пути
+use std::path::PathBuf; // Структура файлового пути
+use std::sync::atomic::{AtomicU64, Ordering}; // Атомарный счетчик идентификаторов
+use std::sync::{Arc, Mutex}; // Разделяемое между задачами состояние
+
+use tokio::sync::{Notify, Semaphore}; // Асинхронное уведомление об отмене и ограничение числа передач
+
+use crate::chunks::Progress; // Счетчики хода передачи
+#[cfg(feature = "gost")]
+use crate::reed_solomon_scheme; // Операции отправки и получения файлов
+
+use errors::*; // Внутренние ошибки
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransferKind {
+    // Тип передачи
+    Send,    // Отправка файла в домен
+    Receive, // Получение файла из домена
+}
+
+#[derive(Clone, Debug)]
+pub struct TransferStatus {
+    // Сведения о выполняющейся передаче
+    pub id: u64,            // Идентификатор передачи
+    pub kind: TransferKind, // Тип передачи
+    pub path: PathBuf,      // Путь к файлу
+    pub done: usize,        // Количество обработанных чанков
+    pub total: usize,       // Общее количество чанков (известно после начала передачи)
+}
+
+struct Transfer {
+    // Запись о выполняющейся передаче
+    kind: TransferKind,
+    path: PathBuf,
+    progress: Arc<Progress>, // Ход передачи
+    cancel: Arc<Notify>,     // Уведомление об отмене
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LimitPolicy {
+    // Поведение при достижении максимального количества одновременных передач
+    Queue,  // Передача ожидает освобождения места
+    Reject, // Передача сразу завершается ошибкой TooManyTransfersError
+}
+
+#[derive(Clone)]
+struct TransferLimit {
+    // Ограничение количества одновременных передач
+    max: usize,
+    policy: LimitPolicy,
+    permits: Arc<Semaphore>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransferStats {
+    // Сведения о загрузке реестра
+    pub active: usize, // Количество выполняющихся передач (без ожидающих в очереди)
+    pub max: Option<usize>, // Максимальное количество одновременных передач (None - без ограничения)
+}
+
+#[derive(Clone, Default)]
+pub struct TransferRegistry {
+    // Реестр выполняющихся передач, позволяющий просматривать и отменять их
+    transfers: Arc<Mutex<HashMap<u64, Transfer>>>,
+    next_id: Arc<AtomicU64>,
+    limit: Option<TransferLimit>, // Ограничение одновременных передач (None - без ограничения)
+}
+
+struct Registration<'a> {
+    // Регистрация передачи, снимаемая при ее завершении (в том числе при отмене)
+    registry: &'a TransferRegistry,
+    id: u64,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.registry.transfers.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl TransferRegistry {
+    pub fn new() -> TransferRegistry {
+        TransferRegistry::default() // Конструктор пустого реестра
+    }
+
+    pub fn with_max_transfers(max: usize, policy: LimitPolicy) -> TransferRegistry {
+        // Конструктор реестра с ограничением одновременных передач: каждая передача занимает
+        // сокет и держит в памяти чанки файла, поэтому встраивающему сервису нужен предел
+        TransferRegistry {
+            limit: Some(TransferLimit {
+                max,
+                policy,
+                permits: Arc::new(Semaphore::new(max)),
+            }),
+            ..TransferRegistry::default()
+        }
+    }
+
+    pub fn stats(&self) -> TransferStats {
+        // Получение количества выполняющихся передач и их предела
+        TransferStats {
+            active: self.transfers.lock().unwrap().len(),
+            max: self.limit.as_ref().map(|l| l.max),
+        }
+    }
+
+    pub fn active_transfers(&self) -> Vec<TransferStatus> {
+        // Получение сведений о всех выполняющихся передачах
+        let mut transfers = self
+            .transfers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, t)| TransferStatus {
+                id: *id,
+                kind: t.kind,
+                path: t.path.clone(),
+                done: t.progress.done(),
+                total: t.progress.total(),
+            })
+            .collect::<Vec<_>>();
+        transfers.sort_by_key(|t| t.id);
+        transfers
+    }
+
+    pub fn cancel(&self, id: u64) -> bool {
+        // Отмена передачи по идентификатору, возвращает false, если такой передачи нет
+        match self.transfers.lock().unwrap().get(&id) {
+            Some(t) => {
+                t.cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn send_file(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        // Отправка файла с регистрацией в реестре
+        self.run(
+            TransferKind::Send,
+            path.as_ref().to_path_buf(),
+            |progress| async move {
+                reed_solomon_scheme::send_file_with_progress(path, &progress).await
+            },
+        )
+        .await
+    }
+
+    #[cfg(feature = "gost")]
+    pub async fn recv_file(
+        &self,
+        path: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Получение файла с регистрацией в реестре
+        self.run(
+            TransferKind::Receive,
+            output.as_ref().to_path_buf(),
+            |progress| async move {
+                reed_solomon_scheme::recv_file_with_progress(path, output, &progress).await
+            },
+        )
+        .await
+    }
+
+    pub async fn run<F, Fut>(
+        &self,
+        kind: TransferKind,
+        path: PathBuf,
+        f: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(Arc<Progress>) -> Fut,
+        Fut: Future<Output = Result<(), Box<dyn Error>>>,
+    {
+        // Выполнение передачи: регистрация, ожидание завершения или отмены, снятие регистрации.
+        // Позволяет регистрировать передачи со своим шифровальщиком (функции reed_solomon_scheme::*_using)
+        let _permit = match &self.limit {
+            None => None,
+            Some(l) if l.policy == LimitPolicy::Reject => Some(
+                l.permits
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| TooManyTransfersError(l.max))?,
+            ),
+            Some(l) => Some(l.permits.clone().acquire_owned().await?), // Ожидание в очереди до регистрации
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let progress = Arc::new(Progress::default());
+        let cancel = Arc::new(Notify::new());
+        self.transfers.lock().unwrap().insert(
+            id,
+            Transfer {
+                kind,
+                path,
+                progress: progress.clone(),
+                cancel: cancel.clone(),
+            },
+        );
+        let _registration = Registration { registry: self, id };
+
+        tokio::select! {
+            result = f(progress) => result,
+            _ = cancel.notified() => Err(Box::new(TransferCancelledError(id))),
+        }
+    }
+}
+
+mod errors {
+    // Модуль с составными ошибками
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::{Display, Formatter};
+
+    #[derive(Debug, Clone)]
+    pub struct TransferCancelledError(pub u64); // Ошибка отмены передачи
+
+    impl Display for TransferCancelledError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "Transfer {} was cancelled", self.0)
+        }
+    }
+
+    impl Error for TransferCancelledError {}
+
+    #[derive(Debug, Clone)]
+    pub struct TooManyTransfersError(pub usize); // Ошибка превышения максимального количества одновременных передач
+
+    impl Display for TooManyTransfersError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "Too many concurrent transfers (maximum {})", self.0)
+        }
+    }
+
+    impl Error for TooManyTransfersError {}
+}
@@ -0,0 +1,231 @@
+use std::error::Error; // Трейт ошибок стандартной библиотеки
+use std::path::{Component, Path, PathBuf}; // Структуры файловых путей
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _}; // BASE64-кодек
+use serde::{Deserialize, Serialize}; // Трейты (де)сериализации
+use tokio::fs; // Асинхронные операции с файловой системой
+
+use crate::chunks::{check_tag, ReedSolomonChunksHashes}; // Метаданные отдельного файла
+use crate::crypto::Encryptor; // Трейт шифровальщика, вычисляющего теги
+
+use consts::*; // Внутренние константы
+use errors::*; // Внутренние ошибки
+
+mod consts {
+    pub const TAG_PREFIX: &[u8] = b"leaf-dir:"; // Префикс данных тега каталога (тег каталога нельзя выдать за тег файла)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum EntryKind {
+    // Тип элемента каталога
+    Dir, // Каталог (сохраняется, чтобы восстанавливались и пустые каталоги)
+    File(Box<ReedSolomonChunksHashes>), // Файл с метаданными его чанков
+    Symlink(PathBuf), // Символическая ссылка с исходным путем назначения (не разыменовывается)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DirEntry {
+    pub path: PathBuf,   // Путь относительно корня каталога
+    pub kind: EntryKind, // Тип элемента
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct DirManifest {
+    // Метаданные каталога: элементы в порядке обхода (родительский каталог раньше вложенных элементов)
+    pub entries: Vec<DirEntry>,
+    #[serde(default)]
+    pub tag: Option<String>, // Тег аутентификации метаданных каталога (в старых метаданных отсутствует)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WalkEntry {
+    // Элемент каталога, найденный при обходе
+    Dir,
+    File,
+    Symlink(PathBuf),
+}
+
+pub async fn walk(root: impl AsRef<Path>) -> Result<Vec<(PathBuf, WalkEntry)>, Box<dyn Error>> {
+    // Рекурсивный обход каталога. Символические ссылки не разыменовываются, прочие специальные
+    // файлы (сокеты, устройства и т.п.) пропускаются. Элементы упорядочены по пути
+    let root = root.as_ref();
+    let mut found = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(rel) = pending.pop() {
+        let mut dir = fs::read_dir(root.join(&rel)).await?;
+        while let Some(item) = dir.next_entry().await? {
+            let path = rel.join(item.file_name());
+            let file_type = item.file_type().await?;
+            if file_type.is_symlink() {
+                let target = fs::read_link(item.path()).await?;
+                found.push((path, WalkEntry::Symlink(target)));
+            } else if file_type.is_dir() {
+                found.push((path.clone(), WalkEntry::Dir));
+                pending.push(path);
+            } else if file_type.is_file() {
+                found.push((path, WalkEntry::File));
+            }
+        }
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(found)
+}
+
+pub fn target_path(target: &Path, rel: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    // Путь восстанавливаемого элемента. Метаданные могут быть подменены, поэтому пути,
+    // выходящие за пределы целевого каталога, отклоняются
+    let safe = rel.components().next().is_some()
+        && rel.components().all(|c| matches!(c, Component::Normal(_)));
+    if !safe {
+        return Err(Box::new(UnsafePathError(rel.display().to_string())));
+    }
+    Ok(target.join(rel))
+}
+
+impl DirManifest {
+    fn tag_message(&self) -> Vec<u8> {
+        // Данные, заверяемые тегом: пути и типы элементов по порядку, для файлов - их списки чанков
+        let mut message = TAG_PREFIX.to_vec();
+        message.extend((self.entries.len() as u64).to_be_bytes());
+        for entry in &self.entries {
+            let path = entry.path.to_string_lossy();
+            message.extend((path.len() as u64).to_be_bytes());
+            message.extend(path.as_bytes());
+            let (kind, data) = match &entry.kind {
+                EntryKind::Dir => (0u8, Vec::new()),
+                EntryKind::File(hashes) => (1, hashes.tag_message()),
+                EntryKind::Symlink(link) => (2, link.to_string_lossy().as_bytes().to_vec()),
+            };
+            message.push(kind);
+            message.extend((data.len() as u64).to_be_bytes());
+            message.extend(data);
+        }
+        message
+    }
+
+    pub fn attach_tag(&mut self, encryptor: &dyn Encryptor) {
+        // Заверение метаданных каталога тегом: без него можно было бы подменить пути элементов,
+        // цели символических ссылок или переставить метаданные файлов между путями
+        self.tag = encryptor.authenticate(&self.tag_message()).map(hex::encode);
+    }
+
+    pub fn verify_tag(
+        &self,
+        encryptor: &dyn Encryptor,
+        allow_untagged: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        check_tag(
+            encryptor,
+            &self.tag_message(),
+            self.tag.as_deref(),
+            allow_untagged,
+        )
+    }
+
+    pub async fn save_to(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let data = BASE64.encode(serde_json::to_vec(self)?); // Сериализация в том же виде, что и метаданные файла
+        fs::write(path, &data).await?;
+        Ok(())
+    }
+
+    pub async fn load_from(path: impl AsRef<Path>) -> Result<DirManifest, Box<dyn Error>> {
+        let content = fs::read(path).await?;
+        Ok(serde_json::from_slice(&BASE64.decode(&content)?)?)
+    }
+}
+
+mod errors {
+    // Модуль с составными ошибками
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::{Display, Formatter};
+
+    #[derive(Debug, Clone)]
+    pub struct UnsafePathError(pub String); // Ошибка пути, выходящего за пределы целевого каталога
+
+    impl Display for UnsafePathError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Refusing to restore entry outside target directory: {}",
+                self.0
+            )
+        }
+    }
+
+    impl Error for UnsafePathError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunks::{ManifestTagError, UntaggedManifestError};
+    use crate::crypto::{DecryptionError, EncryptionError};
+
+    struct Tagging(u8); // Шифровальщик для проверки тегов: тег - простая сумма данных с ключом
+
+    impl Encryptor for Tagging {
+        fn encrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+            Ok(chunk.to_vec())
+        }
+
+        fn decrypt_chunk(&self, chunk: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+            Ok(chunk.to_vec())
+        }
+
+        fn authenticate(&self, message: &[u8]) -> Option<Vec<u8>> {
+            let sum = message.iter().fold(self.0 as u64, |s, &b| {
+                s.wrapping_mul(31).wrapping_add(b as u64)
+            });
+            Some(sum.to_be_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn modified_dir_manifest_fails_tag_verification() {
+        let entry = |path: &str, kind| DirEntry {
+            path: PathBuf::from(path),
+            kind,
+        };
+        let mut manifest = DirManifest {
+            entries: vec![
+                entry("docs", EntryKind::Dir),
+                entry("docs/link", EntryKind::Symlink(PathBuf::from("../notes"))),
+            ],
+            tag: None,
+        };
+        let path = std::env::temp_dir().join(format!("leaf-dir-{}", rand::random::<u64>()));
+        manifest.attach_tag(&Tagging(1));
+        manifest.save_to(&path).await.unwrap();
+        let restored = DirManifest::load_from(&path).await.unwrap();
+        assert!(restored.verify_tag(&Tagging(1), false).is_ok()); // Тег сохраняется в метаданных
+        assert!(restored.verify_tag(&Tagging(2), false).is_err()); // Другой ключ
+        fs::remove_file(&path).await.unwrap();
+
+        let mut redirected = restored.clone();
+        redirected.entries[1].kind = EntryKind::Symlink(PathBuf::from("/etc/passwd"));
+        let err = redirected.verify_tag(&Tagging(1), false).unwrap_err();
+        assert!(err.is::<ManifestTagError>());
+        let mut renamed = restored.clone();
+        renamed.entries[0].path = PathBuf::from("other");
+        assert!(renamed.verify_tag(&Tagging(1), false).is_err());
+
+        manifest.tag = None;
+        let err = manifest.verify_tag(&Tagging(1), false).unwrap_err();
+        assert!(err.is::<UntaggedManifestError>());
+        assert!(manifest.verify_tag(&Tagging(1), true).is_ok());
+    }
+
+    #[test]
+    fn target_path_rejects_escaping_entries() {
+        let target = Path::new("/restore");
+        assert_eq!(
+            target_path(target, Path::new("a/b.txt")).unwrap(),
+            PathBuf::from("/restore/a/b.txt")
+        );
+        assert!(target_path(target, Path::new("../etc/passwd")).is_err());
+        assert!(target_path(target, Path::new("a/../../b")).is_err());
+        assert!(target_path(target, Path::new("/etc/passwd")).is_err());
+        assert!(target_path(target, Path::new("")).is_err());
+    }
+}
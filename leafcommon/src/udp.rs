@@ -0,0 +1,208 @@
+use std::io; // Ошибки ввода-вывода стандартной библиотеки
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}; // Структуры сетевых адресов
+
+use pnet::ipnetwork::IpNetwork; // Сети, настроенные на интерфейсах
+
+use socket2::{Domain, Protocol, Socket, Type}; // Внешняя зависимость для настройки сокета до его открытия
+use tokio::net::UdpSocket; // Асинхронный UDP-сокет
+
+pub const DEFAULT_BUF_SIZE: usize = 4 * 1024 * 1024; // Размер буферов сокета по умолчанию - 4 Мб
+pub const MAX_DSCP: u8 = 63; // Максимальное значение DSCP (6 бит)
+const BROADCAST_ADDR: &str = "255.255.255.255:62092"; // Широковещательный адрес локальной сети с портом
+const DOMAIN_PORT: u16 = 62092; // Порт серверов домена
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressMode {
+    // Способ адресации запросов серверам домена, когда серверы не указаны явно
+    #[default]
+    Ipv4Broadcast, // Широковещание IPv4 (ограниченное или на адрес подсети)
+    Ipv6Multicast(Ipv6Addr), // Многоадресная рассылка IPv6 на группу домена: в IPv6 нет широковещания
+}
+
+impl AddressMode {
+    pub fn client_addr(&self, port: u16) -> SocketAddr {
+        // Адрес клиентского сокета: для IPv6 сокет открывается на всех адресах IPv6
+        let ip = match self {
+            AddressMode::Ipv4Broadcast => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            AddressMode::Ipv6Multicast(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        SocketAddr::new(ip, port)
+    }
+
+    pub fn domain_addr(
+        &self,
+        broadcast: Option<Ipv4Addr>,
+        peers: &[SocketAddr],
+    ) -> io::Result<Option<SocketAddr>> {
+        // Проверка адресации и получение адреса для send_to_domain: адреса широковещания подсети
+        // (только для IPv4) или группы (None - ограниченное широковещание). Ответы на сокет IPv6
+        // приходят с адресов IPv6, поэтому серверы IPv4 в режиме IPv6 не указываются
+        match self {
+            AddressMode::Ipv4Broadcast => broadcast.map(directed_broadcast).transpose(),
+            AddressMode::Ipv6Multicast(group) => {
+                if broadcast.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "broadcast address cannot be used with IPv6 multicast",
+                    ));
+                }
+                if let Some(peer) = peers.iter().find(|p| p.is_ipv4()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("IPv4 peer {} cannot be used with IPv6 multicast", peer),
+                    ));
+                }
+                multicast_group(*group).map(Some)
+            }
+        }
+    }
+}
+
+pub async fn send_to_domain(
+    socket: &UdpSocket,
+    data: &[u8],
+    peers: &[SocketAddr],
+    broadcast: Option<SocketAddr>,
+) -> io::Result<()> {
+    // Отправка запроса серверам домена: широковещательно или, если серверы указаны, каждому из них.
+    // broadcast - адрес направленного широковещания подсети (None - ограниченное широковещание)
+    if peers.is_empty() {
+        match broadcast {
+            Some(addr) => socket.send_to(data, addr).await?,
+            None => socket.send_to(data, BROADCAST_ADDR).await?,
+        };
+        return Ok(());
+    }
+    for peer in peers {
+        socket.send_to(data, peer).await?;
+    }
+    Ok(())
+}
+
+pub fn local_addrs() -> Vec<IpAddr> {
+    // Получение всех IP-адресов сетевых интерфейсов машины
+    pnet::datalink::interfaces()
+        .into_iter()
+        .flat_map(|i| i.ips.into_iter().map(|n| n.ip()))
+        .collect()
+}
+
+pub fn multicast_group(group: Ipv6Addr) -> io::Result<SocketAddr> {
+    // Проверка группы многоадресной рассылки IPv6, на которую отправляются запросы домена
+    if !group.is_multicast() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not an IPv6 multicast address", group),
+        ));
+    }
+    Ok(SocketAddr::new(IpAddr::V6(group), DOMAIN_PORT))
+}
+
+pub fn canonical(addr: SocketAddr) -> SocketAddr {
+    // Адрес отправителя IPv4, принятый сокетом IPv6 в виде ::ffff:a.b.c.d, приводится к IPv4
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+pub fn directed_broadcast(ip: Ipv4Addr) -> io::Result<SocketAddr> {
+    // Проверка адреса широковещания, указанного вместо ограниченного: на узле с несколькими
+    // интерфейсами он выбирает подсеть домена. Адрес должен быть широковещательным адресом
+    // одной из сетей, настроенных на интерфейсах машины
+    let known = ip == Ipv4Addr::BROADCAST
+        || pnet::datalink::interfaces()
+            .iter()
+            .flat_map(|i| i.ips.iter())
+            .any(|n| matches!(n, IpNetwork::V4(n) if n.prefix() < 31 && n.broadcast() == ip));
+    if !known {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is not a broadcast address of any configured interface",
+                ip
+            ),
+        ));
+    }
+    Ok(SocketAddr::new(IpAddr::V4(ip), DOMAIN_PORT))
+}
+
+pub fn bind(addr: &str, buf_size: usize) -> io::Result<UdpSocket> {
+    bind_marked(addr, buf_size, None) // Создание сокета без маркировки трафика
+}
+
+pub fn bind_marked(addr: &str, buf_size: usize, dscp: Option<u8>) -> io::Result<UdpSocket> {
+    // Создание широковещательного UDP-сокета с увеличенными буферами приема и отправки.
+    // dscp - класс обслуживания (DSCP), которым маркируются отправляемые пакеты для QoS.
+    // Сокет IPv6 принимает и пакеты IPv4, поэтому один сокет сервера обслуживает оба протокола
+    let addr: SocketAddr = addr
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_recv_buffer_size(buf_size)?;
+    socket.set_send_buffer_size(buf_size)?;
+    match addr {
+        SocketAddr::V4(_) => socket.set_broadcast(true)?,
+        SocketAddr::V6(_) => socket.set_only_v6(false)?,
+    }
+    if let Some(dscp) = dscp {
+        if addr.is_ipv6() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "DSCP marking is supported only for IPv4 sockets",
+            ));
+        }
+        if dscp > MAX_DSCP {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("DSCP value {} is out of range 0-{}", dscp, MAX_DSCP),
+            ));
+        }
+        socket.set_tos_v4((dscp as u32) << 2)?; // DSCP занимает старшие 6 бит байта ToS
+        println!(
+            "UDP socket {} packets are marked with DSCP {} (ToS 0x{:02x})",
+            addr,
+            dscp,
+            socket.tos_v4()?
+        );
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    println!(
+        "UDP socket {} buffers: receive {} bytes, send {} bytes (requested {} bytes)",
+        addr,
+        socket.recv_buffer_size()?,
+        socket.send_buffer_size()?,
+        buf_size,
+    ); // Ядро может ограничить запрошенный размер, поэтому выводим фактический
+    UdpSocket::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directed_broadcast_must_belong_to_an_interface() {
+        let limited = directed_broadcast(Ipv4Addr::BROADCAST).unwrap();
+        assert_eq!(limited, BROADCAST_ADDR.parse().unwrap());
+        assert!(directed_broadcast(Ipv4Addr::LOCALHOST).is_err()); // Адрес узла, а не подсети
+        assert!(directed_broadcast(Ipv4Addr::new(203, 0, 113, 255)).is_err()); // Подсеть не настроена
+    }
+
+    #[test]
+    fn ipv6_multicast_sends_to_domain_group() {
+        let group: Ipv6Addr = "ff02::1f:6c".parse().unwrap();
+        let mode = AddressMode::Ipv6Multicast(group);
+        let addr = mode.domain_addr(None, &[]).unwrap();
+        assert_eq!(addr, Some(SocketAddr::new(IpAddr::V6(group), DOMAIN_PORT)));
+        assert!(mode.client_addr(0).is_ipv6());
+        assert_eq!(AddressMode::default().domain_addr(None, &[]).unwrap(), None);
+
+        let unicast = AddressMode::Ipv6Multicast(Ipv6Addr::LOCALHOST);
+        assert!(unicast.domain_addr(None, &[]).is_err()); // Адрес узла, а не группы
+        let v4_peer: SocketAddr = "10.0.0.1:62092".parse().unwrap();
+        assert!(mode.domain_addr(None, &[v4_peer]).is_err());
+        assert!(mode.domain_addr(Some(Ipv4Addr::BROADCAST), &[]).is_err());
+
+        let mapped: SocketAddr = "[::ffff:10.0.0.1]:62092".parse().unwrap();
+        assert_eq!(canonical(mapped), v4_peer);
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap; // Зависимость стандартной библиотеки для работы с коллекциями
+use std::io; // Зависимость стандартной библиотеки для работы с ошибками ввода-вывода
+use std::path::Path; // Зависимость стандартной библиотеки для работы с файловыми путями
+
+use leafcommon::reed_solomon_scheme::{Hasher, StreebogHasher, KEYED_HASH_MARK}; // Проверка чанков по содержимому
+use serde::{Deserialize, Serialize};
+use tokio::fs; // Внешняя зависимость для работы с файловыми операциями асинхронно
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter}; // Потоковые чтение и запись архива
+
+use crate::stor::{read_chunk, ServerStorage, UdpServerStorage}; // Хранилище сервера
+
+use consts::*; // Внутренний модуль с константами
+use errors::*; // Внутренний модуль с составными типами ошибок
+
+mod consts {
+    // Модуль с константами
+    pub const TAR_BLOCK: usize = 512; // Размер блока tar: заголовки и данные выравниваются по нему
+    pub const TAR_NAME_LEN: usize = 100; // Максимальная длина имени файла в заголовке ustar без префикса
+    pub const CHUNKS_PREFIX: &str = "chunks/"; // Каталог чанков в архиве, имя файла - хэш чанка
+    pub const STATE_MEMBER: &str = "state.json"; // Токены и метаданные чанков архива
+    pub const MAX_CHUNK_MEMBER: usize = leafcommon::message::MAX_MESSAGE_SIZE as usize; // Чанк больше сообщения не мог быть сохранен
+    pub const MAX_STATE_MEMBER: usize = 512 * 1024 * 1024; // Больше, чем занимает state.json заполненного хранилища
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ArchivedChunk {
+    // Сведения о чанке из индекса хранилища, переносимые вместе с ним
+    token: u64,            // Токен отправки, сохранившей чанк
+    meta: Option<Vec<u8>>, // Метаданные чанка
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArchiveStats {
+    // Результаты выгрузки или загрузки архива
+    pub chunks: usize,   // Количество выгруженных или загруженных чанков
+    pub skipped: usize,  // Чанки, уже хранящиеся на узле (при загрузке)
+    pub rejected: usize, // Чанки, содержимое которых не соответствует хэшу или которые нельзя выгрузить
+}
+
+pub async fn export(
+    storage: &UdpServerStorage,
+    path: impl AsRef<Path>,
+) -> Result<ArchiveStats, Box<dyn std::error::Error>> {
+    // Выгрузка всех чанков хранилища в архив tar для резервного копирования: каждый чанк - файл
+    // chunks/<хэш>, в конце - state.json с токенами и метаданными. При скрытых хэшах хэш чанка
+    // вычисляется по содержимому, поэтому поврежденные и адресуемые ключом чанки не выгружаются.
+    // Выгружается состояние, записанное при остановке сервера, поэтому сервер должен быть остановлен
+    let mut out = BufWriter::new(fs::File::create(path).await?);
+    let (index, obfuscated) = (storage.index_key(), storage.is_index_obfuscated());
+    let mut state: HashMap<String, ArchivedChunk> = HashMap::new();
+    let mut stats = ArchiveStats::default();
+    for (name, file) in storage.stored_files() {
+        let data = read_chunk(&file).await?;
+        let hash = match name.starts_with(KEYED_HASH_MARK) {
+            true if obfuscated => {
+                eprintln!("Chunk {} is addressed by a hidden key, not exported", name);
+                stats.rejected += 1;
+                continue;
+            }
+            true => name.clone(),
+            false => StreebogHasher.calc_hash(&data),
+        };
+        if index.name(&hash) != name {
+            eprintln!(
+                "Chunk {} in {} is corrupted, not exported",
+                name,
+                file.display()
+            );
+            stats.rejected += 1;
+            continue;
+        }
+        write_member(&mut out, &format!("{}{}", CHUNKS_PREFIX, hash), &data).await?;
+        let entry = ArchivedChunk {
+            token: storage.token(&hash).unwrap_or_default(),
+            meta: storage.meta(&hash).map(<[u8]>::to_vec),
+        };
+        state.insert(hash, entry);
+        stats.chunks += 1;
+    }
+    write_member(&mut out, STATE_MEMBER, &serde_json::to_vec(&state)?).await?;
+    out.write_all(&[0u8; 2 * TAR_BLOCK]).await?; // Конец архива - два пустых блока
+    out.flush().await?;
+    Ok(stats)
+}
+
+pub async fn import(
+    storage: &mut UdpServerStorage,
+    path: impl AsRef<Path>,
+) -> Result<ArchiveStats, Box<dyn std::error::Error>> {
+    // Загрузка чанков из архива export. Хэш содержимого каждого чанка (кроме адресуемых ключом)
+    // сверяется с его именем, несоответствующие чанки отбрасываются. Уже хранящиеся чанки
+    // пропускаются, поэтому повторная загрузка того же архива ничего не меняет
+    let mut input = BufReader::new(fs::File::open(path).await?);
+    let mut imported = Vec::new();
+    let mut state: HashMap<String, ArchivedChunk> = HashMap::new();
+    let mut stats = ArchiveStats::default();
+    while let Some((name, data)) = read_member(&mut input).await? {
+        if name == STATE_MEMBER {
+            state = serde_json::from_slice(&data)?;
+            continue;
+        }
+        let Some(hash) = name.strip_prefix(CHUNKS_PREFIX) else {
+            continue; // Посторонние файлы архива не загружаются
+        };
+        if !hash.starts_with(KEYED_HASH_MARK) && StreebogHasher.calc_hash(&data) != hash {
+            eprintln!("Chunk {} in archive is corrupted, not imported", hash);
+            stats.rejected += 1;
+            continue;
+        }
+        if storage.is_hash_presented(hash) {
+            stats.skipped += 1;
+            continue;
+        }
+        storage.save(hash, &data, 0).await?;
+        imported.push(hash.to_string());
+        stats.chunks += 1;
+    }
+    for hash in imported {
+        // Токены и метаданные записываются только загруженным сейчас чанкам
+        let entry = state.remove(&hash).unwrap_or_default();
+        storage.set_token(&hash, entry.token);
+        if let Some(meta) = entry.meta {
+            storage.set_meta(&hash, meta)?;
+        }
+    }
+    storage.flush().await?;
+    Ok(stats)
+}
+
+fn tar_header(name: &str, size: usize) -> io::Result<[u8; TAR_BLOCK]> {
+    // Заголовок обычного файла в формате ustar
+    if name.len() > TAR_NAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("archive member name {} is too long", name),
+        ));
+    }
+    let mut header = [0u8; TAR_BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..107].copy_from_slice(b"0000644"); // Права доступа
+    header[108..115].copy_from_slice(b"0000000"); // Владелец
+    header[116..123].copy_from_slice(b"0000000"); // Группа
+    header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+    header[136..147].copy_from_slice(b"00000000000"); // Время изменения
+    header[156] = b'0'; // Обычный файл
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    let sum = checksum(&header);
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+    Ok(header)
+}
+
+fn checksum(header: &[u8; TAR_BLOCK]) -> u32 {
+    // Контрольная сумма заголовка: сумма байт, поле самой суммы считается пробелами
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| match i {
+            148..156 => b' ' as u32,
+            _ => b as u32,
+        })
+        .sum()
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<usize> {
+    let text = String::from_utf8_lossy(field);
+    let text = text.trim_matches(|c| c == '\0' || c == ' ');
+    usize::from_str_radix(text, 8).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            ArchiveFormatError(text.to_string()),
+        )
+    })
+}
+
+async fn write_member(
+    out: &mut (impl AsyncWrite + Unpin),
+    name: &str,
+    data: &[u8],
+) -> io::Result<()> {
+    // Запись файла в архив: заголовок и данные, дополненные до размера блока
+    out.write_all(&tar_header(name, data.len())?).await?;
+    out.write_all(data).await?;
+    let padding = data.len().next_multiple_of(TAR_BLOCK) - data.len();
+    out.write_all(&[0u8; TAR_BLOCK][..padding]).await
+}
+
+async fn read_member(
+    input: &mut (impl AsyncRead + Unpin),
+) -> io::Result<Option<(String, Vec<u8>)>> {
+    // Чтение очередного обычного файла архива (None - конец архива)
+    loop {
+        let mut header = [0u8; TAR_BLOCK];
+        match input.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        if header.iter().all(|&b| b == 0) {
+            return Ok(None);
+        }
+        if parse_octal(&header[148..156])? != checksum(&header) as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                ArchiveFormatError(String::from("header checksum mismatch")),
+            ));
+        }
+        let size = parse_octal(&header[124..136])?;
+        let padded = size.next_multiple_of(TAR_BLOCK);
+        let len = header[..TAR_NAME_LEN]
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(TAR_NAME_LEN);
+        let name = String::from_utf8_lossy(&header[..len]).into_owned();
+        let limit = match name.as_str() {
+            STATE_MEMBER => MAX_STATE_MEMBER,
+            _ if name.starts_with(CHUNKS_PREFIX) => MAX_CHUNK_MEMBER,
+            _ => 0,
+        };
+        if !matches!(header[156], b'0' | 0) || limit == 0 {
+            // Каталоги, ссылки и посторонние файлы пропускаются без чтения в память
+            tokio::io::copy(&mut input.take(padded as u64), &mut tokio::io::sink()).await?;
+            continue;
+        }
+        if size > limit {
+            // Размер из заголовка проверяется до выделения памяти под данные
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                ArchiveFormatError(format!(
+                    "member {} is {} bytes, at most {} allowed",
+                    name, size, limit
+                )),
+            ));
+        }
+        let mut data = vec![0u8; padded];
+        input.read_exact(&mut data).await?;
+        data.truncate(size);
+        return Ok(Some((name, data)));
+    }
+}
+
+mod errors {
+    // Внутренний модуль с составными типами ошибок
+    use std::error::Error;
+    use std::fmt;
+
+    #[derive(Debug, Clone)]
+    pub struct ArchiveFormatError(pub String); // Тип ошибки разбора архива
+
+    impl fmt::Display for ArchiveFormatError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Invalid chunk archive: {}", self.0)
+        }
+    }
+
+    impl Error for ArchiveFormatError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stor::WriteBatch;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn exported_chunks_are_imported_once_and_verified() {
+        let base = std::env::temp_dir().join(format!("leafd-archive-{}", Uuid::new_v4()));
+        let (src, dst) = (base.join("src"), base.join("dst"));
+        fs::create_dir_all(&src).await.unwrap();
+        fs::create_dir_all(&dst).await.unwrap();
+        let tarball = base.join("chunks.tar");
+
+        let mut storage = UdpServerStorage::new(src, &base.join("src.bin"), WriteBatch::default())
+            .await
+            .unwrap();
+        let chunks: Vec<Vec<u8>> = (1..=3u8).map(|i| vec![i; 700]).collect();
+        for (i, data) in chunks.iter().enumerate() {
+            storage
+                .save(&StreebogHasher.calc_hash(data), data, i as u64 + 1)
+                .await
+                .unwrap();
+        }
+        let first = StreebogHasher.calc_hash(&chunks[0]);
+        storage.set_meta(&first, b"name".to_vec()).unwrap();
+        storage.flush().await.unwrap();
+        let stats = export(&storage, &tarball).await.unwrap();
+        assert_eq!(stats.chunks, 3);
+
+        let mut restored = UdpServerStorage::new(dst, &base.join("dst.bin"), WriteBatch::default())
+            .await
+            .unwrap();
+        let stats = import(&mut restored, &tarball).await.unwrap();
+        assert_eq!((stats.chunks, stats.skipped, stats.rejected), (3, 0, 0));
+        assert_eq!(restored.meta(&first), Some(&b"name"[..]));
+        assert_eq!(restored.token(&first), Some(1)); // Токен отправки переносится из state.json
+        let stats = import(&mut restored, &tarball).await.unwrap();
+        assert_eq!((stats.chunks, stats.skipped), (0, 3)); // Повторная загрузка ничего не меняет
+
+        // Искаженный байт данных первого чанка: чанк отбрасывается
+        let mut archive = fs::read(&tarball).await.unwrap();
+        archive[TAR_BLOCK] ^= 1;
+        fs::write(&tarball, &archive).await.unwrap();
+        let fresh = base.join("fresh");
+        fs::create_dir_all(&fresh).await.unwrap();
+        let mut fresh =
+            UdpServerStorage::new(fresh, &base.join("fresh.bin"), WriteBatch::default())
+                .await
+                .unwrap();
+        let stats = import(&mut fresh, &tarball).await.unwrap();
+        assert_eq!((stats.chunks, stats.rejected), (2, 1));
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversized_member_is_rejected_before_reading() {
+        // Заголовок чанка с наибольшим размером ustar (8 Гб): ошибка без выделения памяти под данные
+        let header = tar_header(&format!("{}hash", CHUNKS_PREFIX), (1 << 33) - 1).unwrap();
+        let err = read_member(&mut &header[..]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Посторонний файл пропускается, следующий за ним читается
+        let mut archive = tar_header("notes.txt", 3).unwrap().to_vec();
+        archive.extend([b'x'; TAR_BLOCK]);
+        archive.extend(tar_header(STATE_MEMBER, 2).unwrap());
+        archive.extend(b"{}");
+        archive.resize(archive.len().next_multiple_of(TAR_BLOCK), 0);
+        let member = read_member(&mut &archive[..]).await.unwrap();
+        assert_eq!(member, Some((STATE_MEMBER.to_string(), b"{}".to_vec())));
+    }
+}
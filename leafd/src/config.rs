@@ -0,0 +1,317 @@
+use std::net::{IpAddr, Ipv6Addr, SocketAddr}; // Структуры сетевых адресов
+use std::path::{Path, PathBuf}; // Структуры файловых путей
+use std::time::Duration; // Структура с длительностью ожидания
+
+use ipnetwork::IpNetwork; // Внешняя зависимость для работы с диапазонами адресов (CIDR)
+use leafcommon::udp::AddressMode; // Адресация домена
+use leafcommon::Message; // Перечисление сообщений
+use serde::{Deserialize, Serialize}; // Внешняя зависимость для (де)сериализации
+use tokio::fs; // Внешняя зависимость для работы с файловыми операциями асинхронно
+
+use crate::stor::{Durability, Watermarks, WriteBatch}; // Параметры записи хранилища
+use crate::workers::RestartPolicy; // Параметры перезапуска приема пакетов
+
+use errors::*; // Внутренний модуль с составными типами ошибок
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrubAction {
+    // Действие с чанком, содержимое которого не соответствует его хэшу
+    Report,     // Только сообщить о повреждении
+    Quarantine, // Убрать чанк из хранилища, переместив файл в каталог карантина
+    Delete,     // Удалить чанк
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMode {
+    // Роль узла в домене
+    #[default]
+    StoreOnly, // Узел сохраняет полученные чанки
+    RelayOnly, // Узел не принимает чанки на хранение (транзитный узел), ранее сохраненные чанки по-прежнему выдаются
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandledMessage {
+    // Типы сообщений, обработку которых можно отключить
+    SendingReq,
+    RetrievingReq,
+    ContentFilled, // В том числе данные с метаданными
+    StoreLocal,
+    DiscoveryReq,
+    DiscoveryAck,
+    MetaReq,
+    HasReq,
+}
+
+impl HandledMessage {
+    pub fn of(message: &Message) -> Option<HandledMessage> {
+        // Тип входящего сообщения (None - сообщение, не обрабатываемое сервером)
+        Some(match message {
+            Message::SendingReq(..) => HandledMessage::SendingReq,
+            Message::RetrievingReq(..) => HandledMessage::RetrievingReq,
+            Message::ContentFilled(..) | Message::ContentFilledMeta(..) => {
+                HandledMessage::ContentFilled
+            }
+            Message::StoreLocal(..) => HandledMessage::StoreLocal,
+            Message::DiscoveryReq => HandledMessage::DiscoveryReq,
+            Message::DiscoveryAck(..) => HandledMessage::DiscoveryAck,
+            Message::MetaReq(..) => HandledMessage::MetaReq,
+            Message::HasReq(..) => HandledMessage::HasReq,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    // Структура конфигурации сервера, читается из JSON-файла, отсутствующие поля принимают значения по умолчанию
+    pub allow: Vec<IpNetwork>, // Диапазоны адресов, которые обслуживаются сервером (пустой список - все адреса)
+    pub deny: Vec<IpNetwork>, // Диапазоны адресов, которые не обслуживаются сервером (приоритетнее allow)
+    pub write_batch_chunks: usize, // Количество чанков, накапливаемых перед записью на диск (1 - запись сразу)
+    pub write_batch_delay_ms: u64, // Максимальное время ожидания чанка в буфере записи, мс
+    pub free_space_low_bytes: usize, // Свободное место хранилища, при котором прием новых чанков прекращается
+    pub free_space_high_bytes: usize, // Свободное место, после превышения которого прием возобновляется (не ниже free_space_low_bytes)
+    pub durability: Durability, // Синхронизация чанков с диском до подтверждения: "strong" (надежно, медленнее) или "relaxed"
+    // Не сохранять чанки, отправленные самим узлом (если на нем запущен и клиент). Адреса узла
+    // включают loopback, поэтому по умолчанию проверка отключена: иначе единственный сервер узла
+    // перестал бы принимать чанки от клиента на той же машине
+    pub ignore_own_packets: bool,
+    pub storage_dir: Option<PathBuf>, // Каталог хранения чанков (по умолчанию - каталог приложения)
+    pub max_concurrent_requests: usize, // Количество одновременно обрабатываемых запросов
+    pub request_timeout_ms: u64,      // Максимальное время обработки одного запроса, мс
+    pub seed_peers: Vec<SocketAddr>, // Серверы, у которых запрашивается список известных серверов при запуске
+    pub max_known_peers: usize,      // Максимальное количество известных серверов
+    pub health_addr: Option<SocketAddr>, // Адрес административного сокета проверки работоспособности (None - отключен)
+    pub scrub_rate: f64, // Количество чанков в секунду, проверяемых на повреждение в фоне (0 - проверка отключена)
+    pub scrub_action: ScrubAction, // Действие с поврежденным чанком
+    pub storage_mode: StorageMode, // Роль узла: хранение чанков или только транзит
+    // Сообщения, которые сервер отбрасывает без обработки. На узлах, доступных из недоверенной
+    // сети, имеет смысл отключить сообщения, раскрывающие сведения об узле и домене: discovery_req
+    // выдает список известных серверов, meta_req позволяет проверить, хранится ли чанк с данным
+    // хэшем, не изымая его. Отключение не мешает хранению и выдаче чанков
+    pub disabled_messages: Vec<HandledMessage>,
+    // Максимальная случайная задержка подтверждения SENDING_ACK, мс (0 - без задержки). Разносит
+    // по времени ответы множества серверов на один широковещательный запрос; должна быть меньше
+    // окна сбора подтверждений клиента (50 мс по умолчанию), иначе ответы этого сервера будут опаздывать
+    pub ack_jitter_ms: u64,
+    pub dscp: Option<u8>, // Класс обслуживания DSCP (0-63) отправляемых сервером пакетов для QoS (None - без маркировки)
+    // Группа IPv6 домена (например, ff02::1f:6c), к которой присоединяется сервер: сокет открывается
+    // на адресах IPv6 и продолжает принимать широковещание IPv4 (None - только IPv4)
+    pub multicast_group: Option<Ipv6Addr>,
+    pub compress_state: bool, // Сжимать ли файл состояния (индекс чанков) zstd; старые версии сервера сжатый файл не прочтут
+    // Хранить чанки в индексе под HMAC хэша на секрете узла (файл index.key в каталоге приложения)
+    // вместо самого хэша: файл состояния не раскрывает, какие чанки хранит узел. Включенное скрытие
+    // нельзя отключить, а без файла секрета сохраненные чанки найти невозможно
+    pub obfuscate_index: bool,
+    pub listener_restarts: usize, // Количество перезапусков подряд приема пакетов после ошибки сокета или паники, после которого сервер завершается
+    pub listener_restart_delay_ms: u64, // Время ожидания перед перезапуском приема пакетов, мс
+    #[serde(skip)]
+    own_addrs: Vec<IpAddr>, // Адреса сетевых интерфейсов узла
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let batch = WriteBatch::default();
+        ServerConfig {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            write_batch_chunks: batch.max_chunks,
+            write_batch_delay_ms: batch.max_delay.as_millis() as u64,
+            free_space_low_bytes: 0,
+            free_space_high_bytes: 0,
+            durability: Durability::default(),
+            ignore_own_packets: false,
+            storage_dir: None,
+            max_concurrent_requests: 16,
+            request_timeout_ms: 5000,
+            seed_peers: Vec::new(),
+            max_known_peers: leafcommon::discovery::MAX_PEERS,
+            health_addr: None,
+            scrub_rate: 0.0,
+            scrub_action: ScrubAction::Report,
+            storage_mode: StorageMode::default(),
+            disabled_messages: Vec::new(),
+            ack_jitter_ms: 0,
+            compress_state: false,
+            obfuscate_index: false,
+            listener_restarts: 5,
+            listener_restart_delay_ms: 1000,
+            dscp: None,
+            multicast_group: None,
+            own_addrs: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub async fn load(path: impl AsRef<Path>) -> Result<ServerConfig, ConfigLoadingError> {
+        // Загрузка конфигурации из файла, при отсутствии файла используется конфигурация по умолчанию
+        let path = path.as_ref();
+        let mut config = if path.exists() {
+            let content = fs::read(path)
+                .await
+                .map_err(|e| ConfigLoadingError(e.to_string()))?;
+            serde_json::from_slice(&content).map_err(|e| ConfigLoadingError(e.to_string()))?
+        } else {
+            ServerConfig::default()
+        };
+        if config.dscp.is_some_and(|d| d > leafcommon::udp::MAX_DSCP) {
+            return Err(ConfigLoadingError(format!(
+                "dscp must be in range 0-{}",
+                leafcommon::udp::MAX_DSCP
+            )));
+        }
+        if let Some(group) = config.multicast_group {
+            leafcommon::udp::multicast_group(group)
+                .map_err(|e| ConfigLoadingError(e.to_string()))?;
+            if config.dscp.is_some() {
+                return Err(ConfigLoadingError(String::from(
+                    "dscp cannot be used with multicast_group",
+                )));
+            }
+        }
+        config.own_addrs = leafcommon::udp::local_addrs(); // Адреса определяются по интерфейсам при запуске
+        Ok(config)
+    }
+
+    pub fn is_handled(&self, message: &Message) -> bool {
+        // Проверка, обрабатывается ли сообщение (отключенные типы отбрасываются)
+        HandledMessage::of(message).is_none_or(|k| !self.disabled_messages.contains(&k))
+    }
+
+    pub fn stores_chunks(&self) -> bool {
+        self.storage_mode == StorageMode::StoreOnly // Проверка, принимает ли узел чанки на хранение
+    }
+
+    pub fn is_own_address(&self, ip: IpAddr) -> bool {
+        // Проверка, отправлен ли пакет самим узлом (при включенной проверке)
+        self.ignore_own_packets && self.own_addrs.contains(&ip)
+    }
+
+    pub fn storage_path(&self, default: PathBuf) -> PathBuf {
+        // Получение каталога хранения чанков
+        self.storage_dir.clone().unwrap_or(default)
+    }
+
+    pub fn write_batch(&self) -> WriteBatch {
+        // Получение параметров пакетной записи хранилища
+        WriteBatch {
+            max_chunks: self.write_batch_chunks.max(1),
+            max_delay: Duration::from_millis(self.write_batch_delay_ms.max(1)),
+        }
+    }
+
+    pub fn watermarks(&self) -> Watermarks {
+        // Получение порогов свободного места для приема чанков
+        Watermarks {
+            low: self.free_space_low_bytes,
+            high: self.free_space_high_bytes,
+        }
+    }
+
+    pub fn ack_delay(&self) -> Duration {
+        // Получение случайной задержки очередного подтверждения в пределах ack_jitter_ms
+        match self.ack_jitter_ms {
+            0 => Duration::ZERO,
+            jitter => Duration::from_millis(rand::random::<u64>() % (jitter + 1)),
+        }
+    }
+
+    pub fn listener_restart(&self) -> RestartPolicy {
+        // Получение параметров перезапуска приема пакетов
+        RestartPolicy {
+            max_restarts: self.listener_restarts,
+            delay: Duration::from_millis(self.listener_restart_delay_ms),
+        }
+    }
+
+    pub fn address_mode(&self) -> AddressMode {
+        // Получение адресации домена: группа IPv6, если она задана, иначе широковещание IPv4
+        self.multicast_group
+            .map_or(AddressMode::Ipv4Broadcast, AddressMode::Ipv6Multicast)
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        // Получение максимального времени обработки запроса
+        Duration::from_millis(self.request_timeout_ms.max(1))
+    }
+
+    pub fn is_peer_allowed(&self, ip: IpAddr) -> bool {
+        // Проверка, обслуживается ли узел с указанным адресом
+        if self.deny.iter().any(|n| n.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|n| n.contains(ip))
+    }
+}
+
+mod errors {
+    // Модуль с составными типами ошибок
+    use std::error::Error; // Зависимость стандартной библиотеки для работы с трейтом ошибок
+    use std::fmt; // Зависимость стандартной библиотеки для работы с форматированием
+
+    #[derive(Debug, Clone)]
+    pub struct ConfigLoadingError(pub String); // Тип ошибки загрузки конфигурации
+
+    impl fmt::Display for ConfigLoadingError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Error loading config: {}", self.0)
+        }
+    }
+
+    impl Error for ConfigLoadingError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn disabled_messages_are_not_handled() {
+        let config: ServerConfig =
+            serde_json::from_str(r#"{"disabled_messages": ["discovery_req", "meta_req"]}"#)
+                .unwrap();
+        assert!(!config.is_handled(&Message::DiscoveryReq));
+        assert!(!config.is_handled(&Message::MetaReq(String::from("hash"))));
+        assert!(config.is_handled(&Message::RetrievingReq(String::from("hash"))));
+        assert!(ServerConfig::default().is_handled(&Message::DiscoveryReq));
+    }
+
+    #[test]
+    fn ack_delays_are_spread_within_jitter() {
+        let config = ServerConfig {
+            ack_jitter_ms: 50,
+            ..ServerConfig::default()
+        };
+        // Задержки множества серверов, ответивших на один запрос, не совпадают
+        let delays: HashSet<Duration> = (0..100).map(|_| config.ack_delay()).collect();
+        assert!(delays.len() > 1);
+        assert!(delays.iter().all(|d| *d <= Duration::from_millis(50)));
+        assert_eq!(ServerConfig::default().ack_delay(), Duration::ZERO);
+    }
+
+    #[test]
+    fn peers_are_matched_against_allow_and_deny_ranges() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(ServerConfig::default().is_peer_allowed(ip("203.0.113.7"))); // Пустой allow - все адреса
+
+        let config: ServerConfig = serde_json::from_str(
+            r#"{"allow": ["10.0.0.0/8", "fd00::/8"], "deny": ["10.1.0.0/16", "10.2.3.4/32"]}"#,
+        )
+        .unwrap();
+        assert!(config.is_peer_allowed(ip("10.9.8.7")));
+        assert!(config.is_peer_allowed(ip("fd12::1")));
+        assert!(!config.is_peer_allowed(ip("192.168.1.1"))); // Вне allow
+        assert!(!config.is_peer_allowed(ip("10.1.2.3"))); // deny приоритетнее allow
+        assert!(!config.is_peer_allowed(ip("10.2.3.4")));
+        assert!(config.is_peer_allowed(ip("10.2.3.5")));
+
+        let deny_only: ServerConfig = serde_json::from_str(r#"{"deny": ["127.0.0.0/8"]}"#).unwrap();
+        assert!(!deny_only.is_peer_allowed(ip("127.0.0.1")));
+        assert!(deny_only.is_peer_allowed(ip("10.0.0.1")));
+    }
+}
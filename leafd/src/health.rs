@@ -0,0 +1,216 @@
+use std::fmt; // Зависимость стандартной библиотеки для отображения отчета
+use std::io; // Зависимость стандартной библиотеки для работы с ошибками ввода-вывода
+use std::net::SocketAddr; // Зависимость стандартной библиотеки для работы с сетевыми адресами
+use std::path::Path; // Зависимость стандартной библиотеки для работы с файловыми путями
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering}; // Атомарная отметка времени
+use std::sync::{Arc, Mutex}; // Разделяемое между задачами состояние
+use std::time::{Duration, Instant}; // Зависимость стандартной библиотеки для работы со временем
+
+use tokio::fs; // Внешняя зависимость для работы с файловыми операциями асинхронно
+use tokio::io::AsyncWriteExt; // Запись ответа в соединение
+use tokio::net::TcpListener; // Административный сокет
+
+use crate::socket::Socket; // Сокет сервера
+
+use consts::*; // Внутренний модуль с константами
+
+mod consts {
+    // Модуль с константами
+    use std::time::Duration;
+
+    pub const PROBE_FILE: &str = ".health-probe"; // Файл, записываемый при проверке каталога хранения
+    pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1); // Период отметок обработчика пакетов
+    pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5); // Время без отметок, после которого обработчик считается зависшим
+}
+
+pub async fn self_check(storage_dir: &Path, state_path: &Path, socket: &Socket) -> Vec<String> {
+    // Проверка при запуске: каталог хранения доступен для записи, файл состояния читается
+    // (если он есть), сокет открыт. Возвращает описания найденных проблем
+    let mut problems = Vec::new();
+    let probe = storage_dir.join(PROBE_FILE);
+    match fs::write(&probe, b"ok").await {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe).await;
+        }
+        Err(e) => problems.push(format!(
+            "storage directory {} is not writable: {}",
+            storage_dir.display(),
+            e
+        )),
+    }
+    if state_path.exists() {
+        if let Err(e) = fs::read(state_path).await {
+            problems.push(format!(
+                "state file {} is not readable: {}",
+                state_path.display(),
+                e
+            ));
+        }
+    }
+    if let Err(e) = socket.local_addr() {
+        problems.push(format!("socket is not bound: {}", e));
+    }
+    problems
+}
+
+pub struct Health {
+    // Состояние работоспособности сервера, обновляется обработчиком пакетов
+    started: Instant,                     // Время запуска
+    heartbeat: AtomicU64,                 // Время последней отметки обработчика от запуска, мс
+    storage_error: Mutex<Option<String>>, // Последняя ошибка записи в хранилище
+    write_degraded: AtomicBool,           // Прием чанков отключен из-за повторяющихся ошибок записи
+    paused: AtomicBool,                   // Прием новых чанков приостановлен оператором
+    scrubbed: AtomicU64,                  // Количество проверенных на повреждение чанков
+    corrupted: AtomicU64,                 // Количество найденных поврежденных чанков
+}
+
+impl Health {
+    pub fn new() -> Arc<Health> {
+        Arc::new(Health {
+            started: Instant::now(),
+            heartbeat: AtomicU64::new(0),
+            storage_error: Mutex::new(None),
+            write_degraded: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            scrubbed: AtomicU64::new(0),
+            corrupted: AtomicU64::new(0),
+        })
+    }
+
+    pub fn chunk_scrubbed(&self, intact: bool) {
+        // Учет результата фоновой проверки одного чанка
+        self.scrubbed.fetch_add(1, Ordering::Relaxed);
+        if !intact {
+            self.corrupted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn heartbeat_interval() -> Duration {
+        HEARTBEAT_INTERVAL // Период, с которым обработчик пакетов должен вызывать beat
+    }
+
+    pub fn beat(&self) {
+        // Отметка обработчика пакетов: он работает и принимает пакеты из очереди
+        let now = self.started.elapsed().as_millis() as u64;
+        self.heartbeat.store(now, Ordering::Relaxed);
+    }
+
+    pub fn storage_result<E: fmt::Display>(&self, result: &Result<(), E>) {
+        // Учет результата записи в хранилище: ошибка сохраняется до следующей успешной записи
+        *self.storage_error.lock().unwrap() = result.as_ref().err().map(|e| e.to_string());
+    }
+
+    pub fn storing_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed); // Учет приостановки приема новых чанков
+    }
+
+    pub fn storage_degraded(&self, degraded: bool) {
+        self.write_degraded.store(degraded, Ordering::Relaxed); // Учет отключения приема чанков хранилищем
+    }
+
+    pub fn report(&self) -> HealthReport {
+        // Получение отчета о работоспособности
+        let mut reasons = Vec::new();
+        let last = Duration::from_millis(self.heartbeat.load(Ordering::Relaxed));
+        let silent = self.started.elapsed().saturating_sub(last);
+        if silent > HEARTBEAT_TIMEOUT {
+            reasons.push(format!(
+                "packet handler is not responding for {} s",
+                silent.as_secs()
+            ));
+        }
+        if let Some(e) = self.storage_error.lock().unwrap().as_ref() {
+            reasons.push(format!("storage write failed: {}", e));
+        }
+        if self.write_degraded.load(Ordering::Relaxed) {
+            reasons.push(String::from(
+                "storage writes keep failing, new chunks are not accepted",
+            ));
+        }
+        let corrupted = self.corrupted.load(Ordering::Relaxed);
+        if corrupted > 0 {
+            reasons.push(format!(
+                "{} of {} scrubbed chunks were corrupted",
+                corrupted,
+                self.scrubbed.load(Ordering::Relaxed)
+            ));
+        }
+        HealthReport {
+            reasons,
+            paused: self.paused.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HealthReport {
+    // Отчет о работоспособности: пустой список причин - сервер исправен
+    pub reasons: Vec<String>,
+    pub paused: bool, // Прием новых чанков приостановлен (не считается неисправностью)
+}
+
+impl HealthReport {
+    pub fn is_ok(&self) -> bool {
+        self.reasons.is_empty()
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            write!(f, "OK")?;
+        } else {
+            write!(f, "DEGRADED: {}", self.reasons.join("; "))?;
+        }
+        if self.paused {
+            write!(f, " (storing paused)")?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn serve(addr: SocketAddr, health: Arc<Health>) -> io::Result<()> {
+    // Административный сокет: каждому подключившемуся отправляется строка отчета, после чего соединение закрывается
+    let listener = TcpListener::bind(addr).await?;
+    println!("Health check listening on {}", addr);
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let line = format!("{}\n", health.report());
+        if let Err(e) = stream.write_all(line.as_bytes()).await {
+            eprintln!("Error answering health check: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_is_degraded_without_heartbeat() {
+        let health = Health::new();
+        health.beat();
+        assert!(health.report().is_ok());
+
+        let health = Health {
+            started: Instant::now() - HEARTBEAT_TIMEOUT * 2,
+            heartbeat: AtomicU64::new(0),
+            storage_error: Mutex::new(None),
+            write_degraded: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            scrubbed: AtomicU64::new(0),
+            corrupted: AtomicU64::new(0),
+        };
+        assert!(!health.report().is_ok());
+        health.beat();
+        assert!(health.report().is_ok());
+
+        health.storage_result(&Err("disk full"));
+        assert_eq!(
+            health.report().to_string(),
+            "DEGRADED: storage write failed: disk full"
+        );
+        health.storage_result::<&str>(&Ok(()));
+        assert!(health.report().is_ok());
+    }
+}
@@ -1,18 +1,33 @@
 #![allow(unused_mut)]
 
+mod archive;
+mod config;
+mod health;
+mod scrub;
 mod socket;
 mod stor;
+mod workers;
 
+use config::ServerConfig;
 use consts::*;
 use errors::*;
+use health::Health;
+use leafcommon::discovery::{PeerSet, SERVER_PORT};
+use leafcommon::message::MAX_META_SIZE; // Максимальный размер метаданных чанка
 use leafcommon::Message;
 use socket::{Packet, Socket};
-use std::{net::SocketAddr, path::PathBuf};
-use stor::{ServerStorage, UdpServerStorage};
-use tokio::sync::mpsc::Receiver;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use stor::{Migration, MigrationError, ServerStorage, UdpServerStorage};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc::Receiver, Mutex};
+use tokio::task::JoinHandle;
+use workers::WorkerPool;
 
-#[cfg(target_os = "linux")]
-use sd_notify;
 #[cfg(target_os = "windows")]
 use windows_service::{
     service::{ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType},
@@ -21,6 +36,14 @@ use windows_service::{
 };
 
 mod consts {
+    use std::time::Duration;
+
+    pub const SOCKET_BUF_SIZE: usize = leafcommon::udp::DEFAULT_BUF_SIZE; // Размер буферов приема и отправки сокета
+    pub const SAVE_ATTEMPTS: usize = 3; // Количество попыток сохранения полученного чанка
+    pub const SAVE_RETRY_DELAY: Duration = Duration::from_millis(200); // Время ожидания перед повторной попыткой сохранения
+    pub const DEAD_LETTER_FILE: &str = "dead_letters.log"; // Журнал несохраненных чанков в каталоге хранения
+    pub const INDEX_KEY_FILE: &str = "index.key"; // Секрет имен чанков в индексе в каталоге приложения
+
     #[cfg(target_os = "linux")]
     pub const APPS_DIR_ABS_PATH: &str = "/var/local";
     #[cfg(target_os = "linux")]
@@ -29,6 +52,8 @@ mod consts {
     pub const CHUNKS_DIR: &str = "chunks";
     #[cfg(target_os = "linux")]
     pub const STATE_FILE: &str = "last_state.bin";
+    #[cfg(target_os = "linux")]
+    pub const CONFIG_FILE: &str = "config.json";
 
     #[cfg(target_os = "windows")]
     pub const APPS_DIR_ABS_PATH: &str = "C:\\Program Files";
@@ -38,20 +63,47 @@ mod consts {
     pub const CHUNKS_DIR: &str = "Chunks";
     #[cfg(target_os = "windows")]
     pub const STATE_FILE: &str = "last_state.bin";
+    #[cfg(target_os = "windows")]
+    pub const CONFIG_FILE: &str = "config.json";
 }
 
 async fn run_server(
     shutdown_rx: Option<tokio::sync::oneshot::Receiver<()>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let socket = Socket::new().await?;
-
     let base_path = PathBuf::from(APPS_DIR_ABS_PATH).join(APP_DIR);
-    let stor_path = base_path.join(CHUNKS_DIR);
     let state_path = base_path.join(STATE_FILE);
-    let storage = UdpServerStorage::new(stor_path, &state_path).await?;
+    let config = ServerConfig::load(base_path.join(CONFIG_FILE)).await?;
+    let socket = Socket::new(SOCKET_BUF_SIZE, config.dscp, config.address_mode()).await?; // Маркировка пакетов и адресация задаются конфигурацией
+    let storage = open_storage(&config, &base_path, &state_path).await?;
+
+    // Проверяем, что сервер действительно может работать, прежде чем сообщать о готовности
+    let problems = health::self_check(storage.path(), &state_path, &socket).await;
+    if !problems.is_empty() {
+        return Err(Box::new(ServerInitError(problems.join("; "))));
+    }
+    let health = Health::new();
+    if let Some(addr) = config.health_addr {
+        let health = health.clone();
+        tokio::spawn(async move {
+            if let Err(e) = health::serve(addr, health).await {
+                eprintln!("Health check stopped: {}", e);
+            }
+        });
+    }
     let socket_clone = socket.clone();
-    let mut storage_clone = storage.clone();
     let (tx, rx) = tokio::sync::mpsc::channel(100);
+    let restart = config.listener_restart();
+
+    // Запрашиваем у серверов-затравок известные им серверы, ответы обрабатываются в обработчике пакетов
+    let mut peers = PeerSet::new(config.max_known_peers);
+    peers.extend(config.seed_peers.iter().copied());
+    let peers = Arc::new(std::sync::Mutex::new(peers));
+    for seed in &config.seed_peers {
+        let req = Message::DiscoveryReq.into_bytes()?;
+        if let Err(e) = socket.send(Packet::new(req, *seed)).await {
+            eprintln!("{}", e);
+        }
+    }
 
     // Уведомляем systemd о готовности (только для Linux)
     #[cfg(target_os = "linux")]
@@ -62,15 +114,47 @@ async fn run_server(
     }
 
     // Запускаем обработчик пакетов в отдельной задаче
+    let handler_base_path = base_path.clone();
     let handler_task = tokio::spawn(async move {
-        packet_handler(rx, &mut storage_clone, &socket_clone).await;
+        let storage = Arc::new(Mutex::new(storage));
+        let scrubber = (config.scrub_rate > 0.0).then(|| {
+            let task = scrub::run(
+                storage.clone(),
+                config.scrub_rate,
+                config.scrub_action,
+                health.clone(),
+            );
+            tokio::spawn(task)
+        });
+        packet_handler(
+            rx,
+            &storage,
+            &socket_clone,
+            config,
+            peers,
+            &handler_base_path,
+            &health,
+        )
+        .await;
+        if let Some(scrubber) = scrubber {
+            scrubber.abort(); // Проверка останавливается, чтобы освободить хранилище
+            let _ = scrubber.await;
+        }
+        storage // Возвращаем хранилище для сохранения его состояния при завершении
     });
 
+    // Прием пакетов перезапускается после ошибки сокета или паники; исчерпав перезапуски,
+    // сервер сохраняет состояние и завершается с ошибкой
+    let listener = workers::supervise("Packet listener", restart, || {
+        let (socket, tx) = (socket.clone(), tx.clone());
+        async move { socket.recv(&tx).await }
+    });
+    let mut failure = None;
     match shutdown_rx {
         Some(mut shutdown_rx) => {
             tokio::select! {
-                _ = socket.recv(&tx) => {
-
+                res = listener => {
+                    failure = res.err();
                 }
                 _ = shutdown_rx => {
                     println!("Shutting down...");
@@ -86,8 +170,8 @@ async fn run_server(
                     tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
 
                 tokio::select! {
-                    _ = socket.recv(&tx) => {
-                // Основной цикл получения данных
+                    res = listener => {
+                        failure = res.err(); // Основной цикл получения данных
                     }
                     _ = sigint.recv() => {
                         println!("Received Ctrl+C, shutting down...");
@@ -102,49 +186,298 @@ async fn run_server(
                     .map_err(|e| ServerInitError(e.to_string()))?;
                 println!("Notified systemd: STOPPING=1");
             }
-        }
-    }
-
-    #[cfg(not(target_os = "linux"))]
-    {
-        // Для Windows или других платформ просто бесконечный цикл
-        loop {
-            socket.recv(&tx).await;
+            #[cfg(not(target_os = "linux"))]
+            {
+                failure = listener.await.err(); // Для Windows или других платформ - до исчерпания перезапусков
+            }
         }
     }
     drop(tx); // Закрываем отправитель, чтобы rx завершился
-    handler_task.await?;
+    let storage = Arc::try_unwrap(handler_task.await?)
+        .map_err(|_| ServerInitError(String::from("Storage is still in use")))?
+        .into_inner();
     storage.shutdown(state_path).await?;
 
+    if let Some(e) = failure {
+        return Err(Box::new(e)); // Состояние сохранено, но сервер остановлен из-за сбоя приема
+    }
     println!("Server shut down gracefully");
     Ok(())
 }
 
-async fn packet_handler(mut rx: Receiver<Packet>, storage: &mut UdpServerStorage, socket: &Socket) {
-    while let Some(p) = rx.recv().await {
-        process_packet(p, storage, &socket).await;
+async fn packet_handler(
+    mut rx: Receiver<Packet>,
+    storage: &Arc<Mutex<UdpServerStorage>>,
+    socket: &Socket,
+    config: ServerConfig,
+    peers: Arc<std::sync::Mutex<PeerSet>>,
+    base_path: &Path,
+    health: &Health,
+) {
+    let mut heartbeat = tokio::time::interval(Health::heartbeat_interval()); // Отметки работоспособности обработчика
+    let mut flush_interval = tokio::time::interval(storage.lock().await.batch().max_delay); // Периодическая запись накопленных чанков
+    let pool = WorkerPool::new(config.max_concurrent_requests, config.request_timeout()); // Размер пула и время ожидания задаются при запуске
+    let mut config = Arc::new(config);
+    let mut reload = ControlSignal::hangup();
+    let mut pause = ControlSignal::user_defined1(); // Приостановка приема новых чанков
+    let mut resume = ControlSignal::user_defined2(); // Возобновление приема новых чанков
+    let mut migration: Option<JoinHandle<Result<Migration, MigrationError>>> = None; // Выполняемый перенос хранилища
+    loop {
+        tokio::select! {
+            p = rx.recv() => match p {
+                Some(p) => {
+                    // Пакеты обрабатываются параллельно, чтобы медленный запрос не задерживал остальные
+                    let task = process_packet(p, storage.clone(), socket.clone(), config.clone(), peers.clone());
+                    pool.spawn(task).await;
+                }
+                None => break,
+            },
+            _ = heartbeat.tick() => {
+                // Отметка ставится в том же цикле, что и прием пакетов: зависший обработчик перестает
+                // отправлять WATCHDOG=1, и systemd перезапускает сервер
+                health.beat();
+                #[cfg(target_os = "linux")]
+                if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]) {
+                    eprintln!("{}", e);
+                }
+            }
+            _ = flush_interval.tick() => {
+                let mut storage = storage.lock().await;
+                let res = storage.flush_if_due().await;
+                health.storage_result(&res);
+                health.storage_degraded(storage.is_write_degraded());
+                if let Err(e) = res {
+                    eprintln!("{}", e);
+                }
+            }
+            _ = reload.recv() => {
+                let mut storage = storage.lock().await;
+                if let Some(c) = reload_config(&mut storage, base_path, &mut migration).await {
+                    config = Arc::new(c);
+                }
+            }
+            _ = pause.recv() => {
+                // Узел перестает подтверждать запросы на отправку, но продолжает выдавать чанки
+                let mut storage = storage.lock().await;
+                storage.pause_storing();
+                health.storing_paused(storage.is_paused());
+                println!("Storing paused");
+            }
+            _ = resume.recv() => {
+                let mut storage = storage.lock().await;
+                storage.resume_storing();
+                health.storing_paused(storage.is_paused());
+                println!("Storing resumed");
+            }
+            res = async { migration.as_mut().unwrap().await }, if migration.is_some() => {
+                migration = None;
+                let res = res
+                    .map_err(|e| MigrationError(e.to_string()))
+                    .and_then(|r| r);
+                let mut storage = storage.lock().await;
+                let res = match res {
+                    Ok(m) => storage.finish_migration(m, &base_path.join(STATE_FILE)).await,
+                    Err(e) => Err(e),
+                };
+                match res {
+                    Ok(()) => println!("Storage moved to {}", storage.path().display()),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+        }
+    }
+    pool.wait_idle().await; // Дожидаемся обработки уже принятых пакетов
+    if let Some(m) = migration {
+        // Незавершенный перенос отменяется, хранилище остается в старом каталоге
+        m.abort();
+        if let Ok(Ok(m)) = m.await {
+            m.discard().await;
+        }
     }
     println!("Packet handler stopped");
 }
 
-async fn process_packet(packet: Packet, storage: &mut UdpServerStorage, socket: &Socket) {
+async fn reload_config(
+    storage: &mut UdpServerStorage,
+    base_path: &Path,
+    migration: &mut Option<JoinHandle<Result<Migration, MigrationError>>>,
+) -> Option<ServerConfig> {
+    // Перечитывание конфигурации по сигналу. Применяются списки доступа и каталог хранения,
+    // параметры пакетной записи и пула обработки применяются только при запуске
+    let new_config = match ServerConfig::load(base_path.join(CONFIG_FILE)).await {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            return None;
+        }
+    };
+    let target = new_config.storage_path(base_path.join(CHUNKS_DIR));
+    if target != storage.path() {
+        if migration.is_some() {
+            eprintln!("Storage migration is already in progress");
+        } else {
+            match storage.start_migration(target).await {
+                Ok(m) => {
+                    println!("Moving storage to {}...", m.target().display());
+                    *migration = Some(tokio::spawn(m.run()));
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+    println!("Config reloaded");
+    Some(new_config)
+}
+
+struct ControlSignal {
+    // Управляющий сигнал (только для Linux): SIGHUP - перечитывание конфигурации,
+    // SIGUSR1 и SIGUSR2 - приостановка и возобновление приема новых чанков
+    #[cfg(target_os = "linux")]
+    inner: Option<tokio::signal::unix::Signal>,
+}
+
+impl ControlSignal {
+    #[cfg(target_os = "linux")]
+    fn new(kind: tokio::signal::unix::SignalKind) -> Self {
+        ControlSignal {
+            inner: tokio::signal::unix::signal(kind).ok(),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new() -> Self {
+        ControlSignal {}
+    }
+
+    fn hangup() -> Self {
+        #[cfg(target_os = "linux")]
+        return Self::new(tokio::signal::unix::SignalKind::hangup());
+        #[cfg(not(target_os = "linux"))]
+        Self::new()
+    }
+
+    fn user_defined1() -> Self {
+        #[cfg(target_os = "linux")]
+        return Self::new(tokio::signal::unix::SignalKind::user_defined1());
+        #[cfg(not(target_os = "linux"))]
+        Self::new()
+    }
+
+    fn user_defined2() -> Self {
+        #[cfg(target_os = "linux")]
+        return Self::new(tokio::signal::unix::SignalKind::user_defined2());
+        #[cfg(not(target_os = "linux"))]
+        Self::new()
+    }
+
+    async fn recv(&mut self) {
+        // Ожидание сигнала, на других платформах сигнал никогда не приходит
+        #[cfg(target_os = "linux")]
+        if let Some(s) = self.inner.as_mut() {
+            if s.recv().await.is_some() {
+                return;
+            }
+            self.inner = None;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+async fn process_packet(
+    packet: Packet,
+    storage: Arc<Mutex<UdpServerStorage>>,
+    socket: Socket,
+    config: Arc<ServerConfig>,
+    peers: Arc<std::sync::Mutex<PeerSet>>,
+) {
+    let (storage, socket) = (storage.as_ref(), &socket);
     let (data, addr) = packet.deconstruct();
-    let message = Message::from_bytes(data).unwrap();
+    if !config.is_peer_allowed(addr.ip()) {
+        println!("Dropping packet from not allowed peer {}", addr);
+        return;
+    }
+    let message = Message::from_slice(&data);
+    socket.pool().put(data); // Буфер пакета больше не нужен
+    let message = match message {
+        Ok(message) => message,
+        Err(e) => {
+            eprintln!("Dropping malformed packet from {}: {}", addr, e);
+            return;
+        }
+    };
+    if !config.is_handled(&message) {
+        return; // Обработка сообщений этого типа отключена в конфигурации
+    }
+    if config.is_own_address(addr.ip())
+        && matches!(
+            message,
+            Message::SendingReq(_, _)
+                | Message::ContentFilled(_, _, _)
+                | Message::ContentFilledMeta(_, _, _, _)
+        )
+    {
+        return; // Узел не должен хранить собственные чанки
+    }
+    if !config.stores_chunks()
+        && matches!(
+            message,
+            Message::SendingReq(_, _)
+                | Message::ContentFilled(_, _, _)
+                | Message::ContentFilledMeta(_, _, _, _)
+                | Message::StoreLocal(_, _, _)
+        )
+    {
+        return; // Транзитный узел не подтверждает отправку и не сохраняет чанки
+    }
     match message.clone() {
-        Message::SendingReq(h) => {
-            if let Err(e) = send_sending_ack(h.clone(), addr, socket, storage).await {
-                eprintln!("{}", e.to_string());
+        Message::SendingReq(h, t) => {
+            let delay = config.ack_delay();
+            if let Err(e) = send_sending_ack(h.clone(), t, addr, socket, storage, delay).await {
+                eprintln!("{}", e);
             }
         }
         Message::RetrievingReq(h) => {
             if let Err(e) = send_content_filled(h.clone(), addr, socket, storage).await {
-                eprintln!("{}", e.to_string());
+                eprintln!("{}", e);
+            }
+        }
+        Message::ContentFilled(h, d, t) => {
+            if let Err(e) = save_content(h, d, t, None, addr, socket, storage).await {
+                eprintln!("{}", e);
+            }
+        }
+        Message::ContentFilledMeta(h, d, t, m) => {
+            if let Err(e) = save_content(h, d, t, Some(m), addr, socket, storage).await {
+                eprintln!("{}", e);
+            }
+        }
+        Message::MetaReq(h) => {
+            if let Err(e) = send_meta_ack(h, addr, socket, storage).await {
+                eprintln!("{}", e);
+            }
+        }
+        Message::HasReq(h) => {
+            if let Err(e) = send_has_ack(h, addr, socket, storage).await {
+                eprintln!("{}", e);
+            }
+        }
+        Message::StoreLocal(h, d, t) => {
+            if let Err(e) = store_local(h, d, t, addr, socket, storage).await {
+                eprintln!("{}", e);
+            }
+        }
+        Message::DiscoveryReq => {
+            if let Err(e) = send_discovery_ack(addr, socket, &config, &peers).await {
+                eprintln!("{}", e);
             }
         }
-        Message::ContentFilled(h, d) => {
-            if let Err(e) = storage.save(&h, &d).await {
-                eprintln!("{}", e.to_string());
+        Message::DiscoveryAck(known) => {
+            // Ответ сервера-затравки: запоминаем его и сообщенные им серверы, кроме собственных адресов
+            let mut peers = peers.lock().unwrap();
+            if !config.is_own_address(addr.ip()) {
+                peers.insert(addr);
             }
+            peers.extend(known.into_iter().filter(|a| !config.is_own_address(a.ip())));
         }
         _ => eprintln!(
             "{:?}",
@@ -155,12 +488,26 @@ async fn process_packet(packet: Packet, storage: &mut UdpServerStorage, socket:
 
 async fn send_sending_ack(
     hash: String,
+    token: u64,
     addr: SocketAddr,
     socket: &Socket,
-    storage: &UdpServerStorage,
+    storage: &Mutex<UdpServerStorage>,
+    delay: Duration,
 ) -> Result<(), SendingAckError> {
-    if storage.can_save() {
-        let ack = Message::SendingAck(hash)
+    let (can_save, can_accept, free_space) = {
+        let storage = storage.lock().await; // Хранилище блокируется только на время проверки
+        (
+            storage.can_save(),
+            storage.can_accept(&hash, token),
+            storage.free_space(),
+        )
+    };
+    if !can_accept {
+        return Err(SendingAckError(format!("Hash {} is already stored", hash)));
+    }
+    if can_save {
+        tokio::time::sleep(delay).await; // Случайная задержка против одновременных ответов всех серверов
+        let ack = Message::SendingAck(hash, free_space as u64, token) // Клиент выбирает сервер с наибольшим свободным местом
             .into_bytes()
             .map_err(|e| SendingAckError(e.to_string()))?;
         let packet = Packet::new(ack, addr);
@@ -171,19 +518,196 @@ async fn send_sending_ack(
         Ok(())
     } else {
         Err(SendingAckError(String::from(
-            "Not enough free space to store",
+            "Not enough free space to store or storage writes are failing",
         )))
     }
 }
 
+async fn send_discovery_ack(
+    addr: SocketAddr,
+    socket: &Socket,
+    config: &ServerConfig,
+    peers: &std::sync::Mutex<PeerSet>,
+) -> Result<(), SendingDiscoveryAck> {
+    let known = {
+        let mut peers = peers.lock().unwrap();
+        if addr.port() == SERVER_PORT && !config.is_own_address(addr.ip()) {
+            peers.insert(addr); // Запрос с порта сервера - запрашивающий узел тоже сервер
+        }
+        peers.list_except(addr)
+    };
+    let message = Message::DiscoveryAck(known)
+        .into_bytes()
+        .map_err(|e| SendingDiscoveryAck(e.to_string()))?;
+    socket
+        .send(Packet::new(message, addr))
+        .await
+        .map_err(|e| SendingDiscoveryAck(e.to_string()))
+}
+
+async fn save_content(
+    hash: String,
+    data: Vec<u8>,
+    token: u64,
+    meta: Option<Vec<u8>>,
+    addr: SocketAddr,
+    socket: &Socket,
+    storage: &Mutex<UdpServerStorage>,
+) -> Result<(), SendingContentStored> {
+    // Сохранение полученного чанка с повторными попытками. Клиент считает чанк сохраненным только
+    // после CONTENT_STORED; если сохранить чанк не удалось, его хэш записывается в журнал
+    // несохраненных чанков, а клиент, не получив подтверждения, считает отправку чанка неудавшейся
+    if meta.as_ref().is_some_and(|m| m.len() > MAX_META_SIZE) {
+        return Err(SendingContentStored(format!(
+            "Metadata of chunk {} exceeds {} bytes",
+            hash, MAX_META_SIZE
+        ))); // Чанк не сохраняется, клиент не получит подтверждения
+    }
+    let mut result = Ok(());
+    for attempt in 0..SAVE_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(SAVE_RETRY_DELAY).await; // Хранилище не блокируется во время ожидания
+        }
+        let mut locked = storage.lock().await;
+        result = locked.save(&hash, &data, token).await;
+        if let (Ok(()), Some(meta)) = (&result, &meta) {
+            result = locked.set_meta(&hash, meta.clone()); // Метаданные сохраняются вместе с чанком
+        }
+        drop(locked); // Хранилище не блокируется во время ожидания перед повторной попыткой
+        if result.is_ok() {
+            break;
+        }
+    }
+    if let Err(e) = result {
+        let (dead_letters, name) = {
+            let storage = storage.lock().await;
+            (
+                storage.path().join(DEAD_LETTER_FILE),
+                storage.index_name(&hash),
+            )
+        };
+        let line = format!(
+            "{} {} {} {}\n",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            name,
+            addr,
+            e
+        );
+        let mut log = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&dead_letters)
+            .await
+            .map_err(|e| SendingContentStored(e.to_string()))?;
+        log.write_all(line.as_bytes())
+            .await
+            .map_err(|e| SendingContentStored(e.to_string()))?;
+        return Err(SendingContentStored(format!(
+            "Chunk {} was not saved after {} attempts: {}",
+            hash, SAVE_ATTEMPTS, e
+        )));
+    }
+    let stored = Message::ContentStored(hash, token)
+        .into_bytes()
+        .map_err(|e| SendingContentStored(e.to_string()))?;
+    socket
+        .send(Packet::new(stored, addr))
+        .await
+        .map_err(|e| SendingContentStored(e.to_string()))
+}
+
+async fn store_local(
+    hash: String,
+    data: Vec<u8>,
+    token: u64,
+    addr: SocketAddr,
+    socket: &Socket,
+    storage: &Mutex<UdpServerStorage>,
+) -> Result<(), SendingAckError> {
+    // Явная запись копии чанка клиентом этого же узла: принимается только через loopback и
+    // сразу записывается на диск, минуя пакетную запись, чтобы копия пережила аварийное завершение
+    if !addr.ip().is_loopback() {
+        return Err(SendingAckError(format!(
+            "Local copy request from non-local address {}",
+            addr
+        )));
+    }
+    let free_space = {
+        let mut storage = storage.lock().await;
+        storage
+            .save(&hash, &data, token)
+            .await
+            .map_err(|e| SendingAckError(e.to_string()))?;
+        storage
+            .flush()
+            .await
+            .map_err(|e| SendingAckError(e.to_string()))?;
+        storage.free_space()
+    };
+    let ack = Message::SendingAck(hash, free_space as u64, token)
+        .into_bytes()
+        .map_err(|e| SendingAckError(e.to_string()))?;
+    socket
+        .send(Packet::new(ack, addr))
+        .await
+        .map_err(|e| SendingAckError(e.to_string()))
+}
+
+async fn send_meta_ack(
+    hash: String,
+    addr: SocketAddr,
+    socket: &Socket,
+    storage: &Mutex<UdpServerStorage>,
+) -> Result<(), SendingMetaAck> {
+    // Выдача метаданных чанка. В отличие от выдачи данных, чанк остается в хранилище;
+    // если чанка или его метаданных нет, ответ не отправляется
+    let Some(meta) = storage.lock().await.meta(&hash).map(<[u8]>::to_vec) else {
+        return Ok(());
+    };
+    let ack = Message::MetaAck(hash, meta)
+        .into_bytes()
+        .map_err(|e| SendingMetaAck(e.to_string()))?;
+    socket
+        .send(Packet::new(ack, addr))
+        .await
+        .map_err(|e| SendingMetaAck(e.to_string()))
+}
+
+async fn send_has_ack(
+    hash: String,
+    addr: SocketAddr,
+    socket: &Socket,
+    storage: &Mutex<UdpServerStorage>,
+) -> Result<(), SendingHasAck> {
+    // Подтверждение наличия чанка для проверки количества копий. Чанк остается в хранилище;
+    // если чанка нет, ответ не отправляется
+    if !storage.lock().await.is_hash_presented(&hash) {
+        return Ok(());
+    }
+    let ack = Message::HasAck(hash)
+        .into_bytes()
+        .map_err(|e| SendingHasAck(e.to_string()))?;
+    socket
+        .send(Packet::new(ack, addr))
+        .await
+        .map_err(|e| SendingHasAck(e.to_string()))
+}
+
 async fn send_content_filled(
     hash: String,
     addr: SocketAddr,
     socket: &Socket,
-    storage: &mut UdpServerStorage,
+    storage: &Mutex<UdpServerStorage>,
 ) -> Result<(), SendingContentFilled> {
-    if let Ok(d) = storage.get(&hash).await {
-        let message = Message::ContentFilled(hash, d)
+    let chunk = storage.lock().await.take(&hash); // Чанк изымается из индекса, файл читается без блокировки
+    let chunk = match chunk {
+        Ok(c) => c.read().await,
+        Err(e) => Err(e),
+    };
+    if let Ok(d) = chunk {
+        let message = Message::ContentFilled(hash, d, 0) // Токен при выдаче данных не используется
             .into_bytes()
             .map_err(|e| SendingContentFilled(e.to_string()))?;
         let packet = Packet::new(message, addr);
@@ -309,8 +833,61 @@ mod windows_service_impl {
     }
 }
 
+async fn open_storage(
+    config: &ServerConfig,
+    base_path: &Path,
+    state_path: &PathBuf,
+) -> Result<UdpServerStorage, Box<dyn std::error::Error>> {
+    // Открытие хранилища с параметрами конфигурации
+    let stor_path = config.storage_path(base_path.join(CHUNKS_DIR));
+    let storage = UdpServerStorage::new(stor_path, state_path, config.write_batch())
+        .await?
+        .with_compressed_state(config.compress_state)
+        .with_durability(config.durability)
+        .with_watermarks(config.watermarks());
+    match config.obfuscate_index {
+        true => {
+            Ok(storage
+                .with_index_key(stor::load_index_key(base_path.join(INDEX_KEY_FILE)).await?)?)
+        }
+        false if storage.is_index_obfuscated() => Err(Box::new(ServerInitError(String::from(
+            "storage index is obfuscated, obfuscate_index must stay enabled",
+        )))),
+        false => Ok(storage),
+    }
+}
+
+async fn run_archive(command: &str, tarball: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // Выгрузка хранилища в архив (export) или загрузка архива в хранилище (import) для
+    // резервного копирования. Выполняется при остановленном сервере
+    let base_path = PathBuf::from(APPS_DIR_ABS_PATH).join(APP_DIR);
+    let state_path = base_path.join(STATE_FILE);
+    let config = ServerConfig::load(base_path.join(CONFIG_FILE)).await?;
+    let mut storage = open_storage(&config, &base_path, &state_path).await?;
+    let stats = match command {
+        "export" => archive::export(&storage, tarball).await?,
+        _ => {
+            let stats = archive::import(&mut storage, tarball).await?;
+            storage.shutdown(state_path).await?; // Загруженные чанки записываются в состояние
+            stats
+        }
+    };
+    println!(
+        "{}ed {} chunks ({} already stored, {} rejected)",
+        command, stats.chunks, stats.skipped, stats.rejected
+    );
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, command, tarball] = args.as_slice() {
+        if command == "export" || command == "import" {
+            return run_archive(command, Path::new(tarball)).await; // leafd export|import <tarball>
+        }
+    }
+
     #[cfg(target_os = "windows")]
     {
         if std::env::args().any(|arg| arg == "--service") {
@@ -328,24 +905,6 @@ mod errors {
     use std::error::Error;
     use std::fmt;
 
-    #[derive(Debug, Clone)]
-    pub struct NoFreeSpaceError;
-    impl fmt::Display for NoFreeSpaceError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(f, "No free space left for keeping data")
-        }
-    }
-    impl Error for NoFreeSpaceError {}
-
-    #[derive(Debug, Clone)]
-    pub struct NoHashError(pub String);
-    impl fmt::Display for NoHashError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(f, "No hash {} was found", self.0)
-        }
-    }
-    impl Error for NoHashError {}
-
     #[derive(Debug, Clone)]
     pub struct InvalidMessageError;
     impl fmt::Display for InvalidMessageError {
@@ -381,4 +940,200 @@ mod errors {
         }
     }
     impl Error for SendingContentFilled {}
+
+    #[derive(Debug, Clone)]
+    pub struct SendingContentStored(pub String);
+    impl fmt::Display for SendingContentStored {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Error saving chunk or sending CONTENT_STORED: {}",
+                self.0
+            )
+        }
+    }
+    impl Error for SendingContentStored {}
+
+    #[derive(Debug, Clone)]
+    pub struct SendingMetaAck(pub String);
+    impl fmt::Display for SendingMetaAck {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Error sending META_ACK: {}", self.0)
+        }
+    }
+    impl Error for SendingMetaAck {}
+
+    #[derive(Debug, Clone)]
+    pub struct SendingHasAck(pub String);
+    impl fmt::Display for SendingHasAck {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Error sending HAS_ACK: {}", self.0)
+        }
+    }
+    impl Error for SendingHasAck {}
+
+    #[derive(Debug, Clone)]
+    pub struct SendingDiscoveryAck(pub String);
+    impl fmt::Display for SendingDiscoveryAck {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Error sending DISCOVERY_ACK: {}", self.0)
+        }
+    }
+    impl Error for SendingDiscoveryAck {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stor::WriteBatch;
+
+    async fn test_storage() -> (Arc<Mutex<UdpServerStorage>>, PathBuf) {
+        // Хранилище во временном каталоге, возвращается вместе с каталогом
+        let base = std::env::temp_dir().join(format!("leafd-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&base).await.unwrap();
+        let storage =
+            UdpServerStorage::new(base.clone(), &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap();
+        (Arc::new(Mutex::new(storage)), base)
+    }
+
+    async fn handle(
+        message: Message,
+        from: SocketAddr,
+        storage: &Arc<Mutex<UdpServerStorage>>,
+        config: ServerConfig,
+    ) {
+        // Обработка одного пакета так же, как при приеме из сети
+        let socket = Socket::bind("127.0.0.1:0").await.unwrap();
+        let peers = PeerSet::new(config.max_known_peers);
+        let packet = Packet::new(message.into_bytes().unwrap(), from);
+        let peers = Arc::new(std::sync::Mutex::new(peers));
+        process_packet(packet, storage.clone(), socket, Arc::new(config), peers).await;
+    }
+
+    async fn reply(client: &tokio::net::UdpSocket) -> Option<Message> {
+        // Ответ сервера клиенту (None - ответа нет)
+        let mut buf = vec![0u8; 65535];
+        let wait = tokio::time::timeout(Duration::from_millis(200), client.recv(&mut buf));
+        let size = wait.await.ok()?.unwrap();
+        Message::from_slice(&buf[..size]).ok()
+    }
+
+    fn chunk(hash: &str) -> Message {
+        Message::ContentFilled(String::from(hash), vec![1, 2, 3], 1)
+    }
+
+    #[tokio::test]
+    async fn own_chunks_are_not_stored_when_guard_is_on() {
+        // Узел с клиентом и сервером: чанк с собственного адреса узла (loopback) не сохраняется
+        let (storage, base) = test_storage().await;
+        let from: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let mut config = ServerConfig::load(base.join("missing.json")).await.unwrap(); // Адреса узла - по интерфейсам
+        assert!(!config.ignore_own_packets); // По умолчанию узел принимает и собственные чанки
+
+        handle(chunk("own"), from, &storage, config.clone()).await;
+        assert!(storage.lock().await.is_hash_presented("own"));
+        config.ignore_own_packets = true;
+        handle(chunk("guarded"), from, &storage, config).await;
+        assert!(!storage.lock().await.is_hash_presented("guarded"));
+
+        tokio::fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn denied_peer_has_no_storage_effect() {
+        let (storage, base) = test_storage().await;
+        storage
+            .lock()
+            .await
+            .save("stored", &[7; 8], 1)
+            .await
+            .unwrap();
+        let from: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let config: ServerConfig = serde_json::from_str(r#"{"deny": ["127.0.0.0/8"]}"#).unwrap();
+
+        handle(chunk("denied"), from, &storage, config.clone()).await;
+        let store = Message::StoreLocal(String::from("local"), vec![1], 2);
+        handle(store, from, &storage, config.clone()).await;
+        let retrieve = Message::RetrievingReq(String::from("stored"));
+        handle(retrieve, from, &storage, config).await;
+        {
+            let storage = storage.lock().await;
+            assert!(!storage.is_hash_presented("denied"));
+            assert!(!storage.is_hash_presented("local"));
+            assert!(storage.is_hash_presented("stored")); // Чанк не выдан запросившему с запрещенного адреса
+        }
+
+        handle(chunk("allowed"), from, &storage, ServerConfig::default()).await;
+        assert!(storage.lock().await.is_hash_presented("allowed"));
+        tokio::fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn relay_only_node_stores_nothing_but_serves_chunks() {
+        let (storage, base) = test_storage().await;
+        storage
+            .lock()
+            .await
+            .save("stored", &[7; 8], 1)
+            .await
+            .unwrap();
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let from = client.local_addr().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage_mode = config::StorageMode::RelayOnly;
+
+        let request = Message::SendingReq(String::from("sent"), 2);
+        handle(request, from, &storage, config.clone()).await;
+        assert!(reply(&client).await.is_none()); // Транзитный узел не подтверждает отправку
+        handle(chunk("filled"), from, &storage, config.clone()).await;
+        let meta = Message::ContentFilledMeta(String::from("meta"), vec![1], 3, vec![2]);
+        handle(meta, from, &storage, config.clone()).await;
+        let store = Message::StoreLocal(String::from("local"), vec![1], 4);
+        handle(store, from, &storage, config.clone()).await;
+        {
+            let storage = storage.lock().await;
+            for hash in ["sent", "filled", "meta", "local"] {
+                assert!(!storage.is_hash_presented(hash));
+            }
+        }
+
+        let retrieve = Message::RetrievingReq(String::from("stored"));
+        handle(retrieve, from, &storage, config).await;
+        let served = reply(&client).await;
+        assert!(
+            matches!(served, Some(Message::ContentFilled(h, d, _)) if h == "stored" && d == [7; 8])
+        );
+        assert!(!storage.lock().await.is_hash_presented("stored")); // Чанк выдан и удален с узла
+        tokio::fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn paused_node_serves_chunks_and_acks_after_resume() {
+        let (storage, base) = test_storage().await;
+        storage
+            .lock()
+            .await
+            .save("stored", &[7; 8], 1)
+            .await
+            .unwrap();
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let from = client.local_addr().unwrap();
+        let request = || Message::SendingReq(String::from("new"), 2);
+
+        storage.lock().await.pause_storing();
+        handle(request(), from, &storage, ServerConfig::default()).await;
+        assert!(reply(&client).await.is_none()); // Приостановленный узел не подтверждает отправку
+        let retrieve = Message::RetrievingReq(String::from("stored"));
+        handle(retrieve, from, &storage, ServerConfig::default()).await;
+        assert!(
+            matches!(reply(&client).await, Some(Message::ContentFilled(h, ..)) if h == "stored")
+        );
+
+        storage.lock().await.resume_storing();
+        handle(request(), from, &storage, ServerConfig::default()).await;
+        assert!(matches!(reply(&client).await, Some(Message::SendingAck(h, _, 2)) if h == "new"));
+        tokio::fs::remove_dir_all(&base).await.unwrap();
+    }
 }
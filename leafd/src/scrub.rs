@@ -0,0 +1,87 @@
+use std::path::Path; // Зависимость стандартной библиотеки для работы с файловыми путями
+use std::sync::Arc; // Разделяемое между задачами состояние
+use std::time::Duration; // Зависимость стандартной библиотеки для работы со временем
+
+use leafcommon::reed_solomon_scheme::{Hasher, StreebogHasher, KEYED_HASH_MARK}; // Хэширование чанков
+use tokio::fs; // Внешняя зависимость для работы с файловыми операциями асинхронно
+use tokio::sync::Mutex; // Асинхронный мьютекс хранилища
+
+use crate::config::ScrubAction; // Действие с поврежденным чанком
+use crate::health::Health; // Счетчики проверки
+use crate::stor::{read_chunk, StoredChunk, UdpServerStorage}; // Хранилище сервера
+
+use consts::*; // Внутренний модуль с константами
+
+mod consts {
+    // Модуль с константами
+    use std::time::Duration;
+
+    pub const QUARANTINE_DIR: &str = "quarantine"; // Каталог поврежденных чанков внутри каталога хранения
+    pub const IDLE_DELAY: Duration = Duration::from_secs(60); // Ожидание перед следующим проходом по пустому хранилищу
+}
+
+pub async fn run(
+    storage: Arc<Mutex<UdpServerStorage>>,
+    rate: f64,
+    action: ScrubAction,
+    health: Arc<Health>,
+) {
+    // Фоновая проверка целостности: файл каждого чанка читается, его хэш вычисляется заново
+    // и сравнивается с хэшем (или его именем в индексе), под которым чанк хранится. Проверяется не более rate чанков
+    // в секунду, чтобы ограничить нагрузку на диск. Хранилище блокируется только на время
+    // получения списка чанков и изъятия поврежденного
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+    loop {
+        let (files, index) = {
+            let storage = storage.lock().await;
+            (storage.stored_files(), storage.index_key())
+        };
+        if files.is_empty() {
+            tokio::time::sleep(IDLE_DELAY).await;
+            continue;
+        }
+        for (hash, path) in files {
+            if hash.starts_with(KEYED_HASH_MARK) {
+                continue; // Чанк адресуется ключом, а не содержимым
+            }
+            ticker.tick().await;
+            let Ok(data) = read_chunk(&path).await else {
+                continue; // Чанк уже выдан или перенесен
+            };
+            let intact = index.name(&StreebogHasher.calc_hash(&data)) == hash; // Имя в индексе, а не сам хэш
+            health.chunk_scrubbed(intact);
+            if !intact {
+                eprintln!("Chunk {} in {} is corrupted", hash, path.display());
+                if action != ScrubAction::Report {
+                    if let Err(e) = discard(&storage, &hash, &path, action).await {
+                        eprintln!("Error discarding corrupted chunk {}: {}", hash, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn discard(
+    storage: &Mutex<UdpServerStorage>,
+    hash: &str,
+    path: &Path,
+    action: ScrubAction,
+) -> std::io::Result<()> {
+    // Изъятие поврежденного чанка из хранилища: файл удаляется или перемещается в карантин
+    let chunk = {
+        let mut storage = storage.lock().await;
+        let quarantine = storage.path().join(QUARANTINE_DIR);
+        storage.take_if_at(hash, path).map(|c| (c, quarantine))
+    };
+    let Some((StoredChunk::File(path), quarantine)) = chunk else {
+        return Ok(()); // Чанк изменился с момента проверки
+    };
+    match action {
+        ScrubAction::Delete => fs::remove_file(&path).await,
+        _ => {
+            fs::create_dir_all(&quarantine).await?;
+            fs::rename(&path, quarantine.join(format!("{}.bin", hash))).await
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use std::net::SocketAddr; // Зависимость стандартной библиотеки для работы с сетевыми адресами
-use std::sync::Arc; // Зависимость стандартной библиотеки для работы с объектами в многопоточном режиме
+use std::io; // Ошибки ввода-вывода сокета
+use std::net::{IpAddr, SocketAddr}; // Зависимость стандартной библиотеки для работы с сетевыми адресами
+use std::sync::{Arc, Mutex}; // Зависимость стандартной библиотеки для работы с объектами в многопоточном режиме
 
+use leafcommon::udp::{self, AddressMode}; // Зависимость общей библиотеки для создания UDP-сокета
 use tokio::net::UdpSocket; // Внешняя зависимость для работы с асинхронным UDP-сокетом
 use tokio::sync::mpsc::Sender; // Внешняя зависимость для работы с асинхронными каналами
 
@@ -10,7 +12,10 @@ use errors::*; // Зависимость внутреннего модуля д
 mod consts {
     // Модуль с константами
     pub const LOCAL_ADDR: &str = "0.0.0.0:62092"; // Строка адреса для открытия сокета
+    pub const LOCAL_ADDR_V6: &str = "[::]:62092"; // Строка адреса сокета IPv6, принимающего и пакеты IPv4
+    pub const ANY_INTERFACE: u32 = 0; // Интерфейс присоединения к группе выбирается системой
     pub const UDP_SOCKET_BUF_SIZE: usize = 65535; // Размер буфера для приема данных из сети (максимальный размер поля полезной нагрузки датаграммы)
+    pub const MAX_POOLED_BUFFERS: usize = 64; // Максимальное количество буферов пакетов, хранимых для повторного использования
 }
 
 #[derive(Clone, Debug)]
@@ -30,30 +35,92 @@ impl Packet {
     } // Удаление пакета и получение его полей
 }
 
+#[derive(Clone, Default)]
+pub struct BufferPool {
+    // Пул буферов данных принятых пакетов: буфер обработанного пакета возвращается в пул
+    // и используется для следующего пакета вместо выделения памяти под каждый пакет
+    free: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl BufferPool {
+    pub fn get(&self) -> Vec<u8> {
+        // Получение пустого буфера из пула (при пустом пуле выделяется новый)
+        self.free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(UDP_SOCKET_BUF_SIZE))
+    }
+
+    pub fn put(&self, mut buf: Vec<u8>) {
+        // Возврат буфера в пул, буферы сверх MAX_POOLED_BUFFERS освобождаются
+        buf.clear();
+        let mut free = self.free.lock().unwrap();
+        if free.len() < MAX_POOLED_BUFFERS {
+            free.push(buf);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Socket {
     // Структура сокета
     socket: Arc<UdpSocket>, // Сокет с возможностью работы в нескольких потоках
+    pool: BufferPool,       // Пул буферов принятых пакетов
 }
 
 impl Socket {
-    pub async fn new() -> Result<Socket, SocketInitError> {
-        // Конструктор нового сокета
-        let socket = Arc::new(
-            UdpSocket::bind(LOCAL_ADDR)
-                .await
-                .map_err(|e| SocketInitError(e.to_string()))?,
-        ); // Создаем UDP-сокет
-        socket
-            .set_broadcast(true)
-            .map_err(|e| SocketInitError(e.to_string()))?; // Устанавливаем сокет как способный работать с широковещательными запросами
-
-        Ok(Socket { socket }) // Возращаем сокет
+    pub async fn new(
+        buf_size: usize,
+        dscp: Option<u8>,
+        mode: AddressMode,
+    ) -> Result<Socket, SocketInitError> {
+        // Конструктор нового сокета, buf_size - размер буферов приема и отправки ядра,
+        // dscp - маркировка отправляемых пакетов для QoS, mode - адресация запросов домена
+        let socket = match mode {
+            AddressMode::Ipv4Broadcast => udp::bind_marked(LOCAL_ADDR, buf_size, dscp),
+            AddressMode::Ipv6Multicast(group) => udp::bind_marked(LOCAL_ADDR_V6, buf_size, dscp)
+                .and_then(|s| {
+                    s.join_multicast_v6(&group, ANY_INTERFACE)?;
+                    println!("UDP socket joined multicast group {}", group);
+                    Ok(s)
+                }),
+        }
+        .map_err(|e| SocketInitError(e.to_string()))?;
+        let socket = Arc::new(socket); // Создаем широковещательный UDP-сокет
+
+        Ok(Socket {
+            socket,
+            pool: BufferPool::default(),
+        }) // Возращаем сокет
+    }
+
+    #[cfg(test)]
+    pub async fn bind(addr: &str) -> Result<Socket, SocketInitError> {
+        // Сокет на указанном адресе (для проверки обработки пакетов без занятия порта сервера)
+        let socket = udp::bind_marked(addr, UDP_SOCKET_BUF_SIZE, None)
+            .map_err(|e| SocketInitError(e.to_string()))?;
+        Ok(Socket {
+            socket: Arc::new(socket),
+            pool: BufferPool::default(),
+        })
+    }
+
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool // Получение пула буферов для возврата буфера обработанного пакета
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr() // Получение адреса, к которому привязан сокет
     }
 
     pub async fn send(&self, packet: Packet) -> Result<(), SendingPacketError> {
         // Метод отправки данных в сеть
-        let (data, addr) = packet.deconstruct(); // Разбор пакета на части
+        let (data, mut addr) = packet.deconstruct(); // Разбор пакета на части
+        if let (SocketAddr::V4(v4), Ok(SocketAddr::V6(_))) = (addr, self.socket.local_addr()) {
+            addr = SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port());
+            // Сокету IPv6 адрес IPv4 передается в виде ::ffff:a.b.c.d
+        }
         self.socket
             .send_to(data.as_slice(), addr)
             .await
@@ -61,14 +128,16 @@ impl Socket {
         Ok(())
     }
 
-    pub async fn recv(&self, tx: &Sender<Packet>) {
-        // Метод получения данных из сети
+    pub async fn recv(&self, tx: &Sender<Packet>) -> io::Result<()> {
+        // Метод получения данных из сети, завершается только с ошибкой сокета
         let mut buf = [0u8; UDP_SOCKET_BUF_SIZE]; // Создаем буфер
-        while let Ok((s, a)) = self.socket.recv_from(&mut buf).await {
-            // Если в сокете есть данные
-            let packet = Packet::new(buf[..s].to_vec(), a); // Собираем из данных пакет
+        loop {
+            let (s, a) = self.socket.recv_from(&mut buf).await?;
+            let mut data = self.pool.get();
+            data.extend_from_slice(&buf[..s]);
+            let packet = Packet::new(data, udp::canonical(a)); // Собираем из данных пакет (адрес IPv4 - в обычном виде)
             if let Err(e) = tx.send(packet).await {
-                eprintln!("{}", e.to_string());
+                eprintln!("{}", e);
             } // Отправляем пакет по каналу получателям для дальнейшей обработки
         }
     }
@@ -103,3 +172,26 @@ mod errors {
 
     impl Error for SendingPacketError {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_reuses_returned_buffers() {
+        let pool = BufferPool::default();
+        let mut buf = pool.get();
+        buf.extend_from_slice(&[1, 2, 3]);
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+
+        let reused = pool.get();
+        assert!(reused.is_empty());
+        assert_eq!(reused.as_ptr(), ptr); // Память не выделяется повторно
+
+        for _ in 0..MAX_POOLED_BUFFERS + 1 {
+            pool.put(Vec::new());
+        }
+        assert_eq!(pool.free.lock().unwrap().len(), MAX_POOLED_BUFFERS);
+    }
+}
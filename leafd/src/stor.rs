@@ -1,23 +1,44 @@
-use std::{collections::HashMap, path::PathBuf}; // Зависимость стандартной библиотеки для работы с файловыми путями
+use std::collections::{HashMap, HashSet}; // Зависимость стандартной библиотеки для работы с коллекциями
+use std::io; // Зависимость стандартной библиотеки для работы с ошибками ввода-вывода
+use std::path::{Path, PathBuf}; // Зависимость стандартной библиотеки для работы с файловыми путями
+use std::time::{Duration, Instant}; // Зависимость стандартной библиотеки для работы со временем
 
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use leafcommon::reed_solomon_scheme::{streebog_hmac, KEYED_HASH_MARK}; // Имена чанков в индексе
+use rand::{rngs::OsRng, Rng}; // Создание секрета индекса
 use serde::{Deserialize, Serialize};
 use tokio::fs; // Внешняя зависимость для работы с файловыми операциями асинхронно
+use tokio::io::AsyncWriteExt; // Запись данных чанка в открытый файл
 use uuid::Uuid; // Внешняя зависимость для генерации UUID
 
 use consts::*; // Внутренний модуль с константами
 use errors::*; // Внутренний модуль с составными типами ошибок
+pub use errors::{IndexKeyError, MigrationError}; // Ошибка переноса возвращается фоновой задачей переноса
+use leafcommon::message::MAX_META_SIZE; // Максимальный размер метаданных чанка
 
 mod consts {
     // Модуль с константами
     pub const MAX_OCCUPIED_SPACE: usize = 10 * 1024 * 1024 * 1024; // Максимальный размер хранилища сервера - 10 Гб
+    pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD]; // Начало кадра zstd: признак сжатого файла состояния (в Base64 не встречается)
+    pub const STATE_COMPRESSION_LEVEL: i32 = 3; // Уровень сжатия файла состояния
+    pub const WRITE_FAILURE_LIMIT: usize = 3; // Количество неудачных записей подряд, после которого хранилище перестает принимать чанки
+    pub const WRITE_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+    // Время, через которое хранилище снова пробует принимать чанки
+    pub const CHUNK_MAGIC: &[u8] = b"LFCK"; // Начало заголовка файла чанка
+    pub const CHUNK_FORMAT_VERSION: u8 = 1; // Версия формата файлов чанков (0 - файлы без заголовка)
+    pub const CHUNK_HEADER_LEN: usize = 6; // Заголовок: CHUNK_MAGIC, версия и флаги
+    pub const INDEX_KEY_SIZE: usize = 32; // Размер секрета индекса в байтах
+    pub const INDEX_CHECK_LABEL: &str = "leaf-index-check"; // Метка проверочного значения секрета в файле состояния
+    #[cfg(unix)]
+    pub const INDEX_KEY_MODE: u32 = 0o600; // Права доступа файла секрета: только владелец
 }
 
 pub trait ServerStorage {
     // Трейт серверного хранилища
-    async fn save(&mut self, hash: &str, data: &[u8]) -> Result<(), SavingDataError>; // Шаблон метода сохранения данных
-    async fn get(&mut self, hash: &str) -> Result<Vec<u8>, RetrievingDataError>; // Шаблон метода получения данных
+    async fn save(&mut self, hash: &str, data: &[u8], token: u64) -> Result<(), SavingDataError>; // Шаблон метода сохранения данных
+    fn take(&mut self, hash: &str) -> Result<StoredChunk, RetrievingDataError>; // Шаблон метода изъятия данных (чтение - через StoredChunk::read)
     fn can_save(&self) -> bool; // Шаблон метода проверки возможности сохранения
+    async fn flush(&mut self) -> Result<(), SavingDataError>; // Шаблон метода записи накопленных данных на диск
     async fn shutdown(self, path: PathBuf) -> Result<(), Box<dyn std::error::Error>>;
 }
 
@@ -25,6 +46,167 @@ pub trait ServerStorage {
 struct UdpServerStorageState {
     pub hashes: HashMap<String, PathBuf>,
     pub size: usize,
+    #[serde(default)]
+    pub tokens: HashMap<String, u64>, // Токены отправки сохраненных чанков (в старом состоянии отсутствуют)
+    #[serde(default)]
+    pub meta: HashMap<String, Vec<u8>>, // Метаданные сохраненных чанков (не более MAX_META_SIZE байт на чанк)
+    #[serde(default)]
+    pub chunk_format: u8, // Версия формата, к которой приведены файлы чанков (в старом состоянии - 0)
+    #[serde(default)]
+    pub index_check: Option<String>, // Имя метки INDEX_CHECK_LABEL при скрытых хэшах (None - индекс по хэшам)
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IndexKey(Option<[u8; INDEX_KEY_SIZE]>);
+
+impl IndexKey {
+    pub fn name(&self, hash: &str) -> String {
+        // Имя чанка в индексе хранилища: сам хэш или HMAC хэша на секрете узла. Файл состояния
+        // и журналы каталога хранения тогда не раскрывают, какие чанки хранит узел. Признак
+        // чанка, адресуемого ключом, сохраняется: такие чанки не проверяются по содержимому
+        let Some(key) = &self.0 else {
+            return hash.to_string();
+        };
+        let mac = streebog_hmac(key, hash.as_bytes());
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        match hash.starts_with(KEYED_HASH_MARK) {
+            true => format!("{}{}", KEYED_HASH_MARK, hex),
+            false => hex,
+        }
+    }
+
+    fn rename<V>(&self, map: &mut HashMap<String, V>) {
+        *map = map.drain().map(|(h, v)| (self.name(&h), v)).collect(); // Перевод индекса по хэшам на имена
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkHeader {
+    // Заголовок файла чанка, по которому различаются форматы хранения. Флаги зарезервированы
+    // для изменений формата (сжатие, метаданные в файле) и в версии 1 равны 0
+    pub version: u8,
+    pub flags: u8,
+}
+
+impl ChunkHeader {
+    pub fn current() -> ChunkHeader {
+        ChunkHeader {
+            version: CHUNK_FORMAT_VERSION,
+            flags: 0,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; CHUNK_HEADER_LEN] {
+        let mut header = [0u8; CHUNK_HEADER_LEN];
+        header[..CHUNK_MAGIC.len()].copy_from_slice(CHUNK_MAGIC);
+        header[CHUNK_MAGIC.len()] = self.version;
+        header[CHUNK_MAGIC.len() + 1] = self.flags;
+        header
+    }
+
+    pub fn parse(content: &[u8]) -> (ChunkHeader, &[u8]) {
+        // Разбор содержимого файла: заголовок и данные чанка. Файл без заголовка - версия 0
+        match content.strip_prefix(CHUNK_MAGIC) {
+            Some(&[version, flags, ..]) if version > 0 => {
+                (ChunkHeader { version, flags }, &content[CHUNK_HEADER_LEN..])
+            }
+            _ => (
+                ChunkHeader {
+                    version: 0,
+                    flags: 0,
+                },
+                content,
+            ),
+        }
+    }
+}
+
+pub async fn read_chunk(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    // Чтение данных чанка из файла любой поддерживаемой версии формата
+    let mut content = fs::read(&path).await?;
+    let (header, data) = ChunkHeader::parse(&content);
+    if header.version > CHUNK_FORMAT_VERSION || header.flags != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} has unsupported chunk format version {} (flags {:#04x})",
+                path.as_ref().display(),
+                header.version,
+                header.flags
+            ),
+        ));
+    }
+    let offset = content.len() - data.len();
+    content.drain(..offset);
+    Ok(content)
+}
+
+pub async fn load_index_key(path: impl AsRef<Path>) -> io::Result<[u8; INDEX_KEY_SIZE]> {
+    // Чтение секрета индекса; при первом запуске секрет создается с правами только для владельца.
+    // Без этого файла чанки хранилища со скрытыми хэшами найти невозможно
+    let path = path.as_ref();
+    match fs::read(path).await {
+        Ok(content) => content.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a storage index key", path.display()),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut key = [0u8; INDEX_KEY_SIZE];
+            OsRng.fill(&mut key[..]);
+            let mut open = fs::OpenOptions::new();
+            open.write(true).create_new(true);
+            #[cfg(unix)]
+            open.mode(INDEX_KEY_MODE);
+            let mut file = open.open(path).await?;
+            file.write_all(&key).await?;
+            file.sync_all().await?;
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WriteBatch {
+    // Параметры пакетной записи чанков на диск. Чанки, ожидающие записи, хранятся в памяти
+    // и теряются при аварийном завершении сервера, поэтому значение max_chunks больше 1
+    // повышает пропускную способность ценой надежности. При завершении работы все чанки записываются
+    pub max_chunks: usize, // Количество накопленных чанков, при котором они записываются на диск
+    pub max_delay: Duration, // Максимальное время ожидания чанка в буфере
+}
+
+impl Default for WriteBatch {
+    fn default() -> Self {
+        WriteBatch {
+            max_chunks: 1, // По умолчанию каждый чанк записывается сразу
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Watermarks {
+    // Пороги свободного места для приема чанков с гистерезисом: прием прекращается, когда
+    // свободного места остается не больше low, и возобновляется, только когда его становится
+    // больше high. Без зазора между порогами при почти заполненном диске одновременные сохранения
+    // и изъятия чанков постоянно переключали бы сервер между приемом и отказом
+    pub low: usize,  // Свободное место в байтах, при котором прием прекращается
+    pub high: usize, // Свободное место в байтах, после превышения которого прием возобновляется
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    // Гарантия сохранности записанных чанков
+    #[default]
+    Relaxed, // Данные передаются ядру без fsync: при отключении питания подтвержденный чанк может быть потерян
+    // Файл каждого чанка и каталог хранилища синхронизируются с диском (fsync) до завершения
+    // записи, поэтому подтверждение CONTENT_STORED гарантирует сохранность чанка. Каждая запись
+    // ждет диска: на HDD это единицы миллисекунд, и пропускная способность приема падает
+    // в разы; пакетная запись (write_batch_chunks) уменьшает число синхронизаций каталога
+    Strong,
 }
 
 #[derive(Clone)]
@@ -32,23 +214,148 @@ pub struct UdpServerStorage {
     // Структура серверного хранилища
     path: PathBuf, // Поле со значением пути хранилища
     state: UdpServerStorageState,
+    batch: WriteBatch,                  // Параметры пакетной записи
+    pending: HashMap<PathBuf, Vec<u8>>, // Чанки, ожидающие записи на диск
+    pending_since: Option<Instant>,     // Время появления первого ожидающего записи чанка
+    compress_state: bool,               // Сжимать ли файл состояния при сохранении
+    durability: Durability,             // Синхронизировать ли записанные чанки с диском
+    watermarks: Watermarks,             // Пороги свободного места для приема чанков
+    full: bool, // Прием прекращен из-за нехватки места (до превышения верхнего порога)
+    write_failures: usize, // Количество неудачных записей на диск подряд
+    degraded_since: Option<Instant>, // Время, с которого запись считается неработающей (например, файловая система только для чтения)
+    paused: bool, // Прием новых чанков приостановлен оператором (сохраненные чанки по-прежнему выдаются)
+    index: IndexKey, // Секрет имен чанков в индексе (по умолчанию - хэши)
+}
+
+pub enum StoredChunk {
+    // Чанк, изъятый из индекса хранилища, но еще не прочитанный. Позволяет читать файл
+    // без блокировки хранилища, т.к. после изъятия чанк недоступен другим запросам
+    Pending(Vec<u8>), // Чанк еще не записан на диск
+    File(PathBuf),    // Путь к файлу чанка
+}
+
+impl StoredChunk {
+    pub async fn read(self) -> Result<Vec<u8>, RetrievingDataError> {
+        // Метод чтения данных чанка, файл чанка удаляется после чтения
+        match self {
+            StoredChunk::Pending(data) => Ok(data),
+            StoredChunk::File(path) => {
+                let data = read_chunk(&path)
+                    .await
+                    .map_err(|e| RetrievingDataError(e.to_string()))?;
+                if let Err(e) = fs::remove_file(&path).await {
+                    eprintln!("Error removing file {}: {}", path.display(), e);
+                }
+                Ok(data)
+            }
+        }
+    }
+}
+
+pub struct Migration {
+    // Перенос хранилища в другой каталог. Копирование выполняется отдельно от хранилища, которое
+    // продолжает сохранять и выдавать чанки из старого каталога до вызова finish_migration
+    target: PathBuf,                   // Новый каталог хранилища
+    files: Vec<(PathBuf, PathBuf)>,    // Файлы, которые нужно перенести: старый путь и новый путь
+    copied: HashMap<PathBuf, PathBuf>, // Перенесенные файлы: старый путь -> новый путь
+}
+
+impl Migration {
+    pub async fn run(mut self) -> Result<Migration, MigrationError> {
+        // Метод копирования файлов чанков в новый каталог
+        fs::create_dir_all(&self.target)
+            .await
+            .map_err(|e| MigrationError(e.to_string()))?;
+        for (old, new) in std::mem::take(&mut self.files) {
+            match transfer_file(&old, &new).await {
+                Ok(()) => {
+                    self.copied.insert(old, new);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {} // Чанк был выдан во время переноса
+                Err(e) => {
+                    self.discard().await;
+                    return Err(MigrationError(format!("{}: {}", old.display(), e)));
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target // Получение нового каталога хранилища
+    }
+
+    pub async fn discard(&self) {
+        // Метод удаления уже перенесенных файлов при ошибке переноса
+        for new in self.copied.values() {
+            if let Err(e) = fs::remove_file(new).await {
+                eprintln!("Error removing file {}: {}", new.display(), e);
+            }
+        }
+    }
+}
+
+async fn write_chunk(path: &Path, data: &[u8], durability: Durability) -> io::Result<()> {
+    // Запись файла чанка с заголовком текущей версии, при строгой гарантии - с синхронизацией с диском
+    let mut file = fs::File::create(path).await?;
+    file.write_all(&ChunkHeader::current().to_bytes()).await?;
+    file.write_all(data).await?;
+    match durability {
+        Durability::Relaxed => file.flush().await,
+        Durability::Strong => file.sync_all().await,
+    }
+}
+
+async fn sync_dir(path: &Path) -> io::Result<()> {
+    // Синхронизация каталога с диском (в Windows каталог не открывается как файл, и записи
+    // каталога сохраняются файловой системой вместе с файлами)
+    #[cfg(unix)]
+    fs::File::open(path).await?.sync_all().await?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+async fn transfer_file(old: &Path, new: &Path) -> io::Result<()> {
+    // Перенос файла жесткой ссылкой в пределах одной файловой системы, иначе копированием
+    if fs::hard_link(old, new).await.is_ok() {
+        return Ok(());
+    }
+    fs::copy(old, new).await.map(|_| ())
 }
 
 impl UdpServerStorageState {
     pub async fn new(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
         if path.exists() {
             let content = fs::read(path).await?;
-            let obj = serde_json::from_slice(&BASE64.decode(&content)?)?;
-            return Ok(obj);
+            let json = match content.starts_with(&ZSTD_MAGIC) {
+                true => zstd::decode_all(content.as_slice())?, // Сжатое состояние
+                false => BASE64.decode(&content)?,
+            };
+            return Ok(serde_json::from_slice(&json)?);
         }
         Ok(UdpServerStorageState {
             hashes: HashMap::new(),
             size: 0,
+            tokens: HashMap::new(),
+            meta: HashMap::new(),
+            chunk_format: CHUNK_FORMAT_VERSION, // Новое хранилище сразу в текущем формате
+            index_check: None,
         })
     }
 
-    pub async fn shutdown(self, path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-        fs::write(path, BASE64.encode(&serde_json::to_vec(&self)?).as_bytes()).await?;
+    pub async fn shutdown(
+        self,
+        path: &PathBuf,
+        compress: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Сохранение состояния: сжатым zstd или, как раньше, в Base64 (читается любой из форматов)
+        let json = serde_json::to_vec(&self)?;
+        let content = match compress {
+            true => zstd::encode_all(json.as_slice(), STATE_COMPRESSION_LEVEL)?,
+            false => BASE64.encode(&json).into_bytes(),
+        };
+        fs::write(path, content).await?;
         Ok(())
     }
 }
@@ -58,32 +365,368 @@ impl UdpServerStorage {
     pub async fn new(
         storage_path: PathBuf,
         state_path: &PathBuf,
+        batch: WriteBatch,
     ) -> Result<UdpServerStorage, Box<dyn std::error::Error>> {
         // Конструктор
-        Ok(UdpServerStorage {
+        let mut storage = UdpServerStorage {
             path: storage_path,
-            state: UdpServerStorageState::new(&state_path).await?,
+            state: UdpServerStorageState::new(state_path).await?,
+            batch,
+            pending: HashMap::new(),
+            pending_since: None,
+            compress_state: false,
+            durability: Durability::default(),
+            watermarks: Watermarks::default(),
+            full: false,
+            write_failures: 0,
+            degraded_since: None,
+            paused: false,
+            index: IndexKey::default(),
+        };
+        let upgraded = storage.upgrade_chunk_files().await?;
+        if upgraded > 0 {
+            println!(
+                "Upgraded {} chunk files to format version {}",
+                upgraded, CHUNK_FORMAT_VERSION
+            );
+        }
+        Ok(storage)
+    }
+
+    async fn upgrade_chunk_files(&mut self) -> io::Result<usize> {
+        // Однократное добавление заголовка файлам чанков, записанным до появления версий формата.
+        // Файл заменяется через временный, поэтому прерванное обновление продолжается при
+        // следующем запуске: состояние с новой версией записывается только при завершении
+        if self.state.chunk_format >= CHUNK_FORMAT_VERSION {
+            return Ok(0);
+        }
+        let mut upgraded = 0;
+        for path in self.state.hashes.values() {
+            let content = match fs::read(path).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue, // Чанк не записан
+                Err(e) => return Err(e),
+            };
+            if content.starts_with(&ChunkHeader::current().to_bytes()) {
+                continue; // Обновлен при прерванном запуске (данные старого файла сравниваются со всем заголовком)
+            }
+            let mut tmp = path.as_os_str().to_owned();
+            tmp.push(".tmp");
+            write_chunk(Path::new(&tmp), &content, self.durability).await?;
+            fs::rename(&tmp, path).await?;
+            upgraded += 1;
+        }
+        self.state.chunk_format = CHUNK_FORMAT_VERSION;
+        Ok(upgraded)
+    }
+
+    pub fn with_index_key(mut self, key: [u8; INDEX_KEY_SIZE]) -> Result<Self, IndexKeyError> {
+        // Включение скрытия хэшей в индексе. Индекс по хэшам переводится на новые имена один раз,
+        // индекс со скрытыми хэшами принимается только с тем же секретом
+        let index = IndexKey(Some(key));
+        let check = index.name(INDEX_CHECK_LABEL);
+        match &self.state.index_check {
+            Some(c) if *c != check => {
+                return Err(IndexKeyError(String::from(
+                    "index key does not match the storage index",
+                )))
+            }
+            Some(_) => {}
+            None => {
+                let state = &mut self.state;
+                index.rename(&mut state.hashes);
+                index.rename(&mut state.tokens);
+                index.rename(&mut state.meta);
+                state.index_check = Some(check);
+            }
+        }
+        self.index = index;
+        Ok(self)
+    }
+
+    pub fn is_index_obfuscated(&self) -> bool {
+        self.state.index_check.is_some() // Проверка, скрыты ли хэши в сохраненном индексе
+    }
+
+    pub fn index_key(&self) -> IndexKey {
+        self.index // Получение способа вычисления имен чанков без блокировки хранилища
+    }
+
+    pub fn index_name(&self, hash: &str) -> String {
+        self.index.name(hash) // Имя чанка в индексе, под которым он упоминается в журналах
+    }
+
+    pub fn with_compressed_state(mut self, compress: bool) -> Self {
+        // Включение сжатия файла состояния
+        self.compress_state = compress;
+        self
+    }
+
+    pub fn with_watermarks(mut self, watermarks: Watermarks) -> Self {
+        // Установка порогов свободного места (верхний порог не ниже нижнего)
+        self.watermarks = Watermarks {
+            high: watermarks.high.max(watermarks.low),
+            ..watermarks
+        };
+        self.full = false;
+        self.note_space();
+        self
+    }
+
+    fn note_space(&mut self) {
+        // Обновление признака нехватки места после изменения занятого места
+        let free = self.free_space();
+        if self.full {
+            self.full = free <= self.watermarks.high;
+        } else {
+            self.full = free <= self.watermarks.low;
+        }
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        // Установка гарантии сохранности записанных чанков
+        self.durability = durability;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path // Получение текущего каталога хранилища
+    }
+
+    pub async fn start_migration(&mut self, target: PathBuf) -> Result<Migration, MigrationError> {
+        // Метод подготовки переноса хранилища: все чанки записываются на диск и составляется список файлов
+        self.flush()
+            .await
+            .map_err(|e| MigrationError(e.to_string()))?;
+        let files = self
+            .state
+            .hashes
+            .values()
+            .filter_map(|old| old.file_name().map(|n| (old.clone(), target.join(n))))
+            .collect();
+        Ok(Migration {
+            target,
+            files,
+            copied: HashMap::new(),
         })
     }
 
+    pub async fn finish_migration(
+        &mut self,
+        mut migration: Migration,
+        state_path: &PathBuf,
+    ) -> Result<(), MigrationError> {
+        // Метод переключения хранилища на новый каталог после копирования файлов. Чанки, сохраненные
+        // во время копирования, переносятся здесь, выданные - не попадают в новый индекс
+        self.flush()
+            .await
+            .map_err(|e| MigrationError(e.to_string()))?;
+        let mut hashes = HashMap::with_capacity(self.state.hashes.len());
+        for (hash, old) in &self.state.hashes {
+            let new = match migration.copied.get(old) {
+                Some(new) => new.clone(),
+                None => {
+                    // Чанк сохранен во время копирования
+                    let new = migration.target.join(old.file_name().unwrap_or_default());
+                    if let Err(e) = transfer_file(old, &new).await {
+                        migration.discard().await;
+                        return Err(MigrationError(format!("{}: {}", old.display(), e)));
+                    }
+                    migration.copied.insert(old.clone(), new.clone());
+                    new
+                }
+            };
+            hashes.insert(hash.clone(), new);
+        }
+
+        let state = UdpServerStorageState {
+            hashes,
+            size: self.state.size,
+            tokens: self.state.tokens.clone(),
+            meta: self.state.meta.clone(),
+            chunk_format: self.state.chunk_format,
+            index_check: self.state.index_check.clone(),
+        };
+        let saved = state
+            .clone()
+            .shutdown(state_path, self.compress_state)
+            .await;
+        if let Err(e) = saved.map_err(|e| MigrationError(e.to_string())) {
+            migration.discard().await;
+            return Err(e);
+        }
+
+        // Переключение: с этого момента чанки сохраняются и выдаются из нового каталога
+        let old_state = std::mem::replace(&mut self.state, state);
+        self.path = migration.target.clone();
+
+        let current: HashSet<&PathBuf> = old_state.hashes.values().collect();
+        for (old, new) in &migration.copied {
+            let stale = if current.contains(old) { old } else { new }; // Выданный чанк удаляется и из нового каталога
+            if let Err(e) = fs::remove_file(stale).await {
+                eprintln!("Error removing file {}: {}", stale.display(), e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn batch(&self) -> WriteBatch {
+        self.batch // Получение параметров пакетной записи
+    }
+
+    pub async fn flush_if_due(&mut self) -> Result<(), SavingDataError> {
+        // Метод записи накопленных чанков, если самый старый из них ожидает дольше max_delay
+        match self.pending_since {
+            Some(t) if t.elapsed() >= self.batch.max_delay => self.flush().await,
+            _ => Ok(()),
+        }
+    }
+
+    pub fn stored_files(&self) -> Vec<(String, PathBuf)> {
+        // Получение записанных на диск чанков: имя в индексе и путь к файлу (ожидающие записи не включаются)
+        self.state
+            .hashes
+            .iter()
+            .filter(|(_, p)| !self.pending.contains_key(*p))
+            .map(|(h, p)| (h.clone(), p.clone()))
+            .collect()
+    }
+
+    pub fn take_if_at(&mut self, name: &str, path: &Path) -> Option<StoredChunk> {
+        // Изъятие чанка по имени из stored_files, только если он все еще хранится в том же файле
+        // (не был выдан или перенесен)
+        if self.state.hashes.get(name).map(PathBuf::as_path) != Some(path) {
+            return None;
+        }
+        self.take_named(name).ok()
+    }
+
+    pub fn set_meta(&mut self, hash: &str, meta: Vec<u8>) -> Result<(), SavingDataError> {
+        // Метод привязки небольших метаданных к сохраненному чанку. Метаданные выдаются без самого
+        // чанка и удаляются вместе с ним
+        let name = self.index.name(hash);
+        if meta.len() > MAX_META_SIZE {
+            return Err(SavingDataError(format!(
+                "Metadata of {} is {} bytes, at most {} allowed",
+                name,
+                meta.len(),
+                MAX_META_SIZE
+            )));
+        }
+        if !self.state.hashes.contains_key(&name) {
+            return Err(SavingDataError(format!("Hash {} is not stored", name)));
+        }
+        self.state.meta.insert(name, meta);
+        Ok(())
+    }
+
+    pub fn meta(&self, hash: &str) -> Option<&[u8]> {
+        let name = self.index.name(hash);
+        self.state.meta.get(&name).map(Vec::as_slice) // Получение метаданных чанка без его изъятия
+    }
+
+    pub fn token(&self, hash: &str) -> Option<u64> {
+        self.state.tokens.get(&self.index.name(hash)).copied() // Получение токена отправки, сохранившей чанк
+    }
+
+    pub fn set_token(&mut self, hash: &str, token: u64) {
+        // Замена токена отправки сохраненного чанка (при загрузке из резервной копии)
+        let name = self.index.name(hash);
+        if self.state.hashes.contains_key(&name) {
+            self.state.tokens.insert(name, token);
+        }
+    }
+
+    pub fn free_space(&self) -> usize {
+        // Метод расчета свободного места хранилища
+        MAX_OCCUPIED_SPACE.saturating_sub(self.get_occupied_space())
+    }
+
     fn get_occupied_space(&self) -> usize {
         // Метод расчета текущего занятого хранилищем места на диске
         self.state.size
     }
 
-    fn is_hash_presented(&self, hash: &str) -> bool {
+    pub fn pause_storing(&mut self) {
+        self.paused = true; // Приостановка приема новых чанков на время обслуживания узла
+    }
+
+    pub fn resume_storing(&mut self) {
+        self.paused = false; // Возобновление приема новых чанков
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_write_degraded(&self) -> bool {
+        // Проверка, отключен ли прием чанков из-за повторяющихся ошибок записи. По истечении
+        // WRITE_RETRY_INTERVAL прием возобновляется, и следующая неудачная запись снова его отключает
+        self.degraded_since
+            .is_some_and(|t| t.elapsed() < WRITE_RETRY_INTERVAL)
+    }
+
+    fn record_write(&mut self, ok: bool) {
+        // Учет результата записи на диск
+        if ok {
+            self.write_failures = 0;
+            self.degraded_since = None;
+            return;
+        }
+        self.write_failures += 1;
+        if self.write_failures >= WRITE_FAILURE_LIMIT {
+            self.degraded_since = Some(Instant::now());
+        }
+    }
+
+    pub fn can_accept(&self, hash: &str, token: u64) -> bool {
+        // Метод проверки, можно ли подтвердить запрос на отправку: чанка нет в хранилище
+        // или это повторный запрос той же отправки
+        !self.is_hash_presented(hash) || self.is_retransmission(hash, token)
+    }
+
+    fn is_retransmission(&self, hash: &str, token: u64) -> bool {
+        // Метод проверки, сохранен ли чанк с этим хэшем той же отправкой
+        self.state.tokens.get(&self.index.name(hash)) == Some(&token)
+    }
+
+    pub fn is_hash_presented(&self, hash: &str) -> bool {
         // Метод вычисления хэш-сумм всех файлов в директории
-        self.state.hashes.contains_key(hash) // В противном случае возвращаем ошибку
+        self.state.hashes.contains_key(&self.index.name(hash)) // В противном случае возвращаем ошибку
+    }
+
+    fn take_named(&mut self, name: &str) -> Result<StoredChunk, RetrievingDataError> {
+        // Метод изъятия чанка из индекса по имени, занятое место освобождается сразу
+        let path = self
+            .state
+            .hashes
+            .remove(name)
+            .ok_or(RetrievingDataError(String::from("No such hash was found")))?;
+        self.state.tokens.remove(name);
+        self.state.meta.remove(name);
+        if let Some(data) = self.pending.remove(&path) {
+            self.state.size = self.state.size.saturating_sub(data.len());
+            self.note_space();
+            return Ok(StoredChunk::Pending(data)); // Данные еще не записаны на диск
+        }
+        let size = std::fs::metadata(&path)
+            .map(|m| (m.len() as usize).saturating_sub(CHUNK_HEADER_LEN))
+            .unwrap_or(0); // Размер данных по размеру файла без его чтения
+        self.state.size = self.state.size.saturating_sub(size);
+        self.note_space();
+        Ok(StoredChunk::File(path))
     }
 }
 
 impl ServerStorage for UdpServerStorage {
     // Реализация трейта для структуры
-    async fn save(&mut self, hash: &str, data: &[u8]) -> Result<(), SavingDataError> {
+    async fn save(&mut self, hash: &str, data: &[u8], token: u64) -> Result<(), SavingDataError> {
         // Реализация метода сохранения данных на диске
-        let hash = String::from(hash); // Переводим хэш в String
-
-        if self.is_hash_presented(&hash) {
+        if self.is_retransmission(hash, token) {
+            return Ok(()); // Повторно полученные данные той же отправки уже сохранены
+        }
+        let hash = self.index.name(hash); // Имя чанка в индексе
+        if self.state.hashes.contains_key(&hash) {
             // Если такой хэш уже представлен в хранилище
             return Err(SavingDataError(format!(
                 "Hash {} already presents file",
@@ -92,40 +735,78 @@ impl ServerStorage for UdpServerStorage {
         }
 
         let filename = self.path.join(format!("{}.bin", Uuid::new_v4())); // Создаем имя нового файла при помощи UUIDv4
-        fs::write(&filename, data)
-            .await
-            .map_err(|e| SavingDataError(e.to_string()))?; // Записываем данные в файл
+        self.pending.insert(filename.clone(), data.to_vec()); // Помещаем данные в буфер записи
+        self.pending_since.get_or_insert_with(Instant::now);
+        self.state.hashes.insert(hash.clone(), filename);
+        self.state.tokens.insert(hash, token);
+        self.state.size += data.len(); // Учитываем занятое чанком место
+        self.note_space();
 
-        self.state.hashes.insert(hash, filename);
+        if self.pending.len() >= self.batch.max_chunks {
+            self.flush().await?; // Записываем накопленные данные на диск
+        }
         Ok(())
     }
 
-    async fn get(&mut self, hash: &str) -> Result<Vec<u8>, RetrievingDataError> {
-        // Реализация метода получения данных из хранилища
-        if self.is_hash_presented(hash) {
-            // Если такой хэш есть в хранилище
-            let path = self.state.hashes.remove(hash).map_or(
-                Err(RetrievingDataError(String::from("No such hash was found"))),
-                |x| Ok(x),
-            )?;
-            let data = fs::read(&path)
-                .await
-                .map_err(|e| RetrievingDataError(e.to_string()))?;
-            if let Err(e) = fs::remove_file(&path).await {
-                eprintln!("Error removing file {}: {}", path.display(), e.to_string());
-            }
-            return Ok(data);
-        }
-        Err(RetrievingDataError(String::from("No such hash was found")))
+    fn take(&mut self, hash: &str) -> Result<StoredChunk, RetrievingDataError> {
+        // Метод изъятия чанка из индекса, занятое место освобождается сразу
+        let name = self.index.name(hash);
+        self.take_named(&name)
     }
 
     fn can_save(&self) -> bool {
         // Реализация метода проверки возможности сохранения файла
-        self.get_occupied_space() < MAX_OCCUPIED_SPACE
+        !self.full && !self.is_write_degraded() && !self.paused
     }
 
-    async fn shutdown(self, path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-        self.state.shutdown(&path).await
+    async fn flush(&mut self) -> Result<(), SavingDataError> {
+        // Реализация метода записи накопленных чанков на диск
+        self.pending_since = None;
+        let written = !self.pending.is_empty();
+        let mut failed = Vec::new();
+        let durability = self.durability;
+        for (filename, data) in self.pending.drain() {
+            if let Err(e) = write_chunk(&filename, &data, durability).await {
+                failed.push(format!("{}: {}", filename.display(), e));
+                let UdpServerStorageState {
+                    hashes,
+                    tokens,
+                    meta,
+                    ..
+                } = &mut self.state;
+                hashes.retain(|h, p| {
+                    let keep = *p != filename;
+                    if !keep {
+                        tokens.remove(h);
+                        meta.remove(h);
+                    }
+                    keep
+                }); // Незаписанный чанк удаляется из индекса
+                self.state.size = self.state.size.saturating_sub(data.len());
+            }
+        }
+        self.note_space(); // Место незаписанных чанков освобождено
+        if written && durability == Durability::Strong {
+            // Новые записи каталога (имена файлов чанков) также должны пережить отключение питания
+            if let Err(e) = sync_dir(&self.path).await {
+                failed.push(format!("{}: {}", self.path.display(), e));
+            }
+        }
+        if written {
+            self.record_write(failed.is_empty());
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(SavingDataError(failed.join(", ")))
+        }
+    }
+
+    async fn shutdown(mut self, path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+        if let Err(e) = self.flush().await {
+            eprintln!("{}", e);
+        }
+        self.state.shutdown(&path, self.compress_state).await
     }
 }
 
@@ -155,4 +836,406 @@ mod errors {
     }
 
     impl Error for RetrievingDataError {}
+
+    #[derive(Debug, Clone)]
+    pub struct MigrationError(pub String); // Тип ошибки переноса хранилища
+
+    impl fmt::Display for MigrationError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Error migrating storage: {}", self.0)
+        }
+    }
+
+    impl Error for MigrationError {}
+
+    #[derive(Debug, Clone)]
+    pub struct IndexKeyError(pub String); // Тип ошибки секрета индекса хранилища
+
+    impl fmt::Display for IndexKeyError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Storage index key error: {}", self.0)
+        }
+    }
+
+    impl Error for IndexKeyError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn migration_keeps_all_chunks() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        let (old_dir, new_dir) = (base.join("old"), base.join("new"));
+        let state_path = base.join("state.bin");
+        fs::create_dir_all(&old_dir).await.unwrap();
+
+        let mut storage =
+            UdpServerStorage::new(old_dir.clone(), &state_path, WriteBatch::default())
+                .await
+                .unwrap();
+        let chunks: Vec<(String, Vec<u8>)> = (0..4u8)
+            .map(|i| (format!("hash{}", i), vec![i; 128]))
+            .collect();
+        for (h, d) in &chunks[..3] {
+            storage.save(h, d, 1).await.unwrap();
+        }
+
+        let migration = storage.start_migration(new_dir.clone()).await.unwrap();
+        let migration = migration.run().await.unwrap();
+        storage.save(&chunks[3].0, &chunks[3].1, 1).await.unwrap(); // Сохранение во время переноса
+        storage
+            .finish_migration(migration, &state_path)
+            .await
+            .unwrap();
+
+        assert_eq!(storage.path(), new_dir.as_path());
+        assert!(std::fs::read_dir(&old_dir).unwrap().next().is_none());
+        let restored = UdpServerStorageState::new(&state_path).await.unwrap();
+        assert!(restored.hashes.values().all(|p| p.starts_with(&new_dir)));
+        for (h, d) in &chunks {
+            assert_eq!(&storage.take(h).unwrap().read().await.unwrap(), d);
+        }
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn state_is_loaded_from_compressed_and_plain_files() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let state_path = base.join("state.bin");
+        let mut state = UdpServerStorageState::new(&state_path).await.unwrap();
+        state.hashes = (0..1000)
+            .map(|i| (format!("hash{}", i), base.join(format!("{}.bin", i))))
+            .collect();
+
+        for compress in [true, false] {
+            state.clone().shutdown(&state_path, compress).await.unwrap();
+            let content = fs::read(&state_path).await.unwrap();
+            assert_eq!(content.starts_with(&ZSTD_MAGIC), compress);
+            let restored = UdpServerStorageState::new(&state_path).await.unwrap();
+            assert_eq!(restored.hashes, state.hashes);
+        }
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn persistent_write_failures_stop_accepting_chunks() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let missing = base.join("missing"); // Запись в несуществующий каталог всегда завершается ошибкой
+        let mut storage =
+            UdpServerStorage::new(missing, &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap();
+
+        for i in 0..WRITE_FAILURE_LIMIT {
+            assert!(storage.can_save());
+            assert!(storage.save(&format!("hash{}", i), &[1], 1).await.is_err());
+        }
+        assert!(storage.is_write_degraded());
+        assert!(!storage.can_save()); // Сервер больше не подтверждает запросы на отправку
+        assert_eq!(storage.get_occupied_space(), 0);
+
+        storage.path = base.clone(); // Запись снова работает
+        storage.record_write(true);
+        storage.pause_storing();
+        assert!(!storage.can_save()); // Прием приостановлен оператором
+        storage.save("hash", &[1], 1).await.unwrap(); // Ранее подтвержденные чанки сохраняются
+        assert!(storage.take("hash").is_ok()); // Выдача чанков продолжается
+        storage.resume_storing();
+        storage.record_write(true);
+        assert!(storage.can_save());
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn strong_durability_writes_chunk_before_returning() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let mut storage =
+            UdpServerStorage::new(base.clone(), &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap()
+                .with_durability(Durability::Strong);
+        storage.save("hash", &[7; 64], 1).await.unwrap();
+        assert!(storage.pending.is_empty()); // Чанк записан и синхронизирован до подтверждения
+        let StoredChunk::File(path) = storage.take("hash").unwrap() else {
+            panic!("chunk is not written to disk");
+        };
+        assert_eq!(read_chunk(&path).await.unwrap(), vec![7; 64]);
+
+        storage.path = base.join("missing"); // Ошибка записи при строгой гарантии сообщается клиенту
+        assert!(storage.save("other", &[1], 1).await.is_err());
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn free_space_watermarks_switch_accepting_with_hysteresis() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let watermarks = Watermarks {
+            low: MAX_OCCUPIED_SPACE - 100, // Прием прекращается при занятых 100 байтах
+            high: MAX_OCCUPIED_SPACE - 50, // и возобновляется при занятых менее 50 байтах
+        };
+        let mut storage =
+            UdpServerStorage::new(base.clone(), &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap()
+                .with_watermarks(watermarks);
+
+        storage.save("a", &[1; 60], 1).await.unwrap();
+        assert!(storage.can_save());
+        storage.save("b", &[2; 40], 1).await.unwrap(); // Занято 100 байт - нижний порог
+        assert!(!storage.can_save());
+        storage.take("b").unwrap(); // Занято 60 байт: место выше нижнего порога, но не выше верхнего
+        assert!(!storage.can_save());
+        storage.save("c", &[3; 10], 1).await.unwrap(); // Ранее подтвержденный чанк сохраняется
+        storage.take("c").unwrap();
+        storage.take("a").unwrap(); // Свободного места больше верхнего порога
+        assert!(storage.can_save());
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn chunk_meta_is_capped_and_taken_with_chunk() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let mut storage =
+            UdpServerStorage::new(base.clone(), &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap();
+
+        assert!(storage.set_meta("hash", vec![1]).is_err()); // Чанк еще не сохранен
+        storage.save("hash", &[1, 2, 3], 7).await.unwrap();
+        assert!(storage
+            .set_meta("hash", vec![0; MAX_META_SIZE + 1])
+            .is_err());
+        storage.set_meta("hash", b"text/plain".to_vec()).unwrap();
+        assert_eq!(storage.meta("hash"), Some(&b"text/plain"[..]));
+        storage.take("hash").unwrap();
+        assert_eq!(storage.meta("hash"), None);
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retransmitted_chunk_is_saved_once() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let mut storage =
+            UdpServerStorage::new(base.clone(), &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap();
+
+        storage.save("hash", &[1, 2, 3], 7).await.unwrap();
+        assert!(storage.can_accept("hash", 7));
+        storage.save("hash", &[1, 2, 3], 7).await.unwrap(); // Повторная передача той же отправки
+        assert!(!storage.can_accept("hash", 8));
+        assert!(storage.save("hash", &[1, 2, 3], 8).await.is_err());
+        assert_eq!(storage.get_occupied_space(), 3);
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn paused_storage_serves_but_does_not_accept_chunks() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let mut storage =
+            UdpServerStorage::new(base.clone(), &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap();
+        storage.save("first", &[1, 2, 3], 1).await.unwrap();
+        storage.save("second", &[4, 5], 2).await.unwrap();
+        assert!(storage.can_save());
+
+        storage.pause_storing();
+        assert!(storage.is_paused());
+        assert!(!storage.can_save()); // SENDING_ACK не отправляется
+        assert_eq!(
+            storage.take("first").unwrap().read().await.unwrap(),
+            [1, 2, 3]
+        ); // Сохраненные чанки выдаются
+
+        storage.resume_storing();
+        assert!(!storage.is_paused());
+        assert!(storage.can_save());
+        assert_eq!(
+            storage.take("second").unwrap().read().await.unwrap(),
+            [4, 5]
+        );
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupted_chunk_is_taken_only_from_checked_file() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let mut storage =
+            UdpServerStorage::new(base.clone(), &base.join("state.bin"), WriteBatch::default())
+                .await
+                .unwrap();
+
+        storage.save("hash", &[1, 2, 3], 1).await.unwrap();
+        let files = storage.stored_files();
+        assert_eq!(files.len(), 1);
+        let (hash, path) = &files[0];
+        assert!(storage.take_if_at(hash, &base.join("other.bin")).is_none());
+        assert!(storage.take_if_at(hash, path).is_some());
+        assert!(storage.take_if_at(hash, path).is_none()); // Чанк уже изъят
+        assert_eq!(storage.get_occupied_space(), 0);
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn chunk_files_are_versioned_and_legacy_files_upgraded() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let state_path = base.join("state.bin");
+        let legacy = base.join("legacy.bin");
+        fs::write(&legacy, [5; 32]).await.unwrap(); // Файл версии 0: данные без заголовка
+        let mut state = UdpServerStorageState::new(&state_path).await.unwrap();
+        state.chunk_format = 0;
+        state.hashes.insert(String::from("legacy"), legacy.clone());
+        state.size = 32;
+        state.shutdown(&state_path, false).await.unwrap();
+
+        let mut storage = UdpServerStorage::new(base.clone(), &state_path, WriteBatch::default())
+            .await
+            .unwrap();
+        let content = fs::read(&legacy).await.unwrap();
+        assert_eq!(
+            ChunkHeader::parse(&content),
+            (ChunkHeader::current(), &[5; 32][..])
+        );
+        assert_eq!(
+            storage.take("legacy").unwrap().read().await.unwrap(),
+            vec![5; 32]
+        );
+        assert_eq!(storage.get_occupied_space(), 0);
+
+        storage.save("hash", &[9; 16], 1).await.unwrap();
+        let StoredChunk::File(path) = storage.take("hash").unwrap() else {
+            panic!("chunk is not written to disk");
+        };
+        assert!(fs::read(&path).await.unwrap().starts_with(CHUNK_MAGIC));
+        assert_eq!(read_chunk(&path).await.unwrap(), vec![9; 16]);
+
+        // Файл более новой версии не читается как данные чанка
+        let future = ChunkHeader {
+            version: CHUNK_FORMAT_VERSION + 1,
+            flags: 0,
+        };
+        fs::write(&path, [&future.to_bytes()[..], &[9; 16]].concat())
+            .await
+            .unwrap();
+        assert!(read_chunk(&path).await.is_err());
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn obfuscated_index_hides_hashes_in_state_file() {
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let state_path = base.join("state.bin");
+        let open = || UdpServerStorage::new(base.clone(), &state_path, WriteBatch::default());
+        let mut storage = open().await.unwrap();
+        storage.save("hash1", &[1], 1).await.unwrap(); // Сохранен до включения скрытия
+
+        let key = load_index_key(base.join("index.key")).await.unwrap();
+        assert_eq!(load_index_key(base.join("index.key")).await.unwrap(), key);
+        let mut storage = storage.with_index_key(key).unwrap();
+        storage.save("hash2", &[2], 2).await.unwrap();
+        storage.set_meta("hash2", vec![7]).unwrap();
+        assert!(storage.is_hash_presented("hash1") && storage.is_hash_presented("hash2"));
+        storage.shutdown(state_path.clone()).await.unwrap();
+        let content = fs::read(&state_path).await.unwrap();
+        let json = String::from_utf8(BASE64.decode(&content).unwrap()).unwrap();
+        assert!(!json.contains("hash1") && !json.contains("hash2"));
+
+        assert!(open().await.unwrap().is_index_obfuscated());
+        assert!(open().await.unwrap().with_index_key([0; 32]).is_err()); // Другой секрет
+        let mut storage = open().await.unwrap().with_index_key(key).unwrap();
+        assert_eq!(storage.meta("hash2"), Some(&[7][..]));
+        assert!(!storage.can_accept("hash1", 3));
+        for hash in ["hash1", "hash2"] {
+            assert!(storage.take(hash).is_ok());
+        }
+
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "benchmark: cargo test -p leafd bench -- --ignored --nocapture"]
+    async fn bench_checkpoint_compression() {
+        // Запись состояния хранилища с 200 000 чанков сжатым zstd и в Base64
+        let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+        fs::create_dir_all(&base).await.unwrap();
+        let state_path = base.join("state.bin");
+        let mut state = UdpServerStorageState::new(&state_path).await.unwrap();
+        for i in 0..200_000 {
+            let hash = format!("{:0128x}", i as u128 * 0x9E37_79B9_7F4A_7C15);
+            state
+                .hashes
+                .insert(hash.clone(), base.join(format!("{}.bin", Uuid::new_v4())));
+            state.tokens.insert(hash, rand::random());
+        }
+        for compress in [false, true] {
+            let started = Instant::now();
+            state.clone().shutdown(&state_path, compress).await.unwrap();
+            let elapsed = started.elapsed();
+            let size = fs::metadata(&state_path).await.unwrap().len();
+            println!("compress={}: {:?}, {} bytes", compress, elapsed, size);
+        }
+        fs::remove_dir_all(&base).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "benchmark: cargo test -p leafd bench -- --ignored --nocapture"]
+    async fn bench_batched_store_throughput() {
+        // Сохранение 2000 чанков по 4 Кб с записью каждого чанка сразу и пакетами по 64
+        let chunk = vec![7u8; 4096];
+        for durability in [Durability::Relaxed, Durability::Strong] {
+            for max_chunks in [1, 64] {
+                let base = std::env::temp_dir().join(format!("leafd-{}", Uuid::new_v4()));
+                fs::create_dir_all(&base).await.unwrap();
+                let batch = WriteBatch {
+                    max_chunks,
+                    ..WriteBatch::default()
+                };
+                let mut storage =
+                    UdpServerStorage::new(base.clone(), &base.join("state.bin"), batch)
+                        .await
+                        .unwrap()
+                        .with_durability(durability);
+                let started = Instant::now();
+                for i in 0..2000 {
+                    storage
+                        .save(&format!("hash{}", i), &chunk, 1)
+                        .await
+                        .unwrap();
+                }
+                storage.flush().await.unwrap();
+                let elapsed = started.elapsed();
+                println!(
+                    "{:?}, max_chunks={}: {:?} ({:.0} chunks/s)",
+                    durability,
+                    max_chunks,
+                    elapsed,
+                    2000.0 / elapsed.as_secs_f64()
+                );
+                fs::remove_dir_all(&base).await.unwrap();
+            }
+        }
+    }
 }
@@ -0,0 +1,221 @@
+use std::future::Future; // Трейт асинхронных операций стандартной библиотеки
+use std::io; // Ошибки ввода-вывода наблюдаемых задач
+use std::sync::Arc; // Умный указатель для разделения семафора между задачами
+use std::time::Duration; // Структура с длительностью ожидания
+
+use tokio::sync::Semaphore; // Внешняя зависимость для ограничения количества одновременных задач
+use tokio::task::JoinSet; // Набор задач, отменяемых при удалении набора
+use tokio::time; // Внешняя зависимость для асинхронного ожидания
+
+use consts::*; // Внутренний модуль с константами
+pub use errors::*; // Внутренний модуль с ошибками
+
+mod consts {
+    // Модуль с константами
+    use std::time::Duration;
+
+    pub const STABLE_RUN: Duration = Duration::from_secs(60); // Время работы задачи, после которого счетчик перезапусков сбрасывается
+}
+
+#[derive(Clone)]
+pub struct WorkerPool {
+    // Пул задач обработки запросов: каждый запрос выполняется в отдельной задаче с ограничением
+    // по времени, количество одновременно выполняемых запросов ограничено семафором
+    semaphore: Arc<Semaphore>, // Семафор свободных мест пула
+    limit: usize,              // Максимальное количество одновременно выполняемых запросов
+    timeout: Duration,         // Максимальное время выполнения запроса
+}
+
+impl WorkerPool {
+    pub fn new(limit: usize, timeout: Duration) -> Self {
+        let limit = limit.max(1);
+        WorkerPool {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+            timeout,
+        }
+    }
+
+    pub async fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // Запуск запроса в отдельной задаче, при заполненном пуле ожидается освобождение места
+        let Ok(permit) = self.semaphore.clone().acquire_owned().await else {
+            return; // Семафор закрыт
+        };
+        let timeout = self.timeout;
+        tokio::spawn(async move {
+            if time::timeout(timeout, task).await.is_err() {
+                eprintln!("Request processing timed out after {:?}", timeout);
+            }
+            drop(permit); // Освобождаем место в пуле
+        });
+    }
+
+    pub async fn wait_idle(&self) {
+        // Ожидание завершения всех запущенных запросов
+        if let Ok(permits) = self.semaphore.acquire_many(self.limit as u32).await {
+            drop(permits);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RestartPolicy {
+    // Параметры перезапуска наблюдаемой задачи
+    pub max_restarts: usize, // Количество перезапусков подряд, после которого задача не перезапускается
+    pub delay: Duration,     // Время ожидания перед перезапуском
+}
+
+pub async fn supervise<F, Fut>(
+    name: &str,
+    policy: RestartPolicy,
+    mut start: F,
+) -> Result<(), TaskFailedError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<()>> + Send + 'static,
+{
+    // Выполнение задачи с перезапуском после ошибки или паники. Задача выполняется в отдельной
+    // задаче tokio, поэтому паника не завершает сервер; при удалении наблюдателя задача отменяется.
+    // Перезапуски считаются подряд: если задача проработала дольше STABLE_RUN, счетчик сбрасывается
+    let mut restarts = 0;
+    loop {
+        let started = time::Instant::now();
+        let mut task = JoinSet::new();
+        task.spawn(start());
+        let failure = match task.join_next().await {
+            Some(Ok(Ok(()))) | None => return Ok(()), // Задача завершилась штатно
+            Some(Ok(Err(e))) => e.to_string(),
+            Some(Err(e)) if e.is_panic() => String::from("task panicked"),
+            Some(Err(e)) => e.to_string(),
+        };
+        if started.elapsed() >= STABLE_RUN {
+            restarts = 0;
+        }
+        if restarts >= policy.max_restarts {
+            return Err(TaskFailedError(name.to_string(), restarts, failure));
+        }
+        restarts += 1;
+        eprintln!(
+            "{} stopped: {}, restarting ({}/{})",
+            name, failure, restarts, policy.max_restarts
+        );
+        time::sleep(policy.delay).await;
+    }
+}
+
+mod errors {
+    // Модуль с ошибками
+    use std::error::Error;
+    use std::fmt;
+
+    #[derive(Debug, Clone)]
+    pub struct TaskFailedError(pub String, pub usize, pub String); // Имя задачи, количество перезапусков и последняя ошибка
+    impl fmt::Display for TaskFailedError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{} stopped after {} restarts: {}",
+                self.0, self.1, self.2
+            )
+        }
+    }
+    impl Error for TaskFailedError {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn slow_request_does_not_delay_fast_one() {
+        let pool = WorkerPool::new(2, Duration::from_secs(5));
+        let (slow_tx, slow_rx) = oneshot::channel();
+        let (fast_tx, fast_rx) = oneshot::channel();
+        let started = Instant::now();
+
+        pool.spawn(async move {
+            time::sleep(Duration::from_millis(500)).await;
+            let _ = slow_tx.send(Instant::now());
+        })
+        .await;
+        pool.spawn(async move {
+            let _ = fast_tx.send(Instant::now());
+        })
+        .await;
+
+        let fast = fast_rx.await.unwrap();
+        assert!(fast.duration_since(started) < Duration::from_millis(250));
+        let slow = slow_rx.await.unwrap();
+        assert!(fast < slow);
+        pool.wait_idle().await;
+    }
+
+    #[tokio::test]
+    async fn request_exceeding_timeout_is_cancelled() {
+        let pool = WorkerPool::new(1, Duration::from_millis(50));
+        let (tx, rx) = oneshot::channel::<()>();
+        pool.spawn(async move {
+            time::sleep(Duration::from_secs(5)).await;
+            let _ = tx.send(());
+        })
+        .await;
+        pool.wait_idle().await;
+        assert!(rx.await.is_err()); // Задача отменена, отправитель удален
+    }
+
+    #[tokio::test]
+    async fn crashed_listener_is_restarted_and_keeps_serving() {
+        let socket = Arc::new(tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let addr = socket.local_addr().unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        let policy = RestartPolicy {
+            max_restarts: 2,
+            delay: Duration::from_millis(10),
+        };
+        // Обработчик принимает один пакет и падает
+        let listener = supervise("Listener", policy, move || {
+            let (socket, tx) = (socket.clone(), tx.clone());
+            async move {
+                let mut buf = [0u8; 16];
+                let (sz, _) = socket.recv_from(&mut buf).await?;
+                tx.send(buf[..sz].to_vec()).await.unwrap();
+                panic!("listener crashed");
+            }
+        });
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let send = async {
+            let mut received = Vec::new();
+            for packet in [b"one", b"two"] {
+                client.send_to(packet, addr).await.unwrap();
+                received.push(rx.recv().await.unwrap());
+            }
+            received
+        };
+        tokio::select! {
+            received = send => assert_eq!(received, vec![b"one".to_vec(), b"two".to_vec()]),
+            res = listener => panic!("listener gave up early: {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_task_stops_after_restart_limit() {
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let policy = RestartPolicy {
+            max_restarts: 2,
+            delay: Duration::ZERO,
+        };
+        let res = supervise("Listener", policy, move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { Err(io::Error::other("socket closed")) }
+        })
+        .await;
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(res.unwrap_err().1, 2);
+    }
+}